@@ -10,8 +10,11 @@ async fn main() {
 
     let app = App::new(ExampleOptions);
 
-    if let Err(e) = app.run().await {
-        log::error!("{e}");
-        std::process::exit(1);
+    match app.run_typed().await {
+        Ok(outcome) => std::process::exit(outcome.exit_code()),
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(1);
+        },
     }
 }
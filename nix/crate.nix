@@ -13,6 +13,29 @@ let
 
       inherit (crane.crateNameFromCargoToml { src = crane.cleanCargoSource ../.; }) pname version;
 
+      # Every feature but `libsql_replica`, which can't be enabled together
+      # with `rusqlite` or `sqlx_sqlite` because both link a SQLite.
+      backendFeatures = [
+        "sqlx_postgres"
+        "sqlx_mysql"
+        "sqlx_sqlite"
+        "tokio_postgres"
+        "deadpool_postgres"
+        "rusqlite"
+        "libsql"
+        "mysql_async"
+        "vault"
+        "aws-secrets"
+        "aws-iam"
+        "gcp-iam"
+        "axum"
+        "actix"
+        "zstd"
+        "tokio"
+      ];
+      features = "--features ${lib.concatStringsSep "," ([ "cli" ] ++ backendFeatures)}";
+      coreFeatures = "--features ${lib.concatStringsSep "," ([ "sqlx" ] ++ backendFeatures)}";
+
       args = {
         # Override if building with `-p`.
         inherit pname version;
@@ -21,7 +44,7 @@ let
           fileset = src;
         };
         strictDeps = true;
-        cargoBuildExtraArgs = "--all-features";
+        cargoBuildExtraArgs = features;
       };
 
       # Build only dependencies so that they can be cached for everything else.
@@ -36,7 +59,7 @@ let
       };
 
       mkTernPackage =
-        pname:
+        pname: featureArgs:
         crane.buildPackage {
           inherit pname cargoArtifacts;
           inherit (args)
@@ -44,13 +67,13 @@ let
             version
             strictDeps
             ;
-          cargoBuildExtraArgs = "--all-features -p ${pname}";
+          cargoBuildExtraArgs = "${featureArgs} -p ${pname}";
         };
 
       # Individual crates as flake outputs.
-      tern-cli = mkTernPackage "tern-cli";
-      tern-core = mkTernPackage "tern-core";
-      tern-derive = mkTernPackage "tern-derive";
+      tern-cli = mkTernPackage "tern-cli" "--all-features";
+      tern-core = mkTernPackage "tern-core" coreFeatures;
+      tern-derive = mkTernPackage "tern-derive" "--all-features";
 
       # The root package.
       tern = crane.buildPackage {
@@ -93,7 +116,7 @@ let
             ;
 
           inherit cargoArtifacts;
-          cargoClippyExtraArgs = "--all-features --all-targets -- -Dwarnings";
+          cargoClippyExtraArgs = "${features} --all-targets -- -Dwarnings";
         };
 
         tern-test = crane.cargoTest {
@@ -104,7 +127,7 @@ let
             ;
           inherit cargoArtifacts;
 
-          cargoTestExtraArgs = "--all-features --all-targets";
+          cargoTestExtraArgs = "${features} --all-targets";
           # We need to add the migration directories for the examples since
           # everywhere else they're filtered out and we need them to compile
           # tests (which includes `examples`).
@@ -9,16 +9,48 @@
 //! ## Executors
 //!
 //! The [`Executor`] trait defines methods representing the minimum required to
-//! connect to a database, run migration queries, and interact with the migration
-//! history table.  Right now, `tern` supports all of the [`sqlx`][sqlx-repo]
-//! pool types via [`Pool`][sqlx-pool], which includes PostgreSQL, MySQL, and
-//! SQLite. These can be enabled via feature flag.
+//! connect to a database, run migration queries, and interact with the
+//! migration history table.  Right now, `tern` supports all of the
+//! [`sqlx`][sqlx-repo] pool types via [`Pool`][sqlx-pool], which includes
+//! PostgreSQL, MySQL, and SQLite. These can be enabled via feature flag.
+//! There is also an executor for a `sqlx` transaction that the caller began,
+//! such as `SqlxPgTxExecutor`, for applying migrations together with other
+//! changes and committing them at once.
+//!
+//! `SqlxCockroachExecutor` is one for CockroachDB with the feature
+//! `sqlx_postgres`.  CockroachDB aborts a transaction that conflicts with
+//! another with a serialization failure, so a migration applied in a
+//! transaction is retried a few times with backoff when that happens.
+//!
+//! With the feature `tokio_postgres`, `TokioPgExecutor` is an executor for a
+//! `tokio_postgres::Client`, for an application that already uses
+//! `tokio-postgres` or a pool of its clients, so that it doesn't need `sqlx`
+//! only to apply migrations.  It makes every query with the one client.  With
+//! the feature `deadpool_postgres`, `DeadpoolPgExecutor` is one with a client
+//! checked out of a `deadpool_postgres::Pool`, so that migrations run with the
+//! pool of the application instead of a second one.
+//!
+//! With the feature `rusqlite`, `RusqliteExecutor` is an executor for a
+//! `rusqlite::Connection`, for an embedded or desktop application that uses
+//! `rusqlite`.  The connection is synchronous, so its queries run on the
+//! blocking thread pool of the `tokio` runtime.
+//!
+//! With the feature `libsql`, `LibsqlExecutor` is an executor for a remote
+//! libSQL database, like one on Turso.  A migration that is in a transaction is
+//! sent in one request as a batch that is applied atomically, since a
+//! transaction over HTTP can't span requests.  The feature `libsql_replica`
+//! adds a local file and an embedded replica, which build the SQLite of libSQL,
+//! so they can't be used in the same binary as `sqlx_sqlite` or `rusqlite`.
+//!
+//! With the feature `mysql_async`, `MySqlAsyncExecutor` is an executor for a
+//! `mysql_async::Pool`, for an application that already uses `mysql_async`,
+//! with the same history table as `SqlxMySqlExecutor`.
 //!
 //! ### Contributing
 //!
-//! Supporting more third-party crates would definitely be nice!  If one you like
-//! is not available here, please feel free to contribute, either with a PR or
-//! feature request.  Adding a new executor seems like it should not be hard.
+//! Supporting more third-party crates would definitely be nice!  If one you
+//! like is not available here, please feel free to contribute, either with a PR
+//! or feature request.  Adding a new executor seems like it should not be hard.
 //! Outside of additional backend types, we are very open and happy to hear
 //! suggestions for how the project could be improved; simply open an issue with
 //! the label "enhancement".  Defects or general issues detracting from usage
@@ -29,7 +61,70 @@
 //! Migrations are defined in a directory within a Rust project's source.
 //! This directory can contain `.rs` and `.sql` files having names matching the
 //! regex `^V(\d+)__(\w+)\.(sql|rs)$`, e.g., `V13__create_a_table.sql` or
-//! `V5__create_a_different_table.rs`.
+//! `V5__create_a_different_table.rs`.  With the attribute
+//! `#[tern(version = "text")]`, the version can instead be any text of letters,
+//! digits, `.` and `-`, such as `V2024.01.15.2__add_index.sql`, and versions
+//! are ordered with runs of digits compared as numbers.  A different order can
+//! be given with `#[tern(ordering = "MyOrdering")]`, naming a value
+//! implementing `MigrationOrdering`.  Services whose migrations share one
+//! history table each set `#[tern(source_id = "my-service")]` to keep their
+//! histories apart.
+//! SQL to set up each database session, like
+//! `#[tern(session_setup = "SET ROLE migrator; SET search_path TO app;")]`,
+//! runs on every connection before it's used for the migrations or the history
+//! table, and a config value for `TERN_SESSION_SETUP` is used instead of it.
+//! On Postgres, `#[tern(search_path = "app, public")]` sets the `search_path`
+//! for the whole run after that, so unqualified names in migrations are in
+//! those schemas, and records it in the history row of each migration.  A
+//! config value for `TERN_SEARCH_PATH`, which can come from the `search_path`
+//! of a CLI config profile, is used instead of it.
+//!
+//! Likewise, `#[tern(post_apply = "SELECT pg_notify('schema', 'changed');")]`
+//! runs SQL once after a run that applied migrations, outside of a
+//! transaction, and a config value for `TERN_POST_APPLY`, which can come from
+//! the `post_apply` of a CLI config profile, is used instead of it.
+//!
+//! With `#[tern(history_content = "checksum")]`, the history table keeps only
+//! the checksum and length of each migration applied instead of all of it,
+//! for projects with migrations big enough that the history would be mostly
+//! their content.  With the feature flag "zstd" enabled,
+//! `#[tern(history_content = "zstd")]` keeps all of it compressed with zstd
+//! instead, and it is decompressed when the history is read, which needs the
+//! feature as well.
+//!
+//! Each row of the history table has the `checksum` of the migration that was
+//! applied, whatever the table keeps of its content, and before every
+//! operation the checksum of each applied SQL migration in the source is
+//! compared with it.  A `V7__*.sql` edited after it was applied fails the run
+//! with the migration in the error instead of being skipped over as applied.
+//! A history table from an earlier version gets the column the first time it's
+//! used, and its older rows are compared by their content as before.
+//!
+//! A file named `R__{description}.sql`, like `R__refresh_views.sql`, is a
+//! repeatable migration.  It has no version, and after the versioned
+//! migrations of a run that applies all of them, it is applied again whenever
+//! its content is not what it was last applied with, which is tracked by its
+//! checksum in the table `<history_table>_repeatable`.  They run in the order
+//! of their descriptions, for views and functions that are replaced as a
+//! whole instead of with a new migration for every change.
+//!
+//! With `#[tern(lockfile = "tern.lock")]`, the migrations are checked against
+//! a committed lockfile of their filenames and checksums, written by
+//! `migrate lock`.  Changing or reordering a locked migration fails to compile,
+//! and a migration that isn't locked yet is refused by the runner, so only
+//! reviewed migrations are run.  `migrate lock --check` does the same check in
+//! CI.
+//!
+//! `#[tern(strict_names)]` fails to compile when two migrations have the same
+//! description, like `V3__add_index.sql` and `V7__add_index.sql`, which is
+//! usually a migration that was copied and not renamed.
+//!
+//! Each row of the history also has the crate version, git commit, and build
+//! time of the binary that applied the migration, in the columns
+//! `build_version`, `build_git_sha`, and `built_at`, so a row in production
+//! can be traced back to the artifact that wrote it.  The git commit is the
+//! environment variable `TERN_GIT_SHA` when the binary is compiled, as CI might
+//! set it, and the build time is `SOURCE_DATE_EPOCH` if that is set.
 //!
 //! An operation over these migrations involves validating the entire set,
 //! collecting the ones that are needed, resolving queries having a runtime
@@ -40,13 +135,13 @@
 //! * [`MigrationSource`]: Collects the migrations for use in the operation by
 //!   parsing the directory into a validated, prepared, sorted, and uniform
 //!   collection, and exposing methods to return a given subset.
-//! * [`MigrationContext`]: A type providing context to perform the operation
-//!   on the migrations passed from `MigrationSource` given some parameters.
+//! * [`MigrationContext`]: A type providing context to perform the operation on
+//!   the migrations passed from `MigrationSource` given some parameters.
 //!
 //! Put together, it looks like this.
 //!
 //! ```rust,no_run
-//! use tern::{MigrationSource, MigrationContext, Runner, SqlxPgExecutor};
+//! use tern::{MigrationContext, MigrationSource, Runner, SqlxPgExecutor};
 //!
 //! /// `$CARGO_MANIFEST_DIR/src/migrations` is a collection of migration files.
 //! /// The optional `table` attribute permits a custom location for a migration
@@ -54,18 +149,94 @@
 //! #[derive(MigrationSource, MigrationContext)]
 //! #[tern(source = "src/migrations", table = "example")]
 //! struct Example {
-//!    // `Example` itself needs to be an executor without this annotation.
-//!    #[tern(executor_via)]
+//!     // `Example` itself needs to be an executor without this annotation.
+//!     #[tern(executor_via)]
 //!     executor: SqlxPgExecutor,
 //! }
 //!
-//! let executor = SqlxPgExecutor::new("postgres://user@localhost").await.unwrap();
+//! let executor =
+//!     SqlxPgExecutor::new("postgres://user@localhost").await.unwrap();
 //! let context = Example { executor };
 //! let mut runner = Runner::new(context);
 //! let report: tern::Report = runner.run_apply_all(false).await.unwrap();
 //! println!("migration run report: {report}");
 //! ```
 //!
+//! The report displays as JSON, and `Report::to_html` renders it as a
+//! self-contained HTML page, with a table of the migrations that sorts by the
+//! column clicked and the SQL of each in a collapsible section, to attach to a
+//! release.  `Report::to_markdown` renders a compact Markdown table of the
+//! version, description, state, and duration of each migration, for a comment
+//! on a pull request, and `Report::to_junit` renders JUnit XML with a test case
+//! for each migration, for CI systems to show with test results.  In the CLI,
+//! `--format html`, `markdown`, or `junit` prints the report in that format,
+//! and with `junit` a run that fails is printed too, with the migration that
+//! failed as a failed test case that has the error from the database.
+//!
+//! Problems that don't stop a run are warnings in the report, and not only in
+//! the logs.  `Report::warnings` has the ones found before applying: a
+//! validation rule that failed with `Severity::Warn`, including the linter, and
+//! statements that behave differently on the database, like a change to the
+//! schema that MySQL commits the transaction for.  `MigrationResult::warnings`
+//! has the ones from applying a migration, like a lock timeout that the
+//! database doesn't support, or a retry.  Each has its `kind`, the migrations
+//! it is about, and a `message`.  They are in the JSON, HTML, and Markdown
+//! reports too, and in JUnit they are the `system-err` of the suite or of the
+//! test case.
+//!
+//! Tools that run the migrations against many targets, like a database for
+//! each tenant or shard, can aggregate the outcomes.  `Report::merge` combines
+//! two reports into one, and with `--run-log <path>`, the CLI appends a line of
+//! JSON for the run to the file, naming it with `--target <name>` or the
+//! profile of `--env`.  `RunLog::parse` reads the file back, and
+//! `RunLog::summary` is a `RunSummary` of the runs, failures, and warnings in
+//! total and for each target, with the last run against it.
+//!
+//! `Runner::timings` has the `RunTimings` of the last run that applied
+//! migrations: how long it took in total, validating, and waiting for the run
+//! lock, how long each migration took, and how many rows of the history it
+//! wrote, for sending to a telemetry pipeline without parsing the logs.
+//!
+//! `Report::to_csv` renders CSV with a row for each migration.  The CLI command
+//! `history dump` writes every row of the history table in the format of
+//! `--format`, JSON by default, to stdout or to a file with `--output`, and
+//! `--no-content` leaves out the SQL of the migrations, which is
+//! `Report::without_content`.  For any command, `--content-lines N` keeps only
+//! the first `N` lines of each migration in the report, or none with `0`,
+//! which is `Runner::with_report_content` in code.
+//!
+//! `Runner::replay` reports the migrations as of a point in time: the ones
+//! applied by then, from `applied_at` in the history, and the rest of the
+//! current source as pending.  In the CLI, it's `history replay --at` with an
+//! RFC 3339 timestamp, to answer what the schema was at the time of an
+//! incident.
+//!
+//! `Runner::status`, or `migrate status`, reports every migration in the source
+//! and the history as applied or pending, in the order they are applied in.
+//! `Report::last_applied` is the latest applied version and `Report::pending`
+//! is how many are pending.
+//!
+//! `Runner::plan` is what a run would do without doing it: a `runner::Plan` of
+//! the pending migrations, in the order they would be applied, with the query
+//! each one builds and whether it runs in a transaction.  It only reads the
+//! history table, and it serializes to JSON, to attach to a pull request for
+//! review.
+//!
+//! A dashboard or a monitoring service that shows the history, but must never
+//! be able to apply migrations, derives `HistoryReader` instead of
+//! `MigrationContext` and `MigrationSource`.  It has `list_applied`, `status`
+//! with the pending migrations, and `validate` with the built-in rules, and
+//! nothing else: it isn't a context, so it can't be given to a `Runner`, and it
+//! never creates or changes the history table.  It takes the same `table`,
+//! `ordering`, `source_id`, and `executor_via` attributes, and `source` embeds
+//! the migrations to compare the history with without building them.
+//!
+//! Every error has a stable code from `Error::code`, like `TERN_OUT_OF_SYNC`,
+//! for automation to match on instead of the message.  `Report::from_error`
+//! is the report of a run that failed, with the code and message of the error
+//! in it, and it is what the CLI prints with `--format json` when a command
+//! fails.  In JUnit, the code is the `type` of the failure.
+//!
 //! For more in-depth examples, see the [examples][examples-repo].
 //!
 //! ## SQL migrations
@@ -82,13 +253,26 @@
 //! }
 //! ```
 //!
+//! To read the SQL migrations when the program runs instead of embedding them,
+//! a context can keep a `source::runtime::FsMigrationSource` and implement
+//! `MigrationSource` by hand, returning its `migration_set`.  A new `.sql` file
+//! put in the directory is then applied by a binary that was already built.
+//! Rust migrations and repeatable migrations can only be embedded.
+//!
+//! To embed the migrations without deriving `MigrationSource`, `tern::embed!`
+//! expands a directory into a `MigrationSet`, and a context that implements
+//! `MigrationSource` by hand can return
+//! `tern::embed!("src/migrations").after(last_applied)` from `migration_set`.
+//! The source digest, lockfile, and repeatable migrations are only embedded by
+//! the derive macro.
+//!
 //! ## Rust migrations
 //!
 //! Migrations can be written in Rust, and these can take advantage of the
 //! arbitrary migration context to flexibly build the query at runtime.  For
 //! this to work, `MigrationSource` and `MigrationContext` get us nearly there,
-//! but the user needs to follow a couple rules and write a simple implementation
-//! of a trait to complete the requirements.
+//! but the user needs to follow a couple rules and write a simple
+//! implementation of a trait to complete the requirements.
 //!
 //! The first rule is that the type deriving `MigrationSource` needs to be
 //! declared in the immediate parent module of the migrations.  So if
@@ -104,18 +288,18 @@
 //! `TernMigration` in any Rust migration source file, and that it derives a
 //! third macro, `Migration`.  `Migration` doesn't contribute much, but the
 //! struct deriving it is still needed because of another implementation detail
-//! of the macros: we need a way for the `Migration` macro to share data with the
-//! other two macros, the alternative being to parse the entire token stream of a
-//! Rust source file in the course of `MigrationSource` performing its duties,
-//! clearly a less appealing option.
+//! of the macros: we need a way for the `Migration` macro to share data with
+//! the other two macros, the alternative being to parse the entire token stream
+//! of a Rust source file in the course of `MigrationSource` performing its
+//! duties, clearly a less appealing option.
 //!
 //! This `TernMigration` is needed because the last thing that's required is a
 //! user-defined method on it that provides instructions for how to build its
 //! query using the migration context.
 //!
 //! ```rust,no_run
-//! use tern::{Query, QueryBuilder, Migration};
 //! use tern::error::TernResult;
+//! use tern::{Migration, Query, QueryBuilder};
 //!
 //! use super::Example;
 //!
@@ -140,6 +324,21 @@
 //! }
 //! ```
 //!
+//! Many migrations only need a value from the environment or a config file to
+//! build their query.  For that, every context has a [`ConfigSource`] available
+//! from `ctx.config()`, which reads environment variables by default.  A field
+//! of the context holding a different one, e.g., one reading a file, can be
+//! marked with `#[tern(config)]` to use that instead.
+//!
+//! A migration can also build a different query depending on feature flags,
+//! from `ctx.flags()`, like to skip an index that takes too long to create on a
+//! development database with `if
+//! !ctx.flags().enabled("skip_expensive_indexes")`. The flags are the list in
+//! the config value `TERN_FLAGS`, like `skip_expensive_indexes,batch_size=500`,
+//! which the CLI adds to with `--flag <name>` or `--flag <name>=<value>`, or
+//! from `flags` in a profile of the config file.  A dry run builds the queries
+//! with the flags, and a report has the flags of its run.
+//!
 //! ## Reversible migrations
 //!
 //! As of now, the official stance is to not support an up-down style of
@@ -153,9 +352,9 @@
 //! By default, a migration and its accompanying schema history table update are
 //! ran together in a database transaction.  Sometimes this is not desirable and
 //! other times it is not even allowed.  For instance, in postgres you cannot
-//! create an index `CONCURRENTLY` in a transaction.  To give the user the option,
-//! `tern` understands certain annotations and will not run that migration in a
-//! database transaction if they are present.
+//! create an index `CONCURRENTLY` in a transaction.  To give the user the
+//! option, `tern` understands certain annotations and will not run that
+//! migration in a database transaction if they are present.
 //!
 //! For a SQL migration:
 //!
@@ -192,10 +391,100 @@
 //! -- is postgres.
 //! ```
 //!
+//! Each statement of a migration that is applied this way is a step in its
+//! `MigrationResult`, with its own state and duration, and a dry run lists the
+//! statements that the file was split into.
+//!
 //! You can also try to remove parts that are problematic, e.g., atypical use of
 //! of commenting, particularly block comments. If the issue still persisist,
 //! please open a bug ticket.
 //!
+//! On Postgres, a migration can also fail fast instead of waiting behind
+//! long-running queries for a lock, with annotations for the `lock_timeout` and
+//! `statement_timeout` settings while it is applied.  A number without a unit
+//! is milliseconds, and the units are `ms`, `s`, `min`, and `h`.  These can be
+//! given for every migration with `Runner::with_lock_timeout` and
+//! `Runner::with_statement_timeout`, or `--lock-timeout` and
+//! `--statement-timeout` in the CLI, which a migration's own annotations take
+//! the place of.
+//!
+//! ```sql
+//! -- tern:lockTimeout=5s
+//! -- tern:statementTimeout=10min
+//! ALTER TABLE users ADD COLUMN age int;
+//! ```
+//!
+//! A migration can be applied as a different role than the rest with
+//! `-- tern:role=owner_role`, which is set for that migration and reset after
+//! it, for when the objects it creates need to be owned by that role.
+//!
+//! A migration with literals that are secrets or personal data can have
+//! `-- tern:redact`, and then its content is replaced in the history table and
+//! in reports by a placeholder with its checksum, which is still compared with
+//! the source to detect a change to it.
+//!
+//! A migration that isn't critical, like an `ANALYZE` or creating an optional
+//! extension, can have `-- tern:allowFailure`, or `#[tern(allow_failure)]` in a
+//! Rust migration.  If it fails, it is recorded in the history table with the
+//! error and is in the report as failed, and the run goes on.  Later runs don't
+//! retry it, but `migrate reapply` does.
+//!
+//! A migration that is known to hit transient errors, like a backfill that
+//! deadlocks with the application now and then, can have `-- tern:retries=3` to
+//! be tried up to that many more times when it fails with a deadlock, a
+//! serialization failure, or a lock that wasn't available in time.  The report
+//! has how many attempts it took.
+//!
+//! When `CREATE INDEX CONCURRENTLY` fails on Postgres, e.g., on a duplicate for
+//! a unique index, it leaves an `INVALID` index behind that has to be dropped
+//! before the migration can be applied again.  A migration with
+//! `-- tern:concurrentIndexRetries=2` does that itself: when it fails, the
+//! indexes that it builds concurrently and that are now invalid are dropped,
+//! and it is tried again, up to that many more times.  Any left invalid by an
+//! earlier run are dropped before it is applied, too.  A migration with
+//! statements before the index that can't run twice should be `tern:resumable`
+//! as well.
+//!
+//! A migration that isn't applied in a transaction and has statements that
+//! can't run twice, like an `INSERT ... SELECT` before a `CREATE INDEX
+//! CONCURRENTLY`, can have `-- tern:resumable`, or every such migration can
+//! with `Runner::with_resume`. Each of its statements is recorded in the table
+//! `<history table>_progress` as it completes, and if one fails, like when it
+//! runs past its `statementTimeout`, the next run starts again from that
+//! statement.  Its recorded statements have to be the same as the first ones of
+//! the migration then, or it is an error.
+//!
+//! A migration that is applied in a transaction is sent to the database all at
+//! once, so nothing shows how far a long one has gotten.  With
+//! `-- tern:splitStatements`, or `Runner::with_split_statements` or
+//! `--split-statements` for every migration, its statements run one at a time in
+//! the same transaction instead, each one is logged with how long it took as it
+//! completes, and the report has a step for each.
+//!
+//! After a big change to the schema or a backfill, the planner can choose badly
+//! until the statistics of the changed tables are refreshed.  With
+//! `-- tern:analyze`, or `Runner::with_analyze` or `--analyze` for every
+//! migration, the tables that the migration inserts into, updates, deletes
+//! from, alters or indexes are analyzed right after it is applied, and the
+//! report lists them.  Not being able to analyze them is a warning.
+//!
+//! In a Rust migration, the annotations go in a `//` comment in the file.
+//!
+//! On Postgres and SQLite, which can roll back changes to the schema,
+//! `Runner::bench` or the CLI command `migrate bench` applies the unapplied
+//! migrations in order in one transaction and rolls it back instead of
+//! committing it, for how long each one takes against a copy of a production
+//! database without changing it.  Migrations that aren't applied in a
+//! transaction are skipped.
+//! `Runner::try_apply_all`, or `migrate try`, does the same for all of the
+//! unapplied migrations, as a check before deploying that they run against the
+//! database and not only that they parse, and it fails if one of them does.
+//!
+//! A migration that was fixed after it was applied, when its query is safe to
+//! run again, can be applied again with `Runner::reapply` or `migrate reapply
+//! <version>`, which replaces its row in the history table with the content,
+//! duration, and time of that run.
+//!
 //! ## CLI
 //!
 //! With the feature flag "cli" enabled the type [`App`] is exported, which is a
@@ -204,6 +493,15 @@
 //! implementation of [`ContextOptions`] which simply says how to create the
 //! context from a connection string.
 //!
+//! To start a new migrations crate, the `tern` binary of `tern-cli`, installed
+//! with `cargo install tern-cli`, has the command `tern init [dir]`.  It writes
+//! a `Cargo.toml`, a `build.rs` that compiles the crate again when a migration
+//! changes, a context deriving `MigrationContext` and `MigrationSource` with its
+//! `ContextOptions`, a sample SQL and Rust migration, a `tern.toml` with a
+//! `local` profile, and a `main` that runs the `App`.  `--database` is
+//! `postgres`, `mysql`, or `sqlite`, and files that already exist are only
+//! overwritten with `--force`.  An `App` has the same command as `init`.
+//!
 //! ```terminal
 //! > $ my-migration-project --help
 //! Usage: my-migration-project <COMMAND>
@@ -211,6 +509,10 @@
 //! Commands:
 //!   migrate  Operations on the set of migration files
 //!   history  Operations on the table storing the history of these migrations
+//!   schema   Operations on the schema that these migrations build
+//!   info     Show the migrations embedded in this binary and their digest
+//!   init     Create a new migrations crate with a context, a SQL and a Rust migration, a config file, and a CLI `main`
+//!   check    Compare the migrations with the history of a database and fail if they differ
 //!   help     Print this message or the help of the given subcommand(s)
 //!
 //! Options:
@@ -224,8 +526,17 @@
 //!   apply         Run the apply operation for a specific range of unapplied migrations
 //!   apply-all     Run any available unapplied migrations
 //!   soft-apply    Insert migrations into the history table without applying them
+//!   baseline      Soft apply every migration up to a version in a database without history and mark that version as the baseline
+//!   adopt         Soft apply the unapplied migrations whose tables, indexes, and views are already in the database
+//!   bench         Time unapplied migrations by applying them in a transaction that is rolled back
+//!   try           Check that the unapplied migrations run by applying them in a transaction that is rolled back
+//!   reapply       Apply a migration that was already applied again and replace its history row
 //!   list-applied  List previously applied migrations
+//!   status        List every migration as applied or pending, with the latest applied version and how many are pending
 //!   new           Create a migration with the description and an auto-selected version
+//!   lint          Check the SQL migrations embedded in this binary for risky operations
+//!   lock          Write the lockfile of the migrations embedded in this binary
+//!   extract       Write the migrations embedded in this binary to a directory
 //!   help          Print this message or the help of the given subcommand(s)
 //!
 //! Options:
@@ -237,14 +548,129 @@
 //! Usage: my-migration-project history <COMMAND>
 //!
 //! Commands:
-//!   init        Create the schema history table
-//!   drop        Drop the schema history table
-//!   help        Print this message or the help of the given subcommand(s)
+//!   init         Create the schema history table
+//!   drop         Drop the schema history table
+//!   set-version  Soft apply or remove history rows so that a version is the latest applied
+//!   forget       Remove the history row of one applied migration
+//!   dump         Write every row of the history table in the format of `--format`, JSON by default
+//!   help         Print this message or the help of the given subcommand(s)
 //!
 //! Options:
 //!   -h, --help  Print help
 //! ```
 //!
+//! `check` compares the migrations embedded in the binary with the history of
+//! a database, given with `--against <url>` or the usual `-D`, and prints a
+//! JSON report of the migrations that are unapplied, the applied ones that are
+//! unknown to the source, and the applied SQL migrations that have changed
+//! since, with their checksums.  It exits with an error if there is any
+//! difference and only reads from the database, so it suits a scheduled job
+//! that looks for drift.  The same report comes from `Runner::check_drift`.
+//!
+//! `history verify` checks the rows of the history table itself for what
+//! otherwise shows up as a confusing failure to apply: versions with more than
+//! one row, rows without a checksum of what was applied, and migrations applied
+//! before a migration with an earlier version.  It prints a JSON report of them
+//! and exits with an error if there are any, and a migration that was reapplied
+//! or applied out of order is in it too, so look at it before fixing anything.
+//! In code, it's `Runner::verify_history`.
+//!
+//! `schema dump` writes the statement that creates each table, index, and view
+//! in the database to `schema.sql`, or the file given with `--path`, as a
+//! snapshot of the schema that all of the migrations build, to commit next to
+//! them so that a change to the schema is reviewed with the migration that made
+//! it.  Run it after applying the migrations to a scratch database.  The
+//! statements come from the database itself: on Postgres they are built from
+//! `pg_catalog` like `pg_dump --schema-only`, on MySQL they are
+//! `SHOW CREATE TABLE` and `SHOW CREATE VIEW`, and on SQLite they are the ones
+//! in `sqlite_master`.  They are sorted by kind and name, and the history
+//! table is left out, so the file only changes when the schema does, and
+//! `schema dump --check` fails in CI if it is not up to date.  In code, it's
+//! `Runner::dump_schema`.
+//!
+//! A `[[guard]]` table in `tern.toml` refuses to run commands against the hosts
+//! of connection strings that match a pattern, for a command copied from a
+//! runbook with the connection string of production still in it:
+//!
+//! ```toml
+//! [[guard]]
+//! operations = ["history drop", "history set-version", "history forget"]
+//! deny = ["*.prod.*"]
+//!
+//! [[guard]]
+//! operations = ["migrate try"]
+//! allow = ["localhost", "127.0.0.1"]
+//! ```
+//!
+//! A command is refused if the host matches a pattern that is denied for it, or
+//! if there are patterns allowed for it and the host matches none of them.  A
+//! pattern matches the whole host, without the port, and `*` in it is any
+//! characters.  Without `operations`, a guard is for every command.  Given
+//! `--force-prod`, a command runs anyway.  In code, `App::with_url_guard` adds a
+//! `guard::UrlGuard` with the same rules.
+//!
+//! `migrate apply-all --dryrun --offline` lists the migrations that would be
+//! applied without connecting to the database at all, for reviewing them where
+//! the database can't be reached, like CI for an air-gapped production.  It
+//! assumes that nothing is applied, or with `--assume-applied <version>`, that
+//! the migrations through that version are.  Nothing is checked against the
+//! history, and a Rust migration shows its source instead of its query.  In
+//! code, it's `Runner::offline_dryrun`.
+//!
+//! `migrate adopt` is for starting to use tern with a database that was
+//! migrated some other way.  It lists the tables, indexes, and views in the
+//! database and shows which unapplied migrations appear to be in effect
+//! already, because every object that they create is there, and which don't.
+//! The migrations up to the last one in effect before the first one that isn't
+//! are the ones it would soft apply, which it does with `--yes` after the
+//! operator has checked them.  A migration that creates nothing to look for,
+//! like one that only adds a column, is soft applied if a later one is.  In
+//! code, it's `Runner::suggest_soft_apply` and the `inventory` module.
+//!
+//! When which migrations are in effect is already known, `migrate baseline
+//! --version <version>` soft applies every migration up to and including it in
+//! a database that has no history yet, and marks the row of that version as the
+//! baseline, so that only the migrations after it are applied from then on.  It
+//! is an error if the history already has migrations.  In code, it's
+//! `Runner::baseline`, and `AppliedMigration::is_baseline` tells the row apart.
+//!
+//! `--preset <name>` sets the options for a kind of environment at once, which
+//! any option given with it takes the place of.  `ci-check` makes an applied
+//! migration that changed an error, lints the unapplied SQL migrations, and
+//! prints a JUnit report.  `prod-apply` does the same with a JSON report of the
+//! first 20 lines of each migration, and also holds the run lock and has a lock
+//! timeout of 5 seconds.  `dev-fast` only warns when the history doesn't match
+//! the source and leaves the content out of reports.  In code, it's
+//! `Runner::with_preset`.
+//!
+//! `history set-version <version>` is an escape hatch for when the history no
+//! longer says what was done to the database, e.g., after an emergency fix by
+//! hand.  It soft applies the migrations up to the version that aren't in the
+//! history and removes the rows of the ones after it, so that the version is
+//! the latest applied, without applying or reverting anything.  Without `--yes`
+//! it only prints the rows that it would insert and remove and fails.  The same
+//! is `Runner::set_history_version`.
+//!
+//! `history forget <version>`, or `Runner::forget`, removes the history row of
+//! one applied migration, for a migration that was reverted by hand, and prints
+//! the row that it removed.  It also needs `--yes` to change anything.
+//!
+//! Before `history drop`, `history set-version`, or `history forget` changes
+//! the history table, it is copied to `<table>_backup_<timestamp>`, so that a
+//! mistake can be undone.  Pass `--no-backup` to skip it, or use
+//! `Runner::with_history_backup(false)`.
+//! `history drop --dryrun` prints the table that would be dropped, how many
+//! rows it has, and the latest version in it, from `Runner::describe_history`.
+//! With a `SOURCE_ID` and rows from other sources in the table, `history drop`
+//! deletes only the rows of this source and keeps the table.
+//!
+//! An application's own operations, like one that grants privileges on the
+//! tables that the migrations created, implement `Operation` and are added
+//! with `App::with_operation`.  Each is a subcommand named by `Operation::name`
+//! that takes the usual options to connect, runs with the context and the
+//! migrations of the source, and gets the rest of the arguments.  Without the
+//! CLI, it's `Runner::run_operation`.
+//!
 //! [`MigrationSource`]: crate::tern_derive::MigrationSource
 //! [`MigrationContext`]: crate::tern_derive::MigrationContext
 //! [`Migration`]: crate::tern_derive::Migration
@@ -257,11 +683,29 @@
 //! [flyway-undo]: https://documentation.red-gate.com/fd/migrations-184127470.html#Migrations-UndoMigrations
 //! [`App`]: crate::App
 //! [`ContextOptions`]: crate::ContextOptions
+//! [`ConfigSource`]: crate::ConfigSource
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+#[doc(inline)]
+pub use tern_core::config::{self, ConfigSource, Flags};
+#[doc(inline)]
+pub use tern_core::credentials;
+
 #[doc(inline)]
 pub use tern_core::error::{self, DatabaseError, Error, TernResult};
 
+#[doc(inline)]
+pub use tern_core::group::{self, GroupReport, TernGroup};
+
+#[doc(inline)]
+pub use tern_core::guard::{self, UrlGuard};
+
+#[doc(inline)]
+pub use tern_core::lint;
+
+#[doc(inline)]
+pub use tern_core::lockfile;
+
 #[doc(inline)]
 pub use tern_core::migration::{
     self, Executor, Migration, MigrationContext, MigrationSet, MigrationSource,
@@ -269,30 +713,111 @@ pub use tern_core::migration::{
 };
 
 #[doc(inline)]
-pub use tern_core::runner::{self, MigrationResult, Report, Runner};
+pub use tern_core::inventory::{self, SoftApplyPlan};
+
+#[doc(inline)]
+pub use tern_core::operation::{self, Operation};
+
+#[doc(inline)]
+pub use tern_core::reader::{self, HistoryReader, HistoryStatus};
+
+#[doc(inline)]
+pub use tern_core::redact;
+
+#[doc(inline)]
+pub use tern_core::run_log::{self, RunLog, RunSummary};
+
+#[doc(inline)]
+pub use tern_core::runner::{
+    self, MigrationResult, Preset, Report, RunTimings, Runner, StopHandle,
+};
+
+#[doc(inline)]
+pub use tern_core::schema;
+
+#[doc(inline)]
+pub use tern_core::source;
+
+#[doc(inline)]
+pub use tern_core::startup;
+
+#[doc(inline)]
+pub use tern_core::validate;
 
 #[cfg(feature = "sqlx_mysql")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sqlx_mysql")))]
 #[doc(inline)]
-pub use tern_core::executor::sqlx_backend::mysql::SqlxMySqlExecutor;
+pub use tern_core::executor::sqlx_backend::mysql::{
+    SqlxMySqlExecutor, SqlxMySqlTxExecutor,
+};
+
+#[cfg(feature = "sqlx_postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sqlx_postgres")))]
+#[doc(inline)]
+pub use tern_core::executor::sqlx_backend::postgres::{
+    SqlxPgExecutor, SqlxPgTxExecutor,
+};
 
 #[cfg(feature = "sqlx_postgres")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sqlx_postgres")))]
 #[doc(inline)]
-pub use tern_core::executor::sqlx_backend::postgres::SqlxPgExecutor;
+pub use tern_core::executor::sqlx_backend::cockroach::SqlxCockroachExecutor;
 
 #[cfg(feature = "sqlx_sqlite")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sqlx_sqlite")))]
 #[doc(inline)]
-pub use tern_core::executor::sqlx_backend::sqlite::SqlxSqliteExecutor;
+pub use tern_core::executor::sqlx_backend::sqlite::{
+    SqlxSqliteExecutor, SqlxSqliteTxExecutor,
+};
+
+#[cfg(feature = "tokio_postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio_postgres")))]
+#[doc(inline)]
+pub use tern_core::executor::tokio_postgres_backend::TokioPgExecutor;
+
+#[cfg(feature = "deadpool_postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "deadpool_postgres")))]
+#[doc(inline)]
+pub use tern_core::executor::tokio_postgres_backend::DeadpoolPgExecutor;
+
+#[cfg(feature = "rusqlite")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rusqlite")))]
+#[doc(inline)]
+pub use tern_core::executor::rusqlite_backend::RusqliteExecutor;
+
+#[cfg(feature = "libsql")]
+#[cfg_attr(docsrs, doc(cfg(feature = "libsql")))]
+#[doc(inline)]
+pub use tern_core::executor::libsql_backend::LibsqlExecutor;
+
+#[cfg(feature = "mysql_async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mysql_async")))]
+#[doc(inline)]
+pub use tern_core::executor::mysql_async_backend::MySqlAsyncExecutor;
 
 pub mod executor {
+    #[cfg(feature = "deadpool_postgres")]
+    pub use super::DeadpoolPgExecutor;
+    #[cfg(feature = "libsql")]
+    pub use super::LibsqlExecutor;
+    #[cfg(feature = "mysql_async")]
+    pub use super::MySqlAsyncExecutor;
+    #[cfg(feature = "rusqlite")]
+    pub use super::RusqliteExecutor;
+    #[cfg(feature = "tokio_postgres")]
+    pub use super::TokioPgExecutor;
     #[cfg(feature = "sqlx_mysql")]
-    pub use super::SqlxMySqlExecutor;
+    pub use super::{SqlxMySqlExecutor, SqlxMySqlTxExecutor};
+    #[cfg(feature = "sqlx_postgres")]
+    pub use super::{SqlxPgExecutor, SqlxPgTxExecutor};
+    #[cfg(feature = "sqlx_sqlite")]
+    pub use super::{SqlxSqliteExecutor, SqlxSqliteTxExecutor};
     #[cfg(feature = "sqlx_postgres")]
-    pub use super::SqlxPgExecutor;
+    pub use tern_core::executor::sqlx_backend::cockroach::SqlxCockroachQueryRepo;
     #[cfg(feature = "sqlx_sqlite")]
-    pub use super::SqlxSqliteExecutor;
+    pub use tern_core::executor::sqlx_backend::sqlite::{
+        SqliteJournalMode, SqliteOptions,
+    };
 }
 
 #[doc(hidden)]
@@ -303,9 +828,11 @@ pub mod future {
 #[cfg(feature = "cli")]
 #[cfg_attr(docsrs, doc(cfg(feature = "cli")))]
 #[doc(inline)]
-pub use tern_cli::{App, ContextOptions};
+pub use tern_cli::{App, ContextOptions, Outcome};
 
 #[doc(hidden)]
 extern crate tern_derive;
 
-pub use tern_derive::{Migration, MigrationContext, MigrationSource};
+pub use tern_derive::{
+    HistoryReader, Migration, MigrationContext, MigrationSource, embed,
+};
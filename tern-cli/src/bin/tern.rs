@@ -0,0 +1,8 @@
+//! `tern init`, for creating a migrations crate to build the rest of the CLI
+//! into.
+fn main() {
+    if let Err(e) = tern_cli::run_init() {
+        eprintln!("Error: {e:#}");
+        std::process::exit(1);
+    }
+}
@@ -1,16 +1,251 @@
-use clap::{Args, Parser, ValueEnum};
+use chrono::{DateTime, Utc};
+use clap::builder::{PossibleValuesParser, TypedValueParser as _};
+use clap::parser::ValueSource;
+use clap::{
+    Arg, ArgMatches, Args, Command, CommandFactory as _, FromArgMatches as _,
+    Parser, ValueEnum,
+};
 use std::path::PathBuf;
+use std::time::Duration;
+use tern_core::executor::tls::{SslMode, TlsOptions};
+use tern_core::lint::Linter;
+use tern_core::lockfile::LOCKFILE_NAME;
+use tern_core::migration::{
+    Dialect, Executor, MigrationContext, QueryRepository,
+};
+use tern_core::runner::{
+    Preset, ReportContent, ReportFormat, Runner, WaitForDb,
+};
+use tern_core::schema::SCHEMA_FILE_NAME;
+use tern_core::validate::{Severity, ValidationCategory};
 
 #[derive(Debug, Parser)]
 pub struct Tern {
+    #[clap(flatten)]
+    pub global: GlobalOpts,
     #[clap(subcommand)]
     pub commands: TernCommands,
+    /// The names of the subcommands, like `history drop`, which is the name
+    /// of the operation for a `UrlGuard`.
+    #[clap(skip)]
+    pub command_name: String,
+    /// Whether `--config` was given instead of being the default, so that a
+    /// missing config file is an error instead of there being no config.
+    #[clap(skip)]
+    pub config_given: bool,
+}
+
+impl Tern {
+    /// Parse the arguments with a subcommand for each custom operation, given
+    /// as its name and description, exiting with the error or the help like
+    /// `Parser::parse`.
+    ///
+    /// With `database_url_env`, the connection string is read from that
+    /// environment variable instead of `DATABASE_URL`.
+    pub fn parse_with_operations<'a>(
+        operations: impl IntoIterator<Item = (&'a str, &'a str)>,
+        database_url_env: Option<&str>,
+    ) -> Self {
+        let mut command = Self::command();
+        let mut names = Vec::new();
+        for (name, about) in operations {
+            names.push(name.to_string());
+            command = command.subcommand(operation_command(name, about));
+        }
+        if let Some(var) = database_url_env {
+            command = database_url_from(command, var);
+        }
+        let mut matches = command.get_matches();
+        let command_name = command_name(&matches);
+        let config_given =
+            matches.value_source("config") == Some(ValueSource::CommandLine);
+        let is_operation = matches
+            .subcommand_name()
+            .is_some_and(|name| names.iter().any(|n| n == name));
+        let tern = if is_operation {
+            let (name, mut op_matches) =
+                matches.remove_subcommand().expect("matched a subcommand");
+            GlobalOpts::from_arg_matches(&matches).and_then(|global| {
+                let connect_opts =
+                    ConnectOpts::from_arg_matches_mut(&mut op_matches)?;
+                let args = op_matches
+                    .remove_many::<String>("args")
+                    .map(Iterator::collect)
+                    .unwrap_or_default();
+                let op = OperationCommand { name, args, connect_opts };
+                Ok(Self {
+                    global,
+                    commands: TernCommands::Operation(op),
+                    command_name: String::new(),
+                    config_given: false,
+                })
+            })
+        } else {
+            Self::from_arg_matches_mut(&mut matches)
+        };
+
+        let mut tern = tern.unwrap_or_else(|e| e.exit());
+        tern.command_name = command_name;
+        tern.config_given = config_given;
+
+        tern
+    }
+
+    /// The format to print the report in, from `--format` or the preset.
+    pub fn format(&self) -> Option<ReportFormat> {
+        self.global
+            .format
+            .or_else(|| self.global.preset.and_then(Preset::report_format))
+    }
+
+    /// The name of the target in the run log, from `--target` or `--env`.
+    pub fn target(&self) -> &str {
+        self.global
+            .target
+            .as_deref()
+            .or(self.global.env.as_deref())
+            .unwrap_or("default")
+    }
+
+    /// How much of the content of each migration the report has.
+    pub fn report_content(&self) -> ReportContent {
+        match self.global.content_lines {
+            None => self
+                .global
+                .preset
+                .map_or(ReportContent::Full, Preset::report_content),
+            Some(0) => ReportContent::Omit,
+            Some(n) => ReportContent::Lines(n),
+        }
+    }
+}
+
+// The names of the subcommands that were matched, separated by spaces.
+fn command_name(matches: &ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut matches = matches;
+    while let Some((name, sub)) = matches.subcommand() {
+        names.push(name);
+        matches = sub;
+    }
+
+    names.join(" ")
+}
+
+// Read the `--database-url` of the command and its subcommands from `var`.
+fn database_url_from(mut command: Command, var: &str) -> Command {
+    if command.get_arguments().any(|arg| arg.get_id() == "database_url") {
+        command =
+            command.mut_arg("database_url", |arg| arg.env(var.to_string()));
+    }
+    let names = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect::<Vec<_>>();
+    for name in names {
+        command =
+            command.mut_subcommand(name, |sub| database_url_from(sub, var));
+    }
+
+    command
+}
+
+// The subcommand for a custom operation, which takes the options to connect
+// and passes the rest of the arguments to the operation.
+fn operation_command(name: &str, about: &str) -> Command {
+    let command = Command::new(name.to_string()).about(about.to_string());
+    ConnectOpts::augment_args(command).arg(
+        Arg::new("args")
+            .help("Arguments for the operation")
+            .num_args(0..)
+            .trailing_var_arg(true)
+            .allow_hyphen_values(true),
+    )
+}
+
+/// Options for every command.
+#[derive(Debug, Args)]
+pub struct GlobalOpts {
+    /// Load environment variables from this `.env` file, without overriding
+    /// ones already set
+    #[clap(long, global = true)]
+    pub env_file: Option<PathBuf>,
+    /// Use the settings of this profile from the config file
+    #[clap(long, global = true)]
+    pub env: Option<String>,
+    /// Path to the config file
+    #[clap(long, global = true, default_value = "tern.toml")]
+    pub config: PathBuf,
+    /// Print the report of the command in this format instead of returning it
+    #[clap(
+        long,
+        global = true,
+        value_parser = PossibleValuesParser::new(ReportFormat::VARIANTS)
+            .map(|s| s.parse::<ReportFormat>().expect("checked possible value")),
+    )]
+    pub format: Option<ReportFormat>,
+    /// Use the options of this preset for the environment, which the other
+    /// options take the place of
+    #[clap(
+        long,
+        global = true,
+        value_parser = PossibleValuesParser::new(Preset::VARIANTS)
+            .map(|s| s.parse::<Preset>().expect("checked possible value")),
+    )]
+    pub preset: Option<Preset>,
+    /// Only put the first N lines of each migration in the report, or none of
+    /// it with 0
+    #[clap(long, global = true, value_name = "N")]
+    pub content_lines: Option<usize>,
+    /// Set a feature flag for Rust migrations to build their query with,
+    /// which is a name or `name=value`, and can be given more than once
+    #[clap(long = "flag", global = true, value_name = "FLAG")]
+    pub flags: Vec<String>,
+    /// Append the outcome of the command to this run log, a file with a line
+    /// of JSON for each run, to summarize runs against many targets
+    #[clap(long, global = true, value_name = "PATH")]
+    pub run_log: Option<PathBuf>,
+    /// The name of the target in the run log, like a tenant or a shard, which
+    /// is the profile from `--env` by default
+    #[clap(long, global = true, value_name = "NAME")]
+    pub target: Option<String>,
+    /// Run the command even if a guard of the config file or the app refuses
+    /// to run it against the host of the connection string
+    #[clap(long, global = true)]
+    pub force_prod: bool,
 }
 
 #[derive(Debug, Parser)]
 pub enum TernCommands {
     Migrate(Migrate),
     History(History),
+    Schema(Schema),
+    /// Show the migrations embedded in this binary and their digest
+    Info,
+    /// Create a new migrations crate with a context, a SQL and a Rust
+    /// migration, a config file, and a CLI `main`
+    Init(InitOpts),
+    /// Compare the migrations with the history of a database and fail if they
+    /// differ
+    Check {
+        /// Connection string of the database to check, instead of
+        /// `--database-url`
+        #[arg(long, value_name = "URL")]
+        against: Option<String>,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// A custom operation added to the `App`, which has its own subcommand
+    #[command(skip)]
+    Operation(OperationCommand),
+}
+
+/// The arguments of a custom operation.
+#[derive(Debug)]
+pub struct OperationCommand {
+    pub name: String,
+    pub args: Vec<String>,
+    pub connect_opts: ConnectOpts,
 }
 
 /// Operations on the set of migration files.
@@ -27,6 +262,13 @@ pub struct History {
     pub commands: HistoryCommands,
 }
 
+/// Operations on the schema that these migrations build.
+#[derive(Debug, Parser)]
+pub struct Schema {
+    #[clap(subcommand)]
+    pub commands: SchemaCommands,
+}
+
 #[derive(Debug, Parser)]
 pub enum MigrateCommands {
     /// Run the apply operation for a specific range of unapplied migrations
@@ -34,9 +276,23 @@ pub enum MigrateCommands {
         /// Render the migration report without applying any migrations
         #[arg(short, long)]
         dryrun: bool,
-        /// Apply unapplied migrations up through this version
+        /// Apply unapplied migrations up through this version, or the
+        /// migration with this name, like `V__create_indexes`, or the next
+        /// `N` unapplied with `+N`
+        #[arg(long, alias = "target", allow_hyphen_values = true)]
+        target_version: Option<String>,
+        /// Skip the remaining migrations after this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        deadline: Option<u64>,
+        /// Go on after a migration fails, skipping the ones that depend on it
         #[arg(long)]
-        target_version: Option<i64>,
+        continue_on_error: bool,
+        #[clap(flatten)]
+        validation_opts: ValidationOpts,
+        #[clap(flatten)]
+        lint_opts: LintOpts,
+        #[clap(flatten)]
+        timeout_opts: TimeoutOpts,
         #[clap(flatten)]
         connect_opts: ConnectOpts,
     },
@@ -45,6 +301,27 @@ pub enum MigrateCommands {
         /// Render the migration report without applying any migrations
         #[arg(short, long)]
         dryrun: bool,
+        /// With `--dryrun`, list the migrations without connecting to the
+        /// database, as if none were applied, or those through
+        /// `--assume-applied`
+        #[arg(long, requires = "dryrun")]
+        offline: bool,
+        /// With `--offline`, assume that the migrations through this version
+        /// are applied
+        #[arg(long, requires = "offline", value_name = "VERSION")]
+        assume_applied: Option<String>,
+        /// Skip the remaining migrations after this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        deadline: Option<u64>,
+        /// Go on after a migration fails, skipping the ones that depend on it
+        #[arg(long)]
+        continue_on_error: bool,
+        #[clap(flatten)]
+        validation_opts: ValidationOpts,
+        #[clap(flatten)]
+        lint_opts: LintOpts,
+        #[clap(flatten)]
+        timeout_opts: TimeoutOpts,
         #[clap(flatten)]
         connect_opts: ConnectOpts,
     },
@@ -53,14 +330,104 @@ pub enum MigrateCommands {
         /// Render the migration report without soft applying any migrations
         #[arg(short, long)]
         dryrun: bool,
-        /// Soft apply unapplied migrations up through this version
+        /// Soft apply unapplied migrations up through this version, or the
+        /// migration with this name, like `V__create_indexes`, or the next
+        /// `N` unapplied with `+N`
+        #[arg(long, alias = "target", allow_hyphen_values = true)]
+        target_version: Option<String>,
+        /// Soft apply unapplied migrations starting from this version
         #[arg(long)]
-        target_version: Option<i64>,
+        from_version: Option<String>,
+        #[clap(flatten)]
+        validation_opts: ValidationOpts,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Soft apply every migration up to a version in a database without
+    /// history and mark that version as the baseline, for adopting tern for a
+    /// database that was migrated some other way
+    Baseline {
+        /// The version to take the baseline at
+        #[arg(long)]
+        version: String,
+        /// Render the migration report without soft applying any migrations
+        #[arg(short, long)]
+        dryrun: bool,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Soft apply the unapplied migrations whose tables, indexes, and views
+    /// are already in the database, for adopting tern for a database that was
+    /// migrated some other way
+    Adopt {
+        /// Soft apply the migrations instead of only showing which ones would
+        /// be soft applied and why
+        #[arg(long)]
+        yes: bool,
+        #[clap(flatten)]
+        validation_opts: ValidationOpts,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Time unapplied migrations by applying them in a transaction that is
+    /// rolled back
+    Bench {
+        /// Bench unapplied migrations up through this version, or the
+        /// migration with this name, like `V__create_indexes`, or the next
+        /// `N` unapplied with `+N`
+        #[arg(long, alias = "target", allow_hyphen_values = true)]
+        target_version: Option<String>,
+        #[clap(flatten)]
+        validation_opts: ValidationOpts,
+        #[clap(flatten)]
+        timeout_opts: TimeoutOpts,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Check that the unapplied migrations run by applying them in a
+    /// transaction that is rolled back
+    Try {
+        #[clap(flatten)]
+        validation_opts: ValidationOpts,
+        #[clap(flatten)]
+        timeout_opts: TimeoutOpts,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Apply a migration that was already applied again and replace its
+    /// history row
+    Reapply {
+        /// Version of the migration to reapply
+        version: String,
+        #[clap(flatten)]
+        timeout_opts: TimeoutOpts,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Apply again only the migrations that failed before and are in the
+    /// quarantine table
+    RetryFailed {
+        /// Render the migration report without applying any migrations
+        #[arg(short, long)]
+        dryrun: bool,
+        /// Go on after a migration fails, skipping the ones that depend on it
+        #[arg(long)]
+        continue_on_error: bool,
+        #[clap(flatten)]
+        timeout_opts: TimeoutOpts,
         #[clap(flatten)]
         connect_opts: ConnectOpts,
     },
     /// List previously applied migrations
     ListApplied {
+        #[clap(flatten)]
+        validation_opts: ValidationOpts,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// List every migration as applied or pending, with the latest applied
+    /// version and how many are pending
+    Status {
         #[clap(flatten)]
         connect_opts: ConnectOpts,
     },
@@ -76,6 +443,33 @@ pub enum MigrateCommands {
         #[clap(flatten)]
         source: Source,
     },
+    /// Check the SQL migrations embedded in this binary for risky operations
+    Lint {
+        /// Only use the rules for this database
+        #[clap(
+            long,
+            value_parser = PossibleValuesParser::new(Dialect::VARIANTS)
+                .map(|s| s.parse::<Dialect>().expect("checked possible value")),
+        )]
+        dialect: Option<Dialect>,
+    },
+    /// Write the lockfile of the migrations embedded in this binary
+    Lock {
+        /// Path to the lockfile
+        #[arg(long, default_value = LOCKFILE_NAME)]
+        path: PathBuf,
+        /// Check that the lockfile is up to date instead of writing it
+        #[arg(long)]
+        check: bool,
+    },
+    /// Write the migrations embedded in this binary to a directory
+    Extract {
+        /// Directory to write the migration files to
+        dir: PathBuf,
+        /// Overwrite files that already exist
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Debug, Parser)]
@@ -87,6 +481,75 @@ pub enum HistoryCommands {
     },
     /// Drop the schema history table
     Drop {
+        /// Show the table that would be dropped and what is in it instead of
+        /// dropping it
+        #[arg(short, long, alias = "dry-run")]
+        dryrun: bool,
+        /// Don't copy the history table to a backup table first
+        #[arg(long)]
+        no_backup: bool,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Soft apply or remove history rows so that a version is the latest
+    /// applied
+    SetVersion {
+        /// The version to make the latest applied version
+        version: String,
+        /// Change the history instead of only showing the rows that would be
+        /// inserted and removed
+        #[arg(long)]
+        yes: bool,
+        /// Don't copy the history table to a backup table first
+        #[arg(long)]
+        no_backup: bool,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Remove the history row of one applied migration
+    Forget {
+        /// The version of the migration to remove
+        version: String,
+        /// Remove the row instead of only showing it
+        #[arg(long)]
+        yes: bool,
+        /// Don't copy the history table to a backup table first
+        #[arg(long)]
+        no_backup: bool,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Write every row of the history table in the format of `--format`,
+    /// JSON by default
+    Dump {
+        /// Leave out the content of the migrations
+        #[arg(long)]
+        no_content: bool,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Show which migrations had been applied at a point in time, and which
+    /// would have been pending
+    Replay {
+        /// The point in time, like `2024-05-01T13:30:00Z`
+        #[arg(long)]
+        at: DateTime<Utc>,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// List the migrations that failed and haven't been applied since, with
+    /// how many times
+    Quarantine {
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+    /// Check the rows of the history table for duplicate versions, missing
+    /// checksums, and migrations applied before earlier versions, and fail if
+    /// there are any
+    Verify {
         #[clap(flatten)]
         connect_opts: ConnectOpts,
     },
@@ -103,6 +566,134 @@ pub enum HistoryCommands {
     },
 }
 
+#[derive(Debug, Parser)]
+pub enum SchemaCommands {
+    /// Write the statement that creates each table, index, and view in the
+    /// database to a file, as a snapshot of the schema to commit with the
+    /// migrations
+    Dump {
+        /// Path to the schema file
+        #[arg(long, default_value = SCHEMA_FILE_NAME)]
+        path: PathBuf,
+        /// Check that the schema file is up to date instead of writing it
+        #[arg(long)]
+        check: bool,
+        #[clap(flatten)]
+        connect_opts: ConnectOpts,
+    },
+}
+
+impl TernCommands {
+    /// The connection options of the command, if it has them.
+    pub fn connect_opts(&self) -> Option<&ConnectOpts> {
+        match self {
+            Self::Migrate(migrate) => match &migrate.commands {
+                MigrateCommands::Apply { connect_opts, .. }
+                | MigrateCommands::ApplyAll { connect_opts, .. }
+                | MigrateCommands::SoftApply { connect_opts, .. }
+                | MigrateCommands::Baseline { connect_opts, .. }
+                | MigrateCommands::Adopt { connect_opts, .. }
+                | MigrateCommands::Bench { connect_opts, .. }
+                | MigrateCommands::Try { connect_opts, .. }
+                | MigrateCommands::Reapply { connect_opts, .. }
+                | MigrateCommands::RetryFailed { connect_opts, .. }
+                | MigrateCommands::ListApplied { connect_opts, .. }
+                | MigrateCommands::Status { connect_opts } => {
+                    Some(connect_opts)
+                },
+                MigrateCommands::New { .. }
+                | MigrateCommands::Lint { .. }
+                | MigrateCommands::Lock { .. }
+                | MigrateCommands::Extract { .. } => None,
+            },
+            Self::History(history) => match &history.commands {
+                HistoryCommands::Init { connect_opts }
+                | HistoryCommands::Drop { connect_opts, .. }
+                | HistoryCommands::SetVersion { connect_opts, .. }
+                | HistoryCommands::Forget { connect_opts, .. }
+                | HistoryCommands::Dump { connect_opts, .. }
+                | HistoryCommands::Replay { connect_opts, .. }
+                | HistoryCommands::Quarantine { connect_opts }
+                | HistoryCommands::Verify { connect_opts }
+                | HistoryCommands::SoftApply { connect_opts, .. } => {
+                    Some(connect_opts)
+                },
+            },
+            Self::Schema(schema) => match &schema.commands {
+                SchemaCommands::Dump { connect_opts, .. } => Some(connect_opts),
+            },
+            Self::Check { connect_opts, .. } => Some(connect_opts),
+            Self::Operation(op) => Some(&op.connect_opts),
+            Self::Info | Self::Init(_) => None,
+        }
+    }
+
+    /// The validation options of the command, if it has them.
+    pub fn validation_opts(&self) -> Option<&ValidationOpts> {
+        match self {
+            Self::Migrate(migrate) => match &migrate.commands {
+                MigrateCommands::Apply { validation_opts, .. }
+                | MigrateCommands::ApplyAll { validation_opts, .. }
+                | MigrateCommands::SoftApply { validation_opts, .. }
+                | MigrateCommands::Adopt { validation_opts, .. }
+                | MigrateCommands::Bench { validation_opts, .. }
+                | MigrateCommands::Try { validation_opts, .. }
+                | MigrateCommands::ListApplied { validation_opts, .. } => {
+                    Some(validation_opts)
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether the command goes on after a migration fails.
+    pub fn continue_on_error(&self) -> bool {
+        match self {
+            Self::Migrate(migrate) => match &migrate.commands {
+                MigrateCommands::Apply { continue_on_error, .. }
+                | MigrateCommands::ApplyAll { continue_on_error, .. }
+                | MigrateCommands::RetryFailed { continue_on_error, .. } => {
+                    *continue_on_error
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// The `tern` binary, which only creates a migrations crate, since the rest of
+/// the commands need one to be built into.
+#[derive(Debug, Parser)]
+#[command(name = "tern")]
+pub enum Standalone {
+    /// Create a new migrations crate with a context, a SQL and a Rust
+    /// migration, a config file, and a CLI `main`
+    Init(InitOpts),
+}
+
+#[derive(Debug, Args)]
+pub struct InitOpts {
+    /// Directory of the crate, which is created if it doesn't exist
+    #[arg(default_value = ".")]
+    pub dir: PathBuf,
+    /// Name of the crate, which is the name of the directory by default
+    #[arg(long)]
+    pub name: Option<String>,
+    /// The database that the migrations are for
+    #[clap(
+        long,
+        default_value = "postgres",
+        value_parser = PossibleValuesParser::new(Dialect::VARIANTS)
+            .map(|s| s.parse::<Dialect>().expect("checked possible value")),
+    )]
+    pub database: Dialect,
+    /// Overwrite files that already exist
+    #[arg(long)]
+    pub force: bool,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum MigrationType {
     Sql,
@@ -116,20 +707,198 @@ pub struct Source {
     pub path: PathBuf,
 }
 
-#[derive(Debug, Args)]
+/// How often to check if the database is ready to begin with for `--wait`.
+pub const WAIT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Args)]
 pub struct ConnectOpts {
     /// Connection string for the database either from the command line or from
     /// the environment variable `DATABASE_URL`.
     #[clap(long, short = 'D', env)]
     pub database_url: Option<String>,
+    /// TLS mode for the connection
+    #[clap(
+        long,
+        value_parser = PossibleValuesParser::new(SslMode::VARIANTS)
+            .map(|s| s.parse::<SslMode>().expect("checked possible value")),
+    )]
+    pub ssl_mode: Option<SslMode>,
+    /// Path to the CA certificate used to verify the server
+    #[clap(long)]
+    pub ssl_root_cert: Option<PathBuf>,
+    /// Path to the client certificate
+    #[clap(long)]
+    pub ssl_client_cert: Option<PathBuf>,
+    /// Path to the private key of the client certificate
+    #[clap(long)]
+    pub ssl_client_key: Option<PathBuf>,
+    /// Wait up to this many seconds for the database to accept connections
+    #[clap(long, value_name = "SECONDS")]
+    pub wait: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidationOpts {
+    /// Stop when a validation rule in this category fails
+    #[clap(
+        long,
+        value_name = "CATEGORY",
+        value_parser = PossibleValuesParser::new(ValidationCategory::VARIANTS)
+            .map(|s| s.parse::<ValidationCategory>().expect("checked possible value")),
+    )]
+    pub deny: Vec<ValidationCategory>,
+    /// Log failures of validation rules in this category instead of stopping
+    #[clap(
+        long,
+        value_name = "CATEGORY",
+        value_parser = PossibleValuesParser::new(ValidationCategory::VARIANTS)
+            .map(|s| s.parse::<ValidationCategory>().expect("checked possible value")),
+    )]
+    pub warn: Vec<ValidationCategory>,
+    /// Skip validation rules in this category
+    #[clap(
+        long,
+        value_name = "CATEGORY",
+        value_parser = PossibleValuesParser::new(ValidationCategory::VARIANTS)
+            .map(|s| s.parse::<ValidationCategory>().expect("checked possible value")),
+    )]
+    pub ignore: Vec<ValidationCategory>,
+}
+
+#[derive(Debug, Args)]
+pub struct LintOpts {
+    /// Check the unapplied SQL migrations for risky operations first, and stop
+    /// if there are any
+    #[clap(long)]
+    pub lint: bool,
+    /// Apply migrations even if `--lint` finds risky operations in them
+    #[clap(long)]
+    pub allow_destructive: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TimeoutOpts {
+    /// Fail a statement that waits longer than this for a lock, unless its
+    /// migration has a `tern:lockTimeout` annotation (Postgres only)
+    #[clap(long, value_name = "MILLISECONDS")]
+    pub lock_timeout: Option<u64>,
+    /// Fail a statement that runs longer than this, unless its migration has
+    /// a `tern:statementTimeout` annotation (Postgres only)
+    #[clap(long, value_name = "MILLISECONDS")]
+    pub statement_timeout: Option<u64>,
+    /// Run the statements of a migration that is applied in a transaction one
+    /// at a time, logging each one as it completes
+    #[clap(long)]
+    pub split_statements: bool,
+    /// Analyze the tables that each migration changes after applying it
+    #[clap(long)]
+    pub analyze: bool,
+}
+
+impl TimeoutOpts {
+    /// Set the default timeouts of the runner, whether it splits the
+    /// statements of migrations, and whether it analyzes the tables they
+    /// change.
+    pub fn apply<C: MigrationContext>(
+        &self,
+        mut runner: Runner<C>,
+    ) -> Runner<C> {
+        if let Some(ms) = self.lock_timeout {
+            runner = runner.with_lock_timeout(Duration::from_millis(ms));
+        }
+        if let Some(ms) = self.statement_timeout {
+            runner = runner.with_statement_timeout(Duration::from_millis(ms));
+        }
+        if self.split_statements {
+            runner = runner.with_split_statements();
+        }
+        if self.analyze {
+            runner = runner.with_analyze();
+        }
+        runner
+    }
+}
+
+impl LintOpts {
+    /// Add the linter to the validation rules of the runner, if asked to,
+    /// using the rules for the database of the context if it is known.
+    pub fn apply<C: MigrationContext>(
+        &self,
+        mut runner: Runner<C>,
+    ) -> Runner<C> {
+        if self.lint {
+            let mut linter = Linter::new();
+            let dialect =
+                <<C::Exec as Executor>::Queries as QueryRepository>::DIALECT;
+            if let Some(dialect) = dialect {
+                linter = linter.with_dialect(dialect);
+            }
+            runner = runner.with_validation_rule(linter);
+        }
+        if self.allow_destructive {
+            runner = runner.with_validation_severity(
+                ValidationCategory::Destructive,
+                Severity::Warn,
+            );
+        }
+        runner
+    }
+}
+
+impl ValidationOpts {
+    /// Set the severities of the validation categories of the runner.
+    pub fn apply<C: MigrationContext>(
+        &self,
+        mut runner: Runner<C>,
+    ) -> Runner<C> {
+        for category in &self.deny {
+            runner =
+                runner.with_validation_severity(*category, Severity::Error);
+        }
+        for category in &self.warn {
+            runner = runner.with_validation_severity(*category, Severity::Warn);
+        }
+        for category in &self.ignore {
+            runner =
+                runner.with_validation_severity(*category, Severity::Ignore);
+        }
+        runner
+    }
 }
 
 impl ConnectOpts {
-    pub fn required_db_url(&self) -> anyhow::Result<&str> {
-        self.database_url.as_deref().ok_or_else(
+    /// The same options with a different connection string, if one is given.
+    pub fn with_db_url(&self, db_url: Option<&str>) -> Self {
+        let mut opts = self.clone();
+        if let Some(db_url) = db_url {
+            opts.database_url = Some(db_url.to_string());
+        }
+        opts
+    }
+
+    /// The connection string with the TLS options added to it.
+    pub fn required_db_url(&self) -> anyhow::Result<String> {
+        let db_url = self.database_url.as_deref().ok_or_else(
             || anyhow::anyhow!(
                 "the `--database-url/-D` option or the `DATABASE_URL` environment variable must be provided"
             )
-        )
+        )?;
+
+        Ok(self.tls_options().apply_to_url(db_url)?)
+    }
+
+    pub fn wait_for_db(&self) -> Option<WaitForDb> {
+        self.wait.map(|secs| {
+            WaitForDb::new(Duration::from_secs(secs), WAIT_INTERVAL)
+        })
+    }
+
+    pub fn tls_options(&self) -> TlsOptions {
+        TlsOptions {
+            ssl_mode: self.ssl_mode,
+            root_cert: self.ssl_root_cert.clone(),
+            client_cert: self.ssl_client_cert.clone(),
+            client_key: self.ssl_client_key.clone(),
+        }
     }
 }
@@ -1,21 +1,26 @@
 use anyhow::Context;
-use regex::Regex;
+use std::fs::File;
+use std::io::Write as _;
 use std::path::{Path, PathBuf};
-use std::{fs::File, sync::OnceLock};
+use tern_core::lint::Linter;
+use tern_core::lockfile::Lockfile;
+use tern_core::migration::{
+    Dialect, EmbeddedMigration, MigrationId, QuarantinedMigration,
+};
+use tern_core::run_log::RunRecord;
+use tern_core::runner::{
+    DriftReport, HistorySummary, HistoryVerification, Report, ReportFormat,
+};
+use tern_core::schema::Schema;
 
-use crate::cli::MigrationType;
-
-fn filename_re() -> &'static Regex {
-    static RE: OnceLock<Regex> = OnceLock::new();
-    RE.get_or_init(|| Regex::new(r"^V(\d+)__(\w+)\.(sql|rs)$").unwrap())
-}
+use crate::cli::{InitOpts, MigrationType};
 
 pub fn new(
     description: String,
     no_tx: bool,
     migration_type: MigrationType,
     path: PathBuf,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<PathBuf> {
     if !path.is_dir() {
         return Err(anyhow::anyhow!(
             "supplied path is not a directory or does not exist"
@@ -35,9 +40,449 @@ pub fn new(
         migration_template(no_tx, migration_type).as_bytes(),
     )?;
 
+    Ok(buf)
+}
+
+pub fn info(source_digest: &str, migrations: Vec<EmbeddedMigration>) {
+    println!("Source digest: {}", console::style(source_digest).cyan());
+    for migration in migrations {
+        let no_tx = if migration.no_tx { "  (no transaction)" } else { "" };
+        println!(
+            "{}  {}{no_tx}",
+            migration.filename,
+            console::style(migration.checksum).dim()
+        );
+    }
+}
+
+pub fn lint(
+    migrations: Vec<EmbeddedMigration>,
+    dialect: Option<Dialect>,
+) -> anyhow::Result<()> {
+    let mut linter = Linter::new();
+    if let Some(dialect) = dialect {
+        linter = linter.with_dialect(dialect);
+    }
+    let findings = linter.lint(&migrations)?;
+    for finding in &findings {
+        println!(
+            "{}  {}: {}",
+            finding.migration,
+            console::style(&finding.rule).yellow(),
+            finding.msg
+        );
+    }
+    if !findings.is_empty() {
+        return Err(anyhow::anyhow!(
+            "lint failed, findings: {}",
+            findings.len()
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn lock(
+    migrations: &[EmbeddedMigration],
+    path: &Path,
+    check: bool,
+) -> anyhow::Result<()> {
+    let lockfile = Lockfile::new(migrations);
+    if !check {
+        println!("Writing {}", console::style(path.display()).cyan());
+        std::fs::write(path, lockfile.to_string())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        return Ok(());
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let mismatches = Lockfile::parse(&contents)?.check(migrations);
+    for mismatch in &mismatches {
+        println!(
+            "{}  {}",
+            console::style(&mismatch.filename).yellow(),
+            mismatch.reason
+        );
+    }
+    if !mismatches.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} is not up to date, differences: {}",
+            path.display(),
+            mismatches.len()
+        ));
+    }
+
     Ok(())
 }
 
+pub fn log_run(path: &Path, record: &RunRecord) -> anyhow::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", record.to_line())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
+
+pub fn dump_schema(
+    schema: &Schema,
+    path: &Path,
+    check: bool,
+) -> anyhow::Result<()> {
+    if !check {
+        println!("Writing {}", console::style(path.display()).cyan());
+        std::fs::write(path, schema.to_string())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        return Ok(());
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if contents != schema.to_string() {
+        return Err(anyhow::anyhow!(
+            "{} is not up to date with the schema of the database, run `schema dump` to update it",
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn check(report: &DriftReport) -> anyhow::Result<()> {
+    println!("{report}");
+    if !report.is_empty() {
+        return Err(anyhow::anyhow!(
+            "database has drifted from the migrations, differences: {}",
+            report.count()
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn verify_history(
+    verification: &HistoryVerification,
+) -> anyhow::Result<()> {
+    println!("{verification}");
+    if !verification.is_empty() {
+        return Err(anyhow::anyhow!(
+            "history table has problems: {}",
+            verification.count()
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn quarantine(quarantined: &[QuarantinedMigration]) {
+    if quarantined.is_empty() {
+        println!("No failed migrations");
+        return;
+    }
+    for migration in quarantined {
+        println!(
+            "{}  {} attempts, last at {}",
+            console::style(migration.migration_id()).cyan(),
+            migration.attempts,
+            migration.last_failed_at
+        );
+        println!("  {}", console::style(&migration.error).dim());
+    }
+}
+
+pub fn drop_dryrun(summary: &HistorySummary) {
+    let latest = match &summary.latest_version {
+        Some(version) => format!("latest version {version}"),
+        _ => "no version applied".to_string(),
+    };
+    if summary.other_rows > 0 {
+        println!(
+            "Would delete {} rows from {}, {latest}, and keep {} rows from other sources",
+            summary.rows,
+            console::style(&summary.table).cyan(),
+            summary.other_rows
+        );
+        return;
+    }
+    println!(
+        "Would drop {} with {} rows, {latest}",
+        console::style(&summary.table).cyan(),
+        summary.rows
+    );
+}
+
+// Stop a command that changes the history unless `--yes` was given, after
+// showing the changes it would make, in the format of `--format` if there is
+// one.
+pub fn confirmed(
+    report: &Report,
+    yes: bool,
+    format: Option<ReportFormat>,
+) -> anyhow::Result<()> {
+    if yes {
+        return Ok(());
+    }
+    match format {
+        Some(format) => println!("{}", report.render(format)),
+        _ => println!("{report}"),
+    }
+    Err(anyhow::anyhow!(
+        "the history was not changed, run again with `--yes` to make these changes: {}",
+        report.count()
+    ))
+}
+
+pub fn dump(
+    report: &Report,
+    format: ReportFormat,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let rendered = report.render(format);
+    match output {
+        Some(path) => std::fs::write(path, rendered)
+            .with_context(|| format!("Failed to write {}", path.display())),
+        _ => {
+            println!("{}", rendered.trim_end_matches('\n'));
+            Ok(())
+        },
+    }
+}
+
+pub fn extract(
+    migrations: Vec<EmbeddedMigration>,
+    dir: &Path,
+    force: bool,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    if !force {
+        if let Some(existing) = migrations
+            .iter()
+            .map(|m| dir.join(m.filename))
+            .find(|path| path.exists())
+        {
+            return Err(anyhow::anyhow!(
+                "{} already exists, use `--force` to overwrite",
+                existing.display()
+            ));
+        }
+    }
+    for migration in migrations {
+        let path = dir.join(migration.filename);
+        println!(
+            "Writing {} {}",
+            console::style(path.display()).cyan(),
+            console::style(migration.checksum).dim()
+        );
+        std::fs::write(&path, migration.content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+pub fn init(opts: &InitOpts) -> anyhow::Result<PathBuf> {
+    let name = match &opts.name {
+        Some(name) => name.clone(),
+        None => crate_name(&opts.dir)?,
+    };
+    let files = scaffold(&name, opts.database);
+    if !opts.force {
+        if let Some(existing) = files
+            .iter()
+            .map(|(path, _)| opts.dir.join(path))
+            .find(|path| path.exists())
+        {
+            return Err(anyhow::anyhow!(
+                "{} already exists, use `--force` to overwrite",
+                existing.display()
+            ));
+        }
+    }
+    for (path, content) in files {
+        let path = opts.dir.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create {}", parent.display())
+            })?;
+        }
+        println!("Creating {}", console::style(path.display()).cyan());
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    Ok(opts.dir.clone())
+}
+
+// The name of the crate in `dir`, from the name of the directory.
+fn crate_name(dir: &Path) -> anyhow::Result<String> {
+    let dir = std::path::absolute(dir)?;
+    let name =
+        dir.file_name().and_then(|name| name.to_str()).ok_or_else(|| {
+            anyhow::anyhow!(
+                "no crate name in {}, use `--name` to set one",
+                dir.display()
+            )
+        })?;
+
+    Ok(name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect())
+}
+
+// The path and contents of each file of a new migrations crate.
+fn scaffold(name: &str, database: Dialect) -> Vec<(&'static str, String)> {
+    let (feature, executor, url) = match database {
+        Dialect::Postgres => (
+            "sqlx_postgres",
+            "SqlxPgExecutor",
+            "postgres://postgres@localhost:5432/postgres",
+        ),
+        Dialect::MySql => (
+            "sqlx_mysql",
+            "SqlxMySqlExecutor",
+            "mysql://root@localhost:3306/mysql",
+        ),
+        Dialect::Sqlite => {
+            ("sqlx_sqlite", "SqlxSqliteExecutor", "sqlite://db.sqlite?mode=rwc")
+        },
+    };
+    let version = env!("CARGO_PKG_VERSION");
+
+    vec![
+        (
+            "Cargo.toml",
+            format!(
+                r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+env_logger = "0.11"
+log = "0.4"
+# The executor needs a runtime for its connection pool.
+sqlx = {{ version = "0.8", features = ["runtime-tokio"] }}
+tern = {{ version = "{version}", features = ["cli", "{feature}"] }}
+tokio = {{ version = "1", features = ["macros", "rt-multi-thread"] }}
+"#
+            ),
+        ),
+        (
+            "build.rs",
+            r#"// The SQL migrations are embedded when the crate is compiled, so it has to
+// be compiled again when one of them changes.
+fn main() {
+    println!("cargo:rerun-if-changed=src/migrations/");
+}
+"#
+            .to_string(),
+        ),
+        (
+            "tern.toml",
+            format!(
+                r#"# Profiles for `--env <name>`, like `--env local`.
+[env.local]
+# Read the connection string from this variable instead of `DATABASE_URL`.
+database_url_env = "LOCAL_DATABASE_URL"
+
+[env.local.defaults]
+LOCAL_DATABASE_URL = "{url}"
+"#
+            ),
+        ),
+        (
+            "src/main.rs",
+            r#"use tern::App;
+
+mod migrations;
+
+#[tokio::main]
+async fn main() {
+    let mut env = env_logger::Builder::from_default_env();
+    env.filter(None, log::LevelFilter::Info).init();
+
+    let app = App::new(migrations::Options);
+    match app.run_typed().await {
+        Ok(outcome) => std::process::exit(outcome.exit_code()),
+        Err(e) => {
+            log::error!("{e:#}");
+            std::process::exit(1);
+        },
+    }
+}
+"#
+            .to_string(),
+        ),
+        (
+            "src/migrations.rs",
+            format!(
+                r#"use tern::error::TernResult;
+use tern::{{ContextOptions, MigrationContext, MigrationSource, {executor}}};
+
+/// The context that the migrations in `src/migrations` are applied with.  A
+/// Rust migration can use anything that is added to it to build its query.
+#[derive(MigrationContext, MigrationSource)]
+#[tern(source = "src/migrations")]
+pub struct Context {{
+    #[tern(executor_via)]
+    pub executor: {executor},
+}}
+
+/// How the CLI connects the context to the database of `--database-url`.
+pub struct Options;
+
+impl ContextOptions for Options {{
+    type Ctx = Context;
+
+    async fn connect(&self, db_url: &str) -> TernResult<Context> {{
+        let executor = {executor}::new(db_url).await?;
+        Ok(Context {{ executor }})
+    }}
+}}
+"#
+            ),
+        ),
+        (
+            "src/migrations/V1__create_example.sql",
+            r#"CREATE TABLE example (
+  id integer PRIMARY KEY,
+  name varchar(255) NOT NULL
+);
+"#
+            .to_string(),
+        ),
+        (
+            "src/migrations/V2__insert_example.rs",
+            r#"use tern::Migration;
+use tern::error::TernResult;
+use tern::migration::{Query, QueryBuilder};
+
+use super::Context;
+
+/// A Rust migration builds its query with the context when it is applied.
+#[derive(Migration)]
+pub struct TernMigration;
+
+impl QueryBuilder for TernMigration {
+    type Ctx = Context;
+
+    async fn build(&self, _ctx: &mut Self::Ctx) -> TernResult<Query> {
+        let sql = "INSERT INTO example (id, name) VALUES (1, 'tern');";
+        Ok(Query::new(sql.to_string()))
+    }
+}
+"#
+            .to_string(),
+        ),
+    ]
+}
+
 fn get_latest_version(source: &Path) -> anyhow::Result<u16> {
     source
         .read_dir()?
@@ -55,14 +500,15 @@ fn get_latest_version(source: &Path) -> anyhow::Result<u16> {
                 .file_name()
                 .into_string()
                 .map_err(|_| anyhow::anyhow!("converting filename to str"))?;
-            let captures = filename_re().captures(&filename).ok_or(
-                anyhow::anyhow!("{filename} does not match expected format"),
-            )?;
-            let version = captures
-                .get(1)
-                .ok_or(anyhow::anyhow!("{filename} missing version"))?
-                .as_str()
-                .parse::<u16>()?;
+            // A repeatable migration has no version.
+            if tern_core::source::fs::is_repeatable(&filename) {
+                return Ok(acc);
+            }
+            let version = MigrationId::from_filename(&filename)?
+                .version()
+                .as_integer()
+                .ok_or(anyhow::anyhow!("{filename} has a text version"))?;
+            let version = u16::try_from(version)?;
             if version > 0 {
                 Ok::<u16, anyhow::Error>(version)
             } else {
@@ -127,3 +573,30 @@ impl QueryBuilder for TernMigration {{
 
     content
 }
+
+#[cfg(test)]
+mod tests {
+    use super::scaffold;
+    use crate::config::ConfigFile;
+    use tern_core::migration::{Dialect, MigrationId};
+
+    #[test]
+    fn scaffold_files() {
+        for database in [Dialect::Postgres, Dialect::MySql, Dialect::Sqlite] {
+            let files = scaffold("migrations", database);
+            let (_, config) =
+                files.iter().find(|(path, _)| *path == "tern.toml").unwrap();
+            let config: ConfigFile = toml::from_str(config).unwrap();
+            assert!(config.profile("local").is_ok());
+
+            let ids = files
+                .iter()
+                .filter_map(|(path, _)| {
+                    path.strip_prefix("src/migrations/")
+                        .map(|name| MigrationId::from_filename(name).unwrap())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(ids.len(), 2);
+        }
+    }
+}
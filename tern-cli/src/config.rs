@@ -0,0 +1,236 @@
+//! The CLI config file, `tern.toml` by default.
+//!
+//! ```toml
+//! [env.staging]
+//! # Read the connection string from this variable instead of `DATABASE_URL`.
+//! database_url_env = "STAGING_DATABASE_URL"
+//! # Track the history in this table instead of the context's default.
+//! history_table = "_tern_migrations_staging"
+//! # Create and look for unqualified names in this schema (Postgres).
+//! search_path = "staging"
+//! # Run this after a run that applied migrations.
+//! post_apply = "REFRESH MATERIALIZED VIEW schema_info;"
+//! # Feature flags for Rust migrations, which `--flag` adds to.
+//! flags = ["skip_expensive_indexes", "batch_size=500"]
+//!
+//! # Values for the context's config that it doesn't already have.
+//! [env.staging.defaults]
+//! REPORT_SCHEMA = "staging"
+//!
+//! # Refuse to run these commands against a host matching a pattern in `deny`,
+//! # or, if there is an `allow`, one matching none of its patterns, without
+//! # `--force-prod`.
+//! [[guard]]
+//! operations = ["history drop", "history set-version"]
+//! deny = ["*.prod.*"]
+//! ```
+use anyhow::Context as _;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tern_core::config::{
+    ConfigSource, FLAGS_KEY, Flags, HISTORY_TABLE_KEY, POST_APPLY_KEY,
+    SEARCH_PATH_KEY,
+};
+use tern_core::guard::UrlGuard;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Named environment profiles.
+    #[serde(default)]
+    pub env: HashMap<String, Profile>,
+    /// Guard rails for the hosts that commands connect to.
+    #[serde(default)]
+    pub guard: Vec<Guard>,
+}
+
+/// Settings for one environment, selected with `--env <name>`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub database_url_env: Option<String>,
+    pub history_table: Option<String>,
+    pub search_path: Option<String>,
+    pub post_apply: Option<String>,
+    #[serde(default)]
+    pub flags: Vec<String>,
+    #[serde(default)]
+    pub defaults: HashMap<String, String>,
+}
+
+/// The hosts that commands are denied or allowed to run against, see
+/// `UrlGuard`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Guard {
+    /// The commands, like `history drop`, which is every command if there are
+    /// none.
+    #[serde(default)]
+    pub operations: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl ConfigFile {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// The guard of all of the `[[guard]]` tables.
+    pub fn url_guard(&self) -> UrlGuard {
+        let mut url_guard = UrlGuard::new();
+        for guard in &self.guard {
+            let all = ["*".to_string()];
+            let operations = if guard.operations.is_empty() {
+                &all[..]
+            } else {
+                &guard.operations
+            };
+            for operation in operations {
+                for pattern in &guard.deny {
+                    url_guard = url_guard.deny(operation, pattern);
+                }
+                for pattern in &guard.allow {
+                    url_guard = url_guard.allow(operation, pattern);
+                }
+            }
+        }
+
+        url_guard
+    }
+
+    pub fn profile(&self, name: &str) -> anyhow::Result<&Profile> {
+        self.env.get(name).ok_or_else(|| {
+            anyhow::anyhow!("no profile `{name}` in the config file")
+        })
+    }
+}
+
+impl Profile {
+    /// The config of a context with this profile, which has the settings of
+    /// the profile in front of the context's own `config`, and the defaults
+    /// of the profile behind it.
+    ///
+    /// The flags are the ones of the profile, or the context's if it has none,
+    /// and then `flags` from the command line.
+    pub fn config(&self, config: &ConfigSource, flags: &Flags) -> ConfigSource {
+        let mut values = HashMap::new();
+        if let Some(table) = &self.history_table {
+            values.insert(HISTORY_TABLE_KEY, table.clone());
+        }
+        if let Some(path) = &self.search_path {
+            values.insert(SEARCH_PATH_KEY, path.clone());
+        }
+        if let Some(sql) = &self.post_apply {
+            values.insert(POST_APPLY_KEY, sql.clone());
+        }
+        let mut all_flags = if self.flags.is_empty() {
+            Flags::from_config(config)
+        } else {
+            Flags::parse(&self.flags.join(","))
+        };
+        all_flags.extend(flags.clone());
+        if !all_flags.is_empty() {
+            values.insert(FLAGS_KEY, all_flags.to_string());
+        }
+
+        ConfigSource::from_map(values)
+            .or(config.clone())
+            .or(ConfigSource::from_map(self.defaults.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigFile, ConfigSource, Flags, HISTORY_TABLE_KEY};
+
+    #[test]
+    fn parse_profiles() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+[env.staging]
+database_url_env = "STAGING_DATABASE_URL"
+history_table = "_tern_staging"
+search_path = "staging, public"
+post_apply = "SELECT 1;"
+flags = ["skip_indexes"]
+
+[env.staging.defaults]
+SCHEMA = "staging"
+
+[env.prod]
+"#,
+        )
+        .unwrap();
+        let staging = config.profile("staging").unwrap();
+        assert_eq!(
+            staging.database_url_env.as_deref(),
+            Some("STAGING_DATABASE_URL")
+        );
+        assert_eq!(staging.history_table.as_deref(), Some("_tern_staging"));
+        assert_eq!(staging.search_path.as_deref(), Some("staging, public"));
+        assert_eq!(staging.post_apply.as_deref(), Some("SELECT 1;"));
+        assert_eq!(staging.flags, ["skip_indexes"]);
+        assert_eq!(staging.defaults["SCHEMA"], "staging");
+        assert!(config.profile("prod").unwrap().history_table.is_none());
+        assert!(config.profile("dev").is_err());
+        assert!(toml::from_str::<ConfigFile>("[env.x]\nurl = 1").is_err());
+    }
+
+    #[test]
+    fn profile_config() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+[env.staging]
+history_table = "_tern_staging"
+flags = ["skip_indexes"]
+
+[env.staging.defaults]
+SCHEMA = "staging"
+TABLE = "t"
+"#,
+        )
+        .unwrap();
+        let staging = config.profile("staging").unwrap();
+        let own = ConfigSource::from_map([
+            (HISTORY_TABLE_KEY, "_tern_migrations"),
+            ("TABLE", "own"),
+        ]);
+        let config = staging.config(&own, &Flags::parse("batch_size=500"));
+        assert_eq!(
+            config.get(HISTORY_TABLE_KEY).as_deref(),
+            Some("_tern_staging")
+        );
+        assert_eq!(config.get("SCHEMA").as_deref(), Some("staging"));
+        assert_eq!(config.get("TABLE").as_deref(), Some("own"));
+        let flags = Flags::from_config(&config);
+        assert!(flags.enabled("skip_indexes"));
+        assert_eq!(flags.get("batch_size"), Some("500"));
+    }
+
+    #[test]
+    fn parse_guards() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+[[guard]]
+operations = ["history drop"]
+deny = ["*.prod.*"]
+
+[[guard]]
+allow = ["localhost", "*.internal"]
+"#,
+        )
+        .unwrap();
+        let guard = config.url_guard();
+        let prod = "postgres://db.prod.internal/app";
+        assert!(guard.check("migrate apply-all", prod).is_ok());
+        assert!(guard.check("history drop", prod).is_err());
+        assert!(guard.check("info", "postgres://db.example.com").is_err());
+    }
+}
@@ -6,17 +6,39 @@
 //! The `App` is the CLI. `ContextOptions` helps to connect a generic context to
 //! the CLI if it is the CLI that is supplying the database connection string.
 //!
+//! The `tern` binary only has the command `init`, which creates a migrations
+//! crate to use them in, see [`run_init`].
+//!
 //! [tern-docs]: https://docs.rs/crate/tern/latest
-use clap::Parser;
-use tern_core::error::TernResult;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser as _};
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once, OnceLock};
+use std::time::Duration;
+use tern_core::config::{ConfigSource, Flags};
+use tern_core::error::{Error, TernResult};
 use tern_core::future::Future;
-use tern_core::migration::MigrationContext;
-use tern_core::runner::{Report, Runner};
+use tern_core::guard::UrlGuard;
+use tern_core::inventory::SoftApplyPlan;
+use tern_core::migration::{MigrationContext, QuarantinedMigration, Version};
+use tern_core::operation::Operation;
+use tern_core::redact::redact;
+use tern_core::run_log::RunRecord;
+use tern_core::runner::{
+    HistorySummary, HistoryVerification, Report, ReportFormat, Runner,
+    StopHandle,
+};
+use tern_core::schema::Schema;
 
 mod cli;
 mod commands;
+mod config;
 
 /// A type that can build a particular context given a database url.
+///
+/// If the password for the database is kept in a secrets manager instead of
+/// the connection string, see `tern::credentials` for filling it in here.
 pub trait ContextOptions {
     type Ctx: MigrationContext;
 
@@ -27,13 +49,106 @@ pub trait ContextOptions {
     ) -> impl Future<Output = TernResult<Self::Ctx>>;
 }
 
+/// Run the `tern` binary, which has only the command `init` for creating a
+/// migrations crate, since the rest need a context that is built into one.
+pub fn run_init() -> anyhow::Result<()> {
+    let cli::Standalone::Init(opts) = cli::Standalone::parse();
+    commands::init(&opts)?;
+
+    Ok(())
+}
+
+/// The exit code of the CLI when a run is stopped by `SIGINT` or `SIGTERM`.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
 /// The CLI app to run.
 ///
 /// ## Usage
 ///
 /// Either build from [`ContextOptions`] and supply the database connection
 /// string with the CLI and `-D`, `--database-url`, or environment variable
-/// `DATABASE_URL`, or build `App` directly from a `MigrationContext`.
+/// `DATABASE_URL`, or build `App` directly from a `MigrationContext`.  In the
+/// first case, the TLS flags `--ssl-mode`, `--ssl-root-cert`,
+/// `--ssl-client-cert`, and `--ssl-client-key` are added to the connection
+/// string as the parameters that the database expects.
+///
+/// With `--wait <seconds>`, connecting is retried until the database accepts
+/// connections or the time runs out, for when it is starting at the same time.
+///
+/// With `--format <json|html|markdown|junit|csv>`, the report of a command is
+/// printed in that format and not returned, so `--format html` makes a page
+/// that can be attached to a release, `--format markdown` a table for a
+/// comment, `--format junit` test results for a CI system, and `--format csv`
+/// rows for a spreadsheet.  In JUnit, a
+/// run that fails is printed as well, with the migration that failed as a
+/// failed test case.
+///
+/// With `--env-file <path>`, variables in the file are loaded into the
+/// environment before anything else reads it.  A variable that is already set
+/// in the environment keeps its value, and a command line flag takes
+/// precedence over both, so `-D` wins over `DATABASE_URL` in the environment,
+/// which wins over `DATABASE_URL` in the file.
+///
+/// On `SIGINT` (Ctrl-C) or `SIGTERM`, `migrate apply` and `migrate apply-all`
+/// finish the migration being applied, release the run lock, print the report
+/// of what was applied, and return an [`Outcome`] whose
+/// [`exit_code`](Outcome::exit_code) is [`EXIT_INTERRUPTED`].  A dry run, or a
+/// second signal, exits at once.  The signal handler is installed once, the
+/// first time a command that can be stopped runs.
+///
+/// With `--continue-on-error`, a command that has applied what it could fails
+/// after printing its report if any migration failed.
+///
+/// With `--env <name>`, the profile `[env.<name>]` in the config file
+/// (`tern.toml`, or the path given with `--config`) is used:
+///
+/// ```toml
+/// [env.staging]
+/// # Read the connection string from this variable instead of `DATABASE_URL`.
+/// database_url_env = "STAGING_DATABASE_URL"
+/// # Track the history in this table instead of the context's default.
+/// history_table = "_tern_migrations_staging"
+/// # Create and look for unqualified names in this schema (Postgres).
+/// search_path = "staging"
+/// # Run this after a run that applied migrations.
+/// post_apply = "REFRESH MATERIALIZED VIEW schema_info;"
+/// # Feature flags for Rust migrations, which `--flag` adds to.
+/// flags = ["skip_expensive_indexes", "batch_size=500"]
+///
+/// # Values for the context's config that it doesn't already have.
+/// [env.staging.defaults]
+/// REPORT_SCHEMA = "staging"
+/// ```
+///
+/// Nothing is set in the environment.  The context is given a config with the
+/// settings of the profile in front of its own config, so they take its place,
+/// and the `defaults` behind it, so they are only used for keys it doesn't
+/// have.  The flags are the profile's, or the context's if it has none, and
+/// then the ones given with `--flag`.
+///
+/// A `[[guard]]` table of the config file refuses to run commands against a
+/// host matching a pattern, or, if it allows some, against a host matching
+/// none of them, unless the command is given `--force-prod`:
+///
+/// ```toml
+/// [[guard]]
+/// operations = ["history drop", "history set-version", "history forget"]
+/// deny = ["*.prod.*"]
+/// ```
+///
+/// The same can be added in code with [`with_url_guard`](Self::with_url_guard).
+///
+/// An [`Operation`] of the application's own, like one that grants privileges
+/// on the tables the migrations created, is added with
+/// [`with_operation`](Self::with_operation) and becomes a subcommand:
+///
+/// ```rust,ignore
+/// App::new(opts).with_operation(SyncGrants).run().await?;
+/// ```
+///
+/// ```terminal
+/// > $ my-app sync-grants -D "$DATABASE_URL" readonly
+/// ```
 ///
 /// ```terminal
 /// > $ my-app --help
@@ -42,179 +157,987 @@ pub trait ContextOptions {
 /// Commands:
 ///   migrate  Operations on the set of migration files
 ///   history  Operations on the table storing the history of these migrations
+///   schema   Operations on the schema that these migrations build
+///   info     Show the migrations embedded in this binary and their digest
+///   check    Compare the migrations with the history of a database and fail if they differ
 ///   help     Print this message or the help of the given subcommand(s)
 /// ```
 pub struct App<T> {
     inner: T,
-    cli: cli::Tern,
+    operations: Vec<RegisteredOperation>,
+    url_guard: UrlGuard,
+    cli: OnceLock<cli::Tern>,
+}
+
+// A custom operation for the context of the app, with the type of the context
+// erased since an `App` can be built from either `ContextOptions` or the
+// context itself.
+struct RegisteredOperation {
+    name: String,
+    about: String,
+    // A `Box<dyn Operation<C>>`.
+    operation: Box<dyn Any + Send + Sync>,
 }
 
 impl<T> App<T> {
     pub fn new(inner: T) -> Self {
-        let cli = cli::Tern::parse();
-        Self { inner, cli }
+        Self {
+            inner,
+            operations: Vec::new(),
+            url_guard: UrlGuard::new(),
+            cli: OnceLock::new(),
+        }
+    }
+
+    /// Refuse to run commands against the hosts that `guard` denies them, in
+    /// addition to the `[[guard]]` tables of the config file, unless the
+    /// command is given `--force-prod`.
+    ///
+    /// The guard is checked against the connection string before connecting,
+    /// so it does nothing for [`run_with_context`](Self::run_with_context).
+    pub fn with_url_guard(mut self, guard: UrlGuard) -> Self {
+        self.url_guard.extend(guard);
+        self
+    }
+
+    /// Add a custom [`Operation`] as a subcommand of the CLI named by
+    /// [`Operation::name`], for a context `C` that has to be the one of the
+    /// app.
+    ///
+    /// The subcommand takes the options to connect to the database, and the
+    /// rest of its arguments are given to the operation.
+    ///
+    /// # Panics
+    ///
+    /// If the name is the name of a command of the CLI or of another
+    /// operation.
+    pub fn with_operation<C, O>(mut self, operation: O) -> Self
+    where
+        C: MigrationContext,
+        O: Operation<C> + 'static,
+    {
+        let name = operation.name().to_string();
+        assert!(
+            cli::Tern::command().find_subcommand(&name).is_none()
+                && !self.operations.iter().any(|op| op.name == name),
+            "the name of the operation {name} is already used"
+        );
+        let about = operation.about().to_string();
+        let operation: Box<dyn Operation<C>> = Box::new(operation);
+        self.operations.push(RegisteredOperation {
+            name,
+            about,
+            operation: Box::new(operation),
+        });
+
+        self
+    }
+
+    // Parse the arguments the first time they're needed, once every operation
+    // is added.
+    fn cli(&self) -> &cli::Tern {
+        self.cli.get_or_init(|| self.parse())
+    }
+
+    fn parse(&self) -> cli::Tern {
+        let operations = || {
+            self.operations
+                .iter()
+                .map(|op| (op.name.as_str(), op.about.as_str()))
+        };
+        let mut cli = cli::Tern::parse_with_operations(operations(), None);
+        // Options can have values from the environment, so if there's a file
+        // to add to it or a profile with a different variable for the
+        // connection string, the arguments have to be parsed again.
+        let mut reparse = false;
+        let mut database_url_env = None;
+        if let Some(path) = &cli.global.env_file {
+            if let Err(e) = dotenvy::from_path(path) {
+                cli::Tern::command()
+                    .error(
+                        ErrorKind::Io,
+                        format!("could not load {}: {e}", path.display()),
+                    )
+                    .exit();
+            }
+            reparse = true;
+        }
+        if let Some(name) = &cli.global.env {
+            match config::ConfigFile::from_path(&cli.global.config).and_then(
+                |c| c.profile(name).map(|p| p.database_url_env.clone()),
+            ) {
+                Ok(var) => {
+                    // Not falling back to `DATABASE_URL`, which could be a
+                    // database from a different environment.
+                    reparse |= var.is_some();
+                    database_url_env = var;
+                },
+                Err(e) => cli::Tern::command()
+                    .error(ErrorKind::InvalidValue, format!("{e:#}"))
+                    .exit(),
+            }
+        }
+        if reparse {
+            cli = cli::Tern::parse_with_operations(
+                operations(),
+                database_url_env.as_deref(),
+            );
+        }
+
+        cli
+    }
+
+    // The config of the context with the settings of the profile from `--env`
+    // and the flags from `--flag` in front of its own `config`, if there are
+    // any.  They're given to the runner instead of being put in the
+    // environment, so that they are only for the context of the app.
+    fn context_config(
+        &self,
+        config: &ConfigSource,
+    ) -> anyhow::Result<Option<ConfigSource>> {
+        let cli = self.cli();
+        let flags = Flags::parse(&cli.global.flags.join(","));
+        let profile = match &cli.global.env {
+            Some(name) => config::ConfigFile::from_path(&cli.global.config)?
+                .profile(name)?
+                .clone(),
+            None if !flags.is_empty() => config::Profile::default(),
+            None => return Ok(None),
+        };
+
+        Ok(Some(profile.config(config, &flags)))
     }
 
     /// Run a CLI that has a `T: ContextOptions`, using the context that these
     /// options can build.
+    ///
+    /// This is the report of the command, if it has one and it wasn't printed
+    /// with `--format`.  See [`run_typed`](Self::run_typed) for what the
+    /// command did.
     pub async fn run(&self) -> anyhow::Result<Option<Report>>
     where
         T: ContextOptions,
     {
-        match &self.cli.commands {
-            cli::TernCommands::History(history) => match &history.commands {
-                cli::HistoryCommands::Init { connect_opts } => {
-                    let db_url = connect_opts.required_db_url()?.to_string();
-                    let context = self.inner.connect(&db_url).await?;
-                    let mut runner = Runner::new(context);
-                    runner.init_history().await?;
-
-                    Ok(None)
-                },
-                cli::HistoryCommands::Drop { connect_opts } => {
-                    let db_url = connect_opts.required_db_url()?.to_string();
-                    let context = self.inner.connect(&db_url).await?;
-                    let mut runner = Runner::new(context);
-                    runner.drop_history().await?;
+        let outcome = self.run_typed().await?;
 
-                    Ok(None)
-                },
-                cli::HistoryCommands::SoftApply { .. } => Err(anyhow::anyhow!(
-                    "Deprecated: use `migrate soft-apply` instead"
-                )),
-            },
-            cli::TernCommands::Migrate(migrate) => match &migrate.commands {
-                cli::MigrateCommands::Apply {
-                    dryrun,
-                    target_version,
-                    connect_opts,
-                } => {
-                    let db_url = connect_opts.required_db_url()?.to_string();
-                    let context = self.inner.connect(&db_url).await?;
-                    let mut runner = Runner::new(context);
-                    let report =
-                        runner.run_apply(*target_version, *dryrun).await?;
-
-                    Ok(Some(report))
-                },
-                cli::MigrateCommands::ApplyAll { dryrun, connect_opts } => {
-                    let db_url = connect_opts.required_db_url()?.to_string();
-                    let context = self.inner.connect(&db_url).await?;
-                    let mut runner = Runner::new(context);
-                    let report = runner.run_apply_all(*dryrun).await?;
+        Ok(self.cli().format().map_or_else(|| outcome.into_report(), |_| None))
+    }
 
-                    Ok(Some(report))
-                },
-                cli::MigrateCommands::SoftApply {
-                    dryrun,
-                    target_version,
-                    connect_opts,
-                } => {
-                    let db_url = connect_opts.required_db_url()?.to_string();
-                    let context = self.inner.connect(&db_url).await?;
-                    let mut runner = Runner::new(context);
-                    let report =
-                        runner.run_soft_apply(*target_version, *dryrun).await?;
-
-                    Ok(Some(report))
-                },
-                cli::MigrateCommands::ListApplied { connect_opts } => {
-                    let db_url = connect_opts.required_db_url()?.to_string();
-                    let context = self.inner.connect(&db_url).await?;
-                    let mut runner = Runner::new(context);
-                    let report = runner.list_applied().await?;
+    /// Like [`run`](Self::run), but returning the [`Outcome`] of the command,
+    /// which has its report even if it was printed with `--format`, and the
+    /// code to exit the process with in [`Outcome::exit_code`].
+    ///
+    /// The error, for printing, is the message of the one that stopped the
+    /// command with any credentials in it redacted.
+    pub async fn run_typed(&self) -> anyhow::Result<Outcome>
+    where
+        T: ContextOptions,
+    {
+        let res = self.run_command().await;
+        log_run(self.cli(), &res);
+        let outcome =
+            print_report(self.cli().format(), res).map_err(redact_error)?;
+        print_interrupted(self.cli().format(), &outcome);
 
-                    Ok(Some(report))
-                },
-                cli::MigrateCommands::New {
-                    description,
-                    no_tx,
-                    migration_type,
-                    source,
-                } => {
-                    commands::new(
-                        description.to_string(),
-                        *no_tx,
-                        *migration_type,
-                        source.path.clone(),
-                    )?;
-
-                    Ok(None)
-                },
-            },
+        let continue_on_error = self.cli().commands.continue_on_error();
+        fail_on_errors(continue_on_error, self.cli().format(), &outcome)?;
+
+        Ok(outcome)
+    }
+
+    async fn run_command(&self) -> anyhow::Result<Outcome>
+    where
+        T: ContextOptions,
+    {
+        dispatch(self.cli(), &self.operations, self).await
+    }
+
+    // Connect with the options, waiting for the database if asked to.
+    async fn runner(
+        &self,
+        connect_opts: &cli::ConnectOpts,
+    ) -> anyhow::Result<Runner<T::Ctx>>
+    where
+        T: ContextOptions,
+    {
+        let db_url = connect_opts.required_db_url()?;
+        self.check_url_guard(&db_url)?;
+        let context = match connect_opts.wait_for_db() {
+            Some(wait) => wait.retry(|| self.inner.connect(&db_url)).await?,
+            _ => self.inner.connect(&db_url).await?,
+        };
+        let config = self.context_config(context.config())?;
+
+        Ok(configure(self.cli(), Runner::new(context), config))
+    }
+
+    // Check the guard of the app and of the config file, if there is one, for
+    // the command and the host of the connection string.
+    fn check_url_guard(&self, db_url: &str) -> anyhow::Result<()> {
+        let cli = self.cli();
+        if cli.global.force_prod {
+            return Ok(());
+        }
+        let mut guard = self.url_guard.clone();
+        // Without `--config`, there doesn't have to be a config file, but a
+        // path that was given has to be one, or a typo would skip its guards.
+        if cli.config_given || cli.global.config.exists() {
+            let config = config::ConfigFile::from_path(&cli.global.config)?;
+            guard.extend(config.url_guard());
         }
+        guard.check(&cli.command_name, db_url)?;
+
+        Ok(())
     }
 
-    /// Run the CLI having already built a `MigrationContext` and initialized the
-    /// `App` from it instead of builder options.
+    /// Run the CLI having already built a `MigrationContext` and initialized
+    /// the `App` from it instead of builder options.
     pub async fn run_with_context(self) -> anyhow::Result<Option<Report>>
     where
         T: MigrationContext,
     {
-        let mut runner = Runner::new(self.inner);
-        let cli = self.cli;
+        let format = self.cli().format();
+        let outcome = self.run_with_context_typed().await?;
 
-        match cli.commands {
-            cli::TernCommands::History(history) => match &history.commands {
-                cli::HistoryCommands::Init { .. } => {
-                    runner.init_history().await?;
+        Ok(format.map_or_else(|| outcome.into_report(), |_| None))
+    }
 
-                    Ok(None)
-                },
-                cli::HistoryCommands::Drop { .. } => {
-                    runner.drop_history().await?;
+    /// Like [`run_with_context`](Self::run_with_context), but returning the
+    /// [`Outcome`] of the command, with the code to exit the process with in
+    /// [`Outcome::exit_code`].  The error has credentials redacted like the
+    /// one of [`run_typed`](Self::run_typed).
+    pub async fn run_with_context_typed(self) -> anyhow::Result<Outcome>
+    where
+        T: MigrationContext,
+    {
+        let format = self.cli().format();
+        let continue_on_error = self.cli().commands.continue_on_error();
+        let run_log = self.cli().global.run_log.clone();
+        let target = self.cli().target().to_string();
+        let res = self.run_command_with_context().await;
+        if let Some(path) = run_log {
+            append_run(&path, &target, &res);
+        }
+        let outcome = print_report(format, res).map_err(redact_error)?;
+        print_interrupted(format, &outcome);
+        fail_on_errors(continue_on_error, format, &outcome)?;
 
-                    Ok(None)
-                },
-                cli::HistoryCommands::SoftApply { .. } => Err(anyhow::anyhow!(
-                    "Deprecated: use `migrate soft-apply` instead"
-                )),
+        Ok(outcome)
+    }
+
+    async fn run_command_with_context(self) -> anyhow::Result<Outcome>
+    where
+        T: MigrationContext,
+    {
+        let config = self.context_config(self.inner.config())?;
+        let App { inner, operations, cli, .. } = self;
+        let cli = cli.into_inner().expect("the arguments are parsed");
+        let mut runner = configure(&cli, Runner::new(inner), config);
+        let wait = cli.commands.connect_opts().and_then(|opts| opts.wait);
+        if let Some(secs) = wait {
+            runner = runner.with_wait_for_db(
+                Duration::from_secs(secs),
+                cli::WAIT_INTERVAL,
+            );
+        }
+
+        dispatch(&cli, &operations, Some(runner)).await
+    }
+}
+
+// Where the dispatcher gets the runner of a command from.  An app built from
+// `ContextOptions` connects with the options of the command, and one built
+// from a context hands over the runner it already has.
+trait RunnerSource<C: MigrationContext> {
+    // Whether the runner connects with the connection string of the command,
+    // which `check --against` needs.
+    const CONNECTS: bool;
+
+    async fn runner(
+        &mut self,
+        connect_opts: &cli::ConnectOpts,
+    ) -> anyhow::Result<Runner<C>>;
+}
+
+impl<T: ContextOptions> RunnerSource<T::Ctx> for &App<T> {
+    const CONNECTS: bool = true;
+
+    async fn runner(
+        &mut self,
+        connect_opts: &cli::ConnectOpts,
+    ) -> anyhow::Result<Runner<T::Ctx>> {
+        App::runner(self, connect_opts).await
+    }
+}
+
+impl<C: MigrationContext> RunnerSource<C> for Option<Runner<C>> {
+    const CONNECTS: bool = false;
+
+    async fn runner(
+        &mut self,
+        _: &cli::ConnectOpts,
+    ) -> anyhow::Result<Runner<C>> {
+        Ok(self.take().expect("a command uses one runner"))
+    }
+}
+
+// Run the command of the arguments, getting the runner from `source` if it
+// needs one.
+async fn dispatch<C, S>(
+    cli: &cli::Tern,
+    operations: &[RegisteredOperation],
+    mut source: S,
+) -> anyhow::Result<Outcome>
+where
+    C: MigrationContext,
+    S: RunnerSource<C>,
+{
+    match &cli.commands {
+        cli::TernCommands::Info => {
+            commands::info(C::SOURCE_DIGEST, C::embedded_migrations());
+
+            Ok(Outcome::Info)
+        },
+        cli::TernCommands::Init(opts) => {
+            let path = commands::init(opts)?;
+
+            Ok(Outcome::Initialized { path })
+        },
+        cli::TernCommands::Operation(op) => {
+            let operation = operation::<C>(operations, &op.name)?;
+            let mut runner = source.runner(&op.connect_opts).await?;
+            runner.run_operation(operation, &op.args).await?;
+
+            Ok(Outcome::Operation { name: op.name.clone() })
+        },
+        cli::TernCommands::Check { against, connect_opts } => {
+            if against.is_some() && !S::CONNECTS {
+                return Err(anyhow::anyhow!(
+                    "`--against` needs the CLI to connect, with `ContextOptions`"
+                ));
+            }
+            let connect_opts = connect_opts.with_db_url(against.as_deref());
+            let mut runner = source.runner(&connect_opts).await?;
+            commands::check(&runner.check_drift().await?)?;
+
+            Ok(Outcome::Checked)
+        },
+        cli::TernCommands::History(history) => match &history.commands {
+            cli::HistoryCommands::Init { connect_opts } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let created = runner.init_history().await?;
+
+                Ok(Outcome::HistoryInitialized { created })
             },
-            cli::TernCommands::Migrate(migrate) => match migrate.commands {
-                cli::MigrateCommands::Apply {
-                    dryrun, target_version, ..
-                } => {
-                    let report =
-                        runner.run_apply(target_version, dryrun).await?;
+            cli::HistoryCommands::Drop { dryrun, no_backup, connect_opts } => {
+                let mut runner = source.runner(connect_opts).await?;
+                if *dryrun {
+                    let summary = runner.describe_history().await?;
+                    commands::drop_dryrun(&summary);
+                    return Ok(Outcome::HistoryDescribed(summary));
+                }
+                runner = runner.with_history_backup(!no_backup);
+                runner.drop_history().await?;
 
-                    Ok(Some(report))
-                },
-                cli::MigrateCommands::ApplyAll { dryrun, .. } => {
-                    let report = runner.run_apply_all(dryrun).await?;
+                Ok(Outcome::HistoryDropped)
+            },
+            cli::HistoryCommands::SetVersion {
+                version,
+                yes,
+                no_backup,
+                connect_opts,
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                runner = runner.with_history_backup(!no_backup);
+                let version = Version::parse(version, C::VERSION_KIND)?;
+                let report = runner.set_history_version(version, !yes).await?;
+                commands::confirmed(&report, *yes, cli.format())?;
 
-                    Ok(Some(report))
-                },
-                cli::MigrateCommands::SoftApply {
-                    dryrun,
-                    target_version,
-                    ..
-                } => {
-                    let report =
-                        runner.run_soft_apply(target_version, dryrun).await?;
-
-                    Ok(Some(report))
-                },
-                cli::MigrateCommands::ListApplied { .. } => {
-                    let report = runner.list_applied().await?;
+                Ok(Outcome::HistoryEdited(report))
+            },
+            cli::HistoryCommands::Forget {
+                version,
+                yes,
+                no_backup,
+                connect_opts,
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                runner = runner.with_history_backup(!no_backup);
+                let version = Version::parse(version, C::VERSION_KIND)?;
+                let report = runner.forget(version, !yes).await?;
+                commands::confirmed(&report, *yes, cli.format())?;
 
-                    Ok(Some(report))
-                },
-                cli::MigrateCommands::New {
-                    description,
-                    no_tx,
-                    migration_type,
-                    source,
-                } => {
-                    commands::new(
-                        description.to_string(),
-                        no_tx,
-                        migration_type,
-                        source.path.clone(),
-                    )?;
-
-                    Ok(None)
-                },
+                Ok(Outcome::HistoryEdited(report))
+            },
+            cli::HistoryCommands::Dump { no_content, output, connect_opts } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let mut report = runner.dump_history().await?;
+                if *no_content {
+                    report = report.without_content();
+                }
+                let format = cli.format().unwrap_or_default();
+                commands::dump(&report, format, output.as_deref())?;
+
+                Ok(Outcome::HistoryDumped)
+            },
+            cli::HistoryCommands::Replay { at, connect_opts } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let report = runner.replay(*at).await?;
+
+                Ok(Outcome::Replayed(report))
+            },
+            cli::HistoryCommands::Quarantine { connect_opts } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let quarantined = runner.quarantined().await?;
+                commands::quarantine(&quarantined);
+
+                Ok(Outcome::Quarantined(quarantined))
+            },
+            cli::HistoryCommands::Verify { connect_opts } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let verification = runner.verify_history().await?;
+                commands::verify_history(&verification)?;
+
+                Ok(Outcome::HistoryVerified(verification))
+            },
+            cli::HistoryCommands::SoftApply { .. } => Err(anyhow::anyhow!(
+                "Deprecated: use `migrate soft-apply` instead"
+            )),
+        },
+        cli::TernCommands::Schema(schema) => match &schema.commands {
+            cli::SchemaCommands::Dump { path, check, connect_opts } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let schema = runner.dump_schema().await?;
+                commands::dump_schema(&schema, path, *check)?;
+
+                Ok(Outcome::SchemaDumped(schema))
+            },
+        },
+        cli::TernCommands::Migrate(migrate) => match &migrate.commands {
+            cli::MigrateCommands::Apply {
+                dryrun,
+                target_version,
+                deadline,
+                continue_on_error,
+                lint_opts,
+                timeout_opts,
+                connect_opts,
+                ..
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                runner = lint_opts.apply(runner);
+                runner = timeout_opts.apply(runner);
+                if let Some(secs) = deadline {
+                    runner = runner.with_deadline(Duration::from_secs(*secs));
+                }
+                if *continue_on_error {
+                    runner = runner.with_continue_on_error();
+                }
+                let target = target(&mut runner, target_version).await?;
+                let _signal = stop_on_signal(&runner, *dryrun);
+                let report = runner.run_apply(target, *dryrun).await?;
+
+                Ok(Outcome::Applied(report))
+            },
+            cli::MigrateCommands::ApplyAll {
+                offline: true,
+                assume_applied,
+                ..
+            } => {
+                let assume_applied = version::<C>(assume_applied)?;
+                let report =
+                    Runner::<C>::offline_dryrun(assume_applied.as_ref())
+                        .with_content(cli.report_content());
+
+                Ok(Outcome::Applied(report))
+            },
+            cli::MigrateCommands::ApplyAll {
+                dryrun,
+                deadline,
+                continue_on_error,
+                lint_opts,
+                timeout_opts,
+                connect_opts,
+                ..
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                runner = lint_opts.apply(runner);
+                runner = timeout_opts.apply(runner);
+                if let Some(secs) = deadline {
+                    runner = runner.with_deadline(Duration::from_secs(*secs));
+                }
+                if *continue_on_error {
+                    runner = runner.with_continue_on_error();
+                }
+                let _signal = stop_on_signal(&runner, *dryrun);
+                let report = runner.run_apply_all(*dryrun).await?;
+
+                Ok(Outcome::Applied(report))
             },
+            cli::MigrateCommands::SoftApply {
+                dryrun,
+                target_version,
+                from_version,
+                connect_opts,
+                ..
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                if let Some(from) = version::<C>(from_version)? {
+                    runner = runner.with_soft_apply_from(from);
+                }
+                let target = target(&mut runner, target_version).await?;
+                let report = runner.run_soft_apply(target, *dryrun).await?;
+
+                Ok(Outcome::SoftApplied(report))
+            },
+            cli::MigrateCommands::Baseline {
+                version,
+                dryrun,
+                connect_opts,
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let version = Version::parse(version, C::VERSION_KIND)?;
+                let report = runner.baseline(version, *dryrun).await?;
+
+                Ok(Outcome::Baselined(report))
+            },
+            cli::MigrateCommands::Adopt { yes, connect_opts, .. } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let plan = runner.suggest_soft_apply().await?;
+                // With `--format`, the plan is in the `Outcome` instead.
+                if cli.format().is_none() {
+                    println!("{plan}");
+                }
+                let report = match &plan.target {
+                    Some(target) => {
+                        let report = runner
+                            .run_soft_apply(Some(target.clone()), !yes)
+                            .await?;
+                        commands::confirmed(&report, *yes, cli.format())?;
+                        report
+                    },
+                    None => Report::default(),
+                };
+
+                Ok(Outcome::Adopted { plan, report })
+            },
+            cli::MigrateCommands::Bench {
+                target_version,
+                timeout_opts,
+                connect_opts,
+                ..
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                runner = timeout_opts.apply(runner);
+                let target = target(&mut runner, target_version).await?;
+                let report = runner.bench(target).await?;
+
+                Ok(Outcome::Benched(report))
+            },
+            cli::MigrateCommands::Try {
+                timeout_opts, connect_opts, ..
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                runner = timeout_opts.apply(runner);
+                let report = runner.try_apply_all().await?;
+
+                Ok(Outcome::Tried(report))
+            },
+            cli::MigrateCommands::Reapply {
+                version,
+                timeout_opts,
+                connect_opts,
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                runner = timeout_opts.apply(runner);
+                let version = Version::parse(version, C::VERSION_KIND)?;
+                let report = runner.reapply(version).await?;
+
+                Ok(Outcome::Applied(report))
+            },
+            cli::MigrateCommands::RetryFailed {
+                dryrun,
+                continue_on_error,
+                timeout_opts,
+                connect_opts,
+            } => {
+                let mut runner = source.runner(connect_opts).await?;
+                runner = timeout_opts.apply(runner);
+                if *continue_on_error {
+                    runner = runner.with_continue_on_error();
+                }
+                let _signal = stop_on_signal(&runner, *dryrun);
+                let report = runner.retry_failed(*dryrun).await?;
+
+                Ok(Outcome::Applied(report))
+            },
+            cli::MigrateCommands::ListApplied { connect_opts, .. } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let report = runner.list_applied().await?;
+
+                Ok(Outcome::Listed(report))
+            },
+            cli::MigrateCommands::Status { connect_opts } => {
+                let mut runner = source.runner(connect_opts).await?;
+                let report = runner.status().await?;
+
+                Ok(Outcome::Status(report))
+            },
+            cli::MigrateCommands::New {
+                description,
+                no_tx,
+                migration_type,
+                source,
+            } => {
+                let path = commands::new(
+                    description.to_string(),
+                    *no_tx,
+                    *migration_type,
+                    source.path.clone(),
+                )?;
+
+                Ok(Outcome::Created { path })
+            },
+            cli::MigrateCommands::Lint { dialect } => {
+                commands::lint(C::embedded_migrations(), *dialect)?;
+
+                Ok(Outcome::Linted)
+            },
+            cli::MigrateCommands::Lock { path, check } => {
+                let migrations = C::embedded_migrations();
+                commands::lock(&migrations, path, *check)?;
+
+                Ok(Outcome::Locked)
+            },
+            cli::MigrateCommands::Extract { dir, force } => {
+                let migrations = C::embedded_migrations();
+                commands::extract(migrations, dir, *force)?;
+
+                Ok(Outcome::Extracted)
+            },
+        },
+    }
+}
+
+// The runner with the config of the context and the options of the command
+// line that every command shares.
+fn configure<C: MigrationContext>(
+    cli: &cli::Tern,
+    mut runner: Runner<C>,
+    config: Option<ConfigSource>,
+) -> Runner<C> {
+    if let Some(config) = config {
+        runner = runner.with_config(config);
+    }
+    if let Some(preset) = cli.global.preset {
+        runner = runner.with_preset(preset);
+    }
+    runner = runner.with_report_content(cli.report_content());
+    if let Some(opts) = cli.commands.validation_opts() {
+        runner = opts.apply(runner);
+    }
+
+    runner
+}
+
+/// What a command of the [`App`] did.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Outcome {
+    /// `migrate apply`, `apply-all`, `reapply`, or `retry-failed` applied the
+    /// migrations in the report, or with `--dryrun`, would have.
+    Applied(Report),
+    /// `migrate soft-apply` marked the migrations in the report as applied.
+    SoftApplied(Report),
+    /// `migrate baseline` marked the migrations in the report as applied, the
+    /// last one as the baseline.
+    Baselined(Report),
+    /// `migrate adopt` soft applied the migrations in the report, which are
+    /// the ones in the plan that appeared to be in effect.  The plan is
+    /// printed as text only without `--format`.
+    Adopted { plan: SoftApplyPlan, report: Report },
+    /// `migrate bench` timed the migrations in the report.
+    Benched(Report),
+    /// `migrate try` applied and rolled back the migrations in the report.
+    Tried(Report),
+    /// `migrate list-applied` listed the history in the report.
+    Listed(Report),
+    /// `migrate status` listed every migration as applied or pending in the
+    /// report.
+    Status(Report),
+    /// `migrate new` created the migration file at this path.
+    Created { path: PathBuf },
+    /// `migrate lint` found no problems.
+    Linted,
+    /// `migrate lock` wrote or checked the lockfile.
+    Locked,
+    /// `migrate extract` wrote the migrations out.
+    Extracted,
+    /// `history init` created the history table, or it already existed.
+    HistoryInitialized { created: bool },
+    /// `history drop` dropped the history table.
+    HistoryDropped,
+    /// `history drop --dryrun` described what dropping the table would lose.
+    HistoryDescribed(HistorySummary),
+    /// `history set-version` or `history forget` changed the history in the
+    /// report, or without `--yes`, would have.
+    HistoryEdited(Report),
+    /// `history dump` wrote the history out.
+    HistoryDumped,
+    /// `history replay` showed the history at a point in time in the report.
+    Replayed(Report),
+    /// `history quarantine` listed the migrations that failed.
+    Quarantined(Vec<QuarantinedMigration>),
+    /// `history verify` found no problems with the rows of the history
+    /// table.
+    HistoryVerified(HistoryVerification),
+    /// `schema dump` wrote the schema file, or with `--check`, found that it
+    /// is up to date.
+    SchemaDumped(Schema),
+    /// `check` found that the migrations match the history.
+    Checked,
+    /// The custom operation with this name ran.
+    Operation { name: String },
+    /// `info` printed the migrations in the binary.
+    Info,
+    /// `init` created a migrations crate in this directory.
+    Initialized { path: PathBuf },
+}
+
+impl Outcome {
+    /// The report of the command, if it has one.
+    pub fn report(&self) -> Option<&Report> {
+        match self {
+            Self::Applied(report)
+            | Self::SoftApplied(report)
+            | Self::Baselined(report)
+            | Self::Benched(report)
+            | Self::Tried(report)
+            | Self::Listed(report)
+            | Self::Status(report)
+            | Self::Replayed(report)
+            | Self::HistoryEdited(report)
+            | Self::Adopted { report, .. } => Some(report),
+            _ => None,
+        }
+    }
+
+    /// The code to exit the process with after the command, which is
+    /// [`EXIT_INTERRUPTED`] if a signal stopped it and `0` otherwise.
+    pub fn exit_code(&self) -> i32 {
+        match self.report() {
+            Some(report) if report.interrupted() => EXIT_INTERRUPTED,
+            _ => 0,
+        }
+    }
+
+    /// Take the report of the command, if it has one.
+    pub fn into_report(self) -> Option<Report> {
+        match self {
+            Self::Applied(report)
+            | Self::SoftApplied(report)
+            | Self::Baselined(report)
+            | Self::Benched(report)
+            | Self::Tried(report)
+            | Self::Listed(report)
+            | Self::Status(report)
+            | Self::Replayed(report)
+            | Self::HistoryEdited(report)
+            | Self::Adopted { report, .. } => Some(report),
+            _ => None,
+        }
+    }
+}
+
+// The operation with this name, if it is for the context `C`.
+fn operation<'a, C: MigrationContext>(
+    operations: &'a [RegisteredOperation],
+    name: &str,
+) -> anyhow::Result<&'a dyn Operation<C>> {
+    operations
+        .iter()
+        .find(|op| op.name == name)
+        .and_then(|op| op.operation.downcast_ref::<Box<dyn Operation<C>>>())
+        .map(AsRef::as_ref)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "the operation {name} is not for the context of this app"
+            )
+        })
+}
+
+// With `--format`, print the report in that format.  In JSON and JUnit, a run
+// that failed is printed too, with the failure in it.
+fn print_report(
+    format: Option<ReportFormat>,
+    res: anyhow::Result<Outcome>,
+) -> anyhow::Result<Outcome> {
+    match (format, res) {
+        (Some(format), Ok(outcome)) => {
+            if let Some(report) = outcome.report() {
+                println!("{}", report.render(format));
+            }
+            Ok(outcome)
+        },
+        (Some(ReportFormat::Json), Err(e)) => {
+            if let Some(error) = e.downcast_ref::<Error>() {
+                println!("{}", Report::from_error(error));
+            }
+            Err(e)
+        },
+        (Some(ReportFormat::Junit), Err(e)) => {
+            if let Some(error) = e.downcast_ref::<Error>() {
+                let report = match error {
+                    Error::Partial { report, .. } => report.clone(),
+                    _ => Report::default(),
+                };
+                println!("{}", report.to_junit_with_error(error));
+            }
+            Err(e)
+        },
+        (_, res) => res,
+    }
+}
+
+// With `--run-log`, append the outcome of the command to the run log.
+// Errors are printed by the caller, and one from connecting can have the
+// password of the connection string in it.
+fn redact_error(e: anyhow::Error) -> anyhow::Error {
+    anyhow::anyhow!(redact(&format!("{e:#}")))
+}
+
+fn log_run(cli: &cli::Tern, res: &anyhow::Result<Outcome>) {
+    if let Some(path) = &cli.global.run_log {
+        append_run(path, cli.target(), res);
+    }
+}
+
+// The record of a command is its report, or the error that stopped it.  A
+// command without a report, like `info`, isn't a run and isn't logged.  Not
+// being able to write the log doesn't change the outcome of the command.
+fn append_run(path: &Path, target: &str, res: &anyhow::Result<Outcome>) {
+    let record = match res {
+        Ok(outcome) => match outcome.report() {
+            Some(report) => RunRecord::new(target, report),
+            None => return,
+        },
+        Err(e) => {
+            let report = match e.downcast_ref::<Error>() {
+                Some(error) => Report::from_error(error),
+                None => Report::from_error(&Error::Execute(
+                    redact(&e.to_string()).into(),
+                )),
+            };
+            RunRecord::new(target, &report)
+        },
+    };
+    if let Err(e) = commands::log_run(path, &record) {
+        eprintln!("Could not append to the run log: {e:#}");
+    }
+}
+
+// The run that `SIGINT` or `SIGTERM` stops, if one is in progress, and
+// whether it is a dry run.  The handler can only be set once in a process, so
+// it is installed the first time and stops whichever run this is.
+static SIGNAL_TARGET: Mutex<Option<(StopHandle, bool)>> = Mutex::new(None);
+static SIGNAL_HANDLER: Once = Once::new();
+
+// Stop the run after the migration being applied on `SIGINT` or `SIGTERM`, so
+// that it is recorded and the lock is released, or exit at once if it is a dry
+// run, which changes nothing, if the run was already told to stop, or if there
+// is no run, until the guard is dropped.
+fn stop_on_signal<Ctx: MigrationContext>(
+    runner: &Runner<Ctx>,
+    dryrun: bool,
+) -> SignalGuard {
+    SIGNAL_HANDLER.call_once(|| {
+        let res = ctrlc::set_handler(|| {
+            let target = SIGNAL_TARGET.lock().unwrap_or_else(|e| e.into_inner());
+            match target.as_ref() {
+                Some((stop, false)) if !stop.is_stopped() => {
+                    eprintln!(
+                        "Stopping after the current migration, interrupt again to exit now"
+                    );
+                    stop.stop();
+                },
+                _ => std::process::exit(EXIT_INTERRUPTED),
+            }
+        });
+        if let Err(e) = res {
+            eprintln!("Could not install a signal handler: {e}");
         }
+    });
+    *SIGNAL_TARGET.lock().unwrap_or_else(|e| e.into_inner()) =
+        Some((runner.stop_handle(), dryrun));
+
+    SignalGuard
+}
+
+// Clears the run that a signal stops when the run is over.
+struct SignalGuard;
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        *SIGNAL_TARGET.lock().unwrap_or_else(|e| e.into_inner()) = None;
     }
 }
+
+// Print the report of a run that was stopped by a signal if `--format` didn't
+// already.
+fn print_interrupted(format: Option<ReportFormat>, outcome: &Outcome) {
+    if format.is_some() {
+        return;
+    }
+    if let Some(report) = outcome.report().filter(|r| r.interrupted()) {
+        println!("{report}");
+    }
+}
+
+// With `--continue-on-error`, fail the command if migrations in its report
+// failed, printing the report first if `--format` didn't already, since the
+// caller won't get it.
+fn fail_on_errors(
+    continue_on_error: bool,
+    format: Option<ReportFormat>,
+    outcome: &Outcome,
+) -> anyhow::Result<()> {
+    let Some(report) =
+        outcome.report().filter(|r| continue_on_error && r.failures() > 0)
+    else {
+        return Ok(());
+    };
+    if format.is_none() {
+        println!("{report}");
+    }
+
+    Err(anyhow::anyhow!(
+        "{} migrations failed, see the report for their errors",
+        report.failures()
+    ))
+}
+
+// The version for a `--target-version`, which can also be a migration name or
+// relative to the latest applied, like `+2`.
+async fn target<Ctx: MigrationContext>(
+    runner: &mut Runner<Ctx>,
+    target: &Option<String>,
+) -> anyhow::Result<Option<Version>> {
+    let Some(target) = target.as_deref() else {
+        return Ok(None);
+    };
+    let version = runner.resolve_relative_target(target).await?;
+
+    Ok(Some(version))
+}
+
+// Parse a version argument as the kind of version the context uses.
+fn version<Ctx: MigrationContext>(
+    version: &Option<String>,
+) -> anyhow::Result<Option<Version>> {
+    let version = version
+        .as_deref()
+        .map(|v| Version::parse(v, Ctx::VERSION_KIND))
+        .transpose()?;
+
+    Ok(version)
+}
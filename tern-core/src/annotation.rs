@@ -0,0 +1,243 @@
+//! Annotations in the source of a migration.
+//!
+//! Besides `tern:noTransaction`, a migration can have options for applying it
+//! as comments of the form `tern:<key>=<value>`, in either a SQL or a Rust
+//! migration:
+//!
+//! ```sql
+//! -- tern:lockTimeout=5s
+//! -- tern:statementTimeout=10min
+//! -- tern:role=owner_role
+//! -- tern:retries=3
+//! ALTER TABLE users ADD COLUMN age int;
+//! ```
+//!
+//! An annotation without a value, like `-- tern:redact`, is a flag.  A value
+//! can be a list separated by commas, like `-- tern:dependsOn=3,5`.
+use crate::error::{Error, TernResult};
+use crate::lint::regex;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The `tern:<key>=<value>` annotations of a migration, and the `tern:<key>`
+/// flags, which have an empty value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotations(HashMap<String, String>);
+
+impl Annotations {
+    /// Find the annotations in the comments of the source of a migration.
+    pub fn parse(content: &str) -> Self {
+        let annotations = content
+            .lines()
+            .map(str::trim_start)
+            .filter(|l| l.starts_with("--") || l.starts_with("//"))
+            .flat_map(|l| annotation_re().captures_iter(l))
+            .map(|caps| {
+                let value = caps.get(2).map_or("", |v| v.as_str());
+                (caps[1].to_string(), value.to_string())
+            })
+            .collect();
+
+        Self(annotations)
+    }
+
+    /// The value of the annotation `key`, if the migration has it with a
+    /// value.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str).filter(|v| !v.is_empty())
+    }
+
+    /// Whether the migration has the annotation `key`, with or without a
+    /// value.
+    pub fn has(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// The `redact` flag, for a migration whose content is not to be kept in
+    /// the history table or shown in reports, because it has secrets or
+    /// personal data in it.
+    pub fn redact(&self) -> bool {
+        self.has("redact")
+    }
+
+    /// The `allowFailure` flag, for a migration that is not critical, like an
+    /// `ANALYZE`, and whose failure is recorded instead of stopping the run.
+    ///
+    /// The failure is recorded as a row in the history table, so the migration
+    /// counts as applied and later runs don't try it again.  Only `reapply`
+    /// does, which replaces the row.
+    pub fn allow_failure(&self) -> bool {
+        self.has("allowFailure")
+    }
+
+    /// The `resumable` flag, for a migration that is not applied in a
+    /// transaction, whose statements are recorded as they complete so that a
+    /// run after one of them failed starts again from that statement.
+    pub fn resumable(&self) -> bool {
+        self.has("resumable")
+    }
+
+    /// The `splitStatements` flag, for a migration that is applied in a
+    /// transaction one statement at a time instead of all at once, so that
+    /// its progress can be followed.
+    pub fn split_statements(&self) -> bool {
+        self.has("splitStatements")
+    }
+
+    /// The `analyze` flag, for a migration after which the statistics of the
+    /// tables it changes are refreshed, like after a big backfill.
+    pub fn analyze(&self) -> bool {
+        self.has("analyze")
+    }
+
+    /// The `dependsOn` annotation, the versions of the migrations that this
+    /// one needs, separated by commas, like `tern:dependsOn=3,5`.
+    pub fn depends_on(&self) -> Vec<&str> {
+        self.get("dependsOn")
+            .map(|v| v.split(',').filter(|v| !v.is_empty()).collect())
+            .unwrap_or_default()
+    }
+
+    /// The `lockTimeout` annotation, the longest to wait for a lock.
+    pub fn lock_timeout(&self) -> TernResult<Option<Duration>> {
+        self.duration("lockTimeout")
+    }
+
+    /// The `statementTimeout` annotation, the longest a statement can run.
+    pub fn statement_timeout(&self) -> TernResult<Option<Duration>> {
+        self.duration("statementTimeout")
+    }
+
+    /// The `retries` annotation, how many more times to try to apply the
+    /// migration when it fails with a transient error, like a deadlock, which
+    /// is 0 without it.
+    pub fn retries(&self) -> TernResult<u32> {
+        self.retry_count("retries")
+    }
+
+    /// The `concurrentIndexRetries` annotation, how many more times to try to
+    /// apply the migration on Postgres when it fails and leaves an index that
+    /// it builds with `CREATE INDEX CONCURRENTLY` invalid, after dropping the
+    /// index, which is 0 without it.
+    pub fn concurrent_index_retries(&self) -> TernResult<u32> {
+        self.retry_count("concurrentIndexRetries")
+    }
+
+    /// The `role` annotation, the role to apply the migration as.
+    pub fn role(&self) -> Option<&str> {
+        self.get("role")
+    }
+
+    fn retry_count(&self, key: &str) -> TernResult<u32> {
+        let retries = self
+            .get(key)
+            .map(|v| {
+                v.parse().map_err(|_| {
+                    Error::Invalid(format!(
+                        "invalid number of retries {v} for tern:{key}, expected a non-negative integer"
+                    ))
+                })
+            })
+            .transpose()?;
+
+        Ok(retries.unwrap_or_default())
+    }
+
+    fn duration(&self, key: &str) -> TernResult<Option<Duration>> {
+        self.get(key)
+            .map(|v| {
+                parse_duration(v).ok_or_else(|| {
+                    Error::Invalid(format!(
+                        "invalid duration {v} for tern:{key}, expected a number with a unit of ms, s, min, or h"
+                    ))
+                })
+            })
+            .transpose()
+    }
+}
+
+// A duration in the format of a Postgres setting, which is a number with a
+// unit, or milliseconds when there is no unit.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let split =
+        value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (n, unit) = value.split_at(split);
+    let n = n.parse::<u64>().ok()?;
+    let ms = match unit.trim() {
+        "" | "ms" => n,
+        "s" => n * 1000,
+        "min" => n * 60 * 1000,
+        "h" => n * 60 * 60 * 1000,
+        _ => return None,
+    };
+    Some(Duration::from_millis(ms))
+}
+
+fn annotation_re() -> &'static Regex {
+    regex(&ANNOTATION_RE, r"tern:(\w+)(?:=(\S+))?")
+}
+
+static ANNOTATION_RE: OnceLock<Regex> = OnceLock::new();
+
+#[cfg(test)]
+mod tests {
+    use super::Annotations;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_annotations() {
+        let sql = "-- tern:noTransaction\n-- tern:lockTimeout=5s tern:statementTimeout=250\nSELECT 'tern:x=y';";
+        let annotations = Annotations::parse(sql);
+        assert_eq!(
+            annotations.lock_timeout().unwrap(),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            annotations.statement_timeout().unwrap(),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(annotations.role(), None);
+        assert_eq!(annotations.get("x"), None);
+        assert!(!annotations.redact());
+        assert!(Annotations::parse("SELECT 1;\n-- tern:redact\n").redact());
+        assert_eq!(annotations.retries().unwrap(), 0);
+        assert_eq!(
+            Annotations::parse("-- tern:retries=3").retries().unwrap(),
+            3
+        );
+        assert!(Annotations::parse("-- tern:retries=-1").retries().is_err());
+        assert_eq!(
+            Annotations::parse("-- tern:concurrentIndexRetries=2")
+                .concurrent_index_retries()
+                .unwrap(),
+            2
+        );
+        assert!(!annotations.resumable());
+        assert!(
+            Annotations::parse("-- tern:noTransaction tern:resumable")
+                .resumable()
+        );
+        assert!(
+            Annotations::parse("-- tern:allowFailure\nANALYZE;")
+                .allow_failure()
+        );
+        assert!(!annotations.split_statements());
+        assert!(
+            Annotations::parse("-- tern:splitStatements").split_statements()
+        );
+        assert!(!annotations.analyze());
+        assert!(Annotations::parse("-- tern:analyze").analyze());
+        assert_eq!(
+            Annotations::parse("-- tern:dependsOn=3,5").depends_on(),
+            ["3", "5"]
+        );
+        assert!(
+            Annotations::parse("// tern:lockTimeout=soon")
+                .lock_timeout()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! Configuration values available to a migration context.
+//!
+//! A lot of what a Rust migration needs from its context when building the
+//! query is a value from the environment or a config file.  A [`ConfigSource`]
+//! provides exactly that, so a context doesn't need a hand-written type for
+//! it.  Every [`MigrationContext`] has one: by default it reads environment
+//! variables, and the derive macro can be pointed at a field holding a
+//! different one with `#[tern(config)]`.
+//!
+//! [`MigrationContext`]: crate::migration::MigrationContext
+use crate::error::{DatabaseError as _, Error, TernResult};
+
+use serde::Serialize;
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+/// The key whose value, if a context's config has one, is used as the name of
+/// the history table instead of the one the context declares.
+pub const HISTORY_TABLE_KEY: &str = "TERN_HISTORY_TABLE";
+
+/// The key whose value, if a context's config has one, is used as the SQL that
+/// sets up each database session instead of the one the context declares.
+pub const SESSION_SETUP_KEY: &str = "TERN_SESSION_SETUP";
+
+/// The key whose value, if a context's config has one, is used as the Postgres
+/// `search_path` of the run instead of the one the context declares.
+pub const SEARCH_PATH_KEY: &str = "TERN_SEARCH_PATH";
+
+/// The key whose value, if a context's config has one, is used as the SQL that
+/// runs after a successful run instead of the one the context declares.
+pub const POST_APPLY_KEY: &str = "TERN_POST_APPLY";
+
+/// The key whose value, if a context's config has one, is the list of
+/// [`Flags`] for the run, like `skip_expensive_indexes,batch_size=500`.
+pub const FLAGS_KEY: &str = "TERN_FLAGS";
+
+/// A lookup of configuration values by key.
+///
+/// Sources can be layered with [`or`](Self::or), in which case a key is looked
+/// up in each of them in order and the first value found is used.
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    layers: Vec<Layer>,
+}
+
+#[derive(Debug, Clone)]
+enum Layer {
+    Env { prefix: Option<String> },
+    Map(HashMap<String, String>),
+}
+
+impl ConfigSource {
+    /// Values come from environment variables.
+    pub fn env() -> Self {
+        Self { layers: vec![Layer::Env { prefix: None }] }
+    }
+
+    /// Values come from environment variables, where the key `KEY` is looked
+    /// up as the variable `{prefix}KEY`.
+    pub fn env_with_prefix(prefix: impl Into<String>) -> Self {
+        Self { layers: vec![Layer::Env { prefix: Some(prefix.into()) }] }
+    }
+
+    /// Values come from the collection of key/value pairs.
+    pub fn from_map<K, V, I>(values: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let map =
+            values.into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+        Self { layers: vec![Layer::Map(map)] }
+    }
+
+    /// Values come from a file of `KEY=VALUE` lines in the format of a `.env`
+    /// file.
+    ///
+    /// The file is read once, here, and the environment is not modified.
+    pub fn from_file(path: impl AsRef<Path>) -> TernResult<Self> {
+        let values = dotenvy::from_path_iter(path.as_ref())
+            .tern_result()?
+            .collect::<Result<Vec<_>, _>>()
+            .tern_result()?;
+
+        Ok(Self::from_map(values))
+    }
+
+    /// Fall back to `other` for keys that this source does not have.
+    pub fn or(mut self, other: ConfigSource) -> Self {
+        self.layers.extend(other.layers);
+        self
+    }
+
+    /// Get the value for `key`.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.layers.iter().find_map(|layer| match layer {
+            Layer::Env { prefix: None } => std::env::var(key).ok(),
+            Layer::Env { prefix: Some(p) } => {
+                std::env::var(format!("{p}{key}")).ok()
+            },
+            Layer::Map(map) => map.get(key).cloned(),
+        })
+    }
+
+    /// Get the value for `key`, which is an error if it is not found.
+    ///
+    /// The error is the one that a `QueryBuilder` would return when it could
+    /// not resolve a query, so this can be used with `?` when building one.
+    pub fn require(&self, key: &str) -> TernResult<String> {
+        self.get(key).ok_or_else(|| {
+            Error::ResolveQuery(format!("missing configuration value `{key}`"))
+        })
+    }
+
+    /// Get the value for `key` parsed as a `T`.
+    pub fn get_parsed<T>(&self, key: &str) -> TernResult<Option<T>>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.get(key)
+            .map(|v| {
+                v.parse::<T>().map_err(|e| {
+                    Error::ResolveQuery(format!(
+                        "invalid configuration value for `{key}`: {e}"
+                    ))
+                })
+            })
+            .transpose()
+    }
+}
+
+impl Default for ConfigSource {
+    fn default() -> Self {
+        Self::env()
+    }
+}
+
+/// Feature flags for a run, which a Rust migration can check when it builds
+/// its query to build a different one, e.g., to skip an index that takes too
+/// long to create on a development database:
+///
+/// ```rust,ignore
+/// let flags = ctx.flags();
+/// let mut sql = String::from("CREATE TABLE events(id bigint, at timestamptz);");
+/// if !flags.enabled("skip_expensive_indexes") {
+///     sql.push_str("CREATE INDEX events_at ON events (at);");
+/// }
+/// ```
+///
+/// A flag is a name, which is enabled unless its value is `false`, `0`,
+/// `off`, or `no`, or a name with a value, written as a list separated by
+/// commas like `skip_expensive_indexes,batch_size=500`.  A context has the
+/// ones in its config under [`FLAGS_KEY`], see
+/// [`MigrationContext::flags`], and from the CLI, they are given with
+/// `--flag` or in a profile of the config file.
+///
+/// [`MigrationContext::flags`]: crate::migration::MigrationContext::flags
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Flags(BTreeMap<String, String>);
+
+impl Flags {
+    /// The flags in a context's config.
+    pub fn from_config(config: &ConfigSource) -> Self {
+        config.get(FLAGS_KEY).map(|v| Self::parse(&v)).unwrap_or_default()
+    }
+
+    /// The flags in a list like `skip_expensive_indexes,batch_size=500`.
+    pub fn parse(list: &str) -> Self {
+        let flags = list
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(|f| match f.split_once('=') {
+                Some((name, value)) => {
+                    (name.trim().to_string(), value.trim().to_string())
+                },
+                None => (f.to_string(), String::new()),
+            })
+            .collect();
+
+        Self(flags)
+    }
+
+    /// Set the flag `name` to `value`, which can be empty for a flag that is
+    /// only a name.
+    pub fn with(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.0.insert(name.into(), value.into());
+        self
+    }
+
+    /// Add the flags in `other`, which take the place of ones here with the
+    /// same name.
+    pub fn extend(&mut self, other: Flags) {
+        self.0.extend(other.0);
+    }
+
+    /// Whether the flag `name` is given and is not `false`, `0`, `off`, or
+    /// `no`.
+    pub fn enabled(&self, name: &str) -> bool {
+        self.0.get(name).is_some_and(|v| {
+            !matches!(
+                v.to_ascii_lowercase().as_str(),
+                "false" | "0" | "off" | "no"
+            )
+        })
+    }
+
+    /// The value of the flag `name`, if it has one.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str).filter(|v| !v.is_empty())
+    }
+
+    /// The value of the flag `name` parsed as a `T`.
+    pub fn get_parsed<T>(&self, name: &str) -> TernResult<Option<T>>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.get(name)
+            .map(|v| {
+                v.parse::<T>().map_err(|e| {
+                    Error::ResolveQuery(format!(
+                        "invalid value for the flag `{name}`: {e}"
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Whether there are no flags.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self
+            .0
+            .iter()
+            .map(|(name, value)| match value.as_str() {
+                "" => name.clone(),
+                value => format!("{name}={value}"),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", flags.join(","))
+    }
+}
+
+/// The source that a context without its own configuration uses.
+pub(crate) fn default_config() -> &'static ConfigSource {
+    static CONFIG: OnceLock<ConfigSource> = OnceLock::new();
+    CONFIG.get_or_init(ConfigSource::env)
+}
+
+// The sources that runners were given for the contexts of a type, see
+// `Runner::with_config`.  A context's `config()` borrows it, so it lives as
+// long as the process.
+fn context_configs() -> &'static RwLock<HashMap<TypeId, &'static ConfigSource>>
+{
+    static CONFIGS: OnceLock<RwLock<HashMap<TypeId, &'static ConfigSource>>> =
+        OnceLock::new();
+    CONFIGS.get_or_init(Default::default)
+}
+
+/// Have every context of type `C` use `config` in place of its own.
+pub(crate) fn set_context_config<C: ?Sized + 'static>(config: ConfigSource) {
+    let config: &'static ConfigSource = Box::leak(Box::new(config));
+    context_configs()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(TypeId::of::<C>(), config);
+}
+
+/// The source that a runner was given for the contexts of type `C`, if there
+/// is one.
+#[doc(hidden)]
+pub fn context_config<C: ?Sized + 'static>() -> Option<&'static ConfigSource> {
+    context_configs()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&TypeId::of::<C>())
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigSource, Flags};
+
+    #[test]
+    fn layered_lookup() {
+        let config = ConfigSource::from_map([("a", "1"), ("b", "2")])
+            .or(ConfigSource::from_map([("b", "3"), ("c", "4")]));
+        assert_eq!(config.get("a").as_deref(), Some("1"));
+        assert_eq!(config.get("b").as_deref(), Some("2"));
+        assert_eq!(config.get("c").as_deref(), Some("4"));
+        assert_eq!(config.get("d"), None);
+        assert!(config.require("d").is_err());
+    }
+
+    #[test]
+    fn parsed_values() {
+        let config = ConfigSource::from_map([("n", "10"), ("x", "ten")]);
+        assert_eq!(config.get_parsed::<i64>("n").unwrap(), Some(10));
+        assert_eq!(config.get_parsed::<i64>("m").unwrap(), None);
+        assert!(config.get_parsed::<i64>("x").is_err());
+    }
+
+    #[test]
+    fn flags() {
+        let config = ConfigSource::from_map([(
+            super::FLAGS_KEY,
+            "skip_indexes, batch_size=500,backfill=off",
+        )]);
+        let flags = Flags::from_config(&config);
+        assert!(flags.enabled("skip_indexes"));
+        assert!(flags.enabled("batch_size"));
+        assert!(!flags.enabled("backfill"));
+        assert!(!flags.enabled("missing"));
+        assert_eq!(flags.get("skip_indexes"), None);
+        assert_eq!(flags.get_parsed::<u32>("batch_size").unwrap(), Some(500));
+        assert!(flags.get_parsed::<u32>("backfill").is_err());
+        assert_eq!(
+            flags.to_string(),
+            "backfill=off,batch_size=500,skip_indexes"
+        );
+        assert!(
+            Flags::from_config(&ConfigSource::from_map([("a", "1")]))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Credentials from AWS.
+//!
+//! These use the `aws` command line tool, so they have the same configuration
+//! and credential chain as that does, and it needs to be installed.
+use super::{CredentialProvider, Credentials};
+use crate::error::{DatabaseError as _, Error, TernResult};
+
+use futures_core::Future;
+
+/// Reads database credentials from a secret in AWS Secrets Manager.
+///
+/// The secret string should be a JSON object with the keys "username" and
+/// "password", which is the format of secrets that RDS manages.
+#[cfg(feature = "aws-secrets")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aws-secrets")))]
+#[derive(Debug, Clone)]
+pub struct AwsSecretProvider {
+    secret_id: String,
+    region: Option<String>,
+    profile: Option<String>,
+}
+
+#[cfg(feature = "aws-secrets")]
+impl AwsSecretProvider {
+    /// Read the secret with this name or ARN.
+    pub fn new(secret_id: impl Into<String>) -> Self {
+        Self { secret_id: secret_id.into(), region: None, profile: None }
+    }
+
+    /// The region of the secret, if not the one configured by default.
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// The named profile to use, if not the one configured by default.
+    pub fn with_profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    async fn fetch_secret(&self) -> TernResult<Credentials> {
+        let mut cmd = aws(&self.region, &self.profile);
+        cmd.args(["secretsmanager", "get-secret-value", "--secret-id"])
+            .arg(&self.secret_id)
+            .args(["--query", "SecretString", "--output", "text"]);
+        let secret =
+            output(cmd, &format!("get secret {}", self.secret_id)).await?;
+
+        parse_secret(&secret)
+    }
+}
+
+#[cfg(feature = "aws-secrets")]
+impl CredentialProvider for AwsSecretProvider {
+    fn fetch(&self) -> impl Future<Output = TernResult<Credentials>> + Send {
+        self.fetch_secret()
+    }
+}
+
+/// Generates IAM authentication tokens for an RDS or Aurora database.
+///
+/// A token is used as the password of a database user that has been granted
+/// the `rds_iam` role (Postgres) or uses the `AWSAuthenticationPlugin`
+/// (MySQL), and it is valid for 15 minutes.  RDS requires TLS for connections
+/// authenticated this way.
+#[cfg(feature = "aws-iam")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aws-iam")))]
+#[derive(Debug, Clone)]
+pub struct RdsIamProvider {
+    hostname: String,
+    port: u16,
+    username: String,
+    region: Option<String>,
+    profile: Option<String>,
+}
+
+#[cfg(feature = "aws-iam")]
+impl RdsIamProvider {
+    /// How long a token is valid for.
+    pub const TOKEN_LIFETIME: chrono::Duration = chrono::Duration::minutes(15);
+
+    /// Generate tokens for `username` on the database at this endpoint.
+    pub fn new(
+        hostname: impl Into<String>,
+        port: u16,
+        username: impl Into<String>,
+    ) -> Self {
+        Self {
+            hostname: hostname.into(),
+            port,
+            username: username.into(),
+            region: None,
+            profile: None,
+        }
+    }
+
+    /// The region of the database, if not the one configured by default.
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// The named profile to use, if not the one configured by default.
+    pub fn with_profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    async fn generate_token(&self) -> TernResult<Credentials> {
+        let mut cmd = aws(&self.region, &self.profile);
+        cmd.args(["rds", "generate-db-auth-token", "--hostname"])
+            .arg(&self.hostname)
+            .arg("--port")
+            .arg(self.port.to_string())
+            .arg("--username")
+            .arg(&self.username);
+        let issued_at = chrono::Utc::now();
+        let token = output(cmd, "generate RDS auth token").await?;
+
+        Ok(Credentials::new(Some(self.username.clone()), token)
+            .expires_at(issued_at + Self::TOKEN_LIFETIME))
+    }
+}
+
+#[cfg(feature = "aws-iam")]
+impl CredentialProvider for RdsIamProvider {
+    fn fetch(&self) -> impl Future<Output = TernResult<Credentials>> + Send {
+        self.generate_token()
+    }
+}
+
+fn aws(
+    region: &Option<String>,
+    profile: &Option<String>,
+) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("aws");
+    if let Some(region) = region {
+        cmd.arg("--region").arg(region);
+    }
+    if let Some(profile) = profile {
+        cmd.arg("--profile").arg(profile);
+    }
+    cmd
+}
+
+// Run the command and return what it wrote to stdout, trimmed.
+async fn output(
+    mut cmd: tokio::process::Command,
+    action: &str,
+) -> TernResult<String> {
+    let output = cmd.output().await.tern_result()?;
+    if !output.status.success() {
+        return Err(Error::Invalid(format!(
+            "could not {action}: {}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(feature = "aws-secrets")]
+fn parse_secret(secret: &str) -> TernResult<Credentials> {
+    let value: serde_json::Value =
+        serde_json::from_str(secret.trim()).tern_result()?;
+    let field = |key: &str| {
+        value[key].as_str().map(String::from).ok_or_else(|| {
+            Error::Invalid(format!("secret has no string field {key}"))
+        })
+    };
+
+    Ok(Credentials::new(Some(field("username")?), field("password")?))
+}
+
+#[cfg(all(test, feature = "aws-secrets"))]
+mod tests {
+    use super::parse_secret;
+
+    #[test]
+    fn parse() {
+        let creds = parse_secret(
+            r#"{"username":"app","password":"pw","engine":"postgres"}"#,
+        )
+        .unwrap();
+        assert_eq!(creds.username.as_deref(), Some("app"));
+        assert_eq!(creds.password, "pw");
+        assert!(parse_secret(r#"{"username":"app"}"#).is_err());
+        assert!(parse_secret("not json").is_err());
+    }
+}
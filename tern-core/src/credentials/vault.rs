@@ -0,0 +1,233 @@
+//! Credentials from HashiCorp Vault.
+use super::{CredentialProvider, Credentials};
+use crate::error::{DatabaseError as _, Error, TernResult};
+
+use futures_core::Future;
+use serde_json::Value;
+
+/// Reads database credentials from Vault over its HTTP API.
+///
+/// Two kinds of secrets are supported: a static username and password stored
+/// in the KV version 2 secrets engine, and dynamic credentials generated by the
+/// database secrets engine.  Dynamic credentials expire with their lease.
+#[derive(Clone)]
+pub struct VaultProvider {
+    client: reqwest::Client,
+    addr: String,
+    token: String,
+    secret: VaultSecret,
+}
+
+impl std::fmt::Debug for VaultProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VaultProvider")
+            .field("client", &self.client)
+            .field("addr", &self.addr)
+            .field("token", &"***")
+            .field("secret", &self.secret)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum VaultSecret {
+    Kv {
+        mount: String,
+        path: String,
+        username_key: String,
+        password_key: String,
+    },
+    Database {
+        mount: String,
+        role: String,
+    },
+}
+
+impl VaultProvider {
+    /// Read the secret at `path` in the KV version 2 engine mounted at `mount`.
+    ///
+    /// The secret is expected to have the keys "username" and "password",
+    /// which can be changed with [`with_keys`](Self::with_keys).
+    pub fn kv(
+        addr: impl Into<String>,
+        token: impl Into<String>,
+        mount: impl Into<String>,
+        path: impl Into<String>,
+    ) -> Self {
+        let secret = VaultSecret::Kv {
+            mount: mount.into(),
+            path: path.into(),
+            username_key: "username".into(),
+            password_key: "password".into(),
+        };
+        Self::new(addr.into(), token.into(), secret)
+    }
+
+    /// Generate credentials for `role` from the database engine mounted at
+    /// `mount`.
+    pub fn database(
+        addr: impl Into<String>,
+        token: impl Into<String>,
+        mount: impl Into<String>,
+        role: impl Into<String>,
+    ) -> Self {
+        let secret =
+            VaultSecret::Database { mount: mount.into(), role: role.into() };
+        Self::new(addr.into(), token.into(), secret)
+    }
+
+    /// Like [`kv`](Self::kv), with the address and token of the Vault server
+    /// from the environment variables `VAULT_ADDR` and `VAULT_TOKEN`.
+    pub fn kv_from_env(
+        mount: impl Into<String>,
+        path: impl Into<String>,
+    ) -> TernResult<Self> {
+        let (addr, token) = Self::env()?;
+        Ok(Self::kv(addr, token, mount, path))
+    }
+
+    /// Like [`database`](Self::database), with the address and token of the
+    /// Vault server from the environment variables `VAULT_ADDR` and
+    /// `VAULT_TOKEN`.
+    pub fn database_from_env(
+        mount: impl Into<String>,
+        role: impl Into<String>,
+    ) -> TernResult<Self> {
+        let (addr, token) = Self::env()?;
+        Ok(Self::database(addr, token, mount, role))
+    }
+
+    /// Use these keys of a KV secret for the username and password.
+    pub fn with_keys(
+        mut self,
+        username_key: impl Into<String>,
+        password_key: impl Into<String>,
+    ) -> Self {
+        if let VaultSecret::Kv { username_key: u, password_key: p, .. } =
+            &mut self.secret
+        {
+            *u = username_key.into();
+            *p = password_key.into();
+        }
+        self
+    }
+
+    fn new(addr: String, token: String, secret: VaultSecret) -> Self {
+        let addr = addr.trim_end_matches('/').to_string();
+        Self { client: reqwest::Client::new(), addr, token, secret }
+    }
+
+    fn env() -> TernResult<(String, String)> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| {
+                Error::Invalid(format!("environment variable {name} not set"))
+            })
+        };
+        Ok((var("VAULT_ADDR")?, var("VAULT_TOKEN")?))
+    }
+
+    fn url(&self) -> String {
+        match &self.secret {
+            VaultSecret::Kv { mount, path, .. } => {
+                format!("{}/v1/{mount}/data/{path}", self.addr)
+            },
+            VaultSecret::Database { mount, role } => {
+                format!("{}/v1/{mount}/creds/{role}", self.addr)
+            },
+        }
+    }
+
+    async fn fetch_secret(&self) -> TernResult<Credentials> {
+        let resp = self
+            .client
+            .get(self.url())
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await
+            .tern_result()?
+            .error_for_status()
+            .tern_result()?
+            .json::<Value>()
+            .await
+            .tern_result()?;
+
+        self.parse(&resp)
+    }
+
+    fn parse(&self, resp: &Value) -> TernResult<Credentials> {
+        let (data, username_key, password_key) = match &self.secret {
+            VaultSecret::Kv { username_key, password_key, .. } => (
+                &resp["data"]["data"],
+                username_key.as_str(),
+                password_key.as_str(),
+            ),
+            VaultSecret::Database { .. } => {
+                (&resp["data"], "username", "password")
+            },
+        };
+        let field = |key: &str| {
+            data[key].as_str().map(String::from).ok_or_else(|| {
+                Error::Invalid(format!(
+                    "vault secret has no string field {key}"
+                ))
+            })
+        };
+        let credentials =
+            Credentials::new(Some(field(username_key)?), field(password_key)?);
+
+        // Dynamic credentials last as long as the lease does.
+        match resp["lease_duration"].as_i64() {
+            Some(secs) if secs > 0 => Ok(credentials.expires_at(
+                chrono::Utc::now() + chrono::Duration::seconds(secs),
+            )),
+            _ => Ok(credentials),
+        }
+    }
+}
+
+impl CredentialProvider for VaultProvider {
+    fn fetch(&self) -> impl Future<Output = TernResult<Credentials>> + Send {
+        self.fetch_secret()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VaultProvider;
+
+    #[test]
+    fn parse_responses() {
+        let kv = VaultProvider::kv("http://vault:8200/", "t", "secret", "db")
+            .with_keys("user", "pass");
+        assert_eq!(kv.url(), "http://vault:8200/v1/secret/data/db");
+        let resp = serde_json::json!({
+            "lease_duration": 0,
+            "data": { "data": { "user": "app", "pass": "pw" }, "metadata": {} }
+        });
+        let creds = kv.parse(&resp).unwrap();
+        assert_eq!(creds.username.as_deref(), Some("app"));
+        assert_eq!(creds.password, "pw");
+        assert!(creds.expires_at.is_none());
+
+        let db =
+            VaultProvider::database("http://vault:8200", "t", "database", "ro");
+        assert_eq!(db.url(), "http://vault:8200/v1/database/creds/ro");
+        let resp = serde_json::json!({
+            "lease_duration": 3600,
+            "data": { "username": "v-ro-123", "password": "pw" }
+        });
+        let creds = db.parse(&resp).unwrap();
+        assert_eq!(creds.username.as_deref(), Some("v-ro-123"));
+        assert!(creds.expires_at.is_some());
+        assert!(db.parse(&serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn debug_hides_token() {
+        let provider =
+            VaultProvider::kv("http://vault:8200", "s.secret", "secret", "db");
+        let debug = format!("{provider:?}");
+        assert!(!debug.contains("s.secret"));
+        assert!(debug.contains(r#"token: "***""#));
+    }
+}
@@ -1,5 +1,7 @@
 //! Error type for migration operations.
+use crate::group::GroupReport;
 use crate::migration::{Migration, MigrationId};
+use crate::redact::redact;
 use crate::runner::{MigrationResult, Report};
 
 use std::error::Error as StdError;
@@ -13,12 +15,22 @@ type BoxDynError = Box<dyn StdError + Send + Sync + 'static>;
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum Error {
+    /// The database connection could not be established.
+    ///
+    /// The message has had credentials removed from it, see
+    /// [`redact`](crate::redact::redact).
+    /// `code` is the error code from the database, if it gave one.
+    #[error("could not connect to the database: {msg}")]
+    Init { msg: String, code: Option<String> },
     /// An error that came from applying migrations.
     #[error("error applying migrations {0}")]
     Execute(#[source] BoxDynError),
     /// Error from one migration.
     #[error("error applying migration: {{name: {1}, no_tx: {2}}}: {0}")]
     ExecuteMigration(#[source] BoxDynError, MigrationId, bool),
+    /// Error from one repeatable migration, with its filename.
+    #[error("error applying repeatable migration {1}: {0}")]
+    ExecuteRepeatable(#[source] BoxDynError, String),
     /// An error resolving the query before applying.
     /// Can be used as a fallthrough to map arbitrary error types to when
     /// implementing `QueryBuilder`.
@@ -39,12 +51,34 @@ pub enum Error {
     /// is expected.
     #[error("inconsistent source: {msg}: {at_issue:?}")]
     OutOfSync { at_issue: Vec<MigrationId>, msg: String },
+    /// The database schema is not up to date with the migration source.
+    ///
+    /// `pending` are migrations in the source that have not been applied and
+    /// `out_of_sync` are applied migrations that are not in the source.
+    #[error(
+        "database is not up to date: pending {pending:?}, out of sync {out_of_sync:?}"
+    )]
+    NotUpToDate { pending: Vec<MigrationId>, out_of_sync: Vec<MigrationId> },
+    /// A [`ValidationRule`](crate::validate::ValidationRule) did not pass.
+    #[error("validation rule {rule} failed: {msg}: {at_issue:?}")]
+    Validation { rule: String, at_issue: Vec<MigrationId>, msg: String },
     /// The options passed are not valid.
     #[error("invalid parameter for the operation requested: {0}")]
     Invalid(String),
+    /// A [`UrlGuard`](crate::guard::UrlGuard) refused to run the operation
+    /// against the host of the connection string.
+    #[error("refusing to run {operation} against {host}: {reason}")]
+    Refused { operation: String, host: String, reason: String },
     /// An error occurred, resulting in a partial migration run.
     #[error("migration could not complete: {source}, partial report: {report}")]
     Partial { source: BoxDynError, report: Report },
+    /// A context in a [`TernGroup`](crate::group::TernGroup) failed.
+    ///
+    /// `report` has the reports of the contexts that finished before it.
+    #[error(
+        "error in context {context}: {source}, reports before it: {report}"
+    )]
+    Group { context: String, source: Box<Error>, report: GroupReport },
 }
 
 impl Error {
@@ -55,11 +89,235 @@ impl Error {
         Self::ResolveQuery(e.to_string())
     }
 
+    /// An [`Error::Init`] from an error connecting, with any credentials in its
+    /// message or the messages of its sources redacted.
+    pub fn init<E>(e: E) -> Self
+    where
+        E: StdError + 'static,
+    {
+        let code = database_code(&e);
+        let mut msg = e.to_string();
+        let mut source = e.source();
+        while let Some(e) = source {
+            // Plenty of errors already include their source in the message.
+            let source_msg = e.to_string();
+            if !msg.contains(&source_msg) {
+                msg.push_str(": ");
+                msg.push_str(&source_msg);
+            }
+            source = e.source();
+        }
+        Self::Init { msg: redact(&msg), code }
+    }
+
+    /// Whether this is the database refusing the credentials it was given.
+    ///
+    /// This recognizes errors from `sqlx` having the SQLSTATE class "28"
+    /// (invalid authorization specification).
+    pub fn is_auth_failure(&self) -> bool {
+        let code = match self {
+            Self::Init { code, .. } => code.clone(),
+            Self::Execute(e)
+            | Self::ExecuteMigration(e, ..)
+            | Self::ExecuteRepeatable(e, _) => database_code(e.as_ref()),
+            Self::Partial { source, .. } => database_code(source.as_ref()),
+            Self::Group { source, .. } => return source.is_auth_failure(),
+            _ => None,
+        };
+        code.is_some_and(|code| code.starts_with("28"))
+    }
+
+    /// Whether this is the database refusing to create a table or a column
+    /// because it already exists, which is how a concurrent `CREATE TABLE IF
+    /// NOT EXISTS` or `ADD COLUMN` can fail.
+    ///
+    /// This recognizes the SQLSTATEs for a duplicate table or column from
+    /// Postgres and MySQL, the unique violation that Postgres raises when two
+    /// sessions create the same table at once, and the messages of SQLite,
+    /// which has no SQLSTATE.
+    pub fn is_already_exists(&self) -> bool {
+        let e: &(dyn StdError + 'static) = match self {
+            Self::Execute(e)
+            | Self::ExecuteMigration(e, ..)
+            | Self::ExecuteRepeatable(e, _) => e.as_ref(),
+            Self::Partial { source, .. } => source.as_ref(),
+            Self::Group { source, .. } => return source.is_already_exists(),
+            _ => return false,
+        };
+        if let Some(code) = database_code(e) {
+            if ["42P07", "42701", "23505", "42S01", "42S21"]
+                .contains(&code.as_str())
+            {
+                return true;
+            }
+        }
+        let msg = e.to_string();
+        msg.contains("already exists") || msg.contains("duplicate column name")
+    }
+
+    /// Whether this is the database refusing a query because a column it
+    /// names doesn't exist, which is how a table created by an older version
+    /// is found to be missing a column.
+    ///
+    /// This recognizes the SQLSTATEs for an undefined column from Postgres
+    /// and MySQL, and the message of SQLite, which has no SQLSTATE.
+    pub fn is_missing_column(&self) -> bool {
+        let e: &(dyn StdError + 'static) = match self {
+            Self::Execute(e)
+            | Self::ExecuteMigration(e, ..)
+            | Self::ExecuteRepeatable(e, _) => e.as_ref(),
+            Self::Partial { source, .. } => source.as_ref(),
+            Self::Group { source, .. } => return source.is_missing_column(),
+            _ => return false,
+        };
+        if database_code(e)
+            .is_some_and(|code| ["42703", "42S22"].contains(&code.as_str()))
+        {
+            return true;
+        }
+        e.to_string().contains("no such column")
+    }
+
+    /// Whether the error is a serialization failure, SQLSTATE `40001`, which
+    /// CockroachDB returns for a transaction that the client should retry
+    /// from the start.
+    pub fn is_serialization_failure(&self) -> bool {
+        if let Self::Group { source, .. } = self {
+            return source.is_serialization_failure();
+        }
+        let mut source: Option<&(dyn StdError + 'static)> = Some(self);
+        while let Some(e) = source {
+            if database_code(e).is_some_and(|c| c == "40001") {
+                return true;
+            }
+            source = e.source();
+        }
+
+        false
+    }
+
+    /// Whether the error can go away by trying again: a deadlock, a
+    /// serialization failure, or a lock that wasn't available in time, from
+    /// the SQLSTATE on Postgres and MySQL, or from the message on MySQL and
+    /// SQLite for the ones that don't have their own SQLSTATE.
+    pub fn is_transient(&self) -> bool {
+        if let Self::Group { source, .. } = self {
+            return source.is_transient();
+        }
+        // The error from the database can be wrapped in more than one of
+        // these, e.g., when it's attached to the migration that failed.
+        let mut source: Option<&(dyn StdError + 'static)> = Some(self);
+        while let Some(e) = source {
+            let code = database_code(e);
+            if code.is_some_and(|c| ["40001", "40P01", "55P03"].contains(&&*c))
+            {
+                return true;
+            }
+            let msg = e.to_string();
+            if msg.contains("Lock wait timeout exceeded")
+                || msg.contains("database is locked")
+                || msg.contains("database table is locked")
+            {
+                return true;
+            }
+            source = e.source();
+        }
+
+        false
+    }
+
+    /// A stable code for the kind of error, such as `TERN_OUT_OF_SYNC`, for
+    /// automation to match on instead of the message.
+    ///
+    /// An [`Error::Partial`] or an [`Error::Group`] has the code of the error
+    /// that stopped the run.  Codes are not removed or changed once added.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Init { .. } => "TERN_INIT",
+            Self::Execute(_) => "TERN_EXECUTE",
+            Self::ExecuteMigration(..) => "TERN_EXECUTE_MIGRATION",
+            Self::ExecuteRepeatable(..) => "TERN_EXECUTE_REPEATABLE",
+            Self::ResolveQuery(_) => "TERN_RESOLVE_QUERY",
+            Self::Sql(_) => "TERN_SQL",
+            Self::Split(..) => "TERN_SPLIT",
+            Self::MissingSource { .. } => "TERN_MISSING_SOURCE",
+            Self::OutOfSync { .. } => "TERN_OUT_OF_SYNC",
+            Self::NotUpToDate { .. } => "TERN_NOT_UP_TO_DATE",
+            Self::Validation { .. } => "TERN_VALIDATION",
+            Self::Invalid(_) => "TERN_INVALID",
+            Self::Refused { .. } => "TERN_REFUSED",
+            Self::Partial { source, .. } => {
+                source.downcast_ref::<Self>().map_or("TERN_PARTIAL", Self::code)
+            },
+            Self::Group { source, .. } => source.code(),
+        }
+    }
+
+    /// The migration that the error came from, if it came from one.
+    pub fn migration_id(&self) -> Option<&MigrationId> {
+        match self {
+            Self::ExecuteMigration(_, id, _) => Some(id),
+            Self::Partial { source, .. } => {
+                source.downcast_ref::<Self>().and_then(Self::migration_id)
+            },
+            Self::Group { source, .. } => source.migration_id(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn group(
+        context: &str,
+        source: Self,
+        report: &GroupReport,
+    ) -> Self {
+        Self::Group {
+            context: context.to_string(),
+            source: Box::new(source),
+            report: report.clone(),
+        }
+    }
+
     pub(crate) fn split_err(idx: usize) -> impl FnMut(std::io::Error) -> Self {
         move |e| Self::Split(e, idx)
     }
 }
 
+// The error code that the database returned, if this is an error from one.
+fn database_code(e: &(dyn StdError + 'static)) -> Option<String> {
+    #[cfg(feature = "sqlx")]
+    if let Some(sqlx::Error::Database(e)) = e.downcast_ref::<sqlx::Error>() {
+        return e.code().map(|code| code.into_owned());
+    }
+    #[cfg(feature = "tokio_postgres")]
+    if let Some(e) = e.downcast_ref::<tokio_postgres::Error>() {
+        return e.code().map(|code| code.code().to_string());
+    }
+    #[cfg(feature = "rusqlite")]
+    if let Some(rusqlite::Error::SqliteFailure(e, _)) =
+        e.downcast_ref::<rusqlite::Error>()
+    {
+        return Some(e.extended_code.to_string());
+    }
+    #[cfg(feature = "libsql")]
+    match e.downcast_ref::<libsql::Error>() {
+        Some(libsql::Error::SqliteFailure(code, _)) => {
+            return Some(code.to_string());
+        },
+        Some(libsql::Error::RemoteSqliteFailure(_, code, _)) => {
+            return Some(code.to_string());
+        },
+        _ => {},
+    }
+    #[cfg(feature = "mysql_async")]
+    if let Some(mysql_async::Error::Server(e)) =
+        e.downcast_ref::<mysql_async::Error>()
+    {
+        return Some(e.state.clone());
+    }
+    let _ = e;
+    None
+}
+
 /// Converting a result with a generic `std::error::Error` to one with this
 /// crate's error type.
 ///
@@ -0,0 +1,174 @@
+//! What the executors of the different backends do the same way.
+use crate::error::TernResult;
+use crate::migration::{Query, Step};
+
+use std::time::Duration;
+
+#[cfg(any(
+    feature = "tokio_postgres",
+    feature = "rusqlite",
+    feature = "libsql",
+    feature = "mysql_async"
+))]
+use crate::migration::{AppliedMigration, Version};
+#[cfg(any(
+    feature = "tokio_postgres",
+    feature = "rusqlite",
+    feature = "libsql",
+    feature = "mysql_async"
+))]
+use chrono::{DateTime, Utc};
+
+// How many times to run a statement creating a table or a column that failed
+// because another runner was creating it, and the pause in between.
+const CREATE_ATTEMPTS: usize = 3;
+const CREATE_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The connection of an executor that creates the history table and the other
+/// tables of the runner.
+pub(crate) trait CreateTables {
+    /// Run a statement that has no results.
+    async fn execute_sql(&mut self, sql: &str) -> TernResult<()>;
+
+    /// Run a statement whose failing is an answer and not the end of what is
+    /// being done, which a connection in a transaction has to do in a
+    /// savepoint.
+    async fn probe(&mut self, sql: &str) -> TernResult<()> {
+        self.execute_sql(sql).await
+    }
+}
+
+/// Create the history table with `create` and add the columns in `columns`
+/// that it is missing, which a table created by an older version is.
+pub(crate) async fn create_history<E: CreateTables>(
+    conn: &mut E,
+    history_table: &str,
+    create: &Query,
+    columns: Vec<(&'static str, Query)>,
+) -> TernResult<()> {
+    execute_create(conn, create.sql()).await?;
+    // Selecting the column is the way to check for it that works for every
+    // database.
+    for (column, query) in columns {
+        let probe =
+            format!("SELECT {column} FROM {history_table} WHERE 1 = 0;");
+        match conn.probe(&probe).await {
+            Err(e) if e.is_missing_column() => {
+                log::info!("adding column {column} to {history_table}");
+                execute_create(conn, query.sql()).await?;
+            },
+            res => res?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a `CREATE TABLE IF NOT EXISTS` or an `ALTER TABLE ADD COLUMN`, which
+/// can still fail when another runner does the same at the same time, e.g.,
+/// with a unique violation in the catalog on Postgres.  It is tried again
+/// after a pause, by when the other one has finished, and it failing again
+/// because the table or column exists means that it does.
+pub(crate) async fn execute_create<E: CreateTables>(
+    conn: &mut E,
+    sql: &str,
+) -> TernResult<()> {
+    let mut attempts = 1;
+    loop {
+        match conn.execute_sql(sql).await {
+            Err(e) if e.is_already_exists() => {
+                if attempts == CREATE_ATTEMPTS {
+                    log::debug!("already exists, continuing: {e}");
+                    return Ok(());
+                }
+                log::debug!("created concurrently, trying again: {e}");
+                futures_timer::Delay::new(CREATE_RETRY_DELAY).await;
+                attempts += 1;
+            },
+            res => return res,
+        }
+    }
+}
+
+/// Log a statement of a migration applied one statement at a time.
+pub(crate) fn log_step(index: usize, count: usize, step: &Step) {
+    log::info!(
+        "statement {} of {count} completed in {}ms",
+        index + 1,
+        step.duration.as_millis()
+    );
+    log::debug!("{}", step.statement);
+}
+
+/// Log the statements of a migration that were applied one at a time.
+#[cfg(any(
+    feature = "tokio_postgres",
+    feature = "rusqlite",
+    feature = "libsql",
+    feature = "mysql_async"
+))]
+pub(crate) fn log_steps(steps: &[Step]) {
+    for (n, step) in steps.iter().enumerate() {
+        log_step(n, steps.len(), step);
+    }
+}
+
+/// A parameter of a query in the type of the driver of a backend that binds
+/// its parameters all at once.
+#[cfg(any(
+    feature = "tokio_postgres",
+    feature = "rusqlite",
+    feature = "libsql",
+    feature = "mysql_async"
+))]
+pub(crate) trait Param: Sized {
+    fn integer(v: i64) -> Self;
+
+    fn text(v: String) -> Self;
+
+    fn optional_text(v: Option<String>) -> Self;
+
+    /// A timestamp, in the type and format that `sqlx` writes it in for the
+    /// database, so that the history table reads the same with either.
+    fn timestamp(at: DateTime<Utc>) -> Self;
+}
+
+/// The version as a parameter of a query, which is the type of the version
+/// column.
+#[cfg(any(
+    feature = "tokio_postgres",
+    feature = "rusqlite",
+    feature = "libsql",
+    feature = "mysql_async"
+))]
+pub(crate) fn version_param<P: Param>(version: &Version) -> P {
+    match version {
+        Version::Integer(v) => P::integer(*v),
+        Version::Text(v) => P::text(v.clone()),
+    }
+}
+
+/// The parameters of the insert or the upsert query of the history table,
+/// which have a placeholder for each field of `AppliedMigration`, in order.
+#[cfg(any(
+    feature = "tokio_postgres",
+    feature = "rusqlite",
+    feature = "libsql",
+    feature = "mysql_async"
+))]
+pub(crate) fn applied_params<P: Param>(applied: &AppliedMigration) -> [P; 12] {
+    [
+        version_param(&applied.version),
+        P::text(applied.description.clone()),
+        P::text(applied.content.clone()),
+        P::integer(applied.duration_ms),
+        P::timestamp(applied.applied_at),
+        P::optional_text(applied.source_digest.clone()),
+        P::text(applied.source_id.clone().unwrap_or_default()),
+        P::optional_text(applied.search_path.clone()),
+        P::optional_text(applied.build_version.clone()),
+        P::optional_text(applied.build_git_sha.clone()),
+        P::optional_text(applied.built_at.clone()),
+        P::optional_text(applied.checksum.clone()),
+    ]
+}
@@ -0,0 +1,547 @@
+//! [`Executor`] for a [`libsql::Database`][database], which is a local file,
+//! a remote database like one on Turso, or an embedded replica of a remote
+//! database.
+//!
+//! A remote database is queried over HTTP, where a transaction can't span
+//! more than one request, so a migration that is applied in a transaction is
+//! sent in one request as a batch that is applied atomically.  A replica sends
+//! its writes to the remote database and is synced before reading the history.
+//!
+//! A local file and an embedded replica need the feature `libsql_replica`,
+//! which builds the SQLite of libSQL.  It can't be linked into the same binary
+//! as the one that the features `sqlx_sqlite` and `rusqlite` build.
+//!
+//! [`Executor`]: crate::migration::Executor
+//! [database]: https://docs.rs/libsql/0.9/libsql/struct.Database.html
+use crate::error::{DatabaseError as _, Error, TernResult};
+use crate::executor::common::{self, CreateTables, Param, log_steps};
+use crate::executor::sqlite::SqliteQueryRepo;
+use crate::inventory::DatabaseObject;
+use crate::migration::{
+    AppliedMigration, AppliedRepeatable, ApplyOptions,
+    Executor as MigrationExecutor, Query, QueryRepository, Step, Version,
+    VersionKind,
+};
+use crate::schema::SchemaObject;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use libsql::params::IntoParams;
+use libsql::{Builder, Connection, Database, Value};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// A `libsql::Database` as a migration executor backend.
+///
+/// SQLite allows one writer at a time, so there is no run lock.  Checkpoints,
+/// recording the statements of a migration, and the table of migrations that
+/// failed are not supported.
+pub struct LibsqlExecutor {
+    db: Database,
+    conn: Connection,
+    // Whether the table of repeatable migrations is known to exist.
+    repeatable: bool,
+}
+
+impl LibsqlExecutor {
+    /// Open the database file at `path`, creating it if it doesn't exist.
+    #[cfg(feature = "libsql_replica")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "libsql_replica")))]
+    pub async fn local(path: impl AsRef<std::path::Path>) -> TernResult<Self> {
+        let db = Builder::new_local(path).build().await.map_err(Error::init)?;
+        Self::connect(db).await
+    }
+
+    /// Connect to a remote database, e.g., `libsql://<db>.turso.io`, with
+    /// an auth token for it.
+    pub async fn remote(url: &str, auth_token: &str) -> TernResult<Self> {
+        let db = Builder::new_remote(url.to_string(), auth_token.to_string())
+            .build()
+            .await
+            .map_err(Error::init)?;
+        Self::connect(db).await
+    }
+
+    /// Open an embedded replica of a remote database in the file at `path`,
+    /// and sync it.
+    #[cfg(feature = "libsql_replica")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "libsql_replica")))]
+    pub async fn remote_replica(
+        path: impl AsRef<std::path::Path>,
+        url: &str,
+        auth_token: &str,
+    ) -> TernResult<Self> {
+        let db = Builder::new_remote_replica(
+            path,
+            url.to_string(),
+            auth_token.to_string(),
+        )
+        .build()
+        .await
+        .map_err(Error::init)?;
+        db.sync().await.map_err(Error::init)?;
+        Self::connect(db).await
+    }
+
+    /// Apply migrations to a database that was built some other way, e.g.,
+    /// with a custom connector.
+    pub async fn from_database(db: Database) -> TernResult<Self> {
+        Self::connect(db).await
+    }
+
+    /// The database, to sync it or make other connections to it.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// The connection, to use it for something else in between runs.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    async fn connect(db: Database) -> TernResult<Self> {
+        let conn = db.connect().map_err(Error::init)?;
+        // A remote database isn't reached until the first query.
+        conn.execute_batch("SELECT 1;").await.map_err(Error::init)?;
+
+        Ok(Self { db, conn, repeatable: false })
+    }
+
+    // Get what has been written to the remote database if this is a replica.
+    #[cfg(feature = "libsql_replica")]
+    async fn sync(&self) -> TernResult<()> {
+        match self.db.sync().await {
+            Ok(_) | Err(libsql::Error::SyncNotSupported(_)) => Ok(()),
+            Err(e) => Err(e).tern_result(),
+        }
+    }
+
+    #[cfg(not(feature = "libsql_replica"))]
+    async fn sync(&self) -> TernResult<()> {
+        Ok(())
+    }
+
+    // Write a row of the history table with the insert or the upsert query.
+    async fn write_applied(
+        &self,
+        query: Query,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let params = common::applied_params::<Value>(applied);
+        self.conn.execute(query.sql(), params).await.void_tern_result()
+    }
+
+    async fn create_repeatable_table(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<()> {
+        let query =
+            SqliteQueryRepo::create_repeatable_table_if_not_exists_query(
+                history_table,
+            );
+        let Some(query) = query.filter(|_| !self.repeatable) else {
+            return Ok(());
+        };
+        common::execute_create(self, query.sql()).await?;
+        self.repeatable = true;
+
+        Ok(())
+    }
+}
+
+impl MigrationExecutor for LibsqlExecutor {
+    type Queries = SqliteQueryRepo;
+
+    async fn apply_tx(&mut self, query: &Query) -> TernResult<()> {
+        self.apply(query, &ApplyOptions::default()).await
+    }
+
+    async fn apply_no_tx(&mut self, query: &Query) -> TernResult<()> {
+        self.apply(query, &ApplyOptions::default().with_no_tx(true)).await
+    }
+
+    async fn apply(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<()> {
+        self.apply_statements(query, options).await.map(drop)
+    }
+
+    /// A migration in a transaction is applied in one batch, so its statements
+    /// are not timed one at a time, even with
+    /// [`ApplyOptions::split_statements`].
+    async fn apply_statements(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<Vec<Step>> {
+        if options.has_session_settings() {
+            log::warn!(
+                "libSQL does not support {options:?}, applying without them"
+            );
+        }
+        if !options.no_tx {
+            self.conn
+                .execute_transactional_batch(query.sql())
+                .await
+                .void_tern_result()?;
+
+            return Ok(Vec::new());
+        }
+        let mut steps = Vec::new();
+        for statement in query.split_statements()? {
+            let start = Instant::now();
+            self.conn.execute_batch(&statement).await.void_tern_result()?;
+            steps.push(Step { statement, duration: start.elapsed() });
+        }
+        log_steps(&steps);
+
+        Ok(steps)
+    }
+
+    async fn create_history_if_not_exists(
+        &mut self,
+        history_table: &str,
+        version: VersionKind,
+    ) -> TernResult<()> {
+        let query = SqliteQueryRepo::create_history_if_not_exists_query(
+            history_table,
+            version,
+        );
+        let columns =
+            SqliteQueryRepo::add_history_columns_queries(history_table);
+        common::create_history(self, history_table, &query, columns).await
+    }
+
+    async fn history_exists(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<bool> {
+        let Some(query) = SqliteQueryRepo::history_exists_query() else {
+            return Ok(self.get_all_applied(history_table).await.is_ok());
+        };
+        self.sync().await?;
+        let rows = fetch(&self.conn, query.sql(), [history_table.to_string()])
+            .await
+            .tern_result()?;
+
+        Ok(!rows.is_empty())
+    }
+
+    async fn drop_history(&mut self, history_table: &str) -> TernResult<()> {
+        if let Some(query) =
+            SqliteQueryRepo::drop_repeatable_table_query(history_table)
+        {
+            self.conn.execute_batch(query.sql()).await.void_tern_result()?;
+            self.repeatable = false;
+        }
+        let query = SqliteQueryRepo::drop_history_query(history_table);
+        self.conn.execute_batch(query.sql()).await.void_tern_result()
+    }
+
+    async fn backup_history(
+        &mut self,
+        history_table: &str,
+        backup_table: &str,
+    ) -> TernResult<()> {
+        let query =
+            SqliteQueryRepo::backup_history_query(history_table, backup_table);
+        self.conn.execute_batch(query.sql()).await.void_tern_result()
+    }
+
+    async fn get_all_applied(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedMigration>> {
+        self.sync().await?;
+        let query =
+            SqliteQueryRepo::select_star_from_history_query(history_table);
+        fetch(&self.conn, query.sql(), ())
+            .await
+            .tern_result()?
+            .iter()
+            .map(Record::applied)
+            .collect::<Result<Vec<_>, _>>()
+            .tern_result()
+    }
+
+    async fn insert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query =
+            SqliteQueryRepo::insert_into_history_query(history_table, applied);
+        self.write_applied(query, applied).await
+    }
+
+    async fn upsert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query =
+            SqliteQueryRepo::upsert_history_query(history_table, applied);
+        self.write_applied(query, applied).await
+    }
+
+    async fn delete_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query = SqliteQueryRepo::delete_from_history_query(history_table);
+        let params: [Value; 2] = [
+            common::version_param(&applied.version),
+            applied.source_id.clone().unwrap_or_default().into(),
+        ];
+        self.conn.execute(query.sql(), params).await.void_tern_result()
+    }
+
+    async fn get_applied_repeatable(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedRepeatable>> {
+        let Some(query) =
+            SqliteQueryRepo::select_repeatable_query(history_table)
+        else {
+            return Ok(Vec::new());
+        };
+        self.create_repeatable_table(history_table).await?;
+        fetch(&self.conn, query.sql(), ())
+            .await
+            .tern_result()?
+            .iter()
+            .map(|record| {
+                Ok(AppliedRepeatable {
+                    description: record.text("description")?,
+                    checksum: record.text("checksum")?,
+                    applied_at: record.timestamp("applied_at")?,
+                    duration_ms: record.integer("duration_ms")?,
+                })
+            })
+            .collect::<Result<Vec<_>, libsql::Error>>()
+            .tern_result()
+    }
+
+    async fn upsert_applied_repeatable(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedRepeatable,
+    ) -> TernResult<()> {
+        let Some(query) =
+            SqliteQueryRepo::upsert_repeatable_query(history_table)
+        else {
+            return Err(Error::Invalid(
+                "repeatable migrations are not supported by this database"
+                    .into(),
+            ));
+        };
+        self.create_repeatable_table(history_table).await?;
+        let params: [Value; 4] = [
+            applied.description.clone().into(),
+            applied.checksum.clone().into(),
+            Value::timestamp(applied.applied_at),
+            applied.duration_ms.into(),
+        ];
+        self.conn.execute(query.sql(), params).await.void_tern_result()
+    }
+
+    /// This runs `query` once, now, with the connection of the executor.  A
+    /// remote database doesn't keep the settings from one request to the
+    /// next.
+    async fn set_session_setup(&mut self, query: Query) -> TernResult<()> {
+        self.conn.execute_batch(query.sql()).await.void_tern_result()
+    }
+
+    async fn inventory(&mut self) -> TernResult<Vec<DatabaseObject>> {
+        let Some(query) = SqliteQueryRepo::inventory_query() else {
+            return Err(Error::Invalid(
+                "listing the objects in the database is not supported for this database"
+                    .into(),
+            ));
+        };
+        fetch(&self.conn, query.sql(), ())
+            .await
+            .tern_result()?
+            .iter()
+            .map(|record| {
+                let kind = record.text("kind").tern_result()?.parse()?;
+                let name = record.text("name").tern_result()?;
+                Ok(DatabaseObject::new(kind, &name))
+            })
+            .collect()
+    }
+
+    async fn schema(&mut self) -> TernResult<Vec<SchemaObject>> {
+        let Some(query) = SqliteQueryRepo::schema_query() else {
+            return Err(Error::Invalid(
+                "dumping the schema is not supported for this database".into(),
+            ));
+        };
+        fetch(&self.conn, query.sql(), ())
+            .await
+            .tern_result()?
+            .iter()
+            .map(|record| {
+                let kind = record.text("kind").tern_result()?.parse()?;
+                let name = record.text("name").tern_result()?;
+                let ddl = record.text("ddl").tern_result()?;
+                Ok(SchemaObject::new(kind, name, ddl))
+            })
+            .collect()
+    }
+
+    async fn ping(&mut self) -> TernResult<()> {
+        self.conn.execute_batch("SELECT 1;").await.void_tern_result()
+    }
+}
+
+impl CreateTables for LibsqlExecutor {
+    async fn execute_sql(&mut self, sql: &str) -> TernResult<()> {
+        self.conn.execute_batch(sql).await.void_tern_result()
+    }
+}
+
+impl Param for Value {
+    fn integer(v: i64) -> Self {
+        Value::Integer(v)
+    }
+
+    fn text(v: String) -> Self {
+        Value::Text(v)
+    }
+
+    fn optional_text(v: Option<String>) -> Self {
+        v.map_or(Value::Null, Value::Text)
+    }
+
+    // SQLite has no type for a timestamp, so it's text, like `sqlx` writes it.
+    fn timestamp(at: DateTime<Utc>) -> Self {
+        Value::Text(at.to_rfc3339())
+    }
+}
+
+// The rows of a query, since a `libsql::Row` only gets a value by the index
+// of its column.
+async fn fetch(
+    conn: &Connection,
+    sql: &str,
+    params: impl IntoParams,
+) -> libsql::Result<Vec<Record>> {
+    let mut rows = conn.query(sql, params).await?;
+    let columns = (0..rows.column_count())
+        .map(|n| rows.column_name(n).unwrap_or_default().to_string())
+        .collect::<Vec<_>>();
+    let mut records = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let mut values = HashMap::with_capacity(columns.len());
+        for (n, column) in (0..).zip(columns.iter()) {
+            values.insert(column.clone(), row.get_value(n)?);
+        }
+        records.push(Record(values));
+    }
+
+    Ok(records)
+}
+
+// A row of a query by the names of its columns.
+struct Record(HashMap<String, Value>);
+
+impl Record {
+    fn value(&self, column: &str) -> libsql::Result<&Value> {
+        self.0
+            .get(column)
+            .ok_or_else(|| libsql::Error::InvalidColumnName(column.into()))
+    }
+
+    fn text(&self, column: &str) -> libsql::Result<String> {
+        match self.value(column)? {
+            Value::Text(v) => Ok(v.clone()),
+            _ => Err(libsql::Error::InvalidColumnType),
+        }
+    }
+
+    // A column that can be null or, in a table created by an older version,
+    // missing.
+    fn optional_text(&self, column: &str) -> libsql::Result<Option<String>> {
+        match self.0.get(column) {
+            None | Some(Value::Null) => Ok(None),
+            Some(_) => self.text(column).map(Some),
+        }
+    }
+
+    fn integer(&self, column: &str) -> libsql::Result<i64> {
+        match self.value(column)? {
+            Value::Integer(v) => Ok(*v),
+            _ => Err(libsql::Error::InvalidColumnType),
+        }
+    }
+
+    // A timestamp written as RFC 3339, or as `CURRENT_TIMESTAMP` is for the
+    // default of a column, which is UTC.
+    fn timestamp(&self, column: &str) -> libsql::Result<DateTime<Utc>> {
+        let text = self.text(column)?;
+        DateTime::parse_from_rfc3339(&text)
+            .or_else(|_| DateTime::parse_from_str(&text, "%F %T%.f%:z"))
+            .map(|at| at.to_utc())
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(&text, "%F %T%.f")
+                    .map(|at| at.and_utc())
+            })
+            .map_err(|_| libsql::Error::InvalidColumnType)
+    }
+
+    // A row of the history table, which is like the `FromRow` impl of the
+    // `sqlx` executors: the version is an integer or text, and a table created
+    // by an older version doesn't have the newer columns.
+    fn applied(&self) -> libsql::Result<AppliedMigration> {
+        let version = match self.value("version")? {
+            Value::Integer(v) => Version::Integer(*v),
+            _ => Version::Text(self.text("version")?),
+        };
+
+        Ok(AppliedMigration {
+            version,
+            description: self.text("description")?,
+            content: self.text("content")?,
+            duration_ms: self.integer("duration_ms")?,
+            applied_at: self.timestamp("applied_at")?,
+            source_digest: self.optional_text("source_digest")?,
+            source_id: self
+                .optional_text("source_id")?
+                .filter(|id| !id.is_empty()),
+            search_path: self.optional_text("search_path")?,
+            build_version: self.optional_text("build_version")?,
+            build_git_sha: self.optional_text("build_git_sha")?,
+            built_at: self.optional_text("built_at")?,
+            checksum: self.optional_text("checksum")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Record;
+
+    use chrono::{TimeZone, Utc};
+    use libsql::Value;
+    use std::collections::HashMap;
+
+    #[test]
+    fn timestamp_formats() {
+        let expected = Utc.with_ymd_and_hms(2025, 3, 4, 5, 6, 7).unwrap();
+        for text in [
+            "2025-03-04T05:06:07+00:00",
+            "2025-03-04 05:06:07+00:00",
+            "2025-03-04 05:06:07.000+00:00",
+            "2025-03-04 05:06:07",
+        ] {
+            let record = Record(HashMap::from([(
+                "applied_at".to_string(),
+                Value::Text(text.into()),
+            )]));
+            assert_eq!(record.timestamp("applied_at").unwrap(), expected);
+        }
+    }
+}
@@ -1,4 +1,62 @@
 //! This module contains assorted third-party database connection types that
 //! implement [`Executor`](crate::migration::Executor).
+//!
+//! An executor is also one in a `Box`, behind a `&mut`, and, with the feature
+//! `tokio`, in an `Arc<tokio::sync::Mutex<_>>`, so an application can share
+//! one with a migration context.
+#[cfg(any(
+    feature = "sqlx",
+    feature = "tokio_postgres",
+    feature = "rusqlite",
+    feature = "libsql",
+    feature = "mysql_async"
+))]
+mod common;
+
+#[cfg(feature = "libsql")]
+#[cfg_attr(docsrs, doc(cfg(feature = "libsql")))]
+pub mod libsql_backend;
+
+#[cfg(any(feature = "sqlx_mysql", feature = "mysql_async"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(feature = "sqlx_mysql", feature = "mysql_async")))
+)]
+pub mod mysql;
+
+#[cfg(feature = "mysql_async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mysql_async")))]
+pub mod mysql_async_backend;
+
+#[cfg(any(feature = "sqlx_postgres", feature = "tokio_postgres"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(feature = "sqlx_postgres", feature = "tokio_postgres")))
+)]
+pub mod postgres;
+
+#[cfg(feature = "rusqlite")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rusqlite")))]
+pub mod rusqlite_backend;
+
+#[cfg(any(feature = "sqlx_sqlite", feature = "rusqlite", feature = "libsql"))]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(
+        feature = "sqlx_sqlite",
+        feature = "rusqlite",
+        feature = "libsql"
+    )))
+)]
+pub mod sqlite;
+
 #[cfg(feature = "sqlx")]
 pub mod sqlx_backend;
+
+#[cfg(feature = "tokio_postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio_postgres")))]
+pub mod tokio_postgres_backend;
+
+pub mod tls;
+
+mod wrapper;
@@ -0,0 +1,506 @@
+//! The queries for the history table on mysql, shared by the executors for
+//! it.
+use crate::inventory::ObjectKind;
+use crate::migration::{
+    AppliedMigration, Dialect, Query, QueryRepository, VersionKind,
+};
+use crate::schema::SchemaObject;
+
+/// The schema history table queries for mysql, which every mysql executor
+/// uses.
+#[derive(Debug, Clone)]
+pub struct MySqlQueryRepo;
+
+impl QueryRepository for MySqlQueryRepo {
+    const DIALECT: Option<Dialect> = Some(Dialect::MySql);
+
+    fn version_column_type(version: VersionKind) -> &'static str {
+        match version {
+            VersionKind::Integer => "bigint",
+            VersionKind::Text => "varchar(255)",
+        }
+    }
+
+    fn create_history_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Query {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}(
+  version {version_type} NOT NULL,
+  description text NOT NULL,
+  content text NOT NULL,
+  duration_ms bigint NOT NULL,
+  applied_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  source_digest text,
+  source_id varchar(255) NOT NULL DEFAULT '',
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn drop_history_query(history_table: &str) -> Query {
+        let sql = format!("DROP TABLE IF EXISTS {history_table};");
+
+        Query::new(sql)
+    }
+
+    fn history_exists_query() -> Option<Query> {
+        let sql = "
+SELECT
+  1
+FROM
+  information_schema.tables
+WHERE
+  table_schema = DATABASE()
+  AND table_name = ?;
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn insert_into_history_query(
+        history_table: &str,
+        _: &AppliedMigration,
+    ) -> Query {
+        // With `sqlx` we're not going to use the `AppliedMigration`, the values
+        // will get in the query by `bind`ing them.
+        let sql = format!(
+            "
+INSERT INTO {history_table}(version, description, content, duration_ms, applied_at, source_digest, source_id, search_path, build_version, build_git_sha, built_at, checksum)
+  VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn add_history_columns_queries(
+        history_table: &str,
+    ) -> Vec<(&'static str, Query)> {
+        let source_digest = format!(
+            "ALTER TABLE {history_table} ADD COLUMN source_digest text;"
+        );
+
+        // The primary key was the version alone, and now it's the version in
+        // the context sharing the table.
+        let source_id = format!(
+            "
+ALTER TABLE {history_table}
+  ADD COLUMN source_id varchar(255) NOT NULL DEFAULT '',
+  DROP PRIMARY KEY,
+  ADD PRIMARY KEY (source_id, version);
+"
+        );
+
+        let search_path =
+            format!("ALTER TABLE {history_table} ADD COLUMN search_path text;");
+
+        let mut queries = vec![
+            ("source_digest", Query::new(source_digest)),
+            ("source_id", Query::new(source_id)),
+            ("search_path", Query::new(search_path)),
+        ];
+        // Where the binary that applied the migration came from.
+        for column in ["build_version", "build_git_sha", "built_at"] {
+            let sql = format!(
+                "ALTER TABLE {history_table} ADD COLUMN {column} text;"
+            );
+            queries.push((column, Query::new(sql)));
+        }
+        // The checksum of what was applied, which the content might not have.
+        let checksum =
+            format!("ALTER TABLE {history_table} ADD COLUMN checksum text;");
+        queries.push(("checksum", Query::new(checksum)));
+
+        queries
+    }
+
+    fn select_star_from_history_query(history_table: &str) -> Query {
+        let sql = format!(
+            "
+SELECT
+  *
+FROM
+  {history_table}
+ORDER BY
+  version;
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn upsert_history_query(
+        history_table: &str,
+        _: &AppliedMigration,
+    ) -> Query {
+        let sql = format!(
+            "
+INSERT INTO {history_table}(version, description, content, duration_ms, applied_at, source_digest, source_id, search_path, build_version, build_git_sha, built_at, checksum)
+  VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+  ON DUPLICATE KEY UPDATE
+    description = VALUES(description),
+    content = VALUES(content),
+    duration_ms = VALUES(duration_ms),
+    applied_at = VALUES(applied_at),
+    source_digest = VALUES(source_digest),
+    search_path = VALUES(search_path),
+    build_version = VALUES(build_version),
+    build_git_sha = VALUES(build_git_sha),
+    built_at = VALUES(built_at),
+    checksum = VALUES(checksum)
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn delete_from_history_query(history_table: &str) -> Query {
+        let sql = format!(
+            "DELETE FROM {history_table} WHERE version = ? AND source_id = ?;"
+        );
+
+        Query::new(sql)
+    }
+
+    fn create_checkpoint_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_checkpoint(
+  version {version_type} NOT NULL,
+  source_id varchar(255) NOT NULL DEFAULT '',
+  checkpoint text NOT NULL,
+  updated_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_checkpoint_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_checkpoint;");
+
+        Some(Query::new(sql))
+    }
+
+    fn select_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  checkpoint,
+  updated_at
+FROM
+  {history_table}_checkpoint
+WHERE
+  version = ?
+  AND source_id = ?;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_checkpoint(version, source_id, checkpoint, updated_at)
+  VALUES (?, ?, ?, ?)
+  ON DUPLICATE KEY UPDATE
+    checkpoint = VALUES(checkpoint),
+    updated_at = VALUES(updated_at);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_checkpoint WHERE version = ? AND source_id = ?;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    /// MySQL commits each statement that changes the schema, even in a
+    /// transaction, so there's no undoing the statements of a migration that
+    /// ran before one that failed.  These record which ones those were.
+    fn create_progress_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_progress(
+  version {version_type} NOT NULL,
+  source_id varchar(255) NOT NULL DEFAULT '',
+  statement_index bigint NOT NULL,
+  statement text NOT NULL,
+  completed_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (source_id, version, statement_index)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_progress_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_progress;");
+
+        Some(Query::new(sql))
+    }
+
+    fn insert_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_progress(version, source_id, statement_index, statement, completed_at)
+  VALUES (?, ?, ?, ?, ?);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  statement_index,
+  statement,
+  completed_at
+FROM
+  {history_table}_progress
+WHERE
+  version = ?
+  AND source_id = ?
+ORDER BY
+  statement_index;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_progress WHERE version = ? AND source_id = ?;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn inventory_query() -> Option<Query> {
+        let sql = "
+SELECT
+  CASE table_type WHEN 'VIEW' THEN 'view' ELSE 'table' END AS kind,
+  CAST(table_name AS CHAR) AS name
+FROM
+  information_schema.tables
+WHERE
+  table_schema = DATABASE()
+UNION
+SELECT DISTINCT
+  'index' AS kind,
+  CAST(index_name AS CHAR) AS name
+FROM
+  information_schema.statistics
+WHERE
+  table_schema = DATABASE()
+  AND index_name <> 'PRIMARY';
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn schema_query() -> Option<Query> {
+        let sql = "
+SELECT
+  CASE table_type WHEN 'VIEW' THEN 'view' ELSE 'table' END AS kind,
+  CAST(table_name AS CHAR) AS name,
+  CAST(NULL AS CHAR) AS ddl
+FROM
+  information_schema.tables
+WHERE
+  table_schema = DATABASE();
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    // The indexes of a table are in the statement that creates it.
+    fn show_create_query(object: &SchemaObject) -> Option<Query> {
+        let kind = match object.kind {
+            ObjectKind::Table => "TABLE",
+            ObjectKind::View => "VIEW",
+            ObjectKind::Index => return None,
+        };
+        let name = object.name.replace('`', "``");
+        let sql = format!("SHOW CREATE {kind} `{name}`;");
+
+        Some(Query::new(sql))
+    }
+
+    fn create_quarantine_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_quarantine(
+  version {version_type} NOT NULL,
+  source_id varchar(255) NOT NULL DEFAULT '',
+  description text NOT NULL,
+  error text NOT NULL,
+  attempts bigint NOT NULL,
+  first_failed_at timestamp NOT NULL,
+  last_failed_at timestamp NOT NULL,
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_quarantine_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_quarantine;");
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_quarantine(version, source_id, description, error, attempts, first_failed_at, last_failed_at)
+  VALUES (?, ?, ?, ?, 1, ?, ?)
+  ON DUPLICATE KEY UPDATE
+    description = VALUES(description),
+    error = VALUES(error),
+    attempts = attempts + 1,
+    last_failed_at = VALUES(last_failed_at);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  version,
+  description,
+  error,
+  attempts,
+  first_failed_at,
+  last_failed_at
+FROM
+  {history_table}_quarantine
+WHERE
+  source_id = ?
+ORDER BY
+  version;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_quarantine WHERE version = ? AND source_id = ?;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn analyze_query(tables: &[String]) -> Option<Query> {
+        let sql = format!("ANALYZE TABLE {};", tables.join(", "));
+
+        Some(Query::new(sql))
+    }
+
+    fn create_repeatable_table_if_not_exists_query(
+        history_table: &str,
+    ) -> Option<Query> {
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_repeatable(
+  description varchar(255) PRIMARY KEY,
+  checksum text NOT NULL,
+  applied_at timestamp NOT NULL,
+  duration_ms bigint NOT NULL
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_repeatable_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_repeatable;");
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_repeatable_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_repeatable(description, checksum, applied_at, duration_ms)
+  VALUES (?, ?, ?, ?)
+  ON DUPLICATE KEY UPDATE
+    checksum = VALUES(checksum),
+    applied_at = VALUES(applied_at),
+    duration_ms = VALUES(duration_ms);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_repeatable_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  description,
+  checksum,
+  applied_at,
+  duration_ms
+FROM
+  {history_table}_repeatable
+ORDER BY
+  description;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn acquire_lock_query(history_table: &str) -> Option<Query> {
+        // A negative timeout waits indefinitely.
+        let sql = format!("SELECT GET_LOCK('{history_table}', -1);");
+
+        Some(Query::new(sql))
+    }
+
+    fn release_lock_query(history_table: &str) -> Option<Query> {
+        let sql = format!("SELECT RELEASE_LOCK('{history_table}');");
+
+        Some(Query::new(sql))
+    }
+}
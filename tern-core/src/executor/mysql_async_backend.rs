@@ -0,0 +1,491 @@
+//! [`Executor`] for a [`mysql_async::Pool`][pool], for an application that
+//! already uses `mysql_async` and doesn't want `sqlx` only to apply
+//! migrations.
+//!
+//! [`Executor`]: crate::migration::Executor
+//! [pool]: https://docs.rs/mysql_async/0.36/mysql_async/struct.Pool.html
+use crate::error::{DatabaseError as _, Error, TernResult};
+use crate::executor::common::{self, CreateTables, Param, log_steps};
+use crate::executor::mysql::MySqlQueryRepo;
+use crate::inventory::DatabaseObject;
+use crate::migration::{
+    AppliedMigration, AppliedRepeatable, ApplyOptions,
+    Executor as MigrationExecutor, Query, QueryRepository, Step, Version,
+    VersionKind,
+};
+use crate::schema::SchemaObject;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use mysql_async::prelude::{FromValue, Queryable as _};
+use mysql_async::{Conn, Pool, Row, TxOpts, Value};
+use std::time::Instant;
+
+// Timestamps are written and read in UTC, which is what `sqlx` does too, so
+// that the history table reads the same with either executor.
+const SET_TIME_ZONE: &str = "SET time_zone = '+00:00';";
+
+/// A `mysql_async::Pool` as a migration executor backend.
+///
+/// The run lock is held by one connection from the pool until it's released.
+/// Checkpoints, recording the statements of a migration, and the table of
+/// migrations that failed are not supported.
+pub struct MySqlAsyncExecutor {
+    pool: Pool,
+    // Whether the table of repeatable migrations is known to exist.
+    repeatable: bool,
+    // The connection holding the run lock, which is a session-level lock, so
+    // it has to be released with the same connection.
+    lock_conn: Option<Conn>,
+    // The query that sets up each connection taken from the pool.
+    session_setup: Option<Query>,
+}
+
+impl MySqlAsyncExecutor {
+    /// Create a pool with default options from a connection string.
+    pub async fn new(db_url: &str) -> TernResult<Self> {
+        let pool = Pool::from_url(db_url).map_err(Error::init)?;
+        // The pool doesn't connect until a connection is asked for.
+        let mut conn = pool.get_conn().await.map_err(Error::init)?;
+        conn.ping().await.map_err(Error::init)?;
+
+        Ok(Self::from_pool(pool))
+    }
+
+    /// Apply migrations with connections from a pool that already exists,
+    /// like the one the application uses.
+    pub fn from_pool(pool: Pool) -> Self {
+        Self { pool, repeatable: false, lock_conn: None, session_setup: None }
+    }
+
+    /// The pool, to use it for something else in between runs.
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    /// The pool, when the executor is no longer needed.
+    pub fn into_pool(self) -> Pool {
+        self.pool
+    }
+
+    // A connection from the pool with the time zone and the session setup.
+    // The pool resets a connection when it's returned, so this is needed every
+    // time.
+    async fn conn(&self) -> TernResult<Conn> {
+        let mut conn = self.pool.get_conn().await.tern_result()?;
+        conn.query_drop(SET_TIME_ZONE).await.tern_result()?;
+        if let Some(setup) = self.session_setup.as_ref() {
+            conn.query_drop(setup.sql()).await.tern_result()?;
+        }
+
+        Ok(conn)
+    }
+
+    async fn query_drop(&self, query: &Query) -> TernResult<()> {
+        self.conn().await?.query_drop(query.sql()).await.tern_result()
+    }
+
+    // The rows of a query, made as a prepared statement so that the values
+    // have the types of their columns.
+    async fn fetch(&self, query: &Query) -> TernResult<Vec<Row>> {
+        self.conn().await?.exec(query.sql(), ()).await.tern_result()
+    }
+
+    // Write a row of the history table with the insert or the upsert query.
+    async fn write_applied(
+        &self,
+        query: Query,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let params = common::applied_params::<Value>(applied).to_vec();
+        self.conn().await?.exec_drop(query.sql(), params).await.tern_result()
+    }
+
+    async fn create_repeatable_table(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<()> {
+        let query = MySqlQueryRepo::create_repeatable_table_if_not_exists_query(
+            history_table,
+        );
+        let Some(query) = query.filter(|_| !self.repeatable) else {
+            return Ok(());
+        };
+        common::execute_create(self, query.sql()).await?;
+        self.repeatable = true;
+
+        Ok(())
+    }
+}
+
+impl MigrationExecutor for MySqlAsyncExecutor {
+    type Queries = MySqlQueryRepo;
+
+    async fn apply_tx(&mut self, query: &Query) -> TernResult<()> {
+        self.apply(query, &ApplyOptions::default()).await
+    }
+
+    async fn apply_no_tx(&mut self, query: &Query) -> TernResult<()> {
+        self.apply(query, &ApplyOptions::default().with_no_tx(true)).await
+    }
+
+    async fn apply(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<()> {
+        self.apply_statements(query, options).await.map(drop)
+    }
+
+    /// MySQL commits each statement that changes the schema, even in a
+    /// transaction, so a migration that fails part of the way through can
+    /// leave the statements before it applied.
+    async fn apply_statements(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<Vec<Step>> {
+        if options.has_session_settings() {
+            log::warn!(
+                "MySQL does not support {options:?}, applying without them"
+            );
+        }
+        let mut conn = self.conn().await?;
+        if !options.no_tx {
+            let mut tx = conn
+                .start_transaction(TxOpts::default())
+                .await
+                .tern_result()?;
+            if !options.split_statements {
+                tx.query_drop(query.sql()).await.tern_result()?;
+                tx.commit().await.tern_result()?;
+
+                return Ok(Vec::new());
+            }
+            let mut steps = Vec::new();
+            for statement in query.split_statements()? {
+                let start = Instant::now();
+                tx.query_drop(&statement).await.tern_result()?;
+                steps.push(Step { statement, duration: start.elapsed() });
+            }
+            tx.commit().await.tern_result()?;
+            log_steps(&steps);
+
+            return Ok(steps);
+        }
+        let mut steps = Vec::new();
+        for statement in query.split_statements()? {
+            let start = Instant::now();
+            conn.query_drop(&statement).await.tern_result()?;
+            steps.push(Step { statement, duration: start.elapsed() });
+        }
+        log_steps(&steps);
+
+        Ok(steps)
+    }
+
+    async fn create_history_if_not_exists(
+        &mut self,
+        history_table: &str,
+        version: VersionKind,
+    ) -> TernResult<()> {
+        let query = MySqlQueryRepo::create_history_if_not_exists_query(
+            history_table,
+            version,
+        );
+        let columns =
+            MySqlQueryRepo::add_history_columns_queries(history_table);
+        common::create_history(self, history_table, &query, columns).await
+    }
+
+    async fn history_exists(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<bool> {
+        let Some(query) = MySqlQueryRepo::history_exists_query() else {
+            return Ok(self.get_all_applied(history_table).await.is_ok());
+        };
+        let row = self
+            .conn()
+            .await?
+            .exec_first::<Row, _, _>(query.sql(), (history_table,))
+            .await
+            .tern_result()?;
+
+        Ok(row.is_some())
+    }
+
+    async fn drop_history(&mut self, history_table: &str) -> TernResult<()> {
+        if let Some(query) =
+            MySqlQueryRepo::drop_repeatable_table_query(history_table)
+        {
+            self.query_drop(&query).await?;
+            self.repeatable = false;
+        }
+        let query = MySqlQueryRepo::drop_history_query(history_table);
+        self.query_drop(&query).await
+    }
+
+    async fn backup_history(
+        &mut self,
+        history_table: &str,
+        backup_table: &str,
+    ) -> TernResult<()> {
+        let query =
+            MySqlQueryRepo::backup_history_query(history_table, backup_table);
+        self.query_drop(&query).await
+    }
+
+    async fn get_all_applied(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedMigration>> {
+        let query =
+            MySqlQueryRepo::select_star_from_history_query(history_table);
+        self.fetch(&query)
+            .await?
+            .iter()
+            .map(applied_from_row)
+            .collect::<Result<Vec<_>, _>>()
+            .tern_result()
+    }
+
+    async fn insert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query =
+            MySqlQueryRepo::insert_into_history_query(history_table, applied);
+        self.write_applied(query, applied).await
+    }
+
+    async fn upsert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query =
+            MySqlQueryRepo::upsert_history_query(history_table, applied);
+        self.write_applied(query, applied).await
+    }
+
+    async fn delete_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query = MySqlQueryRepo::delete_from_history_query(history_table);
+        let params: Vec<Value> = vec![
+            common::version_param(&applied.version),
+            applied.source_id.clone().unwrap_or_default().into(),
+        ];
+        self.conn().await?.exec_drop(query.sql(), params).await.tern_result()
+    }
+
+    async fn get_applied_repeatable(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedRepeatable>> {
+        let Some(query) =
+            MySqlQueryRepo::select_repeatable_query(history_table)
+        else {
+            return Ok(Vec::new());
+        };
+        self.create_repeatable_table(history_table).await?;
+        self.fetch(&query)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(AppliedRepeatable {
+                    description: get(row, "description")?,
+                    checksum: get(row, "checksum")?,
+                    applied_at: get_timestamp(row, "applied_at")?,
+                    duration_ms: get(row, "duration_ms")?,
+                })
+            })
+            .collect::<Result<Vec<_>, mysql_async::Error>>()
+            .tern_result()
+    }
+
+    async fn upsert_applied_repeatable(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedRepeatable,
+    ) -> TernResult<()> {
+        let Some(query) =
+            MySqlQueryRepo::upsert_repeatable_query(history_table)
+        else {
+            return Err(Error::Invalid(
+                "repeatable migrations are not supported by this database"
+                    .into(),
+            ));
+        };
+        self.create_repeatable_table(history_table).await?;
+        let params: Vec<Value> = vec![
+            applied.description.clone().into(),
+            applied.checksum.clone().into(),
+            applied.applied_at.naive_utc().into(),
+            applied.duration_ms.into(),
+        ];
+        self.conn().await?.exec_drop(query.sql(), params).await.tern_result()
+    }
+
+    /// This runs `query` each time a connection is taken from the pool, which
+    /// resets the connections that are returned to it.
+    async fn set_session_setup(&mut self, query: Query) -> TernResult<()> {
+        self.session_setup = Some(query);
+        // Make sure that it works now rather than with the first migration.
+        self.conn().await.map(drop)
+    }
+
+    async fn inventory(&mut self) -> TernResult<Vec<DatabaseObject>> {
+        let Some(query) = MySqlQueryRepo::inventory_query() else {
+            return Err(Error::Invalid(
+                "listing the objects in the database is not supported for this database"
+                    .into(),
+            ));
+        };
+        self.fetch(&query)
+            .await?
+            .iter()
+            .map(|row| {
+                let kind = get::<String>(row, "kind").tern_result()?.parse()?;
+                let name = get::<String>(row, "name").tern_result()?;
+                Ok(DatabaseObject::new(kind, &name))
+            })
+            .collect()
+    }
+
+    async fn schema(&mut self) -> TernResult<Vec<SchemaObject>> {
+        let Some(query) = MySqlQueryRepo::schema_query() else {
+            return Err(Error::Invalid(
+                "dumping the schema is not supported for this database".into(),
+            ));
+        };
+        let mut objects = self
+            .fetch(&query)
+            .await?
+            .iter()
+            .map(|row| {
+                let kind = get::<String>(row, "kind").tern_result()?.parse()?;
+                let name = get::<String>(row, "name").tern_result()?;
+                let ddl = get::<Option<String>>(row, "ddl").tern_result()?;
+                Ok(SchemaObject::new(kind, name, ddl.unwrap_or_default()))
+            })
+            .collect::<TernResult<Vec<_>>>()?;
+        for object in objects.iter_mut().filter(|o| o.ddl.is_empty()) {
+            let Some(query) = MySqlQueryRepo::show_create_query(object) else {
+                continue;
+            };
+            // The statement is the second column of the row.
+            let mut conn = self.conn().await?;
+            let row = conn.query_first::<Row, _>(query.sql()).await;
+            if let Some(mut row) = row.tern_result()? {
+                object.ddl = row.take::<String, _>(1).unwrap_or_default();
+            }
+        }
+
+        Ok(objects)
+    }
+
+    async fn ping(&mut self) -> TernResult<()> {
+        self.pool.get_conn().await.tern_result()?.ping().await.tern_result()
+    }
+
+    async fn acquire_lock(&mut self, history_table: &str) -> TernResult<()> {
+        let Some(query) = MySqlQueryRepo::acquire_lock_query(history_table)
+        else {
+            return Ok(());
+        };
+        let mut conn = self.conn().await?;
+        conn.query_drop(query.sql()).await.tern_result()?;
+        self.lock_conn = Some(conn);
+
+        Ok(())
+    }
+
+    async fn release_lock(&mut self, history_table: &str) -> TernResult<()> {
+        let (Some(query), Some(mut conn)) = (
+            MySqlQueryRepo::release_lock_query(history_table),
+            self.lock_conn.take(),
+        ) else {
+            return Ok(());
+        };
+        conn.query_drop(query.sql()).await.tern_result()
+    }
+}
+
+impl CreateTables for MySqlAsyncExecutor {
+    async fn execute_sql(&mut self, sql: &str) -> TernResult<()> {
+        self.conn().await?.query_drop(sql).await.tern_result()
+    }
+}
+
+impl Param for Value {
+    fn integer(v: i64) -> Self {
+        Value::Int(v)
+    }
+
+    fn text(v: String) -> Self {
+        v.into()
+    }
+
+    fn optional_text(v: Option<String>) -> Self {
+        v.into()
+    }
+
+    // In UTC, see `SET_TIME_ZONE`.
+    fn timestamp(at: DateTime<Utc>) -> Self {
+        at.naive_utc().into()
+    }
+}
+
+// The value of a column of the row, converted to `T`.
+fn get<T: FromValue>(row: &Row, column: &str) -> mysql_async::Result<T> {
+    match row.get_opt::<T, _>(column) {
+        Some(value) => value.map_err(|e| mysql_async::Error::Other(e.into())),
+        None => Err(mysql_async::Error::Other(
+            format!("no column {column} in the row").into(),
+        )),
+    }
+}
+
+// A timestamp, which is in UTC, see `SET_TIME_ZONE`.
+fn get_timestamp(
+    row: &Row,
+    column: &str,
+) -> mysql_async::Result<DateTime<Utc>> {
+    get::<NaiveDateTime>(row, column).map(|at| at.and_utc())
+}
+
+// A row of the history table, which is like the `FromRow` impl of the `sqlx`
+// executors: the version is an integer or text, and a table created by an
+// older version doesn't have the newer columns.
+fn applied_from_row(row: &Row) -> mysql_async::Result<AppliedMigration> {
+    let version = match get::<Value>(row, "version")? {
+        Value::Int(v) => Version::Integer(v),
+        _ => Version::Text(get(row, "version")?),
+    };
+    let optional = |column: &str| -> mysql_async::Result<Option<String>> {
+        if row.columns_ref().iter().any(|c| c.name_str() == column) {
+            get(row, column)
+        } else {
+            Ok(None)
+        }
+    };
+
+    Ok(AppliedMigration {
+        version,
+        description: get(row, "description")?,
+        content: get(row, "content")?,
+        duration_ms: get(row, "duration_ms")?,
+        applied_at: get_timestamp(row, "applied_at")?,
+        source_digest: optional("source_digest")?,
+        source_id: optional("source_id")?.filter(|id| !id.is_empty()),
+        search_path: optional("search_path")?,
+        build_version: optional("build_version")?,
+        build_git_sha: optional("build_git_sha")?,
+        built_at: optional("built_at")?,
+        checksum: optional("checksum")?,
+    })
+}
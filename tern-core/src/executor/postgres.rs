@@ -0,0 +1,640 @@
+//! The queries for the history table on postgres, shared by the executors
+//! for it.
+use crate::migration::{
+    AppliedMigration, ApplyOptions, Dialect, Query, QueryRepository,
+    VersionKind,
+};
+
+use std::time::Duration;
+
+/// The schema history table queries for postgres, which every postgres
+/// executor uses.
+#[derive(Debug, Clone)]
+pub struct PgQueryRepo;
+
+impl QueryRepository for PgQueryRepo {
+    const DIALECT: Option<Dialect> = Some(Dialect::Postgres);
+
+    fn version_column_type(version: VersionKind) -> &'static str {
+        match version {
+            VersionKind::Integer => "bigint",
+            VersionKind::Text => "text",
+        }
+    }
+
+    fn create_history_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Query {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}(
+  version {version_type} PRIMARY KEY,
+  description text NOT NULL,
+  content text NOT NULL,
+  duration_ms bigint NOT NULL,
+  applied_at timestamptz NOT NULL DEFAULT now(),
+  source_digest text
+);
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn drop_history_query(history_table: &str) -> Query {
+        let sql = format!("DROP TABLE IF EXISTS {history_table};");
+
+        Query::new(sql)
+    }
+
+    fn history_exists_query() -> Option<Query> {
+        let sql = "SELECT 1 WHERE to_regclass($1) IS NOT NULL;";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn insert_into_history_query(
+        history_table: &str,
+        _: &AppliedMigration,
+    ) -> Query {
+        // With `sqlx` we're not going to use the `AppliedMigration`, the values
+        // will get in the query by `bind`ing them.
+        let sql = format!(
+            "
+INSERT INTO {history_table}(version, description, content, duration_ms, applied_at, source_digest, source_id, search_path, build_version, build_git_sha, built_at, checksum)
+  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12);
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn add_history_columns_queries(
+        history_table: &str,
+    ) -> Vec<(&'static str, Query)> {
+        let source_digest = format!(
+            "ALTER TABLE {history_table} ADD COLUMN source_digest text;"
+        );
+
+        // The primary key was the version alone, and now it's the version in
+        // the context sharing the table.
+        let source_id = format!(
+            "
+ALTER TABLE {history_table} ADD COLUMN source_id text NOT NULL DEFAULT '';
+DO $$
+DECLARE pkey text;
+BEGIN
+  SELECT conname INTO pkey
+  FROM pg_constraint
+  WHERE conrelid = '{history_table}'::regclass AND contype = 'p';
+  IF pkey IS NOT NULL THEN
+    EXECUTE format('ALTER TABLE {history_table} DROP CONSTRAINT %I', pkey);
+  END IF;
+  ALTER TABLE {history_table} ADD PRIMARY KEY (source_id, version);
+END $$;
+"
+        );
+
+        let search_path =
+            format!("ALTER TABLE {history_table} ADD COLUMN search_path text;");
+
+        let mut queries = vec![
+            ("source_digest", Query::new(source_digest)),
+            ("source_id", Query::new(source_id)),
+            ("search_path", Query::new(search_path)),
+        ];
+        // Where the binary that applied the migration came from.
+        for column in ["build_version", "build_git_sha", "built_at"] {
+            let sql = format!(
+                "ALTER TABLE {history_table} ADD COLUMN {column} text;"
+            );
+            queries.push((column, Query::new(sql)));
+        }
+        // The checksum of what was applied, which the content might not have.
+        let checksum =
+            format!("ALTER TABLE {history_table} ADD COLUMN checksum text;");
+        queries.push(("checksum", Query::new(checksum)));
+
+        queries
+    }
+
+    fn select_star_from_history_query(history_table: &str) -> Query {
+        let sql = format!(
+            "
+SELECT
+  *
+FROM
+  {history_table}
+ORDER BY
+  version;
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn upsert_history_query(
+        history_table: &str,
+        _: &AppliedMigration,
+    ) -> Query {
+        let sql = format!(
+            "
+INSERT INTO {history_table}(version, description, content, duration_ms, applied_at, source_digest, source_id, search_path, build_version, build_git_sha, built_at, checksum)
+  VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+  ON CONFLICT (source_id, version) DO UPDATE
+  SET
+    description = excluded.description,
+    content = excluded.content,
+    duration_ms = excluded.duration_ms,
+    applied_at = excluded.applied_at,
+    source_digest = excluded.source_digest,
+    search_path = excluded.search_path,
+    build_version = excluded.build_version,
+    build_git_sha = excluded.build_git_sha,
+    built_at = excluded.built_at,
+    checksum = excluded.checksum;
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn delete_from_history_query(history_table: &str) -> Query {
+        let sql = format!(
+            "DELETE FROM {history_table} WHERE version = $1 AND source_id = $2;"
+        );
+
+        Query::new(sql)
+    }
+
+    fn create_checkpoint_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_checkpoint(
+  version {version_type} NOT NULL,
+  source_id text NOT NULL DEFAULT '',
+  checkpoint text NOT NULL,
+  updated_at timestamptz NOT NULL DEFAULT now(),
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_checkpoint_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_checkpoint;");
+
+        Some(Query::new(sql))
+    }
+
+    fn select_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  checkpoint,
+  updated_at
+FROM
+  {history_table}_checkpoint
+WHERE
+  version = $1
+  AND source_id = $2;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_checkpoint(version, source_id, checkpoint, updated_at)
+  VALUES ($1, $2, $3, $4)
+  ON CONFLICT (source_id, version) DO UPDATE
+  SET
+    checkpoint = excluded.checkpoint,
+    updated_at = excluded.updated_at;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_checkpoint WHERE version = $1 AND source_id = $2;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    /// Only a migration that is resumable, see `ApplyOptions::resume`, records
+    /// its statements here.
+    fn create_progress_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_progress(
+  version {version_type} NOT NULL,
+  source_id text NOT NULL DEFAULT '',
+  statement_index bigint NOT NULL,
+  statement text NOT NULL,
+  completed_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (source_id, version, statement_index)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_progress_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_progress;");
+
+        Some(Query::new(sql))
+    }
+
+    fn insert_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_progress(version, source_id, statement_index, statement, completed_at)
+  VALUES ($1, $2, $3, $4, $5);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  statement_index,
+  statement,
+  completed_at
+FROM
+  {history_table}_progress
+WHERE
+  version = $1
+  AND source_id = $2
+ORDER BY
+  statement_index;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_progress WHERE version = $1 AND source_id = $2;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn inventory_query() -> Option<Query> {
+        let sql = "
+SELECT
+  CASE c.relkind
+    WHEN 'i' THEN 'index'
+    WHEN 'I' THEN 'index'
+    WHEN 'v' THEN 'view'
+    WHEN 'm' THEN 'view'
+    ELSE 'table'
+  END AS kind,
+  c.relname::text AS name
+FROM
+  pg_catalog.pg_class c
+  JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE
+  c.relkind IN ('r', 'p', 'i', 'I', 'v', 'm')
+  AND n.nspname = ANY (current_schemas(false));
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn invalid_indexes_query() -> Option<Query> {
+        let sql = "
+SELECT
+  'index' AS kind,
+  c.relname::text AS name
+FROM
+  pg_catalog.pg_index i
+  JOIN pg_catalog.pg_class c ON c.oid = i.indexrelid
+  JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE
+  NOT i.indisvalid
+  AND n.nspname = ANY (current_schemas(false));
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn schema_query() -> Option<Query> {
+        let sql = r"
+SELECT
+  'table' AS kind,
+  c.relname::text AS name,
+  'CREATE TABLE ' || quote_ident(c.relname) || E' (\n' || concat_ws(
+    E',\n',
+    (
+      SELECT
+        string_agg(
+          '  ' || quote_ident(a.attname) || ' '
+            || format_type(a.atttypid, a.atttypmod)
+            || COALESCE(' DEFAULT ' || pg_get_expr(d.adbin, d.adrelid), '')
+            || CASE WHEN a.attnotnull THEN ' NOT NULL' ELSE '' END,
+          E',\n' ORDER BY a.attnum
+        )
+      FROM
+        pg_catalog.pg_attribute a
+        LEFT JOIN pg_catalog.pg_attrdef d
+          ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+      WHERE
+        a.attrelid = c.oid
+        AND a.attnum > 0
+        AND NOT a.attisdropped
+    ),
+    (
+      SELECT
+        string_agg(
+          '  CONSTRAINT ' || quote_ident(con.conname) || ' '
+            || pg_get_constraintdef(con.oid),
+          E',\n' ORDER BY con.conname
+        )
+      FROM
+        pg_catalog.pg_constraint con
+      WHERE
+        con.conrelid = c.oid
+        AND con.contype IN ('p', 'u', 'f', 'c', 'x')
+    )
+  ) || E'\n)' AS ddl
+FROM
+  pg_catalog.pg_class c
+  JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE
+  c.relkind IN ('r', 'p')
+  AND n.nspname = ANY (current_schemas(false))
+UNION ALL
+SELECT
+  'index' AS kind,
+  c.relname::text AS name,
+  pg_get_indexdef(c.oid) AS ddl
+FROM
+  pg_catalog.pg_index i
+  JOIN pg_catalog.pg_class c ON c.oid = i.indexrelid
+  JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE
+  n.nspname = ANY (current_schemas(false))
+  AND NOT EXISTS (
+    SELECT
+      1
+    FROM
+      pg_catalog.pg_constraint con
+    WHERE
+      con.conindid = i.indexrelid
+      AND con.contype IN ('p', 'u', 'x')
+  )
+UNION ALL
+SELECT
+  'view' AS kind,
+  c.relname::text AS name,
+  CASE c.relkind
+    WHEN 'm' THEN 'CREATE MATERIALIZED VIEW '
+    ELSE 'CREATE VIEW '
+  END || quote_ident(c.relname) || E' AS\n' || pg_get_viewdef(c.oid, true)
+    AS ddl
+FROM
+  pg_catalog.pg_class c
+  JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE
+  c.relkind IN ('v', 'm')
+  AND n.nspname = ANY (current_schemas(false));
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn create_quarantine_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_quarantine(
+  version {version_type} NOT NULL,
+  source_id text NOT NULL DEFAULT '',
+  description text NOT NULL,
+  error text NOT NULL,
+  attempts bigint NOT NULL,
+  first_failed_at timestamptz NOT NULL,
+  last_failed_at timestamptz NOT NULL,
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_quarantine_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_quarantine;");
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_quarantine(version, source_id, description, error, attempts, first_failed_at, last_failed_at)
+  VALUES ($1, $2, $3, $4, 1, $5, $6)
+  ON CONFLICT (source_id, version) DO UPDATE
+  SET
+    description = excluded.description,
+    error = excluded.error,
+    attempts = {history_table}_quarantine.attempts + 1,
+    last_failed_at = excluded.last_failed_at;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  version,
+  description,
+  error,
+  attempts,
+  first_failed_at,
+  last_failed_at
+FROM
+  {history_table}_quarantine
+WHERE
+  source_id = $1
+ORDER BY
+  version;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_quarantine WHERE version = $1 AND source_id = $2;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn analyze_query(tables: &[String]) -> Option<Query> {
+        let sql = format!("ANALYZE {};", tables.join(", "));
+
+        Some(Query::new(sql))
+    }
+
+    fn create_repeatable_table_if_not_exists_query(
+        history_table: &str,
+    ) -> Option<Query> {
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_repeatable(
+  description text PRIMARY KEY,
+  checksum text NOT NULL,
+  applied_at timestamptz NOT NULL,
+  duration_ms bigint NOT NULL
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_repeatable_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_repeatable;");
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_repeatable_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_repeatable(description, checksum, applied_at, duration_ms)
+  VALUES ($1, $2, $3, $4)
+  ON CONFLICT (description) DO UPDATE
+  SET
+    checksum = excluded.checksum,
+    applied_at = excluded.applied_at,
+    duration_ms = excluded.duration_ms;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_repeatable_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  description,
+  checksum,
+  applied_at,
+  duration_ms
+FROM
+  {history_table}_repeatable
+ORDER BY
+  description;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn acquire_lock_query(history_table: &str) -> Option<Query> {
+        let sql =
+            format!("SELECT pg_advisory_lock(hashtext('{history_table}'));");
+
+        Some(Query::new(sql))
+    }
+
+    fn release_lock_query(history_table: &str) -> Option<Query> {
+        let sql =
+            format!("SELECT pg_advisory_unlock(hashtext('{history_table}'));");
+
+        Some(Query::new(sql))
+    }
+
+    /// `SET LOCAL` in a transaction, so that the settings end with it, and
+    /// `SET` otherwise.
+    fn set_session_query(options: &ApplyOptions) -> Option<Query> {
+        let scope = if options.no_tx { "SET" } else { "SET LOCAL" };
+        let sql = session_settings(options)
+            .map(|(name, value)| format!("{scope} {name} = {value};"))
+            .collect::<Vec<_>>();
+        if sql.is_empty() {
+            return None;
+        }
+
+        Some(Query::new(sql.join("\n")))
+    }
+
+    fn reset_session_query(options: &ApplyOptions) -> Option<Query> {
+        let sql = session_settings(options)
+            .map(|(name, _)| format!("RESET {name};"))
+            .collect::<Vec<_>>();
+        if sql.is_empty() {
+            return None;
+        }
+
+        Some(Query::new(sql.join("\n")))
+    }
+
+    fn set_search_path_query(search_path: &str) -> Option<Query> {
+        Some(Query::new(format!("SET search_path TO {search_path};")))
+    }
+
+    /// `pg_notify` is `NOTIFY` with a channel and payload that can be bound.
+    fn notify_query() -> Option<Query> {
+        Some(Query::new("SELECT pg_notify($1, $2);".into()))
+    }
+}
+
+// The settings in the options that have a value, with the value as a string
+// literal.  `role` is set like any other setting, which is the same as `SET
+// ROLE`, except that the name is taken as is instead of as an identifier.
+fn session_settings(
+    options: &ApplyOptions,
+) -> impl Iterator<Item = (&'static str, String)> {
+    let ms = |timeout: Option<Duration>| {
+        timeout.map(|t| format!("'{}ms'", t.as_millis()))
+    };
+    [
+        ("lock_timeout", ms(options.lock_timeout)),
+        ("statement_timeout", ms(options.statement_timeout)),
+        (
+            "role",
+            options
+                .role
+                .as_ref()
+                .map(|r| format!("'{}'", r.replace('\'', "''"))),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value?)))
+}
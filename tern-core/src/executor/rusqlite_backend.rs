@@ -0,0 +1,773 @@
+//! [`Executor`] for a [`rusqlite::Connection`][connection], for an embedded
+//! or desktop application that already uses `rusqlite` and doesn't want `sqlx`
+//! only to apply migrations.
+//!
+//! `rusqlite` is synchronous, so each query runs on the blocking thread pool of
+//! the `tokio` runtime with [`spawn_blocking`][spawn-blocking].
+//!
+//! [`Executor`]: crate::migration::Executor
+//! [connection]: https://docs.rs/rusqlite/0.32/rusqlite/struct.Connection.html
+//! [spawn-blocking]: https://docs.rs/tokio/1/tokio/task/fn.spawn_blocking.html
+use crate::error::{DatabaseError as _, Error, TernResult};
+use crate::executor::common::{self, CreateTables, Param, log_steps};
+use crate::executor::sqlite::SqliteQueryRepo;
+use crate::inventory::{DatabaseObject, ObjectKind};
+use crate::migration::{
+    AppliedMigration, AppliedRepeatable, ApplyOptions,
+    Executor as MigrationExecutor, Query, QueryRepository, Step, Version,
+    VersionKind,
+};
+use crate::schema::SchemaObject;
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{Connection, Row, params, params_from_iter};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+/// A `rusqlite::Connection` as a migration executor backend.
+///
+/// SQLite allows one writer at a time, so there is no run lock.  Checkpoints,
+/// recording the statements of a migration, and the table of migrations that
+/// failed are not supported.
+pub struct RusqliteExecutor {
+    // A `Connection` can't be shared between threads, and a query runs on
+    // another thread than the one the executor is on.
+    conn: Arc<Mutex<Connection>>,
+    // Whether the table of repeatable migrations is known to exist.
+    repeatable: bool,
+}
+
+impl RusqliteExecutor {
+    /// Open the database file at `path`, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> TernResult<Self> {
+        let conn = Connection::open(path).map_err(Error::init)?;
+
+        Ok(Self::from_connection(conn))
+    }
+
+    /// Open a new database in memory, which is gone when the executor is.
+    pub fn open_in_memory() -> TernResult<Self> {
+        let conn = Connection::open_in_memory().map_err(Error::init)?;
+
+        Ok(Self::from_connection(conn))
+    }
+
+    /// Apply migrations with a connection that is already open.
+    pub fn from_connection(conn: Connection) -> Self {
+        Self { conn: Arc::new(Mutex::new(conn)), repeatable: false }
+    }
+
+    /// The connection, to use it for something else in between runs.
+    ///
+    /// This blocks until a query of the executor that is running is done, so
+    /// it shouldn't be held across an `.await`.
+    pub fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Run `f` with the connection on the blocking thread pool.
+    async fn with_conn<T, F>(&self, f: F) -> TernResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> TernResult<T> + Send + 'static,
+    {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(PoisonError::into_inner);
+            f(&mut conn)
+        })
+        .await
+        .tern_result()?
+    }
+
+    async fn execute_batch(&self, query: Query) -> TernResult<()> {
+        self.with_conn(move |conn| {
+            conn.execute_batch(query.sql()).tern_result()
+        })
+        .await
+    }
+
+    // Write a row of the history table with the insert or the upsert query.
+    async fn write_applied(
+        &self,
+        query: Query,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let params = common::applied_params::<Value>(applied);
+        self.with_conn(move |conn| {
+            conn.execute(query.sql(), params_from_iter(params))
+                .void_tern_result()
+        })
+        .await
+    }
+
+    async fn create_repeatable_table(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<()> {
+        let query =
+            SqliteQueryRepo::create_repeatable_table_if_not_exists_query(
+                history_table,
+            );
+        let Some(query) = query.filter(|_| !self.repeatable) else {
+            return Ok(());
+        };
+        common::execute_create(self, query.sql()).await?;
+        self.repeatable = true;
+
+        Ok(())
+    }
+}
+
+impl MigrationExecutor for RusqliteExecutor {
+    type Queries = SqliteQueryRepo;
+
+    async fn apply_tx(&mut self, query: &Query) -> TernResult<()> {
+        self.apply(query, &ApplyOptions::default()).await
+    }
+
+    async fn apply_no_tx(&mut self, query: &Query) -> TernResult<()> {
+        self.apply(query, &ApplyOptions::default().with_no_tx(true)).await
+    }
+
+    async fn apply(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<()> {
+        self.apply_statements(query, options).await.map(drop)
+    }
+
+    async fn apply_statements(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<Vec<Step>> {
+        if options.has_session_settings() {
+            log::warn!(
+                "SQLite does not support {options:?}, applying without them"
+            );
+        }
+        let no_tx = options.no_tx;
+        let statements = if no_tx || options.split_statements {
+            Some(query.split_statements()?)
+        } else {
+            None
+        };
+        let query = query.clone();
+        let steps = self
+            .with_conn(move |conn| {
+                let Some(statements) = statements else {
+                    let tx = conn.transaction().tern_result()?;
+                    tx.execute_batch(query.sql()).tern_result()?;
+                    tx.commit().tern_result()?;
+
+                    return Ok(Vec::new());
+                };
+                let tx = if no_tx {
+                    None
+                } else {
+                    Some(conn.unchecked_transaction().tern_result()?)
+                };
+                let mut steps = Vec::new();
+                for statement in statements {
+                    let start = Instant::now();
+                    conn.execute_batch(&statement).tern_result()?;
+                    steps.push(Step { statement, duration: start.elapsed() });
+                }
+                if let Some(tx) = tx {
+                    tx.commit().tern_result()?;
+                }
+
+                Ok(steps)
+            })
+            .await?;
+        log_steps(&steps);
+
+        Ok(steps)
+    }
+
+    async fn create_history_if_not_exists(
+        &mut self,
+        history_table: &str,
+        version: VersionKind,
+    ) -> TernResult<()> {
+        let query = SqliteQueryRepo::create_history_if_not_exists_query(
+            history_table,
+            version,
+        );
+        let columns =
+            SqliteQueryRepo::add_history_columns_queries(history_table);
+        common::create_history(self, history_table, &query, columns).await
+    }
+
+    async fn history_exists(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<bool> {
+        let Some(query) = SqliteQueryRepo::history_exists_query() else {
+            return Ok(self.get_all_applied(history_table).await.is_ok());
+        };
+        let history_table = history_table.to_string();
+        self.with_conn(move |conn| {
+            conn.prepare(query.sql())
+                .and_then(|mut stmt| stmt.exists([history_table]))
+                .tern_result()
+        })
+        .await
+    }
+
+    async fn drop_history(&mut self, history_table: &str) -> TernResult<()> {
+        if let Some(query) =
+            SqliteQueryRepo::drop_repeatable_table_query(history_table)
+        {
+            self.execute_batch(query).await?;
+            self.repeatable = false;
+        }
+        let query = SqliteQueryRepo::drop_history_query(history_table);
+        self.execute_batch(query).await
+    }
+
+    async fn backup_history(
+        &mut self,
+        history_table: &str,
+        backup_table: &str,
+    ) -> TernResult<()> {
+        let query =
+            SqliteQueryRepo::backup_history_query(history_table, backup_table);
+        self.execute_batch(query).await
+    }
+
+    async fn get_all_applied(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedMigration>> {
+        let query =
+            SqliteQueryRepo::select_star_from_history_query(history_table);
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(query.sql()).tern_result()?;
+            let columns = stmt
+                .column_names()
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            let applied = stmt
+                .query_map([], |row| applied_from_row(row, &columns))
+                .tern_result()?
+                .collect::<Result<Vec<_>, _>>();
+            applied.tern_result()
+        })
+        .await
+    }
+
+    async fn insert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query =
+            SqliteQueryRepo::insert_into_history_query(history_table, applied);
+        self.write_applied(query, applied).await
+    }
+
+    async fn upsert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query =
+            SqliteQueryRepo::upsert_history_query(history_table, applied);
+        self.write_applied(query, applied).await
+    }
+
+    async fn delete_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query = SqliteQueryRepo::delete_from_history_query(history_table);
+        let version = common::version_param::<Value>(&applied.version);
+        let source_id = applied.source_id.clone().unwrap_or_default();
+        self.with_conn(move |conn| {
+            conn.execute(query.sql(), params![version, source_id])
+                .void_tern_result()
+        })
+        .await
+    }
+
+    async fn get_applied_repeatable(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedRepeatable>> {
+        let Some(query) =
+            SqliteQueryRepo::select_repeatable_query(history_table)
+        else {
+            return Ok(Vec::new());
+        };
+        self.create_repeatable_table(history_table).await?;
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(query.sql()).tern_result()?;
+            let applied = stmt
+                .query_map([], |row| {
+                    Ok(AppliedRepeatable {
+                        description: row.get("description")?,
+                        checksum: row.get("checksum")?,
+                        applied_at: row.get("applied_at")?,
+                        duration_ms: row.get("duration_ms")?,
+                    })
+                })
+                .tern_result()?
+                .collect::<Result<Vec<_>, _>>();
+            applied.tern_result()
+        })
+        .await
+    }
+
+    async fn upsert_applied_repeatable(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedRepeatable,
+    ) -> TernResult<()> {
+        let Some(query) =
+            SqliteQueryRepo::upsert_repeatable_query(history_table)
+        else {
+            return Err(Error::Invalid(
+                "repeatable migrations are not supported by this database"
+                    .into(),
+            ));
+        };
+        self.create_repeatable_table(history_table).await?;
+        let applied = applied.clone();
+        self.with_conn(move |conn| {
+            conn.execute(
+                query.sql(),
+                params![
+                    applied.description,
+                    applied.checksum,
+                    applied.applied_at,
+                    applied.duration_ms,
+                ],
+            )
+            .void_tern_result()
+        })
+        .await
+    }
+
+    /// There is one connection, so this runs `query` once, now.
+    async fn set_session_setup(&mut self, query: Query) -> TernResult<()> {
+        self.execute_batch(query).await
+    }
+
+    async fn inventory(&mut self) -> TernResult<Vec<DatabaseObject>> {
+        let Some(query) = SqliteQueryRepo::inventory_query() else {
+            return Err(Error::Invalid(
+                "listing the objects in the database is not supported for this database"
+                    .into(),
+            ));
+        };
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(query.sql()).tern_result()?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>("kind")?, row.get("name")?))
+                })
+                .tern_result()?
+                .collect::<Result<Vec<(String, String)>, _>>()
+                .tern_result()?;
+            rows.into_iter()
+                .map(|(kind, name)| {
+                    Ok(DatabaseObject::new(kind.parse()?, &name))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn schema(&mut self) -> TernResult<Vec<SchemaObject>> {
+        let Some(query) = SqliteQueryRepo::schema_query() else {
+            return Err(Error::Invalid(
+                "dumping the schema is not supported for this database".into(),
+            ));
+        };
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(query.sql()).tern_result()?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>("kind")?,
+                        row.get::<_, String>("name")?,
+                        row.get::<_, String>("ddl")?,
+                    ))
+                })
+                .tern_result()?
+                .collect::<Result<Vec<_>, _>>()
+                .tern_result()?;
+            rows.into_iter()
+                .map(|(kind, name, ddl)| {
+                    let kind = kind.parse::<ObjectKind>()?;
+                    Ok(SchemaObject::new(kind, name, ddl))
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn ping(&mut self) -> TernResult<()> {
+        self.execute_batch(Query::new("SELECT 1;".into())).await
+    }
+}
+
+impl CreateTables for RusqliteExecutor {
+    async fn execute_sql(&mut self, sql: &str) -> TernResult<()> {
+        self.execute_batch(Query::new(sql.to_string())).await
+    }
+}
+
+impl Param for Value {
+    fn integer(v: i64) -> Self {
+        Value::Integer(v)
+    }
+
+    fn text(v: String) -> Self {
+        Value::Text(v)
+    }
+
+    fn optional_text(v: Option<String>) -> Self {
+        v.map_or(Value::Null, Value::Text)
+    }
+
+    // The format that `rusqlite` writes a `DateTime` in.
+    fn timestamp(at: DateTime<Utc>) -> Self {
+        Value::Text(at.format("%F %T%.f%:z").to_string())
+    }
+}
+
+// A row of the history table, which is like the `FromRow` impl of the `sqlx`
+// executors: the version is an integer or text, and a table created by an
+// older version doesn't have the newer columns.
+fn applied_from_row(
+    row: &Row<'_>,
+    columns: &[String],
+) -> rusqlite::Result<AppliedMigration> {
+    let version = match row.get::<_, i64>("version") {
+        Ok(v) => Version::Integer(v),
+        Err(_) => Version::Text(row.get("version")?),
+    };
+    let optional = |column: &str| -> rusqlite::Result<Option<String>> {
+        if columns.iter().any(|c| c == column) {
+            row.get(column)
+        } else {
+            Ok(None)
+        }
+    };
+
+    Ok(AppliedMigration {
+        version,
+        description: row.get("description")?,
+        content: row.get("content")?,
+        duration_ms: row.get("duration_ms")?,
+        applied_at: row.get("applied_at")?,
+        source_digest: optional("source_digest")?,
+        source_id: optional("source_id")?.filter(|id| !id.is_empty()),
+        search_path: optional("search_path")?,
+        build_version: optional("build_version")?,
+        build_git_sha: optional("build_git_sha")?,
+        built_at: optional("built_at")?,
+        checksum: optional("checksum")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RusqliteExecutor;
+    use crate::error::{Error, TernResult};
+    use crate::future::{BoxFuture, Future};
+    use crate::migration::{
+        Migration, MigrationContext, MigrationId, MigrationSet,
+        MigrationSource, Query, Version,
+    };
+    use crate::runner::{MigrationResult, Report, Runner, StopHandle};
+
+    use rusqlite::Connection;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    // Migrations 1 to 4, or the ones given to `with_migrations`.
+    const MIGRATIONS: &[&str] = &[
+        "CREATE TABLE users (id INTEGER PRIMARY KEY);",
+        "ALTER TABLE users ADD COLUMN name TEXT;",
+        "CREATE INDEX users_name ON users (name);",
+        "INSERT INTO users (name) VALUES ('admin');",
+    ];
+
+    struct TestCtx {
+        executor: RusqliteExecutor,
+        migrations: Vec<&'static str>,
+        // How many more times a migration with `tern:retries` fails to build
+        // with an error that is worth retrying.
+        transient_failures: u32,
+        // Stopped when a migration with `-- stop` is built.
+        stop: StopHandle,
+    }
+
+    impl TestCtx {
+        fn new() -> Self {
+            Self::with_migrations(MIGRATIONS)
+        }
+
+        fn with_migrations(migrations: &[&'static str]) -> Self {
+            Self {
+                executor: RusqliteExecutor::open_in_memory().unwrap(),
+                migrations: migrations.to_vec(),
+                transient_failures: 0,
+                stop: StopHandle::default(),
+            }
+        }
+    }
+
+    impl MigrationContext for TestCtx {
+        const HISTORY_TABLE: &str = "_tern_migrations";
+        type Exec = RusqliteExecutor;
+
+        fn executor(&mut self) -> &mut Self::Exec {
+            &mut self.executor
+        }
+    }
+
+    impl MigrationSource for TestCtx {
+        type Ctx = Self;
+
+        fn migration_set(
+            &self,
+            last_applied: Option<Version>,
+        ) -> MigrationSet<Self> {
+            let migrations = self
+                .migrations
+                .iter()
+                .enumerate()
+                .map(|(n, sql)| {
+                    Box::new(TestMigration { version: n as i64 + 1, sql })
+                        as Box<dyn Migration<Ctx = Self>>
+                })
+                .collect::<Vec<_>>();
+
+            MigrationSet::new(migrations).after(last_applied)
+        }
+    }
+
+    struct TestMigration {
+        version: i64,
+        sql: &'static str,
+    }
+
+    impl Migration for TestMigration {
+        type Ctx = TestCtx;
+
+        fn migration_id(&self) -> MigrationId {
+            MigrationId::new(self.version, format!("m{}", self.version))
+        }
+
+        fn content(&self) -> String {
+            self.sql.to_string()
+        }
+
+        fn no_tx(&self) -> bool {
+            false
+        }
+
+        fn build<'a>(
+            &'a self,
+            ctx: &'a mut TestCtx,
+        ) -> BoxFuture<'a, TernResult<Query>> {
+            Box::pin(async move {
+                if self.sql.contains("tern:retries")
+                    && ctx.transient_failures > 0
+                {
+                    ctx.transient_failures -= 1;
+                    return Err(Error::Execute("database is locked".into()));
+                }
+                if self.sql.contains("-- stop") {
+                    ctx.stop.stop();
+                }
+                if self.sql.contains("-- slow") {
+                    futures_timer::Delay::new(Duration::from_millis(300)).await;
+                }
+                Ok(Query::new(self.sql.to_string()))
+            })
+        }
+    }
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    fn state(result: &MigrationResult) -> String {
+        serde_json::to_value(result).unwrap()["state"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn states(report: &Report) -> Vec<String> {
+        report.iter_results().map(|r| state(&r)).collect()
+    }
+
+    // The connection of the context's executor, to look at the database
+    // while the runner has the context.
+    fn connection(ctx: &TestCtx) -> Arc<Mutex<Connection>> {
+        Arc::clone(&ctx.executor.conn)
+    }
+
+    fn users_columns(conn: &Mutex<Connection>) -> usize {
+        let conn = conn.lock().unwrap();
+        let stmt = conn.prepare("SELECT * FROM users").unwrap();
+        stmt.column_count()
+    }
+
+    #[test]
+    fn apply_all() {
+        block_on(async {
+            let ctx = TestCtx::new();
+            let conn = connection(&ctx);
+            let mut runner = Runner::new(ctx);
+            let report = runner.run_apply_all(false).await.unwrap();
+            assert_eq!(report.count(), 4);
+            assert_eq!(report.last_applied(), Some(&Version::Integer(4)));
+            assert_eq!(users_columns(&conn), 2);
+
+            // Nothing is left to apply, and the history has all of them.
+            let report = runner.run_apply_all(false).await.unwrap();
+            assert_eq!(report.count(), 0);
+            let applied = runner.list_applied().await.unwrap();
+            assert_eq!(applied.count(), 4);
+        });
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        block_on(async {
+            let ctx = TestCtx::with_migrations(&[
+                MIGRATIONS[0],
+                "ALTER TABLE users ADD COLUMN name TEXT;\nSELECT * FROM missing;",
+            ]);
+            let conn = connection(&ctx);
+            let mut runner = Runner::new(ctx);
+            let e = runner.run_apply_all(false).await.unwrap_err();
+            assert!(e.to_string().contains("missing"), "{e}");
+            // The migration's transaction was rolled back.
+            assert_eq!(users_columns(&conn), 1);
+            let applied = runner.list_applied().await.unwrap();
+            assert_eq!(applied.count(), 1);
+        });
+    }
+
+    #[test]
+    fn deadline() {
+        block_on(async {
+            let ctx = TestCtx::with_migrations(&[
+                "-- slow\nCREATE TABLE users (id INTEGER PRIMARY KEY);",
+                MIGRATIONS[1],
+            ]);
+            let conn = connection(&ctx);
+            let mut runner =
+                Runner::new(ctx).with_deadline(Duration::from_millis(200));
+            let report = runner.run_apply_all(false).await.unwrap();
+            // The migration in flight is finished and the rest are skipped.
+            assert!(report.deadline_exceeded());
+            assert_eq!(report.count(), 1);
+            assert_eq!(users_columns(&conn), 1);
+        });
+    }
+
+    #[test]
+    fn relative_target() {
+        block_on(async {
+            let mut runner = Runner::new(TestCtx::new());
+            let target = runner.resolve_relative_target("+2").await.unwrap();
+            assert_eq!(target, Version::Integer(2));
+            let report = runner.run_apply(Some(target), false).await.unwrap();
+            assert_eq!(report.count(), 2);
+
+            // Relative to the latest applied, and no further than the last.
+            let target = runner.resolve_relative_target("+5").await.unwrap();
+            assert_eq!(target, Version::Integer(4));
+            runner.run_apply(Some(target), false).await.unwrap();
+            let target = runner.resolve_relative_target("+1").await.unwrap();
+            assert_eq!(target, Version::Integer(4));
+
+            assert!(runner.resolve_relative_target("-1").await.is_err());
+            assert!(runner.resolve_relative_target("+0").await.is_err());
+        });
+    }
+
+    #[test]
+    fn continue_on_error_skips_dependents() {
+        block_on(async {
+            let ctx = TestCtx::with_migrations(&[
+                MIGRATIONS[0],
+                "SELECT * FROM missing;",
+                "-- tern:dependsOn=2\nCREATE TABLE posts (id INTEGER);",
+                "CREATE TABLE tags (id INTEGER);",
+            ]);
+            let conn = connection(&ctx);
+            let mut runner = Runner::new(ctx).with_continue_on_error();
+            let report = runner.run_apply_all(false).await.unwrap();
+            assert_eq!(
+                states(&report),
+                ["Applied", "Failed", "Skipped", "Applied"]
+            );
+            assert_eq!(report.failures(), 1);
+            let conn = conn.lock().unwrap();
+            let applied: i64 = conn
+                .query_row("SELECT count(*) FROM _tern_migrations", [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(applied, 2);
+        });
+    }
+
+    #[test]
+    fn retries() {
+        block_on(async {
+            let retried = "-- tern:retries=2\nCREATE TABLE users (id INTEGER);";
+            let mut ctx = TestCtx::with_migrations(&[retried]);
+            ctx.transient_failures = 2;
+            let mut runner = Runner::new(ctx);
+            let report = runner.run_apply_all(false).await.unwrap();
+            let results = report.results();
+            assert_eq!(results[0].attempts(), Some(3));
+            assert_eq!(results[0].warnings().len(), 2);
+
+            // One failure more than it has retries is the error.
+            let mut ctx = TestCtx::with_migrations(&[retried]);
+            ctx.transient_failures = 3;
+            let mut runner = Runner::new(ctx);
+            let e = runner.run_apply_all(false).await.unwrap_err();
+            assert!(e.is_transient(), "{e}");
+        });
+    }
+
+    #[test]
+    fn stop() {
+        block_on(async {
+            let ctx = TestCtx::with_migrations(&[
+                "-- stop\nCREATE TABLE users (id INTEGER PRIMARY KEY);",
+                MIGRATIONS[1],
+            ]);
+            let conn = connection(&ctx);
+            let stop = ctx.stop.clone();
+            let mut runner = Runner::new(ctx).with_stop_handle(stop);
+            let report = runner.run_apply_all(false).await.unwrap();
+            // Stopped while the first was being applied, which is finished.
+            assert!(report.interrupted());
+            assert_eq!(report.count(), 1);
+            assert_eq!(users_columns(&conn), 1);
+        });
+    }
+}
@@ -0,0 +1,477 @@
+//! The queries for the history table on SQLite, shared by the executors for
+//! it.
+use crate::migration::{
+    AppliedMigration, Dialect, Query, QueryRepository, VersionKind,
+};
+
+/// The schema history table queries for SQLite, which every SQLite executor
+/// uses.
+#[derive(Debug, Clone)]
+pub struct SqliteQueryRepo;
+
+impl QueryRepository for SqliteQueryRepo {
+    const DIALECT: Option<Dialect> = Some(Dialect::Sqlite);
+
+    fn version_column_type(version: VersionKind) -> &'static str {
+        match version {
+            VersionKind::Integer => "bigint",
+            VersionKind::Text => "text",
+        }
+    }
+
+    fn create_history_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Query {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}(
+  version {version_type} NOT NULL,
+  description text NOT NULL,
+  content text NOT NULL,
+  duration_ms bigint NOT NULL,
+  applied_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  source_digest text,
+  source_id text NOT NULL DEFAULT '',
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn drop_history_query(history_table: &str) -> Query {
+        let sql = format!("DROP TABLE IF EXISTS {history_table};");
+
+        Query::new(sql)
+    }
+
+    fn history_exists_query() -> Option<Query> {
+        let sql =
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1;";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn insert_into_history_query(
+        history_table: &str,
+        _: &AppliedMigration,
+    ) -> Query {
+        // The `AppliedMigration` isn't used, the values are parameters of the
+        // query.
+        let sql = format!(
+            "
+INSERT INTO {history_table}(version, description, content, duration_ms, applied_at, source_digest, source_id, search_path, build_version, build_git_sha, built_at, checksum)
+  VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12);
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn add_history_columns_queries(
+        history_table: &str,
+    ) -> Vec<(&'static str, Query)> {
+        let source_digest = format!(
+            "ALTER TABLE {history_table} ADD COLUMN source_digest text;"
+        );
+
+        // SQLite can't change the primary key of an existing table, so an
+        // older table keeps the version alone as its key and can't be shared.
+        let source_id = format!(
+            "ALTER TABLE {history_table} ADD COLUMN source_id text NOT NULL DEFAULT '';"
+        );
+
+        let search_path =
+            format!("ALTER TABLE {history_table} ADD COLUMN search_path text;");
+
+        let mut queries = vec![
+            ("source_digest", Query::new(source_digest)),
+            ("source_id", Query::new(source_id)),
+            ("search_path", Query::new(search_path)),
+        ];
+        // Where the binary that applied the migration came from.
+        for column in ["build_version", "build_git_sha", "built_at"] {
+            let sql = format!(
+                "ALTER TABLE {history_table} ADD COLUMN {column} text;"
+            );
+            queries.push((column, Query::new(sql)));
+        }
+        // The checksum of what was applied, which the content might not have.
+        let checksum =
+            format!("ALTER TABLE {history_table} ADD COLUMN checksum text;");
+        queries.push(("checksum", Query::new(checksum)));
+
+        queries
+    }
+
+    fn select_star_from_history_query(history_table: &str) -> Query {
+        let sql = format!(
+            "
+SELECT
+  *
+FROM
+  {history_table}
+ORDER BY
+  version;
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn upsert_history_query(
+        history_table: &str,
+        _: &AppliedMigration,
+    ) -> Query {
+        let sql = format!(
+            "
+INSERT INTO {history_table}(version, description, content, duration_ms, applied_at, source_digest, source_id, search_path, build_version, build_git_sha, built_at, checksum)
+  VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+  ON CONFLICT (source_id, version) DO UPDATE
+  SET
+    description = excluded.description,
+    content = excluded.content,
+    duration_ms = excluded.duration_ms,
+    applied_at = excluded.applied_at,
+    source_digest = excluded.source_digest,
+    search_path = excluded.search_path,
+    build_version = excluded.build_version,
+    build_git_sha = excluded.build_git_sha,
+    built_at = excluded.built_at,
+    checksum = excluded.checksum;
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn delete_from_history_query(history_table: &str) -> Query {
+        let sql = format!(
+            "DELETE FROM {history_table} WHERE version = ?1 AND source_id = ?2;"
+        );
+
+        Query::new(sql)
+    }
+
+    fn create_checkpoint_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_checkpoint(
+  version {version_type} NOT NULL,
+  source_id text NOT NULL DEFAULT '',
+  checkpoint text NOT NULL,
+  updated_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_checkpoint_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_checkpoint;");
+
+        Some(Query::new(sql))
+    }
+
+    fn select_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  checkpoint,
+  updated_at
+FROM
+  {history_table}_checkpoint
+WHERE
+  version = ?1
+  AND source_id = ?2;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_checkpoint(version, source_id, checkpoint, updated_at)
+  VALUES (?1, ?2, ?3, ?4)
+  ON CONFLICT (source_id, version) DO UPDATE
+  SET
+    checkpoint = excluded.checkpoint,
+    updated_at = excluded.updated_at;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_checkpoint_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_checkpoint WHERE version = ?1 AND source_id = ?2;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    /// Only a migration that is resumable, see `ApplyOptions::resume`, records
+    /// its statements here.
+    fn create_progress_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_progress(
+  version {version_type} NOT NULL,
+  source_id text NOT NULL DEFAULT '',
+  statement_index integer NOT NULL,
+  statement text NOT NULL,
+  completed_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (source_id, version, statement_index)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_progress_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_progress;");
+
+        Some(Query::new(sql))
+    }
+
+    fn insert_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_progress(version, source_id, statement_index, statement, completed_at)
+  VALUES (?, ?, ?, ?, ?);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  statement_index,
+  statement,
+  completed_at
+FROM
+  {history_table}_progress
+WHERE
+  version = ?
+  AND source_id = ?
+ORDER BY
+  statement_index;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_progress_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_progress WHERE version = ? AND source_id = ?;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn inventory_query() -> Option<Query> {
+        let sql = "
+SELECT
+  type AS kind,
+  name
+FROM
+  sqlite_master
+WHERE
+  type IN ('table', 'index', 'view')
+  AND name NOT LIKE 'sqlite_%';
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn schema_query() -> Option<Query> {
+        let sql = "
+SELECT
+  type AS kind,
+  name,
+  sql AS ddl
+FROM
+  sqlite_master
+WHERE
+  type IN ('table', 'index', 'view')
+  AND name NOT LIKE 'sqlite_%'
+  AND sql IS NOT NULL;
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn create_quarantine_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_quarantine(
+  version {version_type} NOT NULL,
+  source_id text NOT NULL DEFAULT '',
+  description text NOT NULL,
+  error text NOT NULL,
+  attempts integer NOT NULL,
+  first_failed_at timestamp NOT NULL,
+  last_failed_at timestamp NOT NULL,
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_quarantine_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_quarantine;");
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_quarantine(version, source_id, description, error, attempts, first_failed_at, last_failed_at)
+  VALUES (?1, ?2, ?3, ?4, 1, ?5, ?6)
+  ON CONFLICT (source_id, version) DO UPDATE
+  SET
+    description = excluded.description,
+    error = excluded.error,
+    attempts = {history_table}_quarantine.attempts + 1,
+    last_failed_at = excluded.last_failed_at;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  version,
+  description,
+  error,
+  attempts,
+  first_failed_at,
+  last_failed_at
+FROM
+  {history_table}_quarantine
+WHERE
+  source_id = ?1
+ORDER BY
+  version;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn delete_quarantine_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "DELETE FROM {history_table}_quarantine WHERE version = ?1 AND source_id = ?2;"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    /// SQLite analyzes one table at a time.
+    fn analyze_query(tables: &[String]) -> Option<Query> {
+        let sql = tables
+            .iter()
+            .map(|table| format!("ANALYZE {table};"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Some(Query::new(sql))
+    }
+
+    fn create_repeatable_table_if_not_exists_query(
+        history_table: &str,
+    ) -> Option<Query> {
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}_repeatable(
+  description text PRIMARY KEY,
+  checksum text NOT NULL,
+  applied_at timestamp NOT NULL,
+  duration_ms integer NOT NULL
+);
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn drop_repeatable_table_query(history_table: &str) -> Option<Query> {
+        let sql = format!("DROP TABLE IF EXISTS {history_table}_repeatable;");
+
+        Some(Query::new(sql))
+    }
+
+    fn upsert_repeatable_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+INSERT INTO {history_table}_repeatable(description, checksum, applied_at, duration_ms)
+  VALUES (?1, ?2, ?3, ?4)
+  ON CONFLICT (description) DO UPDATE
+  SET
+    checksum = excluded.checksum,
+    applied_at = excluded.applied_at,
+    duration_ms = excluded.duration_ms;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    fn select_repeatable_query(history_table: &str) -> Option<Query> {
+        let sql = format!(
+            "
+SELECT
+  description,
+  checksum,
+  applied_at,
+  duration_ms
+FROM
+  {history_table}_repeatable
+ORDER BY
+  description;
+"
+        );
+
+        Some(Query::new(sql))
+    }
+
+    /// SQLite allows one writer at a time, so there's no need for a lock.
+    fn acquire_lock_query(_: &str) -> Option<Query> {
+        None
+    }
+
+    fn release_lock_query(_: &str) -> Option<Query> {
+        None
+    }
+}
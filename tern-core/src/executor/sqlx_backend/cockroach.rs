@@ -0,0 +1,300 @@
+//! [`Executor`] for CockroachDB with [`sqlx::PgPool`][pg-pool], since
+//! CockroachDB speaks the postgres protocol.
+//!
+//! CockroachDB runs every transaction as `SERIALIZABLE` and aborts one that
+//! conflicts with another with the error `40001`, which the client is expected
+//! to retry.  A migration applied in a transaction is retried a few times with
+//! backoff, see [`SqlxExecutor::with_serialization_retries`].
+//!
+//! [`Executor`]: crate::migration::Executor
+//! [pg-pool]: https://docs.rs/sqlx/0.8.3/sqlx/type.PgPool.html
+use sqlx::Postgres;
+
+use super::pool::SqlxExecutor;
+use crate::executor::postgres::PgQueryRepo;
+use crate::migration::{
+    AppliedMigration, ApplyOptions, Dialect, Query, QueryRepository,
+    VersionKind,
+};
+
+/// Specialization of `SqlxExecutor` to a `sqlx::PgPool` connected to
+/// CockroachDB.
+pub type SqlxCockroachExecutor = SqlxExecutor<Postgres, SqlxCockroachQueryRepo>;
+
+/// The schema history table queries for CockroachDB.
+///
+/// These are the postgres ones, except where CockroachDB doesn't support
+/// them.  The history table is created with every column and its primary key,
+/// because CockroachDB changes a primary key with `ALTER PRIMARY KEY` instead
+/// of dropping and adding the constraint.  CockroachDB has no advisory locks,
+/// so there is no run lock, and no `NOTIFY`.  An index that fails to build is
+/// removed, so there are no invalid ones to list.
+#[derive(Debug, Clone)]
+pub struct SqlxCockroachQueryRepo;
+
+impl QueryRepository for SqlxCockroachQueryRepo {
+    const DIALECT: Option<Dialect> = Some(Dialect::Postgres);
+
+    const SERIALIZATION_RETRIES: u32 = 5;
+
+    fn version_column_type(version: VersionKind) -> &'static str {
+        PgQueryRepo::version_column_type(version)
+    }
+
+    fn create_history_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Query {
+        let version_type = Self::version_column_type(version);
+        let sql = format!(
+            "
+CREATE TABLE IF NOT EXISTS {history_table}(
+  version {version_type} NOT NULL,
+  description text NOT NULL,
+  content text NOT NULL,
+  duration_ms bigint NOT NULL,
+  applied_at timestamptz NOT NULL DEFAULT now(),
+  source_digest text,
+  source_id text NOT NULL DEFAULT '',
+  search_path text,
+  build_version text,
+  build_git_sha text,
+  built_at text,
+  checksum text,
+  PRIMARY KEY (source_id, version)
+);
+"
+        );
+
+        Query::new(sql)
+    }
+
+    fn drop_history_query(history_table: &str) -> Query {
+        PgQueryRepo::drop_history_query(history_table)
+    }
+
+    fn history_exists_query() -> Option<Query> {
+        PgQueryRepo::history_exists_query()
+    }
+
+    fn insert_into_history_query(
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> Query {
+        PgQueryRepo::insert_into_history_query(history_table, applied)
+    }
+
+    /// A table created by another executor, or an older version, can still be
+    /// missing columns.
+    fn add_history_columns_queries(
+        history_table: &str,
+    ) -> Vec<(&'static str, Query)> {
+        let source_id = format!(
+            "
+ALTER TABLE {history_table} ADD COLUMN source_id text NOT NULL DEFAULT '';
+ALTER TABLE {history_table} ALTER PRIMARY KEY USING COLUMNS (source_id, version);
+"
+        );
+        PgQueryRepo::add_history_columns_queries(history_table)
+            .into_iter()
+            .map(|(column, query)| match column {
+                "source_id" => (column, Query::new(source_id.clone())),
+                _ => (column, query),
+            })
+            .collect()
+    }
+
+    fn select_star_from_history_query(history_table: &str) -> Query {
+        PgQueryRepo::select_star_from_history_query(history_table)
+    }
+
+    fn upsert_history_query(
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> Query {
+        PgQueryRepo::upsert_history_query(history_table, applied)
+    }
+
+    fn delete_from_history_query(history_table: &str) -> Query {
+        PgQueryRepo::delete_from_history_query(history_table)
+    }
+
+    fn create_checkpoint_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        PgQueryRepo::create_checkpoint_table_if_not_exists_query(
+            history_table,
+            version,
+        )
+    }
+
+    fn drop_checkpoint_table_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::drop_checkpoint_table_query(history_table)
+    }
+
+    fn select_checkpoint_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::select_checkpoint_query(history_table)
+    }
+
+    fn upsert_checkpoint_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::upsert_checkpoint_query(history_table)
+    }
+
+    fn delete_checkpoint_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::delete_checkpoint_query(history_table)
+    }
+
+    fn create_progress_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        PgQueryRepo::create_progress_table_if_not_exists_query(
+            history_table,
+            version,
+        )
+    }
+
+    fn drop_progress_table_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::drop_progress_table_query(history_table)
+    }
+
+    fn insert_progress_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::insert_progress_query(history_table)
+    }
+
+    fn select_progress_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::select_progress_query(history_table)
+    }
+
+    fn delete_progress_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::delete_progress_query(history_table)
+    }
+
+    fn inventory_query() -> Option<Query> {
+        PgQueryRepo::inventory_query()
+    }
+
+    /// The statement that creates a table includes its indexes.
+    fn schema_query() -> Option<Query> {
+        let sql = "
+SELECT
+  descriptor_type AS kind,
+  descriptor_name AS name,
+  create_statement AS ddl
+FROM
+  crdb_internal.create_statements
+WHERE
+  database_name = current_database()
+  AND schema_name = ANY (current_schemas(false))
+  AND descriptor_type IN ('table', 'view');
+";
+
+        Some(Query::new(sql.to_string()))
+    }
+
+    fn create_quarantine_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        PgQueryRepo::create_quarantine_table_if_not_exists_query(
+            history_table,
+            version,
+        )
+    }
+
+    fn drop_quarantine_table_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::drop_quarantine_table_query(history_table)
+    }
+
+    fn upsert_quarantine_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::upsert_quarantine_query(history_table)
+    }
+
+    fn select_quarantine_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::select_quarantine_query(history_table)
+    }
+
+    fn delete_quarantine_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::delete_quarantine_query(history_table)
+    }
+
+    /// `ANALYZE` takes one table.
+    fn analyze_query(tables: &[String]) -> Option<Query> {
+        let sql = tables
+            .iter()
+            .map(|table| format!("ANALYZE {table};"))
+            .collect::<Vec<_>>();
+
+        Some(Query::new(sql.join("\n")))
+    }
+
+    fn create_repeatable_table_if_not_exists_query(
+        history_table: &str,
+    ) -> Option<Query> {
+        PgQueryRepo::create_repeatable_table_if_not_exists_query(history_table)
+    }
+
+    fn drop_repeatable_table_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::drop_repeatable_table_query(history_table)
+    }
+
+    fn upsert_repeatable_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::upsert_repeatable_query(history_table)
+    }
+
+    fn select_repeatable_query(history_table: &str) -> Option<Query> {
+        PgQueryRepo::select_repeatable_query(history_table)
+    }
+
+    fn acquire_lock_query(_: &str) -> Option<Query> {
+        None
+    }
+
+    fn release_lock_query(_: &str) -> Option<Query> {
+        None
+    }
+
+    fn set_session_query(options: &ApplyOptions) -> Option<Query> {
+        PgQueryRepo::set_session_query(options)
+    }
+
+    fn reset_session_query(options: &ApplyOptions) -> Option<Query> {
+        PgQueryRepo::reset_session_query(options)
+    }
+
+    fn set_search_path_query(search_path: &str) -> Option<Query> {
+        PgQueryRepo::set_search_path_query(search_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqlxCockroachQueryRepo;
+    use crate::executor::postgres::PgQueryRepo;
+    use crate::migration::QueryRepository;
+
+    #[test]
+    fn history_columns_alter_primary_key() {
+        let queries =
+            SqlxCockroachQueryRepo::add_history_columns_queries("history");
+        let columns = queries.iter().map(|(c, _)| *c).collect::<Vec<_>>();
+        let pg_columns = PgQueryRepo::add_history_columns_queries("history")
+            .into_iter()
+            .map(|(c, _)| c)
+            .collect::<Vec<_>>();
+        assert_eq!(columns, pg_columns);
+
+        let (_, source_id) =
+            queries.iter().find(|(c, _)| *c == "source_id").unwrap();
+        assert!(source_id.sql().contains("ALTER PRIMARY KEY"));
+        assert!(!source_id.sql().contains("DO $$"));
+    }
+
+    #[test]
+    fn analyze_one_table_at_a_time() {
+        let tables = ["a".to_string(), "b".to_string()];
+        let query = SqlxCockroachQueryRepo::analyze_query(&tables).unwrap();
+        assert_eq!(query.sql(), "ANALYZE a;\nANALYZE b;");
+    }
+}
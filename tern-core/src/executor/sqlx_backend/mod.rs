@@ -1,3 +1,7 @@
+#[cfg(feature = "sqlx_postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sqlx_postgres")))]
+pub mod cockroach;
+
 #[cfg(feature = "sqlx_mysql")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sqlx_mysql")))]
 pub mod mysql;
@@ -20,3 +24,14 @@ pub mod postgres;
 #[cfg(feature = "sqlx_sqlite")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sqlx_sqlite")))]
 pub mod sqlite;
+
+#[cfg(feature = "sqlx")]
+#[cfg_attr(
+    docsrs,
+    doc(cfg(any(
+        feature = "sqlx_mysql",
+        feature = "sqlx_postgres",
+        feature = "sqlx_sqlite"
+    )))
+)]
+pub mod transaction;
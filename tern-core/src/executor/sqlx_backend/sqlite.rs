@@ -3,87 +3,117 @@
 //! [`Executor`]: crate::migration::Executor
 //! [sqlite-pool]: https://docs.rs/sqlx/0.8.3/sqlx/type.SqlitePool.html
 use sqlx::Sqlite;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::str::FromStr;
+use std::time::Duration;
 
 use super::pool::SqlxExecutor;
-use crate::migration::{AppliedMigration, Query, QueryRepository};
+use super::transaction::SqlxTxExecutor;
+use crate::error::{Error, TernResult};
+
+pub use sqlx::sqlite::SqliteJournalMode;
+
+/// The schema history table queries for SQLite.
+pub use crate::executor::sqlite::SqliteQueryRepo as SqlxSqliteQueryRepo;
 
 /// Specialization of `SqlxExecutor` to `sqlx::SqlitePool`.
 pub type SqlxSqliteExecutor = SqlxExecutor<Sqlite, SqlxSqliteQueryRepo>;
 
-/// The schema history table queries for postgres.
-#[derive(Debug, Clone)]
-pub struct SqlxSqliteQueryRepo;
+/// Specialization of `SqlxTxExecutor` to a `sqlx::Transaction` on SQLite.
+pub type SqlxSqliteTxExecutor = SqlxTxExecutor<Sqlite, SqlxSqliteQueryRepo>;
 
-impl QueryRepository for SqlxSqliteQueryRepo {
-    fn create_history_if_not_exists_query(history_table: &str) -> Query {
-        let sql = format!(
-            "
-CREATE TABLE IF NOT EXISTS {history_table}(
-  version bigint PRIMARY KEY,
-  description text NOT NULL,
-  content text NOT NULL,
-  duration_ms bigint NOT NULL,
-  applied_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
-);
-"
-        );
+impl SqlxSqliteExecutor {
+    /// Create a pool from a connection string with these options for its
+    /// connections.
+    ///
+    /// A database that only exists while a connection to it is open, which is
+    /// an in-memory database like `sqlite::memory:` or a temporary one with an
+    /// empty filename, gets a pool of one connection that is kept open.  With
+    /// more than one, connections can each have their own database, or the
+    /// database can go away when the pool closes its idle connections.
+    pub async fn new_with_options(
+        db_url: &str,
+        options: &SqliteOptions,
+    ) -> TernResult<Self> {
+        let conn_opts = options.connect_options(db_url)?;
+        let pool_opts = if options.in_memory || is_temporary(db_url) {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            SqlitePoolOptions::new()
+        };
 
-        Query::new(sql)
+        Self::new_with(pool_opts, conn_opts).await
     }
+}
 
-    fn drop_history_query(history_table: &str) -> Query {
-        let sql = format!("DROP TABLE IF EXISTS {history_table};");
-
-        Query::new(sql)
-    }
+/// Settings for SQLite connections that are not in the connection string.
+///
+/// Options that are `None` are left to the `sqlx` defaults, which include
+/// enforcing foreign keys and a busy timeout of five seconds.
+#[derive(Debug, Clone, Default)]
+pub struct SqliteOptions {
+    /// The `journal_mode` of the database.  `WAL` lets the history be read
+    /// while a migration is being applied.
+    pub journal_mode: Option<SqliteJournalMode>,
+    /// How long a statement waits for a lock held by another connection before
+    /// failing with `SQLITE_BUSY`.
+    pub busy_timeout: Option<Duration>,
+    /// Whether foreign key constraints are enforced.
+    pub foreign_keys: Option<bool>,
+    /// Keep the database in memory, whatever the connection string says.
+    pub in_memory: bool,
+}
 
-    fn insert_into_history_query(
-        history_table: &str,
-        _: &AppliedMigration,
-    ) -> Query {
-        // With `sqlx` we're not going to use the `AppliedMigration`, the values
-        // will get in the query by `bind`ing them.
-        let sql = format!(
-            "
-INSERT INTO {history_table}(version, description, content, duration_ms, applied_at)
-  VALUES (?1, ?2, ?3, ?4, ?5);
-"
-        );
+impl SqliteOptions {
+    /// The options for connecting with this connection string.
+    pub fn connect_options(
+        &self,
+        db_url: &str,
+    ) -> TernResult<SqliteConnectOptions> {
+        let mut opts =
+            SqliteConnectOptions::from_str(db_url).map_err(Error::init)?;
+        if let Some(mode) = self.journal_mode {
+            opts = opts.journal_mode(mode);
+        }
+        if let Some(timeout) = self.busy_timeout {
+            opts = opts.busy_timeout(timeout);
+        }
+        if let Some(on) = self.foreign_keys {
+            opts = opts.foreign_keys(on);
+        }
+        if self.in_memory {
+            opts = opts.in_memory(true);
+        }
 
-        Query::new(sql)
+        Ok(opts)
     }
+}
 
-    fn select_star_from_history_query(history_table: &str) -> Query {
-        let sql = format!(
-            "
-SELECT
-  version,
-  description,
-  content,
-  duration_ms,
-  applied_at
-FROM
-  {history_table}
-ORDER BY
-  version;
-"
-        );
-
-        Query::new(sql)
-    }
+// Whether the database in the connection string is one that is gone when the
+// last connection to it is closed.
+fn is_temporary(db_url: &str) -> bool {
+    let url = db_url.trim_start_matches("sqlite:").trim_start_matches("//");
+    let (database, params) = url.split_once('?').unwrap_or((url, ""));
+    database.is_empty()
+        || database == ":memory:"
+        || params.split('&').any(|p| p == "mode=memory")
+}
 
-    fn upsert_history_query(
-        history_table: &str,
-        _: &AppliedMigration,
-    ) -> Query {
-        let sql = format!(
-            "
-INSERT INTO {history_table}(version, description, content, duration_ms, applied_at)
-  VALUES (?1, ?2, ?3, ?4, ?5)
-  ON CONFLICT REPLACE;
-"
-        );
+#[cfg(test)]
+mod tests {
+    use super::is_temporary;
 
-        Query::new(sql)
+    #[test]
+    fn temporary_databases() {
+        assert!(is_temporary("sqlite::memory:"));
+        assert!(is_temporary("sqlite://:memory:"));
+        assert!(is_temporary("sqlite://app.db?mode=memory&cache=shared"));
+        assert!(is_temporary("sqlite://"));
+        assert!(!is_temporary("sqlite://app.db"));
+        assert!(!is_temporary("sqlite:///tmp/app.db?mode=rwc"));
     }
 }
@@ -0,0 +1,345 @@
+//! [`Executor`] for a [`sqlx::Transaction`][sqlx-tx] that the caller already
+//! began, so that migrations can be applied together with other changes and
+//! committed or rolled back with them.
+//!
+//! [`Executor`]: crate::migration::Executor
+//! [sqlx-tx]: https://docs.rs/sqlx/0.8.3/sqlx/struct.Transaction.html
+use crate::error::{DatabaseError as _, Error, TernResult};
+use crate::executor::common::{self, CreateTables, log_step};
+use crate::migration::{
+    AppliedMigration, AppliedRepeatable, ApplyOptions,
+    Executor as MigrationExecutor, Query, QueryRepository, Step, VersionKind,
+};
+
+use super::pool::bind_version;
+
+use chrono::{DateTime, Utc};
+use sqlx::{
+    Connection, Database, Encode, Executor, FromRow, IntoArguments,
+    Transaction, Type,
+};
+use std::marker::PhantomData;
+use std::time::Instant;
+
+/// An executor that applies migrations and writes the history in a
+/// transaction that it doesn't commit.
+///
+/// Each migration is applied in a savepoint of the transaction, so one that
+/// fails is rolled back on its own, and the transaction can still be used,
+/// e.g., to roll back the rest of it.  When the run is done, get the
+/// transaction back with [`Runner::into_context`] and [`into_transaction`],
+/// then commit it:
+///
+/// ```rust,ignore
+/// let tx = pool.begin().await?;
+/// let context = MyContext { executor: SqlxPgTxExecutor::new(tx) };
+/// let mut runner = Runner::new(context);
+/// runner.run_apply_all(false).await?;
+/// let mut tx = runner.into_context().executor.into_transaction();
+/// sqlx::query("INSERT INTO settings VALUES ('ready', true)")
+///     .execute(&mut *tx)
+///     .await?;
+/// tx.commit().await?;
+/// ```
+///
+/// A migration that is not applied in a transaction can't be applied with
+/// it, and neither can one on MySQL that changes the schema, since that
+/// commits the transaction.  There is no run lock, since it would be taken
+/// for the session and not the transaction.
+///
+/// [`Runner::into_context`]: crate::runner::Runner::into_context
+/// [`into_transaction`]: Self::into_transaction
+pub struct SqlxTxExecutor<Db, Q>
+where
+    Db: Database,
+    Q: QueryRepository,
+{
+    tx: Transaction<'static, Db>,
+    _q: PhantomData<Q>,
+}
+
+impl<Db, Q> SqlxTxExecutor<Db, Q>
+where
+    Db: Database,
+    Q: QueryRepository,
+{
+    /// Apply migrations in this transaction.
+    pub fn new(tx: Transaction<'static, Db>) -> Self {
+        Self { tx, _q: PhantomData }
+    }
+
+    /// The transaction, to commit or to roll back.
+    pub fn into_transaction(self) -> Transaction<'static, Db> {
+        self.tx
+    }
+}
+
+impl<Db, Q> SqlxTxExecutor<Db, Q>
+where
+    Db: Database,
+    Q: QueryRepository,
+    for<'c> &'c mut <Db as Database>::Connection: Executor<'c, Database = Db>,
+{
+    // The table of repeatable migrations is created in the transaction, like
+    // the history table.
+    async fn create_repeatable_table(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<()> {
+        let Some(query) =
+            Q::create_repeatable_table_if_not_exists_query(history_table)
+        else {
+            return Ok(());
+        };
+        self.tx.execute(sqlx::raw_sql(query.sql())).await.void_tern_result()
+    }
+}
+
+impl<Db, Q> CreateTables for SqlxTxExecutor<Db, Q>
+where
+    Db: Database,
+    Q: QueryRepository,
+    for<'c> &'c mut <Db as Database>::Connection: Executor<'c, Database = Db>,
+{
+    async fn execute_sql(&mut self, sql: &str) -> TernResult<()> {
+        self.tx.execute(sqlx::raw_sql(sql)).await.void_tern_result()
+    }
+
+    // Selecting a missing column is an error that aborts the transaction on
+    // Postgres, so the probe is in a savepoint that is rolled back.
+    async fn probe(&mut self, sql: &str) -> TernResult<()> {
+        let mut savepoint = self.tx.begin().await.tern_result()?;
+        let res =
+            savepoint.execute(sqlx::raw_sql(sql)).await.void_tern_result();
+        savepoint.rollback().await.void_tern_result()?;
+
+        res
+    }
+}
+
+impl<Db, Q> MigrationExecutor for SqlxTxExecutor<Db, Q>
+where
+    Self: Send + Sync + 'static,
+    Q: QueryRepository,
+    Db: Database,
+    for<'c> &'c mut <Db as Database>::Connection: Executor<'c, Database = Db>,
+    for<'q> <Db as Database>::Arguments<'q>: IntoArguments<'q, Db>,
+    for<'r> AppliedMigration: FromRow<'r, <Db as Database>::Row>,
+    for<'r> AppliedRepeatable: FromRow<'r, <Db as Database>::Row>,
+    String: Type<Db> + for<'a> Encode<'a, Db>,
+    Option<String>: Type<Db> + for<'a> Encode<'a, Db>,
+    i64: Type<Db> + for<'a> Encode<'a, Db>,
+    DateTime<Utc>: Type<Db> + for<'a> Encode<'a, Db>,
+{
+    type Queries = Q;
+
+    async fn apply_tx(&mut self, query: &Query) -> TernResult<()> {
+        let mut savepoint = self.tx.begin().await.tern_result()?;
+        savepoint
+            .execute(sqlx::raw_sql(query.sql()))
+            .await
+            .void_tern_result()?;
+        savepoint.commit().await.void_tern_result()
+    }
+
+    /// Session settings are not supported, and with
+    /// [`ApplyOptions::split_statements`] the statements run one at a time in
+    /// the savepoint.
+    async fn apply_statements(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<Vec<Step>> {
+        if options.no_tx || !options.split_statements {
+            return self.apply(query, options).await.map(|()| Vec::new());
+        }
+        let statements = query.split_statements()?;
+        let count = statements.len();
+        let mut savepoint = self.tx.begin().await.tern_result()?;
+        let mut steps = Vec::new();
+        for (n, statement) in statements.into_iter().enumerate() {
+            let start = Instant::now();
+            savepoint
+                .execute(sqlx::raw_sql(&statement))
+                .await
+                .void_tern_result()?;
+            let step = Step { statement, duration: start.elapsed() };
+            log_step(n, count, &step);
+            steps.push(step);
+        }
+        savepoint.commit().await.void_tern_result()?;
+
+        Ok(steps)
+    }
+
+    async fn apply_no_tx(&mut self, _: &Query) -> TernResult<()> {
+        Err(Error::Invalid(
+            "a migration that is not applied in a transaction can't be applied in the caller's transaction"
+                .into(),
+        ))
+    }
+
+    async fn create_history_if_not_exists(
+        &mut self,
+        history_table: &str,
+        version: VersionKind,
+    ) -> TernResult<()> {
+        let query =
+            Q::create_history_if_not_exists_query(history_table, version);
+        let columns = Q::add_history_columns_queries(history_table);
+        common::create_history(self, history_table, &query, columns).await
+    }
+
+    /// Without a query that looks for the table, it is read in a savepoint,
+    /// so that failing to read it doesn't abort the transaction.
+    async fn history_exists(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<bool> {
+        let Some(query) = Q::history_exists_query() else {
+            let query = Q::select_star_from_history_query(history_table);
+            let mut savepoint = self.tx.begin().await.tern_result()?;
+            let res = savepoint.execute(sqlx::raw_sql(query.sql())).await;
+            savepoint.rollback().await.void_tern_result()?;
+            return Ok(res.is_ok());
+        };
+        let row = sqlx::query::<Db>(query.sql())
+            .bind(history_table.to_string())
+            .fetch_optional(&mut *self.tx)
+            .await
+            .tern_result()?;
+
+        Ok(row.is_some())
+    }
+
+    async fn drop_history(&mut self, history_table: &str) -> TernResult<()> {
+        let queries = [
+            Q::drop_checkpoint_table_query(history_table),
+            Q::drop_progress_table_query(history_table),
+            Q::drop_quarantine_table_query(history_table),
+            Q::drop_repeatable_table_query(history_table),
+            Some(Q::drop_history_query(history_table)),
+        ];
+        for query in queries.into_iter().flatten() {
+            self.tx
+                .execute(sqlx::raw_sql(query.sql()))
+                .await
+                .void_tern_result()?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_all_applied(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedMigration>> {
+        let query = Q::select_star_from_history_query(history_table);
+        sqlx::query_as::<Db, AppliedMigration>(query.sql())
+            .fetch_all(&mut *self.tx)
+            .await
+            .tern_result()
+    }
+
+    async fn insert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query = Q::insert_into_history_query(history_table, applied);
+        let q = sqlx::query::<Db>(query.sql());
+        bind_version(q, &applied.version)
+            .bind(applied.description.clone())
+            .bind(applied.content.clone())
+            .bind(applied.duration_ms)
+            .bind(applied.applied_at)
+            .bind(applied.source_digest.clone())
+            .bind(applied.source_id.clone().unwrap_or_default())
+            .bind(applied.search_path.clone())
+            .bind(applied.build_version.clone())
+            .bind(applied.build_git_sha.clone())
+            .bind(applied.built_at.clone())
+            .bind(applied.checksum.clone())
+            .execute(&mut *self.tx)
+            .await
+            .void_tern_result()
+    }
+
+    async fn upsert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query = Q::upsert_history_query(history_table, applied);
+        let q = sqlx::query::<Db>(query.sql());
+        bind_version(q, &applied.version)
+            .bind(applied.description.clone())
+            .bind(applied.content.clone())
+            .bind(applied.duration_ms)
+            .bind(applied.applied_at)
+            .bind(applied.source_digest.clone())
+            .bind(applied.source_id.clone().unwrap_or_default())
+            .bind(applied.search_path.clone())
+            .bind(applied.build_version.clone())
+            .bind(applied.build_git_sha.clone())
+            .bind(applied.built_at.clone())
+            .bind(applied.checksum.clone())
+            .execute(&mut *self.tx)
+            .await
+            .void_tern_result()
+    }
+
+    async fn delete_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query = Q::delete_from_history_query(history_table);
+        let q = sqlx::query::<Db>(query.sql());
+        bind_version(q, &applied.version)
+            .bind(applied.source_id.clone().unwrap_or_default())
+            .execute(&mut *self.tx)
+            .await
+            .void_tern_result()
+    }
+
+    async fn get_applied_repeatable(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedRepeatable>> {
+        let Some(query) = Q::select_repeatable_query(history_table) else {
+            return Ok(Vec::new());
+        };
+        self.create_repeatable_table(history_table).await?;
+        sqlx::query_as::<Db, AppliedRepeatable>(query.sql())
+            .fetch_all(&mut *self.tx)
+            .await
+            .tern_result()
+    }
+
+    async fn upsert_applied_repeatable(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedRepeatable,
+    ) -> TernResult<()> {
+        let Some(query) = Q::upsert_repeatable_query(history_table) else {
+            return Err(Error::Invalid(
+                "repeatable migrations are not supported by this database"
+                    .into(),
+            ));
+        };
+        self.create_repeatable_table(history_table).await?;
+        sqlx::query::<Db>(query.sql())
+            .bind(applied.description.clone())
+            .bind(applied.checksum.clone())
+            .bind(applied.applied_at)
+            .bind(applied.duration_ms)
+            .execute(&mut *self.tx)
+            .await
+            .void_tern_result()
+    }
+
+    async fn ping(&mut self) -> TernResult<()> {
+        self.tx.ping().await.tern_result()
+    }
+}
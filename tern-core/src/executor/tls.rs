@@ -0,0 +1,187 @@
+//! TLS options for connecting to the database.
+//!
+//! The connection string is how the executors here are configured, so
+//! [`TlsOptions`] is applied by adding the corresponding parameters to it,
+//! which are spelled differently for each database.
+use crate::error::{Error, TernResult};
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use url::Url;
+
+/// How much TLS is required of the connection, following the names of the
+/// Postgres `sslmode` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SslMode {
+    /// Do not use TLS.
+    Disable,
+    /// Use TLS if the server supports it.
+    Prefer,
+    /// Use TLS, but do not verify the server certificate.
+    Require,
+    /// Use TLS and verify that the server certificate is signed by a trusted
+    /// authority.
+    VerifyCa,
+    /// Use TLS, verify the server certificate, and verify that the host name
+    /// matches the certificate.
+    VerifyFull,
+}
+
+impl SslMode {
+    /// The names that `SslMode` is parsed from.
+    pub const VARIANTS: [&str; 5] =
+        ["disable", "prefer", "require", "verify-ca", "verify-full"];
+
+    fn postgres(self) -> &'static str {
+        match self {
+            Self::Disable => "disable",
+            Self::Prefer => "prefer",
+            Self::Require => "require",
+            Self::VerifyCa => "verify-ca",
+            Self::VerifyFull => "verify-full",
+        }
+    }
+
+    fn mysql(self) -> &'static str {
+        match self {
+            Self::Disable => "DISABLED",
+            Self::Prefer => "PREFERRED",
+            Self::Require => "REQUIRED",
+            Self::VerifyCa => "VERIFY_CA",
+            Self::VerifyFull => "VERIFY_IDENTITY",
+        }
+    }
+}
+
+impl FromStr for SslMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disable" => Ok(Self::Disable),
+            "prefer" => Ok(Self::Prefer),
+            "require" => Ok(Self::Require),
+            "verify-ca" => Ok(Self::VerifyCa),
+            "verify-full" => Ok(Self::VerifyFull),
+            _ => Err(Error::Invalid(format!("unknown ssl mode {s}"))),
+        }
+    }
+}
+
+/// TLS settings for the connection.
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// The TLS mode.
+    pub ssl_mode: Option<SslMode>,
+    /// Certificate of the authority that signed the server certificate.
+    pub root_cert: Option<PathBuf>,
+    /// Client certificate to authenticate with.
+    pub client_cert: Option<PathBuf>,
+    /// Private key of the client certificate.
+    pub client_key: Option<PathBuf>,
+}
+
+impl TlsOptions {
+    /// Whether any option is set.
+    pub fn is_empty(&self) -> bool {
+        self.ssl_mode.is_none()
+            && self.root_cert.is_none()
+            && self.client_cert.is_none()
+            && self.client_key.is_none()
+    }
+
+    /// Add the parameters for these options to a connection string.
+    ///
+    /// This is an error for a database that the options do not apply to, such
+    /// as SQLite.
+    pub fn apply_to_url(&self, db_url: &str) -> TernResult<String> {
+        if self.is_empty() {
+            return Ok(db_url.to_string());
+        }
+        let scheme = db_url.split_once("://").map(|(s, _)| s);
+        let names = match scheme {
+            Some("postgres" | "postgresql") => {
+                ["sslmode", "sslrootcert", "sslcert", "sslkey"]
+            },
+            Some("mysql" | "mariadb") => {
+                ["ssl-mode", "ssl-ca", "ssl-cert", "ssl-key"]
+            },
+            _ => {
+                return Err(Error::Invalid(
+                    "TLS options are only supported for postgres and mysql"
+                        .into(),
+                ));
+            },
+        };
+        let mode = self.ssl_mode.map(|m| match scheme {
+            Some("mysql" | "mariadb") => m.mysql().to_string(),
+            _ => m.postgres().to_string(),
+        });
+        let path = |p: &Option<PathBuf>| {
+            p.as_ref().map(|p| p.to_string_lossy().into_owned())
+        };
+        let values = [
+            mode,
+            path(&self.root_cert),
+            path(&self.client_cert),
+            path(&self.client_key),
+        ];
+
+        // The error doesn't have the URL in it, which can have a password.
+        let mut url = Url::parse(db_url).map_err(|e| {
+            Error::Invalid(format!("invalid connection string: {e}"))
+        })?;
+        // A path can have characters that need to be percent-encoded.
+        url.query_pairs_mut().extend_pairs(
+            names.iter().zip(values).filter_map(|(n, v)| Some((n, v?))),
+        );
+
+        Ok(url.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SslMode, TlsOptions};
+
+    #[test]
+    fn url_params() {
+        let tls = TlsOptions {
+            ssl_mode: Some(SslMode::VerifyFull),
+            root_cert: Some("/certs/ca.pem".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            tls.apply_to_url("postgres://u@h/db").unwrap(),
+            "postgres://u@h/db?sslmode=verify-full&sslrootcert=%2Fcerts%2Fca.pem"
+        );
+        assert_eq!(
+            tls.apply_to_url("mysql://u@h/db?x=1").unwrap(),
+            "mysql://u@h/db?x=1&ssl-mode=VERIFY_IDENTITY&ssl-ca=%2Fcerts%2Fca.pem"
+        );
+        assert!(tls.apply_to_url("sqlite://db.sqlite").is_err());
+        assert_eq!(
+            TlsOptions::default().apply_to_url("sqlite://db.sqlite").unwrap(),
+            "sqlite://db.sqlite"
+        );
+    }
+
+    #[test]
+    fn url_params_encoded() {
+        let tls = TlsOptions {
+            client_cert: Some("/my certs/client.pem".into()),
+            client_key: Some("/my certs/key&1.pem".into()),
+            ..Default::default()
+        };
+        let url = tls.apply_to_url("postgres://u@h/db").unwrap();
+        assert_eq!(
+            url,
+            "postgres://u@h/db?sslcert=%2Fmy+certs%2Fclient.pem\
+             &sslkey=%2Fmy+certs%2Fkey%261.pem"
+        );
+        let parsed = url::Url::parse(&url).unwrap();
+        let pairs = parsed.query_pairs().collect::<Vec<_>>();
+        assert_eq!(pairs[0].1, "/my certs/client.pem");
+        assert_eq!(pairs[1].1, "/my certs/key&1.pem");
+    }
+}
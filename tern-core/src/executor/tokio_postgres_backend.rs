@@ -0,0 +1,527 @@
+//! [`Executor`] for a [`tokio_postgres::Client`][client], for an application
+//! that already uses `tokio-postgres`, or a pool of its clients like
+//! `deadpool-postgres`, and doesn't want `sqlx` only to apply migrations.
+//!
+//! With the feature `deadpool_postgres`, `DeadpoolPgExecutor` is one with a
+//! client checked out of a [`deadpool_postgres::Pool`][pool], which goes back
+//! to the pool when the executor is dropped.
+//!
+//! [`Executor`]: crate::migration::Executor
+//! [client]: https://docs.rs/tokio-postgres/0.7/tokio_postgres/struct.Client.html
+//! [pool]: https://docs.rs/deadpool-postgres/0.14/deadpool_postgres/type.Pool.html
+use crate::error::{DatabaseError as _, Error, TernResult};
+use crate::executor::common::{self, CreateTables, Param, log_steps};
+use crate::executor::postgres::PgQueryRepo;
+use crate::migration::{
+    AppliedMigration, AppliedRepeatable, ApplyOptions,
+    Executor as MigrationExecutor, Query, QueryRepository, Step, Version,
+    VersionKind,
+};
+
+use chrono::{DateTime, Utc};
+use std::time::Instant;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, NoTls, Row};
+
+/// A connection to make the queries of a [`TokioPgExecutor`] with, which is a
+/// `tokio_postgres::Client` or one checked out of a pool.
+pub trait PgClient: Send + Sync {
+    /// The client.
+    fn client(&self) -> &Client;
+
+    /// The client, to start a transaction with.
+    fn client_mut(&mut self) -> &mut Client;
+}
+
+impl PgClient for Client {
+    fn client(&self) -> &Client {
+        self
+    }
+
+    fn client_mut(&mut self) -> &mut Client {
+        self
+    }
+}
+
+#[cfg(feature = "deadpool_postgres")]
+impl PgClient for deadpool_postgres::Object {
+    fn client(&self) -> &Client {
+        self
+    }
+
+    fn client_mut(&mut self) -> &mut Client {
+        self
+    }
+}
+
+/// A `tokio_postgres::Client` as a migration executor backend.
+///
+/// Every query is made with the one client, which is one connection, so the
+/// run lock and the session settings are for that connection.  Checkpoints,
+/// recording the statements of a migration, the table of migrations that
+/// failed, and listing the objects in the database are not supported.
+pub struct TokioPgExecutor<C = Client> {
+    client: C,
+    // Whether the table of repeatable migrations is known to exist.
+    repeatable: bool,
+    // The query that `set_session_setup` ran, to run again after resetting
+    // the settings of a migration.
+    session_setup: Option<Query>,
+}
+
+/// Specialization of `TokioPgExecutor` to a client checked out of a
+/// `deadpool_postgres::Pool`, like the one the application uses.
+///
+/// The client goes back to the pool when the executor is dropped.  Settings
+/// made with [`set_session_setup`] stay with its connection unless the pool
+/// recycles connections with `RecyclingMethod::Clean`.
+///
+/// [`set_session_setup`]: crate::migration::Executor::set_session_setup
+#[cfg(feature = "deadpool_postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "deadpool_postgres")))]
+pub type DeadpoolPgExecutor = TokioPgExecutor<deadpool_postgres::Object>;
+
+impl TokioPgExecutor {
+    /// Connect without TLS from a connection string, running the connection
+    /// on the current `tokio` runtime.
+    ///
+    /// With TLS, connect with `tokio_postgres::connect` and a TLS connector,
+    /// spawn the connection, and use [`from_client`](Self::from_client).
+    pub async fn new(db_url: &str) -> TernResult<Self> {
+        let (client, connection) = tokio_postgres::connect(db_url, NoTls)
+            .await
+            .map_err(Error::init)?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                log::error!("tokio-postgres connection error: {e}");
+            }
+        });
+
+        Ok(Self::from_client(client))
+    }
+}
+
+#[cfg(feature = "deadpool_postgres")]
+impl DeadpoolPgExecutor {
+    /// Check a client out of the pool to apply migrations with, waiting for
+    /// one if they are all in use.
+    pub async fn from_pool(pool: &deadpool_postgres::Pool) -> TernResult<Self> {
+        let client = pool.get().await.map_err(Error::init)?;
+
+        Ok(Self::from_client(client))
+    }
+}
+
+impl<C: PgClient> TokioPgExecutor<C> {
+    /// Apply migrations with a client that is already connected.
+    pub fn from_client(client: C) -> Self {
+        Self { client, repeatable: false, session_setup: None }
+    }
+
+    /// The client, to use it for something else in between runs.
+    pub fn client(&self) -> &Client {
+        self.client.client()
+    }
+
+    /// The client, when the executor is no longer needed.
+    pub fn into_client(self) -> C {
+        self.client
+    }
+
+    // Apply the statements of the query one at a time, or all of them at
+    // once in a transaction unless they are split.
+    async fn run_statements(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<Vec<Step>> {
+        let session = PgQueryRepo::set_session_query(options);
+        if !options.no_tx {
+            let tx =
+                self.client.client_mut().transaction().await.tern_result()?;
+            if let Some(session) = session {
+                tx.batch_execute(session.sql()).await.void_tern_result()?;
+            }
+            if !options.split_statements {
+                tx.batch_execute(query.sql()).await.void_tern_result()?;
+                tx.commit().await.void_tern_result()?;
+
+                return Ok(Vec::new());
+            }
+            let mut steps = Vec::new();
+            for statement in query.split_statements()? {
+                let start = Instant::now();
+                tx.batch_execute(&statement).await.void_tern_result()?;
+                steps.push(Step { statement, duration: start.elapsed() });
+            }
+            tx.commit().await.void_tern_result()?;
+            log_steps(&steps);
+
+            return Ok(steps);
+        }
+
+        let statements = query.split_statements()?;
+        let mut res = Ok(());
+        if let Some(session) = session.as_ref() {
+            res = self
+                .client
+                .client()
+                .batch_execute(session.sql())
+                .await
+                .void_tern_result();
+        }
+        let mut steps = Vec::new();
+        for statement in statements {
+            if res.is_err() {
+                break;
+            }
+            let start = Instant::now();
+            res = self
+                .client
+                .client()
+                .batch_execute(&statement)
+                .await
+                .void_tern_result();
+            if res.is_ok() {
+                steps.push(Step { statement, duration: start.elapsed() });
+            }
+        }
+        log_steps(&steps);
+        // Resetting a setting goes back to its value from before the session
+        // setup, so that has to run again too.
+        if let Some(reset) =
+            session.and(PgQueryRepo::reset_session_query(options))
+        {
+            self.client
+                .client()
+                .batch_execute(reset.sql())
+                .await
+                .void_tern_result()?;
+            if let Some(setup) = self.session_setup.as_ref() {
+                self.client
+                    .client()
+                    .batch_execute(setup.sql())
+                    .await
+                    .void_tern_result()?;
+            }
+        }
+
+        res.map(|()| steps)
+    }
+
+    // Write a row of the history table with the insert or the upsert query.
+    async fn write_applied(
+        &mut self,
+        query: Query,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let params = common::applied_params::<PgParam>(applied);
+        self.client
+            .client()
+            .execute_raw(query.sql(), params)
+            .await
+            .void_tern_result()
+    }
+
+    async fn create_repeatable_table(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<()> {
+        let query = PgQueryRepo::create_repeatable_table_if_not_exists_query(
+            history_table,
+        );
+        let Some(query) = query.filter(|_| !self.repeatable) else {
+            return Ok(());
+        };
+        common::execute_create(self, query.sql()).await?;
+        self.repeatable = true;
+
+        Ok(())
+    }
+}
+
+impl<C: PgClient> MigrationExecutor for TokioPgExecutor<C> {
+    type Queries = PgQueryRepo;
+
+    async fn apply_tx(&mut self, query: &Query) -> TernResult<()> {
+        self.apply(query, &ApplyOptions::default()).await
+    }
+
+    async fn apply_no_tx(&mut self, query: &Query) -> TernResult<()> {
+        self.apply(query, &ApplyOptions::default().with_no_tx(true)).await
+    }
+
+    async fn apply(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<()> {
+        self.apply_statements(query, options).await.map(drop)
+    }
+
+    /// The settings from [`set_session_query`] are made in the transaction of
+    /// the migration, or, outside of one, before it and undone after it.
+    ///
+    /// [`set_session_query`]: crate::migration::QueryRepository::set_session_query
+    async fn apply_statements(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> TernResult<Vec<Step>> {
+        self.run_statements(query, options).await
+    }
+
+    async fn create_history_if_not_exists(
+        &mut self,
+        history_table: &str,
+        version: VersionKind,
+    ) -> TernResult<()> {
+        let query = PgQueryRepo::create_history_if_not_exists_query(
+            history_table,
+            version,
+        );
+        let columns = PgQueryRepo::add_history_columns_queries(history_table);
+        common::create_history(self, history_table, &query, columns).await
+    }
+
+    async fn history_exists(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<bool> {
+        let Some(query) = PgQueryRepo::history_exists_query() else {
+            return Ok(self.get_all_applied(history_table).await.is_ok());
+        };
+        let row = self
+            .client
+            .client()
+            .query_opt(query.sql(), &[&history_table])
+            .await
+            .tern_result()?;
+
+        Ok(row.is_some())
+    }
+
+    async fn drop_history(&mut self, history_table: &str) -> TernResult<()> {
+        if let Some(query) =
+            PgQueryRepo::drop_repeatable_table_query(history_table)
+        {
+            self.client
+                .client()
+                .batch_execute(query.sql())
+                .await
+                .void_tern_result()?;
+            self.repeatable = false;
+        }
+        let query = PgQueryRepo::drop_history_query(history_table);
+        self.client.client().batch_execute(query.sql()).await.void_tern_result()
+    }
+
+    async fn backup_history(
+        &mut self,
+        history_table: &str,
+        backup_table: &str,
+    ) -> TernResult<()> {
+        let query =
+            PgQueryRepo::backup_history_query(history_table, backup_table);
+        self.client.client().batch_execute(query.sql()).await.void_tern_result()
+    }
+
+    async fn get_all_applied(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedMigration>> {
+        let query = PgQueryRepo::select_star_from_history_query(history_table);
+        self.client
+            .client()
+            .query(query.sql(), &[])
+            .await
+            .tern_result()?
+            .iter()
+            .map(applied_from_row)
+            .collect::<Result<Vec<_>, _>>()
+            .tern_result()
+    }
+
+    async fn insert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query =
+            PgQueryRepo::insert_into_history_query(history_table, applied);
+        self.write_applied(query, applied).await
+    }
+
+    async fn upsert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query = PgQueryRepo::upsert_history_query(history_table, applied);
+        self.write_applied(query, applied).await
+    }
+
+    async fn delete_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> TernResult<()> {
+        let query = PgQueryRepo::delete_from_history_query(history_table);
+        let params: [PgParam; 2] = [
+            common::version_param(&applied.version),
+            PgParam::text(applied.source_id.clone().unwrap_or_default()),
+        ];
+        self.client
+            .client()
+            .execute_raw(query.sql(), params)
+            .await
+            .void_tern_result()
+    }
+
+    async fn get_applied_repeatable(
+        &mut self,
+        history_table: &str,
+    ) -> TernResult<Vec<AppliedRepeatable>> {
+        let Some(query) = PgQueryRepo::select_repeatable_query(history_table)
+        else {
+            return Ok(Vec::new());
+        };
+        self.create_repeatable_table(history_table).await?;
+        self.client
+            .client()
+            .query(query.sql(), &[])
+            .await
+            .tern_result()?
+            .iter()
+            .map(|row| {
+                Ok(AppliedRepeatable {
+                    description: row.try_get("description")?,
+                    checksum: row.try_get("checksum")?,
+                    applied_at: row.try_get("applied_at")?,
+                    duration_ms: row.try_get("duration_ms")?,
+                })
+            })
+            .collect::<Result<Vec<_>, tokio_postgres::Error>>()
+            .tern_result()
+    }
+
+    async fn upsert_applied_repeatable(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedRepeatable,
+    ) -> TernResult<()> {
+        let Some(query) = PgQueryRepo::upsert_repeatable_query(history_table)
+        else {
+            return Err(Error::Invalid(
+                "repeatable migrations are not supported by this database"
+                    .into(),
+            ));
+        };
+        self.create_repeatable_table(history_table).await?;
+        self.client
+            .client()
+            .execute(
+                query.sql(),
+                &[
+                    &applied.description,
+                    &applied.checksum,
+                    &applied.applied_at,
+                    &applied.duration_ms,
+                ],
+            )
+            .await
+            .void_tern_result()
+    }
+
+    /// There is one connection, so this runs `query` once, now.
+    async fn set_session_setup(&mut self, query: Query) -> TernResult<()> {
+        self.client
+            .client()
+            .batch_execute(query.sql())
+            .await
+            .void_tern_result()?;
+        self.session_setup = Some(query);
+
+        Ok(())
+    }
+
+    async fn ping(&mut self) -> TernResult<()> {
+        self.client.client().batch_execute("SELECT 1;").await.void_tern_result()
+    }
+
+    async fn acquire_lock(&mut self, history_table: &str) -> TernResult<()> {
+        let Some(query) = PgQueryRepo::acquire_lock_query(history_table) else {
+            return Ok(());
+        };
+        self.client.client().batch_execute(query.sql()).await.void_tern_result()
+    }
+
+    async fn release_lock(&mut self, history_table: &str) -> TernResult<()> {
+        let Some(query) = PgQueryRepo::release_lock_query(history_table) else {
+            return Ok(());
+        };
+        self.client.client().batch_execute(query.sql()).await.void_tern_result()
+    }
+}
+
+impl<C: PgClient> CreateTables for TokioPgExecutor<C> {
+    async fn execute_sql(&mut self, sql: &str) -> TernResult<()> {
+        self.client.client().batch_execute(sql).await.void_tern_result()
+    }
+}
+
+// A parameter of a query, which has the type of its column.
+type PgParam = Box<dyn ToSql + Sync + Send>;
+
+impl Param for PgParam {
+    fn integer(v: i64) -> Self {
+        Box::new(v)
+    }
+
+    fn text(v: String) -> Self {
+        Box::new(v)
+    }
+
+    fn optional_text(v: Option<String>) -> Self {
+        Box::new(v)
+    }
+
+    fn timestamp(at: DateTime<Utc>) -> Self {
+        Box::new(at)
+    }
+}
+
+// A row of the history table, which is like the `FromRow` impl of the `sqlx`
+// executors: the version is an integer or text, and a table created by an
+// older version doesn't have the newer columns.
+fn applied_from_row(
+    row: &Row,
+) -> Result<AppliedMigration, tokio_postgres::Error> {
+    let version = match row.try_get::<_, i64>("version") {
+        Ok(v) => Version::Integer(v),
+        Err(_) => Version::Text(row.try_get("version")?),
+    };
+    let optional =
+        |column: &str| -> Result<Option<String>, tokio_postgres::Error> {
+            if row.columns().iter().any(|c| c.name() == column) {
+                row.try_get(column)
+            } else {
+                Ok(None)
+            }
+        };
+
+    Ok(AppliedMigration {
+        version,
+        description: row.try_get("description")?,
+        content: row.try_get("content")?,
+        duration_ms: row.try_get("duration_ms")?,
+        applied_at: row.try_get("applied_at")?,
+        source_digest: optional("source_digest")?,
+        source_id: optional("source_id")?.filter(|id| !id.is_empty()),
+        search_path: optional("search_path")?,
+        build_version: optional("build_version")?,
+        build_git_sha: optional("build_git_sha")?,
+        built_at: optional("built_at")?,
+        checksum: optional("checksum")?,
+    })
+}
@@ -0,0 +1,391 @@
+//! [`Executor`] for types wrapping one, so that an application can share its
+//! executor with a migration context.
+use crate::error::TernResult;
+use crate::inventory::DatabaseObject;
+use crate::migration::{
+    AppliedMigration, AppliedRepeatable, ApplyOptions, Checkpoint, Executor,
+    MigrationId, QuarantinedMigration, Query, RolledBack, StatementProgress,
+    Step, Version, VersionKind,
+};
+use crate::schema::SchemaObject;
+
+use std::future::Future;
+
+// Implements every method of `Executor`, including the ones that have a
+// default, by calling it on the executor that `$inner` gets from `$this`, which
+// is the wrapper.
+macro_rules! forward_executor {
+    ($this:ident => $inner:expr) => {
+        type Queries = E::Queries;
+
+        fn apply_tx(
+            &mut self,
+            query: &Query,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.apply_tx(query).await
+            }
+        }
+
+        fn apply_no_tx(
+            &mut self,
+            query: &Query,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.apply_no_tx(query).await
+            }
+        }
+
+        fn apply(
+            &mut self,
+            query: &Query,
+            options: &ApplyOptions,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.apply(query, options).await
+            }
+        }
+
+        fn apply_statements(
+            &mut self,
+            query: &Query,
+            options: &ApplyOptions,
+        ) -> impl Future<Output = TernResult<Vec<Step>>> + Send {
+            async move {
+                let $this = self;
+                $inner.apply_statements(query, options).await
+            }
+        }
+
+        fn create_history_if_not_exists(
+            &mut self,
+            history_table: &str,
+            version: VersionKind,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner
+                    .create_history_if_not_exists(history_table, version)
+                    .await
+            }
+        }
+
+        fn history_exists(
+            &mut self,
+            history_table: &str,
+        ) -> impl Future<Output = TernResult<bool>> + Send {
+            async move {
+                let $this = self;
+                $inner.history_exists(history_table).await
+            }
+        }
+
+        fn drop_history(
+            &mut self,
+            history_table: &str,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.drop_history(history_table).await
+            }
+        }
+
+        fn get_all_applied(
+            &mut self,
+            history_table: &str,
+        ) -> impl Future<Output = TernResult<Vec<AppliedMigration>>> + Send {
+            async move {
+                let $this = self;
+                $inner.get_all_applied(history_table).await
+            }
+        }
+
+        fn insert_applied_migration(
+            &mut self,
+            history_table: &str,
+            applied: &AppliedMigration,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.insert_applied_migration(history_table, applied).await
+            }
+        }
+
+        fn upsert_applied_migration(
+            &mut self,
+            history_table: &str,
+            applied: &AppliedMigration,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.upsert_applied_migration(history_table, applied).await
+            }
+        }
+
+        fn backup_history(
+            &mut self,
+            history_table: &str,
+            backup_table: &str,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.backup_history(history_table, backup_table).await
+            }
+        }
+
+        fn delete_applied_migration(
+            &mut self,
+            history_table: &str,
+            applied: &AppliedMigration,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.delete_applied_migration(history_table, applied).await
+            }
+        }
+
+        fn get_checkpoint(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            version: &Version,
+        ) -> impl Future<Output = TernResult<Option<Checkpoint>>> + Send {
+            async move {
+                let $this = self;
+                $inner.get_checkpoint(history_table, source_id, version).await
+            }
+        }
+
+        fn save_checkpoint(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            version: &Version,
+            checkpoint: &str,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner
+                    .save_checkpoint(
+                        history_table,
+                        source_id,
+                        version,
+                        checkpoint,
+                    )
+                    .await
+            }
+        }
+
+        fn clear_checkpoint(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            version: &Version,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.clear_checkpoint(history_table, source_id, version).await
+            }
+        }
+
+        fn apply_with_progress(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            version: &Version,
+            query: &Query,
+            options: &ApplyOptions,
+        ) -> impl Future<Output = TernResult<Vec<Step>>> + Send {
+            async move {
+                let $this = self;
+                $inner
+                    .apply_with_progress(
+                        history_table,
+                        source_id,
+                        version,
+                        query,
+                        options,
+                    )
+                    .await
+            }
+        }
+
+        fn get_progress(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            version: &Version,
+        ) -> impl Future<Output = TernResult<Vec<StatementProgress>>> + Send {
+            async move {
+                let $this = self;
+                $inner.get_progress(history_table, source_id, version).await
+            }
+        }
+
+        fn clear_progress(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            version: &Version,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.clear_progress(history_table, source_id, version).await
+            }
+        }
+
+        fn record_failure(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            id: &MigrationId,
+            error: &str,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.record_failure(history_table, source_id, id, error).await
+            }
+        }
+
+        fn get_quarantined(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            version: VersionKind,
+        ) -> impl Future<Output = TernResult<Vec<QuarantinedMigration>>> + Send
+        {
+            async move {
+                let $this = self;
+                $inner.get_quarantined(history_table, source_id, version).await
+            }
+        }
+
+        fn release_quarantined(
+            &mut self,
+            history_table: &str,
+            source_id: Option<&str>,
+            version: &Version,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner
+                    .release_quarantined(history_table, source_id, version)
+                    .await
+            }
+        }
+
+        fn get_applied_repeatable(
+            &mut self,
+            history_table: &str,
+        ) -> impl Future<Output = TernResult<Vec<AppliedRepeatable>>> + Send {
+            async move {
+                let $this = self;
+                $inner.get_applied_repeatable(history_table).await
+            }
+        }
+
+        fn upsert_applied_repeatable(
+            &mut self,
+            history_table: &str,
+            applied: &AppliedRepeatable,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.upsert_applied_repeatable(history_table, applied).await
+            }
+        }
+
+        fn set_session_setup(
+            &mut self,
+            query: Query,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.set_session_setup(query).await
+            }
+        }
+
+        fn apply_rolled_back(
+            &mut self,
+            queries: &[(Query, ApplyOptions)],
+        ) -> impl Future<Output = TernResult<RolledBack>> + Send {
+            async move {
+                let $this = self;
+                $inner.apply_rolled_back(queries).await
+            }
+        }
+
+        fn inventory(
+            &mut self,
+        ) -> impl Future<Output = TernResult<Vec<DatabaseObject>>> + Send {
+            async move {
+                let $this = self;
+                $inner.inventory().await
+            }
+        }
+
+        fn invalid_indexes(
+            &mut self,
+        ) -> impl Future<Output = TernResult<Vec<DatabaseObject>>> + Send {
+            async move {
+                let $this = self;
+                $inner.invalid_indexes().await
+            }
+        }
+
+        fn schema(
+            &mut self,
+        ) -> impl Future<Output = TernResult<Vec<SchemaObject>>> + Send {
+            async move {
+                let $this = self;
+                $inner.schema().await
+            }
+        }
+
+        fn ping(&mut self) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.ping().await
+            }
+        }
+
+        fn acquire_lock(
+            &mut self,
+            history_table: &str,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.acquire_lock(history_table).await
+            }
+        }
+
+        fn release_lock(
+            &mut self,
+            history_table: &str,
+        ) -> impl Future<Output = TernResult<()>> + Send {
+            async move {
+                let $this = self;
+                $inner.release_lock(history_table).await
+            }
+        }
+    };
+}
+
+impl<E: Executor> Executor for Box<E> {
+    forward_executor!(this => (**this));
+}
+
+impl<E: Executor> Executor for &mut E {
+    forward_executor!(this => (**this));
+}
+
+/// The executor is locked for each call and not for a whole migration run, so
+/// other users of it can have it between the calls of a run.
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+impl<E: Executor> Executor for std::sync::Arc<tokio::sync::Mutex<E>> {
+    forward_executor!(this => this.lock().await);
+}
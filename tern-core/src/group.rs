@@ -0,0 +1,200 @@
+//! Running the migrations of several contexts together.
+//!
+//! An application can have more than one [`MigrationContext`], such as one for
+//! its own schema and one for an analytics schema, each with its own source,
+//! history table, and possibly database.  A [`TernGroup`] holds a [`Runner`]
+//! for each of them and runs them in the order they were added, collecting a
+//! [`GroupReport`] with the report of each.
+//!
+//! ```rust,ignore
+//! let report = TernGroup::new()
+//!     .with_context("app", app_context)
+//!     .with_runner("analytics", Runner::new(analytics_context).with_lock())
+//!     .run_apply_all(false)
+//!     .await?;
+//! ```
+//!
+//! The contexts can't share a transaction, so a group is all-or-nothing only
+//! as far as it can be: before applying anything, every context validates its
+//! migrations against its history, and if any of them fails, no context is
+//! changed.  The queries aren't built until a context is applied, since one
+//! can read what the contexts before it created.  A failure while applying
+//! stops the run, and the migrations already applied by the contexts before
+//! it stay applied.
+use crate::error::{Error, TernResult};
+use crate::future::BoxFuture;
+use crate::migration::MigrationContext;
+use crate::run_log::RunSummary;
+use crate::runner::{Report, Runner, StopHandle};
+
+use display_json::{DebugAsJson, DisplayAsJsonPretty};
+use serde::Serialize;
+
+// A runner with the type of its context erased, so that a group can have
+// runners for different contexts.
+trait GroupMember: Send {
+    fn validate(&mut self) -> BoxFuture<'_, TernResult<()>>;
+
+    fn run_apply_all(
+        &mut self,
+        dryrun: bool,
+    ) -> BoxFuture<'_, TernResult<Report>>;
+}
+
+impl<C: MigrationContext> GroupMember for Runner<C> {
+    fn validate(&mut self) -> BoxFuture<'_, TernResult<()>> {
+        Box::pin(async move { Runner::validate(self, None).await.map(|_| ()) })
+    }
+
+    fn run_apply_all(
+        &mut self,
+        dryrun: bool,
+    ) -> BoxFuture<'_, TernResult<Report>> {
+        Box::pin(Runner::run_apply_all(self, dryrun))
+    }
+}
+
+/// Runs the migrations of several contexts, one context after another.
+#[derive(Default)]
+pub struct TernGroup {
+    members: Vec<(String, Box<dyn GroupMember>)>,
+    stop: StopHandle,
+}
+
+impl TernGroup {
+    /// Create a group with no contexts in it.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a context to run after the ones already added, with a default
+    /// [`Runner`].
+    ///
+    /// `name` identifies the context in the report and in errors.
+    pub fn with_context<C: MigrationContext>(
+        self,
+        name: impl Into<String>,
+        context: C,
+    ) -> Self {
+        self.with_runner(name, Runner::new(context))
+    }
+
+    /// Add a runner to run after the ones already added, for when the runner
+    /// of a context needs options of its own, like a lock or a deadline.
+    ///
+    /// The runner is given the group's [`StopHandle`] in place of its own.
+    pub fn with_runner<C: MigrationContext>(
+        mut self,
+        name: impl Into<String>,
+        runner: Runner<C>,
+    ) -> Self {
+        let runner = runner.with_stop_handle(self.stop.clone());
+        self.members.push((name.into(), Box::new(runner)));
+        self
+    }
+
+    /// A handle for stopping a run of the group after the migration being
+    /// applied, which leaves the contexts after it unrun.
+    ///
+    /// See [`Runner::stop_handle`].
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// The names of the contexts in the order they run.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.members.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Apply all unapplied migrations of every context, in order.
+    ///
+    /// Every context first validates its migrations against its history, and
+    /// nothing is applied unless all of them pass.  With `dryrun`, every
+    /// context does a dry run instead, which also builds its queries, so a
+    /// Rust migration reading a table that an earlier context creates can
+    /// fail there.  If a context stops because of its deadline or the
+    /// group's [`StopHandle`], the contexts after it are not run, since they
+    /// may depend on what it didn't get to.
+    ///
+    /// An error from a context is an [`Error::Group`] having the name of the
+    /// context and the reports of the contexts that finished before it.
+    pub async fn run_apply_all(
+        &mut self,
+        dryrun: bool,
+    ) -> TernResult<GroupReport> {
+        if dryrun {
+            let mut planned = GroupReport::default();
+            for (name, runner) in self.members.iter_mut() {
+                let report = runner
+                    .run_apply_all(true)
+                    .await
+                    .map_err(|e| Error::group(name, e, &planned))?;
+                planned.push(name, report);
+            }
+            return Ok(planned);
+        }
+        for (name, runner) in self.members.iter_mut() {
+            runner
+                .validate()
+                .await
+                .map_err(|e| Error::group(name, e, &GroupReport::default()))?;
+        }
+
+        let mut applied = GroupReport::default();
+        for (name, runner) in self.members.iter_mut() {
+            let report = runner
+                .run_apply_all(false)
+                .await
+                .map_err(|e| Error::group(name, e, &applied))?;
+            let stop = report.deadline_exceeded() || report.interrupted();
+            applied.push(name, report);
+            if stop {
+                log::warn!(
+                    "context {name} stopped early, not running the contexts after it"
+                );
+                break;
+            }
+        }
+
+        Ok(applied)
+    }
+}
+
+/// The reports of the contexts in a [`TernGroup`] run.
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty, Default)]
+pub struct GroupReport {
+    contexts: Vec<ContextReport>,
+}
+
+#[derive(Clone, Serialize)]
+struct ContextReport {
+    name: String,
+    #[serde(flatten)]
+    report: Report,
+}
+
+impl GroupReport {
+    fn push(&mut self, name: &str, report: Report) {
+        self.contexts.push(ContextReport { name: name.to_string(), report });
+    }
+
+    /// The report of the context named `name`, if it ran.
+    pub fn get(&self, name: &str) -> Option<&Report> {
+        self.iter().find(|(n, _)| *n == name).map(|(_, report)| report)
+    }
+
+    /// The name and report of each context that ran, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Report)> {
+        self.contexts.iter().map(|c| (c.name.as_str(), &c.report))
+    }
+
+    /// The number of migrations in all of the reports.
+    pub fn count(&self) -> usize {
+        self.contexts.iter().map(|c| c.report.count()).sum()
+    }
+
+    /// The summary of the run, with a breakdown for each context.
+    pub fn summary(&self) -> RunSummary {
+        RunSummary::from_reports(self.iter())
+    }
+}
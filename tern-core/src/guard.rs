@@ -0,0 +1,252 @@
+//! Guard rails for the databases that operations run against.
+//!
+//! A command copied from a runbook with the connection string of production
+//! still in it can drop the history of production.  A [`UrlGuard`] has
+//! patterns for the host of the connection string of each operation: it
+//! refuses to run an operation against a host that matches a pattern it
+//! denies for it, and, if it allows some hosts for the operation, against a
+//! host that matches none of them.
+//!
+//! ```rust,ignore
+//! let guard = UrlGuard::new()
+//!     .deny("history drop", "*.prod.*")
+//!     .allow("migrate try", "localhost");
+//! guard.check("history drop", "postgres://app@db.prod.internal/app")?;
+//! ```
+//!
+//! A pattern is matched against the whole host without the port, without
+//! regard to case, and `*` in it matches any characters, so `*.prod.*`
+//! matches `db.prod.internal`.  Operations are named like the commands of the
+//! CLI, such as `history drop` or `migrate apply-all`, and the operation `*`
+//! is every operation.
+//!
+//! The CLI checks the guard of the `App`, with the rules of the `[[guard]]`
+//! tables of its config file added to it, before it connects, unless it is
+//! given `--force-prod`.
+use crate::error::{Error, TernResult};
+
+/// Patterns of the hosts that operations are denied or allowed to run
+/// against.
+#[derive(Debug, Clone, Default)]
+pub struct UrlGuard {
+    rules: Vec<GuardRule>,
+}
+
+#[derive(Debug, Clone)]
+struct GuardRule {
+    operation: String,
+    pattern: String,
+    allow: bool,
+}
+
+impl GuardRule {
+    fn applies_to(&self, operation: &str) -> bool {
+        self.operation == "*" || self.operation == operation
+    }
+}
+
+impl UrlGuard {
+    /// A guard that allows everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse to run `operation` against a host matching `pattern`.
+    pub fn deny(
+        mut self,
+        operation: impl Into<String>,
+        pattern: impl Into<String>,
+    ) -> Self {
+        self.push(operation.into(), pattern.into(), false);
+        self
+    }
+
+    /// Only run `operation` against hosts matching `pattern`, or one of the
+    /// other patterns allowed for it.
+    pub fn allow(
+        mut self,
+        operation: impl Into<String>,
+        pattern: impl Into<String>,
+    ) -> Self {
+        self.push(operation.into(), pattern.into(), true);
+        self
+    }
+
+    fn push(&mut self, operation: String, pattern: String, allow: bool) {
+        let operation = operation.split_whitespace().collect::<Vec<_>>();
+        self.rules.push(GuardRule {
+            operation: operation.join(" "),
+            pattern,
+            allow,
+        });
+    }
+
+    /// Add the rules of `other`.
+    pub fn extend(&mut self, other: UrlGuard) {
+        self.rules.extend(other.rules);
+    }
+
+    /// Whether the guard has no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Check that `operation` may run against the host of `db_url`, which is
+    /// an [`Error::Refused`] if it may not.
+    ///
+    /// A connection string without a host, like the path of a SQLite
+    /// database, is matched by its path.
+    pub fn check(&self, operation: &str, db_url: &str) -> TernResult<()> {
+        let rules = self
+            .rules
+            .iter()
+            .filter(|r| r.applies_to(operation))
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            return Ok(());
+        }
+        let host = host(db_url);
+        let refused = |reason: String| Error::Refused {
+            operation: operation.to_string(),
+            host: host.to_string(),
+            reason,
+        };
+        if let Some(rule) =
+            rules.iter().find(|r| !r.allow && matches(&r.pattern, host))
+        {
+            return Err(refused(format!(
+                "the host matches the denied pattern `{}`",
+                rule.pattern
+            )));
+        }
+        let allowed = rules.iter().filter(|r| r.allow).collect::<Vec<_>>();
+        if !allowed.is_empty()
+            && !allowed.iter().any(|r| matches(&r.pattern, host))
+        {
+            let patterns = allowed
+                .iter()
+                .map(|r| format!("`{}`", r.pattern))
+                .collect::<Vec<_>>();
+            return Err(refused(format!(
+                "the host matches none of the allowed patterns {}",
+                patterns.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The host of a connection string, without the user, password, or port, or
+/// the value of `host=` in a connection string of `key=value` pairs, or in the
+/// query string of a URL without a host, like `postgres:///app?host=db`.
+pub fn host(db_url: &str) -> &str {
+    let Some((_, rest)) = db_url.split_once("://") else {
+        return db_url
+            .split_whitespace()
+            .find_map(|kv| kv.strip_prefix("host="))
+            .unwrap_or_default();
+    };
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let host = match host.strip_prefix('[') {
+        // An IPv6 address, which has colons of its own.
+        Some(v6) => v6.split(']').next().unwrap_or(v6),
+        None => host.split(':').next().unwrap_or(host),
+    };
+    if host.is_empty() {
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let query = query.split('#').next().unwrap_or_default();
+        if let Some(host) =
+            query.split('&').find_map(|kv| kv.strip_prefix("host="))
+        {
+            return host;
+        }
+        // SQLite, which has a path instead.
+        return path.split('#').next().unwrap_or(path);
+    }
+
+    host
+}
+
+// Whether the whole of `text` matches `pattern`, where `*` is any characters,
+// without regard to case.
+fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UrlGuard, host, matches};
+
+    #[test]
+    fn hosts() {
+        assert_eq!(
+            host("postgres://u:p@db.prod.internal:5432/app"),
+            "db.prod.internal"
+        );
+        assert_eq!(host("mysql://db.staging?ssl-mode=REQUIRED"), "db.staging");
+        assert_eq!(host("postgres://[::1]:5432/app"), "::1");
+        assert_eq!(
+            host("host=db.prod.internal dbname=app"),
+            "db.prod.internal"
+        );
+        assert_eq!(
+            host("sqlite:///var/lib/app.db?mode=rwc"),
+            "/var/lib/app.db"
+        );
+        assert_eq!(
+            host("postgres:///app?user=u&host=db.prod.internal"),
+            "db.prod.internal"
+        );
+        assert_eq!(host("postgres://@/app?host=db.prod#x"), "db.prod");
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches("*.prod.*", "db.PROD.internal"));
+        assert!(!matches("*.prod.*", "db.production.internal"));
+        assert!(matches("localhost", "localhost"));
+        assert!(!matches("localhost", "localhost.example.com"));
+        assert!(matches("*", ""));
+        assert!(matches("db-*-1", "db-a-b-1"));
+        assert!(!matches("a*a", "a"));
+    }
+
+    #[test]
+    fn guard_operations() {
+        let guard = UrlGuard::new()
+            .deny("history  drop", "*.prod.*")
+            .allow("migrate try", "localhost")
+            .allow("migrate try", "127.0.0.1");
+        let prod = "postgres://db.prod.internal/app";
+
+        let err = guard.check("history drop", prod).unwrap_err();
+        assert_eq!(err.code(), "TERN_REFUSED");
+        assert!(guard.check("migrate apply-all", prod).is_ok());
+        assert!(guard.check("migrate try", prod).is_err());
+        assert!(guard.check("migrate try", "postgres://127.0.0.1/app").is_ok());
+
+        let everything = UrlGuard::new().deny("*", "*.prod.*");
+        assert!(everything.check("info", prod).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+//! Finding which migrations are already in effect in a database.
+//!
+//! Adopting tern for a database that was migrated some other way means soft
+//! applying the migrations that were already applied to it, but which ones
+//! those are isn't always known.  An [`Inventory`] of the tables, indexes, and
+//! views in the database can tell: a migration whose `CREATE TABLE`,
+//! `CREATE INDEX`, and `CREATE VIEW` statements all have their object in it
+//! appears to be in effect, and one with an object that isn't there does not.
+//!
+//! [`Runner::suggest_soft_apply`] compares the unapplied migrations with the
+//! inventory of the database and makes a [`SoftApplyPlan`], which is the
+//! evidence for each one and the version to soft apply up to, for an operator
+//! to confirm before soft applying them with [`Runner::run_soft_apply`].  From
+//! the CLI, this is `migrate adopt`, which soft applies the migrations with
+//! `--yes`.
+//!
+//! [`Runner::suggest_soft_apply`]: crate::runner::Runner::suggest_soft_apply
+//! [`Runner::run_soft_apply`]: crate::runner::Runner::run_soft_apply
+use crate::error::{Error, TernResult};
+use crate::lint::{normalize, regex};
+use crate::migration::{MigrationId, Query};
+use crate::version::Version;
+
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// The kind of an object in the database.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectKind {
+    Table,
+    Index,
+    View,
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Table => write!(f, "table"),
+            Self::Index => write!(f, "index"),
+            Self::View => write!(f, "view"),
+        }
+    }
+}
+
+impl FromStr for ObjectKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "index" => Ok(Self::Index),
+            "view" | "materialized view" => Ok(Self::View),
+            _ => Err(Error::Invalid(format!("unknown kind of object {s}"))),
+        }
+    }
+}
+
+/// A table, index, or view, by its name without the schema.
+///
+/// Names are compared without regard to case, since a name that isn't quoted
+/// can be written in any case.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct DatabaseObject {
+    /// The kind of object.
+    pub kind: ObjectKind,
+    /// The name of the object.
+    pub name: String,
+}
+
+impl DatabaseObject {
+    /// The object of this kind with this name, which can be quoted and have a
+    /// schema in front of it, like `"app"."users"`.
+    pub fn new(kind: ObjectKind, name: &str) -> Self {
+        let name = name.rsplit('.').next().unwrap_or(name);
+        let name = name.trim_matches(|c| matches!(c, '"' | '`' | '[' | ']'));
+
+        Self { kind, name: name.to_lowercase() }
+    }
+}
+
+impl fmt::Display for DatabaseObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.name)
+    }
+}
+
+/// The objects that are in a database, from [`Executor::inventory`].
+///
+/// [`Executor::inventory`]: crate::migration::Executor::inventory
+#[derive(Debug, Clone, Default)]
+pub struct Inventory(HashSet<DatabaseObject>);
+
+impl Inventory {
+    /// The inventory of these objects.
+    pub fn new(objects: impl IntoIterator<Item = DatabaseObject>) -> Self {
+        Self(objects.into_iter().collect())
+    }
+
+    /// Whether the object is in the database.
+    pub fn contains(&self, object: &DatabaseObject) -> bool {
+        self.0.contains(object)
+    }
+
+    /// How many objects are in the database.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no objects in the database.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The tables, indexes, and views that the SQL creates, in order.
+///
+/// Temporary tables aren't included, since they don't outlast the session,
+/// and neither is an index without a name.
+pub fn created_objects(sql: &str) -> TernResult<Vec<DatabaseObject>> {
+    let re = regex(
+        &CREATE_RE,
+        r"^CREATE (?:OR REPLACE )?(?:UNLOGGED )?(?:UNIQUE )?(TABLE|INDEX|VIEW|MATERIALIZED VIEW) (?:CONCURRENTLY )?(?:IF NOT EXISTS )?([^\s(]+)",
+    );
+    objects(sql, re)
+}
+
+/// The tables, indexes, and views that the SQL drops, in order.
+pub fn dropped_objects(sql: &str) -> TernResult<Vec<DatabaseObject>> {
+    let re = regex(
+        &DROP_RE,
+        r"^DROP (TABLE|INDEX|VIEW|MATERIALIZED VIEW) (?:CONCURRENTLY )?(?:IF EXISTS )?([^\s(,;]+)",
+    );
+    objects(sql, re)
+}
+
+/// The indexes that the SQL creates with `CREATE INDEX CONCURRENTLY`, in
+/// order, which are left invalid in the database if building one fails.
+pub fn concurrent_indexes(sql: &str) -> TernResult<Vec<DatabaseObject>> {
+    let re = regex(
+        &CONCURRENT_INDEX_RE,
+        r"^CREATE (?:UNIQUE )?(INDEX) CONCURRENTLY (?:IF NOT EXISTS )?([^\s(]+)",
+    );
+    objects(sql, re)
+}
+
+fn objects(sql: &str, re: &Regex) -> TernResult<Vec<DatabaseObject>> {
+    let objects = Query::new(sql.to_string())
+        .split_statements()?
+        .iter()
+        .map(|s| normalize(s))
+        .filter_map(|s| {
+            let caps = re.captures(&s)?;
+            // `CREATE INDEX ON t (c)` lets the database name the index.
+            if caps[2].eq_ignore_ascii_case("ON") {
+                return None;
+            }
+            let kind = caps[1].parse().ok()?;
+            Some(DatabaseObject::new(kind, &caps[2]))
+        })
+        .collect();
+
+    Ok(objects)
+}
+
+static CREATE_RE: OnceLock<Regex> = OnceLock::new();
+static DROP_RE: OnceLock<Regex> = OnceLock::new();
+static CONCURRENT_INDEX_RE: OnceLock<Regex> = OnceLock::new();
+
+/// What the inventory says about one unapplied migration.
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryMatch {
+    /// The migration.
+    pub id: MigrationId,
+    /// The objects it creates that are in the database.
+    pub found: Vec<DatabaseObject>,
+    /// The objects it creates that are not in the database.
+    pub missing: Vec<DatabaseObject>,
+    /// Whether the plan soft applies it.
+    pub soft_apply: bool,
+}
+
+impl InventoryMatch {
+    /// Compare the objects that the migration creates with the inventory,
+    /// leaving out the ones in `dropped`, which a later migration drops, so
+    /// that they wouldn't be in the database either way.
+    pub fn new(
+        id: MigrationId,
+        created: Vec<DatabaseObject>,
+        dropped: &HashSet<DatabaseObject>,
+        inventory: &Inventory,
+    ) -> Self {
+        let (found, missing) = created
+            .into_iter()
+            .filter(|object| !dropped.contains(object))
+            .partition(|object| inventory.contains(object));
+
+        Self { id, found, missing, soft_apply: false }
+    }
+
+    /// Whether the migration appears to be in effect, or `None` if it doesn't
+    /// create anything to look for, e.g., if it only inserts rows or adds a
+    /// column.
+    pub fn in_effect(&self) -> Option<bool> {
+        if self.found.is_empty() && self.missing.is_empty() {
+            return None;
+        }
+
+        Some(self.missing.is_empty())
+    }
+}
+
+/// The unapplied migrations that appear to be in effect in a database, from
+/// [`Runner::suggest_soft_apply`].
+///
+/// The migrations to soft apply are the ones up to the last that is in effect
+/// before the first that is not.  A migration in between that has nothing to
+/// look for is soft applied too, since a later one being in effect means that
+/// it most likely ran.
+///
+/// [`Runner::suggest_soft_apply`]: crate::runner::Runner::suggest_soft_apply
+#[derive(Debug, Clone, Serialize)]
+pub struct SoftApplyPlan {
+    /// Every unapplied migration, in order.
+    pub migrations: Vec<InventoryMatch>,
+    /// The version to soft apply up to, if there is one that is in effect.
+    pub target: Option<Version>,
+}
+
+impl SoftApplyPlan {
+    /// The plan for the unapplied migrations, which are in order.
+    pub fn new(mut migrations: Vec<InventoryMatch>) -> Self {
+        let stop = migrations
+            .iter()
+            .position(|m| m.in_effect() == Some(false))
+            .unwrap_or(migrations.len());
+        let last = migrations[..stop]
+            .iter()
+            .rposition(|m| m.in_effect() == Some(true));
+        let target = last.map(|n| migrations[n].id.version());
+        for m in migrations.iter_mut().take(last.map_or(0, |n| n + 1)) {
+            m.soft_apply = true;
+        }
+
+        Self { migrations, target }
+    }
+}
+
+impl fmt::Display for SoftApplyPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |objects: &[DatabaseObject]| {
+            objects
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        for m in &self.migrations {
+            let verdict = match (m.soft_apply, m.in_effect()) {
+                (true, Some(_)) => "soft apply, in effect",
+                (true, None) => "soft apply, nothing to look for",
+                (false, Some(true)) => "in effect, after one that is not",
+                (false, Some(false)) => "not in effect",
+                (false, None) => "nothing to look for",
+            };
+            write!(f, "{}: {verdict}", m.id)?;
+            if !m.found.is_empty() {
+                write!(f, "; found {}", list(&m.found))?;
+            }
+            if !m.missing.is_empty() {
+                write!(f, "; missing {}", list(&m.missing))?;
+            }
+            writeln!(f)?;
+        }
+        match &self.target {
+            Some(target) => write!(f, "soft apply up to version {target}"),
+            None => write!(f, "no migration appears to be in effect"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DatabaseObject, InventoryMatch, ObjectKind, SoftApplyPlan,
+        concurrent_indexes, created_objects, dropped_objects,
+    };
+    use crate::migration::MigrationId;
+
+    use std::collections::HashSet;
+
+    fn object(kind: ObjectKind, name: &str) -> DatabaseObject {
+        DatabaseObject::new(kind, name)
+    }
+
+    #[test]
+    fn created_and_dropped() {
+        let sql = "
+-- CREATE TABLE commented_out (id int);
+CREATE TABLE IF NOT EXISTS app.\"Users\" (id int);
+CREATE UNIQUE INDEX CONCURRENTLY users_email ON users (email);
+CREATE INDEX ON users (name);
+CREATE TEMPORARY TABLE scratch (id int);
+CREATE OR REPLACE VIEW active_users AS SELECT * FROM users;
+DROP TABLE IF EXISTS legacy;
+";
+        assert_eq!(
+            created_objects(sql).unwrap(),
+            vec![
+                object(ObjectKind::Table, "users"),
+                object(ObjectKind::Index, "users_email"),
+                object(ObjectKind::View, "active_users"),
+            ]
+        );
+        assert_eq!(
+            dropped_objects(sql).unwrap(),
+            vec![object(ObjectKind::Table, "legacy")]
+        );
+        assert_eq!(
+            concurrent_indexes(sql).unwrap(),
+            vec![object(ObjectKind::Index, "users_email")]
+        );
+    }
+
+    #[test]
+    fn plan_stops_at_first_not_in_effect() {
+        let inventory = super::Inventory::new([
+            object(ObjectKind::Table, "users"),
+            object(ObjectKind::Table, "orders"),
+        ]);
+        let m = |v: i64, created: &[&str]| {
+            let created = created
+                .iter()
+                .map(|name| object(ObjectKind::Table, name))
+                .collect();
+            InventoryMatch::new(
+                MigrationId::new(v, "m".into()),
+                created,
+                &HashSet::new(),
+                &inventory,
+            )
+        };
+        let plan = SoftApplyPlan::new(vec![
+            m(1, &["users"]),
+            m(2, &[]),
+            m(3, &["orders"]),
+            m(4, &["invoices"]),
+            m(5, &["users"]),
+        ]);
+        assert_eq!(plan.target.map(|v| v.to_string()), Some("3".into()));
+        let soft_applied =
+            plan.migrations.iter().map(|m| m.soft_apply).collect::<Vec<_>>();
+        assert_eq!(soft_applied, [true, true, true, false, false]);
+    }
+}
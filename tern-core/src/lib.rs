@@ -7,11 +7,40 @@
 //! [Executor]: self::executor::Executor
 //! [executor]: self::executor
 #![cfg_attr(docsrs, feature(doc_cfg))]
+
+// The SQLite that libSQL builds has the same symbols as the one that
+// `rusqlite` and `sqlx` build, so they fail to link together.
+#[cfg(all(
+    feature = "libsql_replica",
+    any(feature = "rusqlite", feature = "sqlx_sqlite")
+))]
+compile_error!(
+    "the feature `libsql_replica` can't be enabled together with `rusqlite` or `sqlx_sqlite`"
+);
+
+mod annotation;
+pub mod config;
+pub mod credentials;
 pub mod error;
 pub mod executor;
+pub mod group;
+pub mod guard;
+pub mod inventory;
+pub mod lint;
+pub mod lockfile;
+pub mod locks;
 pub mod migration;
+pub mod operation;
 mod query;
+pub mod reader;
+pub mod redact;
+pub mod run_log;
 pub mod runner;
+pub mod schema;
+pub mod source;
+pub mod startup;
+pub mod validate;
+mod version;
 
 #[doc(hidden)]
 pub mod future {
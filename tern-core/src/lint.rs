@@ -0,0 +1,452 @@
+//! Finding risky operations in SQL migrations.
+//!
+//! A [`Linter`] checks the statements of SQL migrations with a set of
+//! [`LintRule`]s for operations that are valid but dangerous to run against a
+//! live database.  The built-in rules are:
+//!
+//! * [`DropTable`]: `DROP TABLE` loses the data in the table for good.
+//! * [`SetNotNull`]: `ALTER TABLE ... SET NOT NULL` scans the whole table while
+//!   holding an exclusive lock, unless there is already a `CHECK` constraint
+//!   saying the same thing.
+//! * [`NonConcurrentIndex`]: `CREATE INDEX` on Postgres blocks writes to the
+//!   table until it finishes, where `CREATE INDEX CONCURRENTLY` does not.
+//!
+//! Linting is opt-in.  It can be run on its own, from the CLI with
+//! `migrate lint`, or in a test so that it fails the build:
+//!
+//! ```rust,ignore
+//! #[test]
+//! fn migrations_pass_lint() {
+//!     let findings = Linter::new()
+//!         .with_dialect(Dialect::Postgres)
+//!         .lint(&MyContext::embedded_migrations())
+//!         .unwrap();
+//!     assert!(findings.is_empty(), "{findings:?}");
+//! }
+//! ```
+//!
+//! A `Linter` is also a [`ValidationRule`] in the category
+//! [`ValidationCategory::Destructive`], so with
+//! [`Runner::with_validation_rule`] the unapplied migrations are linted before
+//! they are applied, and a finding stops the run.  Setting that category to
+//! [`Severity::Warn`] is the way to apply them anyway, which is what the CLI
+//! option `--allow-destructive` does.
+//!
+//! [`Runner::with_validation_rule`]: crate::runner::Runner::with_validation_rule
+//! [`Severity::Warn`]: crate::validate::Severity::Warn
+use crate::error::{Error, TernResult};
+use crate::migration::{Dialect, EmbeddedMigration, MigrationId, Query};
+use crate::validate::{Validation, ValidationCategory, ValidationRule};
+
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// A check for a risky operation in the statements of a migration.
+pub trait LintRule: Send + Sync {
+    /// The name of the rule.
+    fn name(&self) -> &str;
+
+    /// Check the statements of one migration, returning a message for each
+    /// problem found.
+    ///
+    /// The statements have had comments removed, and their whitespace is
+    /// collapsed to single spaces.  `dialect` is `None` when it isn't known,
+    /// in which case a rule for one dialect should assume that it applies.
+    fn check(
+        &self,
+        statements: &[String],
+        dialect: Option<Dialect>,
+    ) -> Vec<String>;
+}
+
+/// A problem found by a [`LintRule`].
+#[derive(Debug, Clone, Serialize)]
+pub struct LintFinding {
+    /// The migration having the problem.
+    pub migration: MigrationId,
+    /// The name of the rule that found it.
+    pub rule: String,
+    /// What the problem is.
+    pub msg: String,
+}
+
+impl std::fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.migration, self.rule, self.msg)
+    }
+}
+
+/// Checks SQL migrations with a set of [`LintRule`]s.
+pub struct Linter {
+    rules: Vec<Box<dyn LintRule>>,
+    dialect: Option<Dialect>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linter {
+    /// A linter with the built-in rules, for any dialect.
+    pub fn new() -> Self {
+        Self {
+            rules: vec![
+                Box::new(DropTable),
+                Box::new(SetNotNull),
+                Box::new(NonConcurrentIndex),
+            ],
+            dialect: None,
+        }
+    }
+
+    /// Only check with the rules that apply to this dialect.
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = Some(dialect);
+        self
+    }
+
+    /// Also check with `rule`.
+    pub fn with_rule(mut self, rule: impl LintRule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Lint the SQL migrations in `migrations`.
+    ///
+    /// Rust migrations are skipped, since their query isn't known until it is
+    /// built.
+    pub fn lint(
+        &self,
+        migrations: &[EmbeddedMigration],
+    ) -> TernResult<Vec<LintFinding>> {
+        let mut findings = Vec::new();
+        for migration in
+            migrations.iter().filter(|m| m.filename.ends_with(".sql"))
+        {
+            let statements = statements(migration.content)?;
+            for rule in &self.rules {
+                findings.extend(
+                    rule.check(&statements, self.dialect).into_iter().map(
+                        |msg| LintFinding {
+                            migration: migration.migration_id(),
+                            rule: rule.name().to_string(),
+                            msg,
+                        },
+                    ),
+                );
+            }
+        }
+
+        Ok(findings)
+    }
+}
+
+impl ValidationRule for Linter {
+    fn name(&self) -> &str {
+        "lint"
+    }
+
+    fn category(&self) -> ValidationCategory {
+        ValidationCategory::Destructive
+    }
+
+    fn validate(&self, validation: &Validation<'_>) -> TernResult<()> {
+        let unapplied =
+            validation.unapplied().map(|m| m.id()).collect::<HashSet<_>>();
+        let migrations = validation
+            .embedded()
+            .iter()
+            .filter(|m| unapplied.contains(&m.migration_id()))
+            .cloned()
+            .collect::<Vec<_>>();
+        let findings = self.lint(&migrations)?;
+        if findings.is_empty() {
+            return Ok(());
+        }
+
+        let mut at_issue =
+            findings.iter().map(|f| f.migration.clone()).collect::<Vec<_>>();
+        at_issue.dedup();
+        let msg = findings
+            .iter()
+            .map(|f| format!("{}: {}", f.rule, f.msg))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        Err(Error::Validation { rule: self.name().to_string(), at_issue, msg })
+    }
+}
+
+/// `DROP TABLE`, which deletes the table and its data.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DropTable;
+
+impl LintRule for DropTable {
+    fn name(&self) -> &str {
+        "drop-table"
+    }
+
+    fn check(&self, statements: &[String], _: Option<Dialect>) -> Vec<String> {
+        let re = regex(&DROP_TABLE_RE, r"^DROP TABLE (?:IF EXISTS )?(.+)$");
+        statements
+            .iter()
+            .filter_map(|s| re.captures(s))
+            .map(|caps| format!("drops table {}", &caps[1]))
+            .collect()
+    }
+}
+
+/// `ALTER TABLE ... ALTER COLUMN ... SET NOT NULL` when the migration doesn't
+/// also have a `CHECK (<column> IS NOT NULL)` constraint.
+///
+/// Without one, the database reads the whole table to check that the column
+/// has no nulls, and writes to the table wait until it's done.  On Postgres,
+/// adding the constraint as `NOT VALID` and validating it first lets the
+/// `SET NOT NULL` skip that scan.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SetNotNull;
+
+impl LintRule for SetNotNull {
+    fn name(&self) -> &str {
+        "set-not-null"
+    }
+
+    fn check(&self, statements: &[String], _: Option<Dialect>) -> Vec<String> {
+        let re = regex(
+            &SET_NOT_NULL_RE,
+            r"^ALTER TABLE (?:IF EXISTS )?(?:ONLY )?(\S+) .*ALTER (?:COLUMN )?(\S+) SET NOT NULL",
+        );
+        let checked = |column: &str| {
+            let column = regex::escape(column.trim_matches('"'));
+            let check = Regex::new(&format!(
+                r#"(?i)CHECK ?\( ?"?{column}"? IS NOT NULL ?\)"#
+            ))
+            .unwrap();
+            statements.iter().any(|s| check.is_match(s))
+        };
+        statements
+            .iter()
+            .filter_map(|s| re.captures(s))
+            .filter(|caps| !checked(&caps[2]))
+            .map(|caps| {
+                format!(
+                    "sets {}.{} NOT NULL without a CHECK constraint, which scans the table under an exclusive lock",
+                    &caps[1], &caps[2]
+                )
+            })
+            .collect()
+    }
+}
+
+/// `CREATE INDEX` without `CONCURRENTLY` on Postgres, for a table that wasn't
+/// created in the same migration.
+///
+/// Creating an index concurrently can't happen in a transaction, so the
+/// migration also needs the `tern:noTransaction` annotation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NonConcurrentIndex;
+
+impl LintRule for NonConcurrentIndex {
+    fn name(&self) -> &str {
+        "non-concurrent-index"
+    }
+
+    fn check(
+        &self,
+        statements: &[String],
+        dialect: Option<Dialect>,
+    ) -> Vec<String> {
+        if dialect.is_some_and(|d| d != Dialect::Postgres) {
+            return Vec::new();
+        }
+        let re = regex(
+            &CREATE_INDEX_RE,
+            r"^CREATE (?:UNIQUE )?INDEX (CONCURRENTLY )?.*? ON (?:ONLY )?(\S+?)[ (]",
+        );
+        let create_table = regex(
+            &CREATE_TABLE_RE,
+            r"^CREATE TABLE (?:IF NOT EXISTS )?(\S+?)[ (]",
+        );
+        let created = statements
+            .iter()
+            .filter_map(|s| create_table.captures(s))
+            .map(|caps| caps[1].to_string())
+            .collect::<HashSet<_>>();
+        statements
+            .iter()
+            .filter_map(|s| re.captures(s))
+            .filter(|caps| caps.get(1).is_none() && !created.contains(&caps[2]))
+            .map(|caps| {
+                format!(
+                    "creates an index on {} without CONCURRENTLY, which blocks writes to the table",
+                    &caps[2]
+                )
+            })
+            .collect()
+    }
+}
+
+static DROP_TABLE_RE: OnceLock<Regex> = OnceLock::new();
+static SET_NOT_NULL_RE: OnceLock<Regex> = OnceLock::new();
+static CREATE_INDEX_RE: OnceLock<Regex> = OnceLock::new();
+static CREATE_TABLE_RE: OnceLock<Regex> = OnceLock::new();
+
+// What the statements of a SQL migration that is applied in a transaction do
+// on the database that the migration might not expect, for the runner to warn
+// about: on Postgres, a statement that can't run in a transaction, and on
+// MySQL, a change to the schema, which commits the transaction.
+pub(crate) fn dialect_caveats(
+    migration: &EmbeddedMigration,
+    dialect: Dialect,
+) -> TernResult<Vec<String>> {
+    if migration.no_tx || !migration.filename.ends_with(".sql") {
+        return Ok(Vec::new());
+    }
+    let statements = statements(migration.content)?;
+    let caveats = match dialect {
+        Dialect::Postgres => {
+            let re = regex(
+                &NO_TX_RE,
+                r"^(?:(?:CREATE|DROP) (?:UNIQUE )?INDEX CONCURRENTLY|REINDEX .*CONCURRENTLY|VACUUM|(?:CREATE|DROP) DATABASE|ALTER SYSTEM)",
+            );
+            statements
+                .iter()
+                .filter_map(|s| re.find(s))
+                .map(|m| {
+                    format!(
+                        "`{}` can't run in a transaction on Postgres, so the migration needs `tern:noTransaction`",
+                        m.as_str()
+                    )
+                })
+                .collect()
+        },
+        Dialect::MySql => {
+            let re = regex(
+                &IMPLICIT_COMMIT_RE,
+                r"^(?:CREATE|ALTER|DROP|RENAME|TRUNCATE) \S+",
+            );
+            statements
+                .iter()
+                .filter(|s| !s.to_uppercase().contains(" TEMPORARY "))
+                .find_map(|s| re.find(s))
+                .map(|m| {
+                    format!(
+                        "MySQL commits the transaction at `{}`, so the migration can't be rolled back if it fails after that",
+                        m.as_str()
+                    )
+                })
+                .into_iter()
+                .collect()
+        },
+        Dialect::Sqlite => Vec::new(),
+    };
+
+    Ok(caveats)
+}
+
+static NO_TX_RE: OnceLock<Regex> = OnceLock::new();
+static IMPLICIT_COMMIT_RE: OnceLock<Regex> = OnceLock::new();
+
+// The statements of the SQL without comments and empty statements.
+fn statements(sql: &str) -> TernResult<Vec<String>> {
+    let statements = Query::new(sql.to_string())
+        .split_statements()?
+        .iter()
+        .map(|s| normalize(s))
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    Ok(statements)
+}
+
+pub(crate) fn regex(
+    cell: &'static OnceLock<Regex>,
+    re: &str,
+) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(&format!("(?i){re}")).unwrap())
+}
+
+// Remove comments and collapse whitespace, so that rules can match statements
+// with simple patterns.
+pub(crate) fn normalize(statement: &str) -> String {
+    let re = regex(&COMMENT_RE, r"--[^\n]*|/\*(?s:.*?)\*/");
+    let uncommented = re.replace_all(statement, " ");
+    let collapsed = uncommented.split_whitespace().collect::<Vec<_>>();
+    collapsed.join(" ").trim_end_matches(';').trim_end().to_string()
+}
+
+static COMMENT_RE: OnceLock<Regex> = OnceLock::new();
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Dialect, LintRule, NonConcurrentIndex, SetNotNull, dialect_caveats,
+        normalize,
+    };
+    use crate::migration::{EmbeddedMigration, Version};
+
+    fn statements(sql: &[&str]) -> Vec<String> {
+        sql.iter().map(|s| normalize(s)).collect()
+    }
+
+    #[test]
+    fn set_not_null_with_check() {
+        let bare = statements(&[
+            "ALTER TABLE users\n  ALTER COLUMN email SET NOT NULL;",
+        ]);
+        assert_eq!(SetNotNull.check(&bare, None).len(), 1);
+
+        let checked = statements(&[
+            "ALTER TABLE users ADD CONSTRAINT email_nn CHECK (email IS NOT NULL) NOT VALID;",
+            "ALTER TABLE users VALIDATE CONSTRAINT email_nn;",
+            "ALTER TABLE users ALTER COLUMN email SET NOT NULL;",
+        ]);
+        assert!(SetNotNull.check(&checked, None).is_empty());
+    }
+
+    #[test]
+    fn non_concurrent_index() {
+        let sql = statements(&[
+            "-- tern:noTransaction\nCREATE INDEX CONCURRENTLY a_idx ON a (x);",
+            "create index b_idx on b(x);",
+            "CREATE TABLE c (x int);",
+            "CREATE UNIQUE INDEX c_idx ON c (x);",
+        ]);
+        let found = NonConcurrentIndex.check(&sql, Some(Dialect::Postgres));
+        assert_eq!(found.len(), 1);
+        assert!(found[0].contains(" b "));
+        assert!(
+            NonConcurrentIndex.check(&sql, Some(Dialect::MySql)).is_empty()
+        );
+    }
+
+    #[test]
+    fn dialect_caveats_in_tx() {
+        let migration = |no_tx, content| EmbeddedMigration {
+            version: Version::Integer(1),
+            description: "m",
+            filename: "V1__m.sql",
+            no_tx,
+            checksum: "",
+            content,
+        };
+        let sql = "CREATE TEMPORARY TABLE t (x int);\nALTER TABLE a ADD x int;\nCREATE INDEX CONCURRENTLY a_idx ON a (x);";
+        let postgres =
+            dialect_caveats(&migration(false, sql), Dialect::Postgres).unwrap();
+        assert_eq!(postgres.len(), 1);
+        assert!(postgres[0].starts_with("`CREATE INDEX CONCURRENTLY`"));
+        let mysql =
+            dialect_caveats(&migration(false, sql), Dialect::MySql).unwrap();
+        assert_eq!(mysql.len(), 1);
+        assert!(mysql[0].contains("`ALTER TABLE`"));
+        assert!(
+            dialect_caveats(&migration(true, sql), Dialect::Postgres)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
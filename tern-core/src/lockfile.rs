@@ -0,0 +1,207 @@
+//! A lockfile of the migrations that have been reviewed.
+//!
+//! `tern.lock` is a file committed next to the migrations that has the
+//! filename and checksum of every migration, in order, and it is written with
+//! the CLI command `migrate lock`.  A context opts in to it with the derive
+//! attribute `#[tern(lockfile = "tern.lock")]`, naming the path from the
+//! crate root, and then:
+//!
+//! * The derive macro fails to compile if a migration in the lockfile has been
+//!   changed or removed, or if a migration that isn't in it comes before one
+//!   that is.
+//! * The [`Locked`] validation rule refuses to run if any migration is missing
+//!   from the lockfile, so a migration nobody locked can't be applied.
+//! * `migrate lock --check` fails if the lockfile isn't up to date, for CI.
+//!
+//! [`Locked`]: crate::validate::Locked
+use crate::error::{Error, TernResult};
+use crate::migration::EmbeddedMigration;
+
+use std::collections::HashMap;
+
+/// The default name of the lockfile.
+pub const LOCKFILE_NAME: &str = "tern.lock";
+
+const HEADER: &str =
+    "# This file is generated by `migrate lock`.  Do not edit it by hand.";
+
+/// The contents of a lockfile.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lockfile {
+    entries: Vec<LockEntry>,
+}
+
+/// One migration in a lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockEntry {
+    /// The name of the source file of the migration.
+    pub filename: String,
+    /// The hex-encoded SHA-256 hash of the source file.
+    pub checksum: String,
+}
+
+impl Lockfile {
+    /// The lockfile for these migrations, in the order given.
+    pub fn new(migrations: &[EmbeddedMigration]) -> Self {
+        let entries = migrations
+            .iter()
+            .map(|m| LockEntry {
+                filename: m.filename.to_string(),
+                checksum: m.checksum.to_string(),
+            })
+            .collect();
+
+        Self { entries }
+    }
+
+    /// Parse the contents of a lockfile, which has a line of `<filename>
+    /// <checksum>` for each migration, and comments starting with `#`.
+    pub fn parse(contents: &str) -> TernResult<Self> {
+        let entries = contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| match l.split_whitespace().collect::<Vec<_>>()[..] {
+                [filename, checksum] => Ok(LockEntry {
+                    filename: filename.to_string(),
+                    checksum: checksum.to_string(),
+                }),
+                _ => Err(Error::Invalid(format!(
+                    "invalid line in lockfile, expected a filename and a checksum: {l}"
+                ))),
+            })
+            .collect::<TernResult<Vec<_>>>()?;
+
+        Ok(Self { entries })
+    }
+
+    /// The migrations in the lockfile, in order.
+    pub fn entries(&self) -> &[LockEntry] {
+        &self.entries
+    }
+
+    /// How `migrations` differ from the lockfile, or nothing if they are the
+    /// same.
+    ///
+    /// A migration differs if it was changed since it was locked, if it isn't
+    /// in the lockfile, or if it was locked in a different position.
+    pub fn check(&self, migrations: &[EmbeddedMigration]) -> Vec<LockMismatch> {
+        let locked = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.filename.as_str(), (i, e.checksum.as_str())))
+            .collect::<HashMap<_, _>>();
+        let mut mismatches = Vec::new();
+        for (position, m) in migrations.iter().enumerate() {
+            let reason = match locked.get(m.filename) {
+                None => "is not locked",
+                Some((_, checksum)) if *checksum != m.checksum => {
+                    "changed since it was locked"
+                },
+                Some((i, _)) if *i != position => {
+                    "is in a different position than it was locked in"
+                },
+                Some(_) => continue,
+            };
+            mismatches.push(LockMismatch::new(m.filename, reason));
+        }
+        let filenames =
+            migrations.iter().map(|m| m.filename).collect::<Vec<_>>();
+        mismatches.extend(
+            self.entries
+                .iter()
+                .filter(|e| !filenames.contains(&e.filename.as_str()))
+                .map(|e| {
+                    LockMismatch::new(&e.filename, "is locked but missing")
+                }),
+        );
+
+        mismatches
+    }
+}
+
+/// A difference between a migration and the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockMismatch {
+    /// The name of the source file of the migration.
+    pub filename: String,
+    /// How it differs.
+    pub reason: &'static str,
+}
+
+impl LockMismatch {
+    fn new(filename: &str, reason: &'static str) -> Self {
+        Self { filename: filename.to_string(), reason }
+    }
+}
+
+impl std::fmt::Display for LockMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.filename, self.reason)
+    }
+}
+
+impl std::fmt::Display for Lockfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for entry in &self.entries {
+            writeln!(f, "{} {}", entry.filename, entry.checksum)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lockfile;
+    use crate::migration::{EmbeddedMigration, Version};
+
+    fn embedded(
+        filename: &'static str,
+        checksum: &'static str,
+    ) -> EmbeddedMigration {
+        EmbeddedMigration {
+            version: Version::Integer(filename[1..2].parse().unwrap()),
+            description: "",
+            filename,
+            no_tx: false,
+            checksum,
+            content: "",
+        }
+    }
+
+    #[test]
+    fn check_against_lockfile() {
+        let migrations =
+            [embedded("V1__a.sql", "aa"), embedded("V2__b.sql", "bb")];
+        let lockfile =
+            Lockfile::parse(&Lockfile::new(&migrations).to_string()).unwrap();
+        assert!(lockfile.check(&migrations).is_empty());
+
+        let changed =
+            [embedded("V1__a.sql", "ab"), embedded("V2__b.sql", "bb")];
+        let mismatches = lockfile.check(&changed);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(
+            mismatches[0].to_string(),
+            "V1__a.sql changed since it was locked"
+        );
+
+        let inserted = [
+            embedded("V1__a.sql", "aa"),
+            embedded("V2__c.sql", "cc"),
+            embedded("V3__b.sql", "bb"),
+        ];
+        let mismatches = lockfile.check(&inserted);
+        assert_eq!(
+            mismatches.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+            [
+                "V2__c.sql is not locked",
+                "V3__b.sql is not locked",
+                "V2__b.sql is locked but missing",
+            ]
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Predicting the locks that Postgres statements take.
+//!
+//! Every statement in Postgres takes a lock on the relations it touches, and
+//! the lock decides what else has to wait: an `ACCESS EXCLUSIVE` lock blocks
+//! even reads of the table, while the `SHARE UPDATE EXCLUSIVE` lock of
+//! `CREATE INDEX CONCURRENTLY` lets reads and writes go on.  [`analyze`] finds
+//! the lock of each statement in a query from the kind of statement, following
+//! the [Postgres documentation][pg-locks], so that a dry run can show what a
+//! migration would block before it runs in production.  [`changed_tables`]
+//! reads the tables that the statements change the same way, for refreshing
+//! their statistics after a migration.
+//!
+//! This only looks at the text of the statements.  It doesn't know about locks
+//! taken on other relations, like the indexes of a table or the tables that a
+//! trigger touches, and statements that it doesn't recognize are left out.
+//!
+//! [pg-locks]: https://www.postgresql.org/docs/current/explicit-locking.html
+use crate::error::TernResult;
+use crate::lint::{normalize, regex};
+use crate::migration::Query;
+
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// A table-level lock mode in Postgres, from weakest to strongest.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize,
+)]
+pub enum LockLevel {
+    #[serde(rename = "ACCESS SHARE")]
+    AccessShare,
+    #[serde(rename = "ROW SHARE")]
+    RowShare,
+    #[serde(rename = "ROW EXCLUSIVE")]
+    RowExclusive,
+    #[serde(rename = "SHARE UPDATE EXCLUSIVE")]
+    ShareUpdateExclusive,
+    #[serde(rename = "SHARE")]
+    Share,
+    #[serde(rename = "SHARE ROW EXCLUSIVE")]
+    ShareRowExclusive,
+    #[serde(rename = "EXCLUSIVE")]
+    Exclusive,
+    #[serde(rename = "ACCESS EXCLUSIVE")]
+    AccessExclusive,
+}
+
+impl LockLevel {
+    /// Whether the lock keeps other sessions from writing to the relation.
+    pub fn blocks_writes(&self) -> bool {
+        *self >= Self::Share
+    }
+
+    /// Whether the lock keeps other sessions from reading the relation.
+    pub fn blocks_reads(&self) -> bool {
+        *self == Self::AccessExclusive
+    }
+
+    fn from_mode(mode: &str) -> Option<Self> {
+        let level = match mode.to_uppercase().as_str() {
+            "ACCESS SHARE" => Self::AccessShare,
+            "ROW SHARE" => Self::RowShare,
+            "ROW EXCLUSIVE" => Self::RowExclusive,
+            "SHARE UPDATE EXCLUSIVE" => Self::ShareUpdateExclusive,
+            "SHARE" => Self::Share,
+            "SHARE ROW EXCLUSIVE" => Self::ShareRowExclusive,
+            "EXCLUSIVE" => Self::Exclusive,
+            "ACCESS EXCLUSIVE" => Self::AccessExclusive,
+            _ => return None,
+        };
+        Some(level)
+    }
+}
+
+impl std::fmt::Display for LockLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = match self {
+            Self::AccessShare => "ACCESS SHARE",
+            Self::RowShare => "ROW SHARE",
+            Self::RowExclusive => "ROW EXCLUSIVE",
+            Self::ShareUpdateExclusive => "SHARE UPDATE EXCLUSIVE",
+            Self::Share => "SHARE",
+            Self::ShareRowExclusive => "SHARE ROW EXCLUSIVE",
+            Self::Exclusive => "EXCLUSIVE",
+            Self::AccessExclusive => "ACCESS EXCLUSIVE",
+        };
+        write!(f, "{mode}")
+    }
+}
+
+/// The lock that one statement takes.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementLock {
+    /// The statement, without comments and with its whitespace collapsed.
+    pub statement: String,
+    /// The strongest lock the statement takes.
+    pub lock: LockLevel,
+    /// The relations that the statement locks.
+    pub relations: Vec<String>,
+}
+
+/// The locks taken by the statements in a Postgres query, in order.
+pub fn analyze(sql: &str) -> TernResult<Vec<StatementLock>> {
+    let locks = Query::new(sql.to_string())
+        .split_statements()?
+        .iter()
+        .map(|s| normalize(s))
+        .filter_map(|statement| {
+            let (lock, relations) = statement_lock(&statement)?;
+            Some(StatementLock { statement, lock, relations })
+        })
+        .collect();
+
+    Ok(locks)
+}
+
+/// The tables whose rows or indexes the statements of a query change, in the
+/// order they are first changed, which are the ones whose statistics are out
+/// of date after it.
+///
+/// Tables that the query drops or renames after changing them are left out,
+/// since they can't be analyzed by that name.
+pub fn changed_tables(sql: &str) -> TernResult<Vec<String>> {
+    let changed = regex(
+        &CHANGED_TABLE_RE,
+        r"^(?:INSERT INTO|UPDATE(?: ONLY)?|DELETE FROM(?: ONLY)?|MERGE INTO(?: ONLY)?|ALTER TABLE(?: IF EXISTS)?(?: ONLY)?|CREATE (?:UNIQUE )?INDEX(?: CONCURRENTLY)?(?: IF NOT EXISTS)?(?: \S+)? ON(?: ONLY)?) (?P<rel>[^\s(]+)",
+    );
+    let gone = regex(
+        &GONE_TABLE_RE,
+        r"^(?:DROP TABLE (?:IF EXISTS )?(?P<dropped>.+?)(?: CASCADE| RESTRICT)?|ALTER TABLE (?:IF EXISTS )?(?:ONLY )?(?P<renamed>\S+) RENAME TO .+)$",
+    );
+    let mut tables = Vec::<String>::new();
+    for statement in Query::new(sql.to_string()).split_statements()? {
+        let statement = normalize(&statement);
+        if let Some(caps) = gone.captures(&statement) {
+            let gone = caps
+                .name("dropped")
+                .or(caps.name("renamed"))
+                .map(|rel| relations(rel.as_str()))
+                .unwrap_or_default();
+            tables.retain(|table| !gone.contains(table));
+            continue;
+        }
+        if let Some(caps) = changed.captures(&statement) {
+            let table = caps["rel"].to_string();
+            if !tables.contains(&table) {
+                tables.push(table);
+            }
+        }
+    }
+
+    Ok(tables)
+}
+
+// The kinds of statement that take a fixed lock, with the relations in the
+// capture group `rel`.  The first one to match is used.
+fn statement_locks() -> &'static [(Regex, LockLevel)] {
+    static LOCKS: OnceLock<Vec<(Regex, LockLevel)>> = OnceLock::new();
+    LOCKS.get_or_init(|| {
+        use LockLevel::*;
+
+        [
+            (
+                r"^CREATE (?:UNIQUE )?INDEX CONCURRENTLY (?:IF NOT EXISTS )?(?:\S+ )?ON (?:ONLY )?(?P<rel>[^\s(]+)",
+                ShareUpdateExclusive,
+            ),
+            (
+                r"^CREATE (?:UNIQUE )?INDEX (?:IF NOT EXISTS )?(?:\S+ )?ON (?:ONLY )?(?P<rel>[^\s(]+)",
+                Share,
+            ),
+            (
+                r"^DROP INDEX CONCURRENTLY (?:IF EXISTS )?(?P<rel>.+?)(?: CASCADE| RESTRICT)?$",
+                ShareUpdateExclusive,
+            ),
+            (
+                r"^DROP (?:INDEX|TABLE|VIEW|MATERIALIZED VIEW|SEQUENCE) (?:IF EXISTS )?(?P<rel>.+?)(?: CASCADE| RESTRICT)?$",
+                AccessExclusive,
+            ),
+            (
+                r"^TRUNCATE (?:TABLE )?(?:ONLY )?(?P<rel>.+?)(?: RESTART IDENTITY| CONTINUE IDENTITY)?(?: CASCADE| RESTRICT)?$",
+                AccessExclusive,
+            ),
+            (
+                r"^ALTER (?:INDEX|SEQUENCE|VIEW|MATERIALIZED VIEW) (?:IF EXISTS )?(?P<rel>\S+)",
+                AccessExclusive,
+            ),
+            (
+                r"^CREATE (?:OR REPLACE )?(?:CONSTRAINT )?TRIGGER .*? ON (?P<rel>\S+)",
+                ShareRowExclusive,
+            ),
+            (r"^DROP TRIGGER (?:IF EXISTS )?\S+ ON (?P<rel>\S+)", AccessExclusive),
+            (
+                r"^VACUUM (?:\(.*FULL.*\) |.*FULL )(?:\S+ )*?(?P<rel>[^\s(]+)$",
+                AccessExclusive,
+            ),
+            (r"^VACUUM (?:\(.*?\) )?(?:\S+ )*?(?P<rel>[^\s(]+)$", ShareUpdateExclusive),
+            (r"^ANALYZE (?:\(.*?\) )?(?:VERBOSE )?(?P<rel>[^\s(]+)", ShareUpdateExclusive),
+            (r"^CLUSTER (?:VERBOSE )?(?P<rel>[^\s(]+)", AccessExclusive),
+            (
+                r"^REINDEX (?:\(.*?\) )?(?:INDEX|TABLE) CONCURRENTLY (?P<rel>\S+)",
+                ShareUpdateExclusive,
+            ),
+            (r"^REINDEX (?:\(.*?\) )?(?:INDEX|TABLE) (?P<rel>\S+)", Share),
+            (
+                r"^REFRESH MATERIALIZED VIEW CONCURRENTLY (?P<rel>\S+)",
+                Exclusive,
+            ),
+            (r"^REFRESH MATERIALIZED VIEW (?P<rel>\S+)", AccessExclusive),
+            (r"^INSERT INTO (?P<rel>[^\s(]+)", RowExclusive),
+            (r"^UPDATE (?:ONLY )?(?P<rel>\S+)", RowExclusive),
+            (r"^DELETE FROM (?:ONLY )?(?P<rel>\S+)", RowExclusive),
+            (r"^MERGE INTO (?:ONLY )?(?P<rel>\S+)", RowExclusive),
+            (
+                r"^SELECT .*? FROM (?P<rel>[^\s(]+).* FOR (?:NO KEY )?(?:UPDATE|SHARE|KEY SHARE)",
+                RowShare,
+            ),
+            (r"^SELECT .*? FROM (?P<rel>[^\s(]+)", AccessShare),
+        ]
+        .into_iter()
+        .map(|(re, lock)| (Regex::new(&format!("(?i){re}")).unwrap(), lock))
+        .collect()
+    })
+}
+
+fn statement_lock(statement: &str) -> Option<(LockLevel, Vec<String>)> {
+    if let Some(caps) = regex(
+        &ALTER_TABLE_RE,
+        r"^ALTER TABLE (?:IF EXISTS )?(?:ONLY )?(\S+) (.+)$",
+    )
+    .captures(statement)
+    {
+        return Some(alter_table_lock(&caps[1], &caps[2]));
+    }
+    if let Some(caps) = regex(
+        &LOCK_TABLE_RE,
+        r"^LOCK (?:TABLE )?(?:ONLY )?(.+?)(?: IN (.+) MODE)?(?: NOWAIT)?$",
+    )
+    .captures(statement)
+    {
+        let lock = caps
+            .get(2)
+            .and_then(|mode| LockLevel::from_mode(mode.as_str()))
+            .unwrap_or(LockLevel::AccessExclusive);
+        return Some((lock, relations(&caps[1])));
+    }
+    // A new table locks only the tables that its foreign keys reference.
+    if regex(&CREATE_TABLE_RE, r"^CREATE (?:UNLOGGED )?TABLE ")
+        .is_match(statement)
+    {
+        let referenced = referenced(statement);
+        return (!referenced.is_empty())
+            .then_some((LockLevel::ShareRowExclusive, referenced));
+    }
+
+    statement_locks().iter().find_map(|(re, lock)| {
+        let caps = re.captures(statement)?;
+        Some((*lock, relations(&caps["rel"])))
+    })
+}
+
+// `ALTER TABLE` takes `ACCESS EXCLUSIVE` unless every one of its actions needs
+// less than that.
+fn alter_table_lock(table: &str, actions: &str) -> (LockLevel, Vec<String>) {
+    let share_update_exclusive = regex(
+        &SHARE_UPDATE_EXCLUSIVE_RE,
+        r"^(?:VALIDATE CONSTRAINT|ALTER (?:COLUMN )?\S+ SET STATISTICS|SET \(|RESET \(|CLUSTER ON|SET WITHOUT CLUSTER|ATTACH PARTITION|DETACH PARTITION .* CONCURRENTLY)",
+    );
+    let share_row_exclusive = regex(
+        &SHARE_ROW_EXCLUSIVE_RE,
+        r"^(?:ADD (?:CONSTRAINT \S+ )?FOREIGN KEY|(?:ENABLE|DISABLE) (?:ALWAYS |REPLICA )?TRIGGER)",
+    );
+    let lock = split_actions(actions)
+        .iter()
+        .map(|action| {
+            if share_update_exclusive.is_match(action) {
+                LockLevel::ShareUpdateExclusive
+            } else if share_row_exclusive.is_match(action) {
+                LockLevel::ShareRowExclusive
+            } else {
+                LockLevel::AccessExclusive
+            }
+        })
+        .max()
+        .unwrap_or(LockLevel::AccessExclusive);
+    let mut relations = vec![table.to_string()];
+    relations.extend(referenced(actions));
+
+    (lock, relations)
+}
+
+// The actions of `ALTER TABLE`, which are separated by commas outside of
+// parentheses.
+fn split_actions(actions: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in actions.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                split.push(actions[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    split.push(actions[start..].trim());
+    split
+}
+
+// Tables referenced by foreign keys.
+fn referenced(statement: &str) -> Vec<String> {
+    regex(&REFERENCES_RE, r"REFERENCES ([^\s(),]+)")
+        .captures_iter(statement)
+        .map(|caps| caps[1].to_string())
+        .collect()
+}
+
+fn relations(list: &str) -> Vec<String> {
+    list.split(',').map(|rel| rel.trim().to_string()).collect()
+}
+
+static ALTER_TABLE_RE: OnceLock<Regex> = OnceLock::new();
+static LOCK_TABLE_RE: OnceLock<Regex> = OnceLock::new();
+static CREATE_TABLE_RE: OnceLock<Regex> = OnceLock::new();
+static SHARE_UPDATE_EXCLUSIVE_RE: OnceLock<Regex> = OnceLock::new();
+static SHARE_ROW_EXCLUSIVE_RE: OnceLock<Regex> = OnceLock::new();
+static REFERENCES_RE: OnceLock<Regex> = OnceLock::new();
+static CHANGED_TABLE_RE: OnceLock<Regex> = OnceLock::new();
+static GONE_TABLE_RE: OnceLock<Regex> = OnceLock::new();
+
+#[cfg(test)]
+mod tests {
+    use super::{LockLevel, analyze, changed_tables};
+
+    fn locks(sql: &str) -> Vec<(LockLevel, Vec<String>)> {
+        analyze(sql)
+            .unwrap()
+            .into_iter()
+            .map(|lock| (lock.lock, lock.relations))
+            .collect()
+    }
+
+    #[test]
+    fn lock_levels() {
+        let sql = "
+CREATE INDEX CONCURRENTLY users_email_idx ON users (email);
+create index on orders(user_id);
+ALTER TABLE users ADD COLUMN age int;
+ALTER TABLE users VALIDATE CONSTRAINT users_age_check;
+ALTER TABLE orders ADD CONSTRAINT orders_user_fk FOREIGN KEY (user_id) REFERENCES users (id) NOT VALID;
+DROP TABLE IF EXISTS a, b CASCADE;
+LOCK TABLE accounts IN SHARE ROW EXCLUSIVE MODE;
+UPDATE users SET age = 0;
+CREATE TABLE t (id int);
+";
+        assert_eq!(
+            locks(sql),
+            vec![
+                (LockLevel::ShareUpdateExclusive, vec!["users".into()]),
+                (LockLevel::Share, vec!["orders".into()]),
+                (LockLevel::AccessExclusive, vec!["users".into()]),
+                (LockLevel::ShareUpdateExclusive, vec!["users".into()]),
+                (
+                    LockLevel::ShareRowExclusive,
+                    vec!["orders".into(), "users".into()]
+                ),
+                (LockLevel::AccessExclusive, vec!["a".into(), "b".into()]),
+                (LockLevel::ShareRowExclusive, vec!["accounts".into()]),
+                (LockLevel::RowExclusive, vec!["users".into()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn alter_table_strongest_action() {
+        let sql = "ALTER TABLE users SET (fillfactor = 70), ALTER COLUMN name TYPE text;";
+        assert_eq!(
+            locks(sql),
+            vec![(LockLevel::AccessExclusive, vec!["users".into()])]
+        );
+    }
+
+    #[test]
+    fn tables_changed() {
+        let sql = "
+CREATE TABLE t (id int);
+INSERT INTO t (id) SELECT id FROM users;
+UPDATE ONLY orders SET total = 0;
+create index orders_total_idx on orders (total);
+ALTER TABLE users ADD COLUMN age int;
+DELETE FROM scratch;
+DROP TABLE IF EXISTS scratch;
+ALTER TABLE t RENAME TO t2;
+SELECT * FROM accounts;
+";
+        assert_eq!(changed_tables(sql).unwrap(), ["orders", "users"]);
+    }
+}
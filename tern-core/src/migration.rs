@@ -12,13 +12,27 @@
 //!
 //! Generally these shouldn't be implemented; use the corresponding derive macro
 //! instead.
-use crate::error::{DatabaseError as _, TernResult};
+use crate::config::{self, ConfigSource, Flags};
+use crate::error::{DatabaseError as _, Error, TernResult};
+use crate::inventory::DatabaseObject;
+use crate::schema::SchemaObject;
+use crate::source::fs::checksum;
 
 use chrono::{DateTime, Utc};
-use futures_core::{Future, future::BoxFuture};
-use std::time::Instant;
+use futures_core::Future;
+use futures_core::future::BoxFuture;
+use regex::Regex;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
+pub use crate::annotation::Annotations;
 pub use crate::query::Query;
+pub use crate::version::{
+    MigrationOrdering, NumericOrdering, Version, VersionKind,
+};
 
 /// The context in which a migration run occurs.
 pub trait MigrationContext
@@ -32,12 +46,175 @@ where
     /// database driver if using the derive macro for this trait.
     const HISTORY_TABLE: &str;
 
+    /// The kind of version that the migrations have.
+    ///
+    /// It decides the type of the version column when the history table is
+    /// created, so changing it for an existing history table means changing
+    /// the type of that column too.
+    const VERSION_KIND: VersionKind = VersionKind::Integer;
+
+    /// The order that migrations are applied in.
+    ///
+    /// By default this is the order of the version numbers.
+    const ORDERING: &'static dyn MigrationOrdering = &NumericOrdering;
+
+    /// An identifier for this migration set, for when the history table is
+    /// shared with other migration sets, such as those of other services using
+    /// the same database.
+    ///
+    /// Applied migrations are saved with it, and only the rows having it are
+    /// read from the history table, so the sets can have the same versions.
+    /// The history table has to be created with the `source_id` column as part
+    /// of its primary key for that, which is the case for a new table, and for
+    /// an older table on Postgres and MySQL when the column is added to it.
+    /// Checkpoints, the statements recorded by a resumable migration, and
+    /// failed migrations are kept by source as well.
+    const SOURCE_ID: Option<&'static str> = None;
+
+    /// SQL to run on each database connection before it's used for migrations
+    /// or the history table, such as `SET ROLE migrator; SET search_path TO
+    /// app;`.
+    ///
+    /// The [`Runner`](crate::runner::Runner) sets it up before its first
+    /// operation; see [`prepare_session`](Self::prepare_session).
+    const SESSION_SETUP: Option<&'static str> = None;
+
+    /// The Postgres `search_path` for the whole run, such as `app, public`, so
+    /// that the unqualified names in migrations, and the history table if its
+    /// name is unqualified, are in the intended schema.
+    ///
+    /// It is set on each connection after the
+    /// [`SESSION_SETUP`](Self::SESSION_SETUP), and recorded in the history row
+    /// of each migration applied; see [`search_path`](Self::search_path).
+    const SEARCH_PATH: Option<&'static str> = None;
+
+    /// SQL to run once after a run that applied migrations, such as
+    /// `SELECT pg_notify('schema', 'changed');` or refreshing a view of the
+    /// schema.
+    ///
+    /// See [`post_apply`](Self::post_apply).
+    const POST_APPLY: Option<&'static str> = None;
+
+    /// What to keep in the `content` column of the history table for an
+    /// applied migration.
+    ///
+    /// By default this is the whole query.  With [`HistoryContent::Checksum`]
+    /// it is only its checksum and length, for a project with migrations so
+    /// big that the history table would be mostly their content.  With the
+    /// feature `zstd`, [`HistoryContent::Zstd`] keeps all of it compressed,
+    /// and it is decompressed when the history is read.
+    const HISTORY_CONTENT: HistoryContent = HistoryContent::Full;
+
     /// The type for executing queries in a migration run.
     type Exec: Executor;
 
     /// A reference to the underlying `Executor`.
     fn executor(&mut self) -> &mut Self::Exec;
 
+    /// Configuration values available when building queries.
+    ///
+    /// This reads environment variables unless the context says otherwise,
+    /// which the derive macro does for a field marked `#[tern(config)]`.  A
+    /// source given to a runner with [`Runner::with_config`] takes the place
+    /// of either one.
+    ///
+    /// [`Runner::with_config`]: crate::runner::Runner::with_config
+    fn config(&self) -> &ConfigSource {
+        config::context_config::<Self>().unwrap_or_else(config::default_config)
+    }
+
+    /// The name of the history table to use for this run.
+    ///
+    /// This is [`HISTORY_TABLE`](Self::HISTORY_TABLE) unless the context's
+    /// config has a value for [`HISTORY_TABLE_KEY`], which is how the same
+    /// migrations can be tracked in a different table per environment.
+    ///
+    /// [`HISTORY_TABLE_KEY`]: crate::config::HISTORY_TABLE_KEY
+    fn history_table(&self) -> String {
+        self.config()
+            .get(config::HISTORY_TABLE_KEY)
+            .unwrap_or_else(|| Self::HISTORY_TABLE.to_string())
+    }
+
+    /// The SQL that sets up each database session for this run.
+    ///
+    /// This is [`SESSION_SETUP`](Self::SESSION_SETUP) unless the context's
+    /// config has a value for [`SESSION_SETUP_KEY`].
+    ///
+    /// [`SESSION_SETUP_KEY`]: crate::config::SESSION_SETUP_KEY
+    fn session_setup(&self) -> Option<Query> {
+        self.config()
+            .get(config::SESSION_SETUP_KEY)
+            .or_else(|| Self::SESSION_SETUP.map(str::to_string))
+            .map(Query::new)
+    }
+
+    /// The `search_path` for this run.
+    ///
+    /// This is [`SEARCH_PATH`](Self::SEARCH_PATH) unless the context's config
+    /// has a value for [`SEARCH_PATH_KEY`].
+    ///
+    /// [`SEARCH_PATH_KEY`]: crate::config::SEARCH_PATH_KEY
+    fn search_path(&self) -> Option<String> {
+        self.config()
+            .get(config::SEARCH_PATH_KEY)
+            .or_else(|| Self::SEARCH_PATH.map(str::to_string))
+    }
+
+    /// The SQL to run after a run that applied migrations.
+    ///
+    /// This is [`POST_APPLY`](Self::POST_APPLY) unless the context's config
+    /// has a value for [`POST_APPLY_KEY`].  It is not run in a transaction, so
+    /// that it can be anything, and it is run while the run still has the
+    /// lock.
+    ///
+    /// [`POST_APPLY_KEY`]: crate::config::POST_APPLY_KEY
+    fn post_apply(&self) -> Option<Query> {
+        self.config()
+            .get(config::POST_APPLY_KEY)
+            .or_else(|| Self::POST_APPLY.map(str::to_string))
+            .map(Query::new)
+    }
+
+    /// The feature flags of this run, which are the ones in the context's
+    /// config under [`FLAGS_KEY`], for a Rust migration to build a different
+    /// query depending on them.
+    ///
+    /// [`FLAGS_KEY`]: crate::config::FLAGS_KEY
+    fn flags(&self) -> Flags {
+        Flags::from_config(self.config())
+    }
+
+    /// Have the executor run the [`session_setup`](Self::session_setup) SQL on
+    /// its connections, if there is any, followed by setting the
+    /// [`search_path`](Self::search_path), which is an error for a database
+    /// other than Postgres.
+    ///
+    /// This needs to happen before anything else is done with the executor.
+    fn prepare_session(&mut self) -> BoxFuture<'_, TernResult<()>> {
+        let setup = self.session_setup();
+        let search_path = self.search_path().map(|path| {
+            <<Self::Exec as Executor>::Queries as QueryRepository>::set_search_path_query(&path)
+                .ok_or_else(|| {
+                    Error::Invalid(format!(
+                        "cannot set the search_path to {path} with this executor"
+                    ))
+                })
+        });
+        Box::pin(async move {
+            let setup = match (setup, search_path.transpose()?) {
+                (Some(setup), Some(path)) => {
+                    Some(Query::new(format!("{}\n{}", setup.sql(), path.sql())))
+                },
+                (setup, path) => setup.or(path),
+            };
+            match setup {
+                Some(query) => self.executor().set_session_setup(query).await,
+                _ => Ok(()),
+            }
+        })
+    }
+
     /// For a migration that is capable of building its query in this migration
     /// context, this builds the query, applies the migration, then updates the
     /// schema history table after.
@@ -45,51 +222,69 @@ where
         &'conn mut self,
         migration: &'migration M,
     ) -> BoxFuture<'migration, TernResult<AppliedMigration>>
+    where
+        M: Migration<Ctx = Self> + Send + Sync + ?Sized,
+    {
+        self.apply_with(migration, ApplyOptions::default())
+    }
+
+    /// Like [`apply`](Self::apply), with options for applying the migration
+    /// when the migration doesn't have its own in an annotation.
+    fn apply_with<'migration, 'conn: 'migration, M>(
+        &'conn mut self,
+        migration: &'migration M,
+        defaults: ApplyOptions,
+    ) -> BoxFuture<'migration, TernResult<AppliedMigration>>
+    where
+        M: Migration<Ctx = Self> + Send + Sync + ?Sized,
+    {
+        Box::pin(async move {
+            let (applied, ..) =
+                self.apply_with_steps(migration, defaults).await?;
+            Ok(applied)
+        })
+    }
+
+    /// Like [`apply_with`](Self::apply_with), also returning the statements
+    /// that the migration was applied as when they were applied one at a
+    /// time, see [`Executor::apply_statements`], and the query that it built,
+    /// so that what comes after applying it doesn't have to build it again.
+    #[allow(clippy::type_complexity)]
+    fn apply_with_steps<'migration, 'conn: 'migration, M>(
+        &'conn mut self,
+        migration: &'migration M,
+        defaults: ApplyOptions,
+    ) -> BoxFuture<'migration, TernResult<(AppliedMigration, Vec<Step>, Query)>>
     where
         M: Migration<Ctx = Self> + Send + Sync + ?Sized,
     {
         Box::pin(async move {
             let start = Instant::now();
+            // Invalid options fail before building, which can have effects.
+            defaults
+                .for_migration(migration)
+                .tern_migration_result(migration)?;
             let query = M::build(migration, self).await?;
-            let executor = self.executor();
-
-            if migration.no_tx() {
-                executor
-                    .apply_no_tx(&query)
-                    .await
-                    .void_tern_migration_result(migration)?;
-            } else {
-                executor
-                    .apply_tx(&query)
-                    .await
-                    .void_tern_migration_result(migration)?;
-            }
+            let (applied, steps) =
+                apply_built(self, migration, &query, defaults, start.elapsed())
+                    .await?;
 
-            let applied_at = Utc::now();
-            let duration_ms = start.elapsed().as_millis() as i64;
-            let applied =
-                migration.to_applied(duration_ms, applied_at, query.sql());
-            executor
-                .insert_applied_migration(Self::HISTORY_TABLE, &applied)
-                .await?;
-
-            Ok(applied)
+            Ok((applied, steps, query))
         })
     }
 
     /// Gets the version of the most recently applied migration.
-    fn latest_version(&mut self) -> BoxFuture<'_, TernResult<Option<i64>>> {
+    fn latest_version(&mut self) -> BoxFuture<'_, TernResult<Option<Version>>> {
+        let history_table = self.history_table();
         Box::pin(async move {
             let latest = self
                 .executor()
-                .get_all_applied(Self::HISTORY_TABLE)
+                .get_all_applied(&history_table)
                 .await?
                 .into_iter()
-                .fold(None, |acc, m| match acc {
-                    None => Some(m.version),
-                    Some(v) if m.version > v => Some(m.version),
-                    _ => acc,
-                });
+                .filter(|m| m.source_id.as_deref() == Self::SOURCE_ID)
+                .map(|m| m.version)
+                .max_by(|a, b| Self::ORDERING.cmp(a, b));
 
             Ok(latest)
         })
@@ -99,19 +294,40 @@ where
     fn previously_applied(
         &mut self,
     ) -> BoxFuture<'_, TernResult<Vec<AppliedMigration>>> {
-        Box::pin(self.executor().get_all_applied(Self::HISTORY_TABLE))
+        let history_table = self.history_table();
+        Box::pin(async move {
+            let applied = self
+                .executor()
+                .get_all_applied(&history_table)
+                .await?
+                .into_iter()
+                .filter(|m| m.source_id.as_deref() == Self::SOURCE_ID)
+                .map(AppliedMigration::decompressed)
+                .collect::<TernResult<Vec<_>>>()?;
+
+            Ok(applied)
+        })
     }
 
     /// Check that the history table exists and create it if not.
     fn check_history_table(&mut self) -> BoxFuture<'_, TernResult<()>> {
-        Box::pin(
-            self.executor().create_history_if_not_exists(Self::HISTORY_TABLE),
-        )
+        let history_table = self.history_table();
+        Box::pin(async move {
+            self.executor()
+                .create_history_if_not_exists(
+                    &history_table,
+                    Self::VERSION_KIND,
+                )
+                .await
+        })
     }
 
     /// Drop the history table if requested.
     fn drop_history_table(&mut self) -> BoxFuture<'_, TernResult<()>> {
-        Box::pin(self.executor().drop_history(Self::HISTORY_TABLE))
+        let history_table = self.history_table();
+        Box::pin(
+            async move { self.executor().drop_history(&history_table).await },
+        )
     }
 
     /// Insert an applied migration.
@@ -119,10 +335,12 @@ where
         &'conn mut self,
         applied: &'migration AppliedMigration,
     ) -> BoxFuture<'migration, TernResult<()>> {
-        Box::pin(
+        let history_table = self.history_table();
+        Box::pin(async move {
             self.executor()
-                .insert_applied_migration(Self::HISTORY_TABLE, applied),
-        )
+                .insert_applied_migration(&history_table, applied)
+                .await
+        })
     }
 
     /// Upsert applied migrations.
@@ -130,18 +348,145 @@ where
         &'conn mut self,
         applied: &'migration AppliedMigration,
     ) -> BoxFuture<'migration, TernResult<()>> {
-        Box::pin(
+        let history_table = self.history_table();
+        Box::pin(async move {
             self.executor()
-                .upsert_applied_migration(Self::HISTORY_TABLE, applied),
+                .upsert_applied_migration(&history_table, applied)
+                .await
+        })
+    }
+
+    /// Delete the history row of an applied migration.
+    fn delete_applied<'migration, 'conn: 'migration>(
+        &'conn mut self,
+        applied: &'migration AppliedMigration,
+    ) -> BoxFuture<'migration, TernResult<()>> {
+        let history_table = self.history_table();
+        Box::pin(async move {
+            self.executor()
+                .delete_applied_migration(&history_table, applied)
+                .await
+        })
+    }
+
+    /// Take the run lock for this history table.
+    fn lock(&mut self) -> BoxFuture<'_, TernResult<()>> {
+        let history_table = self.history_table();
+        Box::pin(
+            async move { self.executor().acquire_lock(&history_table).await },
         )
     }
+
+    /// Release the run lock for this history table.
+    fn unlock(&mut self) -> BoxFuture<'_, TernResult<()>> {
+        let history_table = self.history_table();
+        Box::pin(
+            async move { self.executor().release_lock(&history_table).await },
+        )
+    }
+
+    /// Get the checkpoint last saved by the migration with this version, if
+    /// there is one.
+    ///
+    /// This is meant for long-running Rust migrations that do their work in
+    /// batches while building the query.  Resuming from the checkpoint means
+    /// that a run that crashed partway through doesn't have to start over.
+    fn checkpoint(
+        &mut self,
+        version: impl Into<Version>,
+    ) -> BoxFuture<'_, TernResult<Option<String>>> {
+        let history_table = self.history_table();
+        let version = version.into().to_kind(Self::VERSION_KIND);
+        Box::pin(async move {
+            let checkpoint = self
+                .executor()
+                .get_checkpoint(&history_table, Self::SOURCE_ID, &version?)
+                .await?
+                .map(|c| c.checkpoint);
+
+            Ok(checkpoint)
+        })
+    }
+
+    /// Save a checkpoint for the migration with this version, replacing any
+    /// that was saved before.
+    ///
+    /// The checkpoint is removed once the migration is applied successfully.
+    fn save_checkpoint<'a, 'conn: 'a>(
+        &'conn mut self,
+        version: impl Into<Version>,
+        checkpoint: &'a str,
+    ) -> BoxFuture<'a, TernResult<()>> {
+        let history_table = self.history_table();
+        let version = version.into().to_kind(Self::VERSION_KIND);
+        Box::pin(async move {
+            self.executor()
+                .save_checkpoint(
+                    &history_table,
+                    Self::SOURCE_ID,
+                    &version?,
+                    checkpoint,
+                )
+                .await
+        })
+    }
+}
+
+// Apply a migration with the query that was built for it, then update the
+// schema history table.  `built_in` is how long building the query took, which
+// counts toward how long the migration took to apply.
+pub(crate) async fn apply_built<C, M>(
+    ctx: &mut C,
+    migration: &M,
+    query: &Query,
+    defaults: ApplyOptions,
+    built_in: Duration,
+) -> TernResult<(AppliedMigration, Vec<Step>)>
+where
+    C: MigrationContext + ?Sized,
+    M: Migration<Ctx = C> + Send + Sync + ?Sized,
+{
+    let start = Instant::now();
+    let options =
+        defaults.for_migration(migration).tern_migration_result(migration)?;
+    let history_table = ctx.history_table();
+    let search_path = ctx.search_path();
+    let executor = ctx.executor();
+
+    let steps = executor
+        .apply_with_progress(
+            &history_table,
+            C::SOURCE_ID,
+            &migration.version(),
+            query,
+            &options,
+        )
+        .await
+        .tern_migration_result(migration)?;
+
+    let applied_at = Utc::now();
+    let duration_ms = (built_in + start.elapsed()).as_millis() as i64;
+    let mut applied =
+        migration.to_applied(duration_ms, applied_at, query.sql());
+    applied.search_path = search_path;
+    executor.insert_applied_migration(&history_table, &applied).await?;
+    // A completed migration has no use for a checkpoint it may have saved
+    // along the way.
+    executor
+        .clear_checkpoint(&history_table, C::SOURCE_ID, &migration.version())
+        .await?;
+    executor
+        .clear_progress(&history_table, C::SOURCE_ID, &migration.version())
+        .await?;
+
+    Ok((applied, steps))
 }
 
 /// The "executor" type for the database backend ultimately responsible for
 /// issuing migration and schema history queries.
 pub trait Executor
 where
-    Self: Send + Sync + 'static,
+    Self: Send + Sync,
 {
     /// The type of value that can produce queries for the history table of this
     /// migration set.
@@ -159,63 +504,728 @@ where
         query: &Query,
     ) -> impl Future<Output = TernResult<()>> + Send;
 
-    /// `CREATE IF NOT EXISTS` the history table.
+    /// Apply the `Query` for the migration with these options.
+    ///
+    /// By default this is [`apply_no_tx`](Self::apply_no_tx) or
+    /// [`apply_tx`](Self::apply_tx), and options other than `no_tx` are
+    /// ignored.
+    fn apply(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        async move {
+            if options.has_session_settings() {
+                log::warn!(
+                    "this executor does not support {options:?}, applying without them"
+                );
+            }
+            if options.no_tx {
+                self.apply_no_tx(query).await
+            } else {
+                self.apply_tx(query).await
+            }
+        }
+    }
+
+    /// Like [`apply`](Self::apply), returning each statement with how long
+    /// it took when the statements of the query are applied one at a time,
+    /// which is how a migration that is not applied in a transaction is, and
+    /// one that is with [`ApplyOptions::split_statements`].
+    ///
+    /// By default this is `apply`, and there are no statements.
+    fn apply_statements(
+        &mut self,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> impl Future<Output = TernResult<Vec<Step>>> + Send {
+        async move { self.apply(query, options).await.map(|()| Vec::new()) }
+    }
+
+    /// `CREATE IF NOT EXISTS` the history table, with a version column for
+    /// this kind of version.
     fn create_history_if_not_exists(
         &mut self,
         history_table: &str,
+        version: VersionKind,
+    ) -> impl Future<Output = TernResult<()>> + Send;
+
+    /// Whether the history table exists.
+    ///
+    /// By default it is taken to exist if its rows can be read, which doesn't
+    /// tell a missing table apart from failing to read it for another reason,
+    /// so the executors here look for it in the catalog with
+    /// [`QueryRepository::history_exists_query`] instead.
+    fn history_exists(
+        &mut self,
+        history_table: &str,
+    ) -> impl Future<Output = TernResult<bool>> + Send {
+        async move { Ok(self.get_all_applied(history_table).await.is_ok()) }
+    }
+
+    /// `DROP` the history table.
+    fn drop_history(
+        &mut self,
+        history_table: &str,
+    ) -> impl Future<Output = TernResult<()>> + Send;
+
+    /// Get the complete history of applied migrations.
+    fn get_all_applied(
+        &mut self,
+        history_table: &str,
+    ) -> impl Future<Output = TernResult<Vec<AppliedMigration>>> + Send;
+
+    /// Insert an applied migration into the history table.
+    fn insert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> impl Future<Output = TernResult<()>> + Send;
+
+    /// Update or insert an applied migration.
+    fn upsert_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
     ) -> impl Future<Output = TernResult<()>> + Send;
 
-    /// `DROP` the history table.
-    fn drop_history(
-        &mut self,
-        history_table: &str,
-    ) -> impl Future<Output = TernResult<()>> + Send;
+    /// Copy the history table, with its rows, to a new table.
+    ///
+    /// Executors that don't support this return an error.
+    fn backup_history(
+        &mut self,
+        history_table: &str,
+        backup_table: &str,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = (history_table, backup_table);
+        async {
+            Err(Error::Invalid(
+                "backing up the history table is not supported by this executor"
+                    .into(),
+            ))
+        }
+    }
+
+    /// Delete the row of an applied migration from the history table.
+    ///
+    /// Executors that don't support this return an error.
+    fn delete_applied_migration(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = (history_table, applied);
+        async {
+            Err(Error::Invalid(
+                "deleting from the history table is not supported by this executor"
+                    .into(),
+            ))
+        }
+    }
+
+    /// Get the checkpoint saved by the migration with this version.
+    ///
+    /// Executors that don't support checkpoints never have one to return.
+    fn get_checkpoint(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        version: &Version,
+    ) -> impl Future<Output = TernResult<Option<Checkpoint>>> + Send {
+        let _ = (history_table, source_id, version);
+        async { Ok(None) }
+    }
+
+    /// Insert or replace the checkpoint for the migration with this version.
+    fn save_checkpoint(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        version: &Version,
+        checkpoint: &str,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = (history_table, source_id, version, checkpoint);
+        async {
+            Err(Error::Invalid(
+                "checkpoints are not supported by this executor".into(),
+            ))
+        }
+    }
+
+    /// Remove the checkpoint for the migration with this version.
+    fn clear_checkpoint(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        version: &Version,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = (history_table, source_id, version);
+        async { Ok(()) }
+    }
+
+    /// Like [`apply_statements`](Self::apply_statements), recording each
+    /// statement of the migration with this version as it completes, for a
+    /// database that commits changes to the schema as they are made, or for a
+    /// migration that is not applied in a transaction and has
+    /// [`ApplyOptions::resume`].  If the run is interrupted, the statements
+    /// that were recorded are the ones that already ran, see
+    /// [`get_progress`](Self::get_progress).  With `resume`, those are skipped
+    /// the next time, as long as they are the same statements.
+    ///
+    /// The statements are recorded with `source_id`, the
+    /// [`SOURCE_ID`](MigrationContext::SOURCE_ID) of the migration set, so
+    /// that sets sharing the history table can have the same versions.
+    ///
+    /// By default this is `apply_statements`, and nothing is recorded.
+    fn apply_with_progress(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        version: &Version,
+        query: &Query,
+        options: &ApplyOptions,
+    ) -> impl Future<Output = TernResult<Vec<Step>>> + Send {
+        let _ = (history_table, source_id, version);
+        self.apply_statements(query, options)
+    }
+
+    /// Get the statements recorded by
+    /// [`apply_with_progress`](Self::apply_with_progress) for the migration
+    /// with this version, in the order they ran.
+    ///
+    /// They are removed once the migration is in the history table, so this
+    /// is empty unless applying it did not finish.
+    fn get_progress(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        version: &Version,
+    ) -> impl Future<Output = TernResult<Vec<StatementProgress>>> + Send {
+        let _ = (history_table, source_id, version);
+        async { Ok(Vec::new()) }
+    }
+
+    /// Remove the statements recorded for the migration with this version.
+    fn clear_progress(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        version: &Version,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = (history_table, source_id, version);
+        async { Ok(()) }
+    }
+
+    /// Record that applying the migration with this version failed with
+    /// `error`, adding to the attempts if it failed before, so that a migration
+    /// that keeps failing across runs can be found with
+    /// [`get_quarantined`](Self::get_quarantined).
+    ///
+    /// By default nothing is recorded.
+    fn record_failure(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        id: &MigrationId,
+        error: &str,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = (history_table, source_id, id, error);
+        async { Ok(()) }
+    }
+
+    /// Get the migrations recorded by
+    /// [`record_failure`](Self::record_failure) that haven't been applied
+    /// since.
+    fn get_quarantined(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        version: VersionKind,
+    ) -> impl Future<Output = TernResult<Vec<QuarantinedMigration>>> + Send
+    {
+        let _ = (history_table, source_id, version);
+        async { Ok(Vec::new()) }
+    }
+
+    /// Remove the migration with this version from the failed migrations, once
+    /// it has been applied.
+    fn release_quarantined(
+        &mut self,
+        history_table: &str,
+        source_id: Option<&str>,
+        version: &Version,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = (history_table, source_id, version);
+        async { Ok(()) }
+    }
+
+    /// Get the repeatable migrations that have been applied, with the checksum
+    /// of the content that each was last applied with.
+    ///
+    /// Executors that don't support repeatable migrations never have any.
+    fn get_applied_repeatable(
+        &mut self,
+        history_table: &str,
+    ) -> impl Future<Output = TernResult<Vec<AppliedRepeatable>>> + Send {
+        let _ = history_table;
+        async { Ok(Vec::new()) }
+    }
+
+    /// Insert or replace the row of a repeatable migration that was applied.
+    fn upsert_applied_repeatable(
+        &mut self,
+        history_table: &str,
+        applied: &AppliedRepeatable,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = (history_table, applied);
+        async {
+            Err(Error::Invalid(
+                "repeatable migrations are not supported by this executor"
+                    .into(),
+            ))
+        }
+    }
+
+    /// Run `query` on every connection before it's used, to set up the
+    /// session, e.g., the role or the `search_path`.
+    ///
+    /// Executors that don't support this return an error.
+    fn set_session_setup(
+        &mut self,
+        query: Query,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = query;
+        async {
+            Err(Error::Invalid(
+                "session setup is not supported by this executor".into(),
+            ))
+        }
+    }
+
+    /// Apply the queries, in order, in one transaction that is then rolled
+    /// back, so that they can be tried and timed without changing anything.
+    ///
+    /// This stops at the first query that fails.  Executors for a database
+    /// that commits changes to the schema as they are made, or that don't
+    /// support this, return an error.
+    fn apply_rolled_back(
+        &mut self,
+        queries: &[(Query, ApplyOptions)],
+    ) -> impl Future<Output = TernResult<RolledBack>> + Send {
+        let _ = queries;
+        async {
+            Err(Error::Invalid(
+                "applying in a transaction that is rolled back is not supported by this executor"
+                    .into(),
+            ))
+        }
+    }
+
+    /// List the tables, indexes, and views in the database, which are the
+    /// ones in the schemas of the `search_path` on Postgres, for finding the
+    /// migrations that are already in effect, see [`inventory`].
+    ///
+    /// Executors that don't support this return an error.
+    ///
+    /// [`inventory`]: crate::inventory
+    fn inventory(
+        &mut self,
+    ) -> impl Future<Output = TernResult<Vec<DatabaseObject>>> + Send {
+        async {
+            Err(Error::Invalid(
+                "listing the objects in the database is not supported by this executor"
+                    .into(),
+            ))
+        }
+    }
+
+    /// List the indexes that are invalid, because building them concurrently
+    /// failed, which are the ones in the schemas of the `search_path` on
+    /// Postgres.
+    ///
+    /// Executors that don't support this return an error.
+    fn invalid_indexes(
+        &mut self,
+    ) -> impl Future<Output = TernResult<Vec<DatabaseObject>>> + Send {
+        async {
+            Err(Error::Invalid(
+                "listing invalid indexes is not supported by this executor"
+                    .into(),
+            ))
+        }
+    }
+
+    /// The statement that creates each table, index, and view in the
+    /// database, which are the ones in the schemas of the `search_path` on
+    /// Postgres, for a snapshot of the schema, see [`schema`].
+    ///
+    /// Executors that don't support this return an error.
+    ///
+    /// [`schema`]: crate::schema
+    fn schema(
+        &mut self,
+    ) -> impl Future<Output = TernResult<Vec<SchemaObject>>> + Send {
+        async {
+            Err(Error::Invalid(
+                "dumping the schema is not supported by this executor".into(),
+            ))
+        }
+    }
+
+    /// Check that the database is reachable and accepting queries.
+    fn ping(&mut self) -> impl Future<Output = TernResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Take the lock that keeps concurrent migration runs from interfering
+    /// with each other, waiting until it's available.
+    ///
+    /// By default there is no lock.
+    fn acquire_lock(
+        &mut self,
+        history_table: &str,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = history_table;
+        async { Ok(()) }
+    }
+
+    /// Release the lock taken by `acquire_lock`.
+    fn release_lock(
+        &mut self,
+        history_table: &str,
+    ) -> impl Future<Output = TernResult<()>> + Send {
+        let _ = history_table;
+        async { Ok(()) }
+    }
+}
+
+/// The SQL dialect of a database, for what depends on which database the
+/// migrations are for, like some lint rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Postgres,
+    MySql,
+    Sqlite,
+}
+
+impl Dialect {
+    pub const VARIANTS: [&str; 3] = ["postgres", "mysql", "sqlite"];
+}
+
+impl FromStr for Dialect {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(Self::Postgres),
+            "mysql" => Ok(Self::MySql),
+            "sqlite" => Ok(Self::Sqlite),
+            _ => Err(Error::Invalid(format!("unknown SQL dialect {s}"))),
+        }
+    }
+}
+
+/// A type that has a library of "administrative" queries that are needed during
+/// a migration run.
+pub trait QueryRepository {
+    /// The SQL dialect of the database that the queries are for, if it is one
+    /// of the known ones.
+    const DIALECT: Option<Dialect> = None;
+
+    /// How many times an executor applies a migration in a transaction again
+    /// when the transaction fails with a serialization failure, see
+    /// [`Error::is_serialization_failure`].
+    const SERIALIZATION_RETRIES: u32 = 0;
+
+    /// The type of the version column for this kind of version.
+    ///
+    /// The default is `bigint` for integer versions and `varchar(255)` for
+    /// text versions, which can be the primary key on every supported
+    /// database.
+    fn version_column_type(version: VersionKind) -> &'static str {
+        match version {
+            VersionKind::Integer => "bigint",
+            VersionKind::Text => "varchar(255)",
+        }
+    }
+
+    /// The query that creates the schema history table or does nothing if it
+    /// already exists.
+    fn create_history_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Query;
+
+    /// The query that drops the history table if requested.
+    fn drop_history_query(history_table: &str) -> Query;
+
+    /// The query that selects a row if the history table exists and none if
+    /// it doesn't, with a placeholder for its name, or `None` if there is no
+    /// such query for this database.  See [`Executor::history_exists`].
+    fn history_exists_query() -> Option<Query> {
+        None
+    }
+
+    /// The query to update the schema history table with an applied migration.
+    fn insert_into_history_query(
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> Query;
+
+    /// Columns that were added to the history table after it was first
+    /// released, paired with the query that adds each one to a table that was
+    /// created before then.
+    fn add_history_columns_queries(
+        history_table: &str,
+    ) -> Vec<(&'static str, Query)>;
+
+    /// The query to return all rows from the schema history table.
+    fn select_star_from_history_query(history_table: &str) -> Query;
+
+    /// Query to insert or update a record in the history table.
+    fn upsert_history_query(
+        history_table: &str,
+        applied: &AppliedMigration,
+    ) -> Query;
+
+    /// The query that copies the history table, with its rows, to a new table.
+    fn backup_history_query(history_table: &str, backup_table: &str) -> Query {
+        let sql = format!(
+            "CREATE TABLE {backup_table} AS SELECT * FROM {history_table};"
+        );
+
+        Query::new(sql)
+    }
+
+    /// Query to delete the record of one migration version from the history
+    /// table, with placeholders for the version and the source ID.
+    fn delete_from_history_query(history_table: &str) -> Query;
+
+    /// The query that creates the table holding migration checkpoints, which
+    /// lives next to the history table, or does nothing if it already exists.
+    ///
+    /// It is `None` if there are no checkpoints for this database, in which
+    /// case saving one is an error.  See [`Executor::save_checkpoint`].
+    fn create_checkpoint_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let _ = (history_table, version);
+        None
+    }
+
+    /// The query that drops the checkpoint table.
+    fn drop_checkpoint_table_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// The query to select the checkpoint for one migration version.
+    fn select_checkpoint_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// Query to insert or replace the checkpoint for a migration version.
+    fn upsert_checkpoint_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// Query to delete the checkpoint for a migration version.
+    fn delete_checkpoint_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// The query that takes the lock for a migration run, waiting for it if
+    /// another run has it, or `None` if the database has no such lock.
+    fn acquire_lock_query(history_table: &str) -> Option<Query>;
+
+    /// The query that releases the lock for a migration run.
+    fn release_lock_query(history_table: &str) -> Option<Query>;
+
+    /// The query that sets up the session for applying a migration with these
+    /// options, such as its timeouts, or `None` if the database has no such
+    /// settings.
+    ///
+    /// It runs just before the migration.  If the migration is in a
+    /// transaction the settings only need to last until the end of it.
+    fn set_session_query(options: &ApplyOptions) -> Option<Query> {
+        let _ = options;
+        None
+    }
+
+    /// The query that undoes [`set_session_query`] after a migration that
+    /// is applied outside of a transaction.
+    ///
+    /// [`set_session_query`]: Self::set_session_query
+    fn reset_session_query(options: &ApplyOptions) -> Option<Query> {
+        let _ = options;
+        None
+    }
+
+    /// The query that sets the `search_path` of a session, or `None` if the
+    /// database doesn't have one.
+    fn set_search_path_query(search_path: &str) -> Option<Query> {
+        let _ = search_path;
+        None
+    }
+
+    /// The query that notifies listeners on a channel that a migration was
+    /// applied, or `None` if the database has no such notifications.
+    ///
+    /// It has placeholders for the channel and then the version.
+    fn notify_query() -> Option<Query> {
+        None
+    }
+
+    /// The query that creates the table recording the statements of a
+    /// migration as they complete, or `None` if there is no such table for
+    /// this database.  See [`Executor::apply_with_progress`].
+    ///
+    /// The `sqlx` executors record the statements of every migration on
+    /// MySQL, and on the others only of one with [`ApplyOptions::resume`].
+    fn create_progress_table_if_not_exists_query(
+        history_table: &str,
+        version: VersionKind,
+    ) -> Option<Query> {
+        let _ = (history_table, version);
+        None
+    }
+
+    /// The query that drops the table recording statements.
+    fn drop_progress_table_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// The query that records a statement of a migration, with placeholders
+    /// for the version, the source ID, the index of the statement, the
+    /// statement, and when it completed.
+    fn insert_progress_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// The query that selects the statements recorded for a migration, with
+    /// placeholders for the version and the source ID.
+    fn select_progress_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// The query that removes the statements recorded for a migration, with
+    /// placeholders for the version and the source ID.
+    fn delete_progress_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// The query that selects the tables, indexes, and views in the database
+    /// as rows with the columns `kind`, which is `table`, `index`, or `view`,
+    /// and `name`, or `None` if there is none for this database.  See
+    /// [`Executor::inventory`].
+    fn inventory_query() -> Option<Query> {
+        None
+    }
+
+    /// The query that selects the indexes that are invalid, because building
+    /// them concurrently failed, as rows with the columns `kind`, which is
+    /// `index`, and `name`, or `None` if there is none for this database.  See
+    /// [`Executor::invalid_indexes`].
+    fn invalid_indexes_query() -> Option<Query> {
+        None
+    }
+
+    /// The query that selects the tables, indexes, and views in the database
+    /// with the statement that creates each, as rows with the columns `kind`,
+    /// `name`, and `ddl`, or `None` if there is none for this database.  A row
+    /// whose `ddl` is `NULL` has the statement from
+    /// [`show_create_query`](Self::show_create_query).  See
+    /// [`Executor::schema`].
+    fn schema_query() -> Option<Query> {
+        None
+    }
+
+    /// The query that shows the statement that creates the object, in the
+    /// second column of its only row, like `SHOW CREATE TABLE` on MySQL, or
+    /// `None` if there is none for this database or this kind of object.
+    fn show_create_query(object: &SchemaObject) -> Option<Query> {
+        let _ = object;
+        None
+    }
 
-    /// Get the complete history of applied migrations.
-    fn get_all_applied(
-        &mut self,
+    /// The query that creates the table of migrations that failed, or `None`
+    /// if there is no such table for this database.  See
+    /// [`Executor::record_failure`].
+    fn create_quarantine_table_if_not_exists_query(
         history_table: &str,
-    ) -> impl Future<Output = TernResult<Vec<AppliedMigration>>> + Send;
+        version: VersionKind,
+    ) -> Option<Query> {
+        let _ = (history_table, version);
+        None
+    }
 
-    /// Insert an applied migration into the history table.
-    fn insert_applied_migration(
-        &mut self,
-        history_table: &str,
-        applied: &AppliedMigration,
-    ) -> impl Future<Output = TernResult<()>> + Send;
+    /// The query that drops the table of failed migrations.
+    fn drop_quarantine_table_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
 
-    /// Update or insert an applied migration.
-    fn upsert_applied_migration(
-        &mut self,
-        history_table: &str,
-        applied: &AppliedMigration,
-    ) -> impl Future<Output = TernResult<()>> + Send;
-}
+    /// The query that records a failed attempt of a migration, inserting it
+    /// with one attempt or adding one to its attempts.
+    fn upsert_quarantine_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
 
-/// A type that has a library of "administrative" queries that are needed during
-/// a migration run.
-pub trait QueryRepository {
-    /// The query that creates the schema history table or does nothing if it
-    /// already exists.
-    fn create_history_if_not_exists_query(history_table: &str) -> Query;
+    /// The query that selects every failed migration.
+    fn select_quarantine_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
 
-    /// The query that drops the history table if requested.
-    fn drop_history_query(history_table: &str) -> Query;
+    /// The query that removes a failed migration.
+    fn delete_quarantine_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
 
-    /// The query to update the schema history table with an applied migration.
-    fn insert_into_history_query(
+    /// The query that refreshes the statistics of the tables, which the query
+    /// planner uses, or `None` if this database doesn't have one.
+    fn analyze_query(tables: &[String]) -> Option<Query> {
+        let _ = tables;
+        None
+    }
+
+    /// The query that creates the table of repeatable migrations, or `None` if
+    /// there is no such table for this database.  See
+    /// [`Executor::upsert_applied_repeatable`].
+    fn create_repeatable_table_if_not_exists_query(
         history_table: &str,
-        applied: &AppliedMigration,
-    ) -> Query;
+    ) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
 
-    /// The query to return all rows from the schema history table.
-    fn select_star_from_history_query(history_table: &str) -> Query;
+    /// The query that drops the table of repeatable migrations.
+    fn drop_repeatable_table_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
 
-    /// Query to insert or update a record in the history table.
-    fn upsert_history_query(
-        history_table: &str,
-        applied: &AppliedMigration,
-    ) -> Query;
+    /// The query that inserts or replaces the row of a repeatable migration.
+    fn upsert_repeatable_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
+
+    /// The query that selects every repeatable migration.
+    fn select_repeatable_query(history_table: &str) -> Option<Query> {
+        let _ = history_table;
+        None
+    }
 }
 
 /// A single migration in a migration set.
@@ -236,6 +1246,25 @@ where
     /// Whether this migration should not be applied in a database transaction.
     fn no_tx(&self) -> bool;
 
+    /// The `tern:<key>=<value>` annotations in the source of the migration.
+    fn annotations(&self) -> Annotations {
+        Annotations::parse(&self.content())
+    }
+
+    /// Whether a failure of this migration is recorded in the history and the
+    /// report instead of stopping the run, which is the annotation
+    /// `tern:allowFailure`.
+    ///
+    /// A migration that failed this way has a history row like an applied
+    /// one does, so [`Runner::run_apply`] doesn't retry it, and applying it
+    /// again takes [`Runner::reapply`].
+    ///
+    /// [`Runner::run_apply`]: crate::runner::Runner::run_apply
+    /// [`Runner::reapply`]: crate::runner::Runner::reapply
+    fn allow_failure(&self) -> bool {
+        self.annotations().allow_failure()
+    }
+
     /// Produce a future resolving to the migration query when `await`ed.
     fn build<'a>(
         &'a self,
@@ -243,24 +1272,42 @@ where
     ) -> BoxFuture<'a, TernResult<Query>>;
 
     /// The migration version.
-    fn version(&self) -> i64 {
+    fn version(&self) -> Version {
         self.migration_id().version()
     }
 
     /// Convert this migration to an [`AppliedMigration`] assuming that it was
     /// successfully applied.
+    ///
+    /// The content of a migration with the annotation `tern:redact` is
+    /// replaced with a placeholder, see [`AppliedMigration::is_redacted`], and
+    /// so is the content of any migration in a context that only keeps its
+    /// checksum, see [`MigrationContext::HISTORY_CONTENT`].
     fn to_applied(
         &self,
         duration_ms: i64,
         applied_at: DateTime<Utc>,
         content: &str,
     ) -> AppliedMigration {
-        AppliedMigration::new(
+        let mut applied = AppliedMigration::new(
             self.migration_id(),
-            content,
+            &history_content(self, content),
             duration_ms,
             applied_at,
-        )
+        );
+        let digest = <Self::Ctx as MigrationSource>::SOURCE_DIGEST;
+        if !digest.is_empty() {
+            applied.source_digest = Some(digest.to_string());
+        }
+        applied.source_id =
+            <Self::Ctx as MigrationContext>::SOURCE_ID.map(str::to_string);
+        let build = <Self::Ctx as MigrationSource>::BUILD_INFO;
+        applied.build_version = build.crate_version.map(str::to_string);
+        applied.build_git_sha = build.git_sha.map(str::to_string);
+        applied.built_at = build.built_at.map(str::to_string);
+        applied.checksum = Some(checksum(content));
+
+        applied
     }
 }
 
@@ -271,11 +1318,183 @@ pub trait MigrationSource {
     /// need in order to be applied.
     type Ctx: MigrationContext;
 
+    /// A hash of all of the migration sources, which identifies the exact set
+    /// of migrations that a binary was built with.
+    ///
+    /// The derive macro computes it from the filename and checksum of every
+    /// migration, and it is saved with each migration applied, so a difference
+    /// between the migrations in a build and the ones in the repository can be
+    /// found.  It is empty if the source doesn't have one.
+    const SOURCE_DIGEST: &'static str = "";
+
+    /// The contents of the lockfile of the migrations, when the source has
+    /// one.
+    ///
+    /// The derive macro embeds it for `#[tern(lockfile = "tern.lock")]`, as
+    /// the empty string if the file doesn't exist yet.  See
+    /// [`lockfile`](crate::lockfile).
+    const LOCKFILE: Option<&'static str> = None;
+
+    /// The binary that the migrations were built into, which is recorded in
+    /// the history with each migration it applies.
+    ///
+    /// The derive macro fills it in from the crate that derives it.
+    const BUILD_INFO: BuildInfo = BuildInfo::EMPTY;
+
     /// The set of migrations since `last_applied`.
     fn migration_set(
         &self,
-        last_applied: Option<i64>,
+        last_applied: Option<Version>,
     ) -> MigrationSet<Self::Ctx>;
+
+    /// Every migration in the source, described without needing a context or
+    /// a connection to the database.
+    ///
+    /// The derive macro builds this when compiling, so it describes exactly
+    /// the migrations embedded in the binary.
+    fn embedded_migrations() -> Vec<EmbeddedMigration>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+
+    /// The repeatable migrations in the source, in the order they are
+    /// applied, which is by description.
+    ///
+    /// The derive macro embeds every `R__{description}.sql` file in the
+    /// source directory.
+    fn repeatable_migrations() -> Vec<RepeatableMigration>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+}
+
+/// Where the binary applying migrations came from, so that a row in the history
+/// can be traced back to the artifact that wrote it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// The version of the crate that was built, from `CARGO_PKG_VERSION`.
+    pub crate_version: Option<&'static str>,
+    /// The git commit that it was built from, from the environment variable
+    /// `TERN_GIT_SHA` when it was built.
+    pub git_sha: Option<&'static str>,
+    /// When it was built, in RFC 3339.
+    pub built_at: Option<&'static str>,
+}
+
+impl BuildInfo {
+    /// Nothing known about the build.
+    pub const EMPTY: Self =
+        Self { crate_version: None, git_sha: None, built_at: None };
+}
+
+/// What is known about a migration from its source file alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmbeddedMigration {
+    /// The version of the migration.
+    pub version: Version,
+    /// The description from the filename.
+    pub description: &'static str,
+    /// The name of the source file.
+    pub filename: &'static str,
+    /// Whether the migration is applied outside of a transaction.
+    pub no_tx: bool,
+    /// The hex-encoded SHA-256 hash of the source file.
+    pub checksum: &'static str,
+    /// The content of the source file.
+    #[serde(skip)]
+    pub content: &'static str,
+}
+
+impl EmbeddedMigration {
+    /// The `MigrationId` of this migration.
+    pub fn migration_id(&self) -> MigrationId {
+        MigrationId::new(self.version.clone(), self.description.to_string())
+    }
+}
+
+/// An embedded SQL migration that can be applied in any context, since its
+/// query is the content of the file.
+///
+/// This is what `tern::embed!` makes of a `.sql` file.
+pub struct SqlMigration<C> {
+    embedded: EmbeddedMigration,
+    ctx: PhantomData<fn() -> C>,
+}
+
+impl<C> SqlMigration<C> {
+    pub fn new(embedded: EmbeddedMigration) -> Self {
+        Self { embedded, ctx: PhantomData }
+    }
+}
+
+impl<C: MigrationContext> Migration for SqlMigration<C> {
+    type Ctx = C;
+
+    fn migration_id(&self) -> MigrationId {
+        self.embedded.migration_id()
+    }
+
+    fn content(&self) -> String {
+        self.embedded.content.to_string()
+    }
+
+    fn no_tx(&self) -> bool {
+        self.embedded.no_tx
+    }
+
+    fn build<'a>(
+        &'a self,
+        _: &'a mut Self::Ctx,
+    ) -> BoxFuture<'a, TernResult<Query>> {
+        let sql = self.embedded.content.to_string();
+        Box::pin(async move { Ok(Query::new(sql)) })
+    }
+}
+
+/// A repeatable migration, from a file named `R__{description}.sql`.
+///
+/// It has no version: it is applied after the versioned migrations whenever
+/// its checksum is not the one it was last applied with, so it is for things
+/// that are replaced as a whole, like views and functions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepeatableMigration {
+    /// The description from the filename, which identifies the migration.
+    pub description: &'static str,
+    /// The name of the source file.
+    pub filename: &'static str,
+    /// Whether the migration is applied outside of a transaction.
+    pub no_tx: bool,
+    /// The hex-encoded SHA-256 hash of the source file.
+    pub checksum: &'static str,
+    /// The content of the source file.
+    #[serde(skip)]
+    pub content: &'static str,
+}
+
+impl RepeatableMigration {
+    /// The query of the migration.
+    pub fn query(&self) -> Query {
+        Query::new(self.content.to_string())
+    }
+}
+
+/// A row of the table of repeatable migrations, which has the checksum of
+/// the last content of each one that was applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct AppliedRepeatable {
+    /// The description of the migration.
+    pub description: String,
+    /// The checksum of the content it was last applied with.
+    pub checksum: String,
+    /// When it was last applied.
+    pub applied_at: DateTime<Utc>,
+    /// How long it took the last time it was applied.
+    pub duration_ms: i64,
 }
 
 /// The `Migration`s derived from the files in the source directory that need to
@@ -293,7 +1512,8 @@ where
         T: Into<Vec<Box<dyn Migration<Ctx = Ctx>>>>,
     {
         let mut migrations = vs.into();
-        migrations.sort_by_key(|m| m.version());
+        migrations
+            .sort_by(|a, b| Ctx::ORDERING.cmp(&a.version(), &b.version()));
         MigrationSet { migrations }
     }
 
@@ -303,7 +1523,7 @@ where
     }
 
     /// Versions present in this migration set.
-    pub fn versions(&self) -> Vec<i64> {
+    pub fn versions(&self) -> Vec<Version> {
         self.migrations.iter().map(|m| m.version()).collect::<Vec<_>>()
     }
 
@@ -313,14 +1533,29 @@ where
     }
 
     /// The latest version in the set.
-    pub fn max(&self) -> Option<i64> {
-        self.versions().iter().max().copied()
+    pub fn max(&self) -> Option<Version> {
+        self.versions().into_iter().max_by(|a, b| Ctx::ORDERING.cmp(a, b))
     }
 
     /// The set is empty for the requested operation.
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    /// The migrations in the set after `last_applied`, which is what a
+    /// `MigrationSource` that is implemented by hand returns.
+    pub fn after(self, last_applied: Option<Version>) -> MigrationSet<Ctx> {
+        let Some(v) = last_applied else {
+            return self;
+        };
+        let migrations = self
+            .migrations
+            .into_iter()
+            .filter(|m| Ctx::ORDERING.cmp(&m.version(), &v).is_gt())
+            .collect();
+
+        MigrationSet { migrations }
+    }
 }
 
 /// A helper trait for [`Migration`].
@@ -340,26 +1575,43 @@ pub trait QueryBuilder {
 }
 
 /// Name/version derived from the migration source filename.
-#[derive(Debug, Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialOrd, Ord, PartialEq, Eq, Serialize)]
 pub struct MigrationId {
     /// Version parsed from the migration filename.
-    version: i64,
+    version: Version,
     /// Description parsed from the migration filename.
     description: String,
 }
 
 impl MigrationId {
-    pub fn new(version: i64, description: String) -> Self {
-        Self { version, description }
+    pub fn new(version: impl Into<Version>, description: String) -> Self {
+        Self { version: version.into(), description }
     }
 
-    pub fn version(&self) -> i64 {
-        self.version
+    pub fn version(&self) -> Version {
+        self.version.clone()
     }
 
     pub fn description(&self) -> String {
         self.description.clone()
     }
+
+    /// The filename of the migration with this ID and extension, e.g.,
+    /// `V1__create_users.sql` for `"sql"`.
+    pub fn to_filename(&self, ext: &str) -> String {
+        format!("{self}.{ext}")
+    }
+
+    /// Parse the ID from the filename of a migration,
+    /// `V{version}__{description}.sql` or `V{version}__{description}.rs`.
+    pub fn from_filename(filename: &str) -> TernResult<Self> {
+        match filename.rsplit_once('.') {
+            Some((id, "sql" | "rs")) => id.parse(),
+            _ => Err(Error::Invalid(format!(
+                "invalid migration filename {filename}, expected a .sql or .rs file"
+            ))),
+        }
+    }
 }
 
 impl std::fmt::Display for MigrationId {
@@ -368,6 +1620,37 @@ impl std::fmt::Display for MigrationId {
     }
 }
 
+/// Parses `V{version}__{description}`, the way the derive macro parses the
+/// names of migration files.
+///
+/// A version that is an integer written the usual way is an integer version,
+/// and any other, such as `0042` or `2024.01.15`, is a text version, which is
+/// letters, digits, `.`, and `-`.  The description is letters, digits, and
+/// `_`.
+impl FromStr for MigrationId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let captures = migration_id_re().captures(s).ok_or_else(|| {
+            Error::Invalid(format!(
+                "invalid migration name {s}, expected V{{version}}__{{description}}"
+            ))
+        })?;
+        let version = &captures[1];
+        let version = match version.parse::<i64>() {
+            Ok(v) if v.to_string() == version => Version::Integer(v),
+            _ => Version::Text(version.to_string()),
+        };
+
+        Ok(Self { version, description: captures[2].to_string() })
+    }
+}
+
+fn migration_id_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^V([0-9A-Za-z.\-]+)__(\w+)$").unwrap())
+}
+
 impl From<AppliedMigration> for MigrationId {
     fn from(value: AppliedMigration) -> Self {
         Self { version: value.version, description: value.description }
@@ -377,10 +1660,9 @@ impl From<AppliedMigration> for MigrationId {
 /// An `AppliedMigration` is the information about a migration that completed
 /// successfully and it is also a row in the schema history table.
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
 pub struct AppliedMigration {
     /// The migration version.
-    pub version: i64,
+    pub version: Version,
     /// The description of the migration.
     pub description: String,
     /// The contents of the migration file at the time it was applied.
@@ -389,6 +1671,37 @@ pub struct AppliedMigration {
     pub duration_ms: i64,
     /// The timestamp of when the migration was applied.
     pub applied_at: DateTime<Utc>,
+    /// The [`SOURCE_DIGEST`] of the migration source that applied it, if it
+    /// has one.
+    ///
+    /// [`SOURCE_DIGEST`]: MigrationSource::SOURCE_DIGEST
+    pub source_digest: Option<String>,
+    /// The [`SOURCE_ID`] of the migration set that it is from, if it has one.
+    ///
+    /// [`SOURCE_ID`]: MigrationContext::SOURCE_ID
+    pub source_id: Option<String>,
+    /// The [`search_path`] that it was applied with, if the run set one.
+    ///
+    /// [`search_path`]: MigrationContext::search_path
+    pub search_path: Option<String>,
+    /// The crate version of the binary that applied it, from [`BUILD_INFO`].
+    ///
+    /// [`BUILD_INFO`]: MigrationSource::BUILD_INFO
+    pub build_version: Option<String>,
+    /// The git commit of the binary that applied it, from [`BUILD_INFO`].
+    ///
+    /// [`BUILD_INFO`]: MigrationSource::BUILD_INFO
+    pub build_git_sha: Option<String>,
+    /// When the binary that applied it was built, from [`BUILD_INFO`].
+    ///
+    /// [`BUILD_INFO`]: MigrationSource::BUILD_INFO
+    pub built_at: Option<String>,
+    /// The hex-encoded SHA-256 hash of the content that was applied, before
+    /// it was redacted, compressed, or cut down to its checksum for the
+    /// history table, if the row has one.
+    ///
+    /// See [`checksum`](Self::checksum).
+    pub checksum: Option<String>,
 }
 
 impl AppliedMigration {
@@ -404,6 +1717,458 @@ impl AppliedMigration {
             content: content.into(),
             duration_ms,
             applied_at,
+            source_digest: None,
+            source_id: None,
+            search_path: None,
+            build_version: None,
+            build_git_sha: None,
+            built_at: None,
+            checksum: None,
+        }
+    }
+
+    /// Whether the content is a placeholder because the migration has the
+    /// annotation `tern:redact`.
+    ///
+    /// The placeholder has the checksum of the content that was applied, so
+    /// the migration can still be compared with its source.
+    pub fn is_redacted(&self) -> bool {
+        self.content.starts_with(REDACTED)
+    }
+
+    /// Whether this is the row of the migration that a baseline was taken at,
+    /// which was soft applied with the ones before it by
+    /// [`Runner::baseline`].
+    ///
+    /// [`Runner::baseline`]: crate::runner::Runner::baseline
+    pub fn is_baseline(&self) -> bool {
+        self.content.starts_with(BASELINE)
+    }
+
+    // Whether the migration of this row ran, instead of being soft applied,
+    // including at a baseline, or failing when it is allowed to.
+    pub(crate) fn was_run(&self) -> bool {
+        ![SOFT_APPLIED, BASELINE, FAILED]
+            .iter()
+            .any(|marker| self.content.starts_with(marker))
+    }
+
+    /// Whether the content is only the checksum and length of the content
+    /// that was applied, because the context keeps only that in the history.
+    ///
+    /// See [`MigrationContext::HISTORY_CONTENT`].
+    pub fn is_checksum_only(&self) -> bool {
+        self.content.starts_with(CHECKSUM_ONLY)
+    }
+
+    /// Whether the content is compressed, because the context keeps it that
+    /// way in the history.
+    ///
+    /// See [`HistoryContent::Zstd`].
+    pub fn is_compressed(&self) -> bool {
+        self.content.starts_with(ZSTD)
+    }
+
+    /// This with the content decompressed if it is compressed.
+    ///
+    /// It is an error if it is compressed and the feature `zstd` is not
+    /// enabled, or if it is not what compressing content would produce.
+    pub fn decompressed(mut self) -> TernResult<Self> {
+        if let Some(encoded) = self.content.strip_prefix(ZSTD) {
+            self.content = decompress(encoded).map_err(|e| {
+                Error::Invalid(format!(
+                    "content of migration {} could not be decompressed: {e}",
+                    self.version
+                ))
+            })?;
+        }
+        Ok(self)
+    }
+
+    /// The hex-encoded SHA-256 hash of the content that was applied, which is
+    /// the checksum of the source of a SQL migration that hasn't changed.
+    ///
+    /// This is the `checksum` column of the row, or for a row from before the
+    /// history table had it, the checksum of the content or the one in its
+    /// placeholder.
+    pub fn checksum(&self) -> String {
+        if let Some(checksum) = &self.checksum {
+            return checksum.clone();
+        }
+        let placeholder = self
+            .content
+            .strip_prefix(REDACTED)
+            .or_else(|| self.content.strip_prefix(CHECKSUM_ONLY));
+        match placeholder.and_then(|p| p.split_whitespace().next()) {
+            Some(checksum) => checksum.to_string(),
+            None => checksum(&self.content),
+        }
+    }
+
+    /// Whether there is a checksum of what was applied, which there isn't if
+    /// the content is empty, or if it is a placeholder without a SHA-256 hash
+    /// in it.
+    ///
+    /// The content should be [`decompressed`](Self::decompressed) first.
+    pub fn has_checksum(&self) -> bool {
+        if self.checksum.is_some() {
+            return true;
+        }
+        let placeholder = self
+            .content
+            .strip_prefix(REDACTED)
+            .or_else(|| self.content.strip_prefix(CHECKSUM_ONLY));
+        match placeholder {
+            Some(p) => p.split_whitespace().next().is_some_and(|checksum| {
+                checksum.len() == 64
+                    && checksum.bytes().all(|b| b.is_ascii_hexdigit())
+            }),
+            None => !self.content.trim().is_empty(),
+        }
+    }
+}
+
+/// What the history table keeps of the content of an applied migration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HistoryContent {
+    /// The whole query.
+    #[default]
+    Full,
+    /// Only the checksum and the length in bytes of the query.
+    Checksum,
+    /// The whole query compressed with zstd and base64-encoded.
+    #[cfg(feature = "zstd")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+    Zstd,
+}
+
+// The start of the placeholders for the content of a migration, which are
+// followed by the checksum of the content.
+const REDACTED: &str = "-- REDACTED sha256:";
+const CHECKSUM_ONLY: &str = "-- CONTENT NOT KEPT sha256:";
+// Followed by the compressed content instead.
+const ZSTD: &str = "-- ZSTD base64:";
+// The first line of the content of the migration that a baseline was taken at,
+// instead of the one of a soft applied migration.
+pub(crate) const BASELINE: &str = "-- BASELINE:";
+// The first line of the content of a soft applied migration.
+pub(crate) const SOFT_APPLIED: &str = "-- SOFT APPLIED:";
+// The first line of the content of a migration that failed and was allowed to,
+// followed by the error.
+pub(crate) const FAILED: &str = "-- FAILED:";
+
+// The placeholder for `content` in a migration that has the annotation
+// `tern:redact`.
+pub(crate) fn redacted(content: &str) -> String {
+    format!("{REDACTED}{}", checksum(content))
+}
+
+// What to keep of `content` in the history table for this migration.
+pub(crate) fn history_content<M>(migration: &M, content: &str) -> String
+where
+    M: Migration + ?Sized,
+{
+    if migration.annotations().redact() {
+        return redacted(content);
+    }
+    match <M::Ctx as MigrationContext>::HISTORY_CONTENT {
+        HistoryContent::Full => content.to_string(),
+        HistoryContent::Checksum => format!(
+            "{CHECKSUM_ONLY}{} bytes:{}",
+            checksum(content),
+            content.len()
+        ),
+        #[cfg(feature = "zstd")]
+        HistoryContent::Zstd => compressed(content),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn compressed(content: &str) -> String {
+    use base64::Engine as _;
+
+    // Compressing from memory to memory only fails if it runs out of memory.
+    let bytes = zstd::encode_all(content.as_bytes(), 0)
+        .expect("compressing in memory does not fail");
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+    format!("{ZSTD}{encoded}")
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(encoded: &str) -> Result<String, String> {
+    use base64::Engine as _;
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| e.to_string())?;
+    let bytes =
+        zstd::decode_all(bytes.as_slice()).map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_: &str) -> Result<String, String> {
+    Err("it is compressed and the feature `zstd` is not enabled".to_string())
+}
+
+/// How to apply a migration, besides its query.
+///
+/// The timeouts are for Postgres, where they are the `lock_timeout` and
+/// `statement_timeout` settings.  A migration can have its own with the
+/// annotations `tern:lockTimeout` and `tern:statementTimeout`, for example
+/// `-- tern:lockTimeout=5s`, which take the place of the ones here.  The role
+/// is also for Postgres, and it only comes from the annotation `tern:role`.
+/// A migration can be resumable with the flag `tern:resumable` as well, have
+/// its statements split with `tern:splitStatements`, and have the tables it
+/// changes analyzed after it with `tern:analyze`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ApplyOptions {
+    /// Apply the migration outside of a transaction.
+    pub no_tx: bool,
+    /// The longest a statement waits for a lock before failing.
+    pub lock_timeout: Option<Duration>,
+    /// The longest a statement can run before failing.
+    pub statement_timeout: Option<Duration>,
+    /// The role to apply the migration as, which owns the objects that it
+    /// creates.
+    pub role: Option<String>,
+    /// Record each statement of a migration that is not applied in a
+    /// transaction as it completes, and skip the ones that were recorded by a
+    /// run that failed partway through it.
+    pub resume: bool,
+    /// Run the statements of a migration that is applied in a transaction one
+    /// at a time in the transaction, instead of all of them at once, so that
+    /// each one is logged and has a step in the report as it completes.
+    pub split_statements: bool,
+    /// Refresh the statistics of the tables that the migration changes once
+    /// it is applied, see [`QueryRepository::analyze_query`].
+    pub analyze: bool,
+}
+
+impl ApplyOptions {
+    /// Apply outside of a transaction or not.
+    pub fn with_no_tx(mut self, no_tx: bool) -> Self {
+        self.no_tx = no_tx;
+        self
+    }
+
+    /// Fail a statement that waits longer than this for a lock.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail a statement that runs longer than this.
+    pub fn with_statement_timeout(mut self, timeout: Duration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Apply as this role.
+    pub fn with_role(mut self, role: impl Into<String>) -> Self {
+        self.role = Some(role.into());
+        self
+    }
+
+    /// Resume a migration that is not applied in a transaction from the
+    /// statement that failed, or not.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Split the statements of a migration that is applied in a transaction,
+    /// or not.
+    pub fn with_split_statements(mut self, split: bool) -> Self {
+        self.split_statements = split;
+        self
+    }
+
+    /// Analyze the tables that a migration changes after it, or not.
+    pub fn with_analyze(mut self, analyze: bool) -> Self {
+        self.analyze = analyze;
+        self
+    }
+
+    /// The options for one migration, which are these unless the migration
+    /// has annotations for them.
+    pub fn for_migration<M: Migration + ?Sized>(
+        &self,
+        migration: &M,
+    ) -> TernResult<Self> {
+        let annotations = migration.annotations();
+        let options = Self {
+            no_tx: migration.no_tx(),
+            lock_timeout: annotations.lock_timeout()?.or(self.lock_timeout),
+            statement_timeout: annotations
+                .statement_timeout()?
+                .or(self.statement_timeout),
+            role: annotations.role().map(str::to_string).or(self.role.clone()),
+            resume: annotations.resumable() || self.resume,
+            split_statements: annotations.split_statements()
+                || self.split_statements,
+            analyze: annotations.analyze() || self.analyze,
+        };
+
+        Ok(options)
+    }
+
+    /// Whether there is anything to set for the session besides the
+    /// transaction.
+    pub fn has_session_settings(&self) -> bool {
+        self.lock_timeout.is_some()
+            || self.statement_timeout.is_some()
+            || self.role.is_some()
+    }
+}
+
+/// A statement of a migration that was applied by itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// The statement.
+    pub statement: String,
+    /// How long it took.
+    pub duration: Duration,
+}
+
+/// The outcome of [`Executor::apply_rolled_back`].
+#[derive(Debug, Default)]
+pub struct RolledBack {
+    /// How long each query took, up to the one that failed if one did.
+    pub durations: Vec<Duration>,
+    /// The error from the query that failed.
+    pub error: Option<Error>,
+}
+
+/// Progress saved by a long-running migration so that it can pick up where it
+/// left off if the run is interrupted.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Checkpoint {
+    /// The checkpoint itself, e.g., the last id processed in a batch.
+    pub checkpoint: String,
+    /// When the checkpoint was last saved.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A statement of a migration that completed, recorded by
+/// [`Executor::apply_with_progress`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct StatementProgress {
+    /// The position of the statement in the migration, starting at 0.
+    pub statement_index: i64,
+    /// The statement.
+    pub statement: String,
+    /// When it completed.
+    pub completed_at: DateTime<Utc>,
+}
+
+/// A migration that failed, and how many times, recorded by
+/// [`Executor::record_failure`].
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantinedMigration {
+    /// The version of the migration.
+    pub version: Version,
+    /// The description of the migration.
+    pub description: String,
+    /// The error of the last attempt.
+    pub error: String,
+    /// How many times applying it failed.
+    pub attempts: i64,
+    /// When it first failed.
+    pub first_failed_at: DateTime<Utc>,
+    /// When it last failed.
+    pub last_failed_at: DateTime<Utc>,
+}
+
+impl QuarantinedMigration {
+    /// The id of the migration.
+    pub fn migration_id(&self) -> MigrationId {
+        MigrationId::new(self.version.clone(), self.description.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AppliedMigration, BASELINE, FAILED, MigrationId, SOFT_APPLIED, Version,
+        redacted,
+    };
+    use crate::source::fs::checksum;
+
+    use chrono::Utc;
+
+    #[test]
+    fn parse_migration_id() {
+        let id = "V12__add_users".parse::<MigrationId>().unwrap();
+        assert_eq!(id.version(), Version::Integer(12));
+        assert_eq!(id.description(), "add_users");
+        assert_eq!(id.to_filename("sql"), "V12__add_users.sql");
+
+        let id = MigrationId::from_filename("V2024.01.15__a__b.rs").unwrap();
+        assert_eq!(id.version(), Version::Text("2024.01.15".into()));
+        assert_eq!(id.description(), "a__b");
+        let id = MigrationId::from_filename("V0042__x.sql").unwrap();
+        assert_eq!(id.to_string(), "V0042__x");
+
+        assert!("12__add_users".parse::<MigrationId>().is_err());
+        assert!("V12_add_users".parse::<MigrationId>().is_err());
+        assert!(MigrationId::from_filename("V12__add_users.txt").is_err());
+    }
+
+    #[test]
+    fn redacted_checksum() {
+        let id = MigrationId::new(Version::Integer(1), "secret".into());
+        let sql = "CREATE ROLE app PASSWORD 'hunter2';\n";
+        let applied = AppliedMigration::new(id, &redacted(sql), 1, Utc::now());
+
+        assert!(applied.is_redacted());
+        assert!(!applied.content.contains("hunter2"));
+        assert_eq!(applied.checksum(), checksum(sql));
+    }
+
+    #[test]
+    fn checksum_column() {
+        let id = MigrationId::new(Version::Integer(7), "users".into());
+        let sql = "CREATE TABLE users (id bigint);\n";
+        let mut applied = AppliedMigration::new(id, "", 1, Utc::now());
+        assert!(!applied.has_checksum());
+
+        applied.checksum = Some(checksum(sql));
+        assert!(applied.has_checksum());
+        assert_eq!(applied.checksum(), checksum(sql));
+    }
+
+    #[test]
+    fn was_run() {
+        let id = MigrationId::new(Version::Integer(3), "users".into());
+        let sql = "CREATE TABLE users (id bigint);\n";
+        let applied = AppliedMigration::new(id.clone(), sql, 1, Utc::now());
+        assert!(applied.was_run());
+
+        for marker in [SOFT_APPLIED, BASELINE, FAILED] {
+            let content = format!("{marker}\n\n{sql}");
+            let applied =
+                AppliedMigration::new(id.clone(), &content, 0, Utc::now());
+            assert!(!applied.was_run());
         }
     }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_roundtrip() {
+        let id = MigrationId::new(Version::Integer(1), "big".into());
+        let sql = "INSERT INTO t VALUES (1);\n".repeat(100);
+        let stored = super::compressed(&sql);
+        assert!(stored.len() < sql.len());
+
+        let applied = AppliedMigration::new(id, &stored, 1, Utc::now());
+        assert!(applied.is_compressed());
+        let applied = applied.decompressed().unwrap();
+        assert!(!applied.is_compressed());
+        assert_eq!(applied.content, sql);
+        assert_eq!(applied.checksum(), checksum(&sql));
+    }
 }
@@ -0,0 +1,67 @@
+//! Custom operations to run with a context.
+//!
+//! An [`Operation`] is something an application needs to do with the database
+//! of its migrations that isn't applying them, like granting privileges on the
+//! tables that the migrations created.  It runs with the context, after its
+//! session is set up, and every migration in the source, with
+//! [`Runner::run_operation`]:
+//!
+//! ```rust,ignore
+//! struct SyncGrants;
+//!
+//! impl Operation<MyContext> for SyncGrants {
+//!     fn name(&self) -> &str {
+//!         "sync-grants"
+//!     }
+//!
+//!     fn about(&self) -> &str {
+//!         "Grant the read-only role access to every table"
+//!     }
+//!
+//!     fn run<'a>(
+//!         &'a self,
+//!         context: &'a mut MyContext,
+//!         _: &'a MigrationSet<MyContext>,
+//!         args: &'a [String],
+//!     ) -> BoxFuture<'a, TernResult<()>> {
+//!         Box::pin(async move {
+//!             let role = args.first().map_or("readonly", String::as_str);
+//!             let query = Query::new(format!(
+//!                 "GRANT SELECT ON ALL TABLES IN SCHEMA public TO {role};"
+//!             ));
+//!             context.executor().apply_tx(&query).await
+//!         })
+//!     }
+//! }
+//! ```
+//!
+//! With the feature "cli", an operation added to the `App` with
+//! `App::with_operation` is a subcommand of it, named by [`Operation::name`],
+//! that takes the options for connecting to the database and passes the rest
+//! of its arguments to the operation.
+//!
+//! [`Runner::run_operation`]: crate::runner::Runner::run_operation
+use crate::error::TernResult;
+use crate::future::BoxFuture;
+use crate::migration::{MigrationContext, MigrationSet};
+
+/// An operation of an application's own that runs with a context `C`.
+pub trait Operation<C: MigrationContext>: Send + Sync {
+    /// The name of the operation, which is the subcommand for it in the CLI.
+    fn name(&self) -> &str;
+
+    /// A short description of the operation for the help of the CLI.
+    fn about(&self) -> &str {
+        ""
+    }
+
+    /// Run the operation with the context, the set of every migration in its
+    /// source, and the arguments given to it, which are the ones after the
+    /// name of the subcommand in the CLI.
+    fn run<'a>(
+        &'a self,
+        context: &'a mut C,
+        migrations: &'a MigrationSet<C>,
+        args: &'a [String],
+    ) -> BoxFuture<'a, TernResult<()>>;
+}
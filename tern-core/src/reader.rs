@@ -0,0 +1,243 @@
+//! Reading the migration history without being able to apply migrations.
+//!
+//! A dashboard or a monitoring service wants to show which migrations are
+//! applied, which are pending, and whether the history agrees with the
+//! migrations, but it should never be able to apply them.  A
+//! [`HistoryReader`] has only those read paths: it is not a
+//! `MigrationContext`, so it can't be given to a `Runner`, and nothing it does
+//! creates or changes the history table.
+//!
+//! ```rust,ignore
+//! use tern::{HistoryReader, SqlxPgExecutor};
+//!
+//! #[derive(HistoryReader)]
+//! #[tern(source = "src/migrations", table = "_my_migration_history")]
+//! pub struct Dashboard {
+//!     #[tern(executor_via)]
+//!     executor: SqlxPgExecutor,
+//! }
+//!
+//! let status = dashboard.status().await?;
+//! println!("{} pending", status.pending.len());
+//! ```
+//!
+//! The derive macro takes the attributes of `MigrationContext` that are about
+//! reading the history, `table`, `ordering`, and `source_id`, and with
+//! `source` it embeds the migrations to compare the history with, like
+//! `MigrationSource` does, but without building them.  Without `source`, a
+//! reader can only list the history.
+use crate::config::{self, ConfigSource};
+use crate::error::TernResult;
+use crate::future::BoxFuture;
+use crate::migration::{
+    AppliedMigration, EmbeddedMigration, Executor, MigrationId,
+    MigrationOrdering, NumericOrdering, Version,
+};
+use crate::runner::Warning;
+use crate::validate::{self, Severities, Validation};
+
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A type that reads the history of a migration set, and never changes it.
+pub trait HistoryReader
+where
+    Self: Send + Sync + 'static,
+{
+    /// The name of the history table, like
+    /// [`MigrationContext::HISTORY_TABLE`].
+    ///
+    /// [`MigrationContext::HISTORY_TABLE`]: crate::migration::MigrationContext::HISTORY_TABLE
+    const HISTORY_TABLE: &str;
+
+    /// The order that the migrations are applied in.
+    const ORDERING: &'static dyn MigrationOrdering = &NumericOrdering;
+
+    /// The identifier of the migration set in a shared history table, like
+    /// [`MigrationContext::SOURCE_ID`].
+    ///
+    /// [`MigrationContext::SOURCE_ID`]: crate::migration::MigrationContext::SOURCE_ID
+    const SOURCE_ID: Option<&'static str> = None;
+
+    /// The contents of the lockfile of the migrations, when the source has
+    /// one.
+    const LOCKFILE: Option<&'static str> = None;
+
+    /// The type for executing queries.
+    type Exec: Executor;
+
+    /// A reference to the underlying `Executor`.
+    fn executor(&mut self) -> &mut Self::Exec;
+
+    /// Configuration values, which is where the history table can come from.
+    fn config(&self) -> &ConfigSource {
+        config::default_config()
+    }
+
+    /// The name of the history table, which is
+    /// [`HISTORY_TABLE`](Self::HISTORY_TABLE) unless the config has a value
+    /// for [`HISTORY_TABLE_KEY`](crate::config::HISTORY_TABLE_KEY).
+    fn history_table(&self) -> String {
+        self.config()
+            .get(config::HISTORY_TABLE_KEY)
+            .unwrap_or_else(|| Self::HISTORY_TABLE.to_string())
+    }
+
+    /// The migrations that the history is compared with, which is none unless
+    /// the derive macro has `source`.
+    ///
+    /// Whether a Rust migration is applied in a transaction isn't known
+    /// without building it, so it is always `false` for one here.
+    fn embedded_migrations() -> Vec<EmbeddedMigration>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+
+    /// Every applied migration of this migration set, in order.
+    fn list_applied(
+        &mut self,
+    ) -> BoxFuture<'_, TernResult<Vec<AppliedMigration>>> {
+        let history_table = self.history_table();
+        Box::pin(async move {
+            let mut applied = self
+                .executor()
+                .get_all_applied(&history_table)
+                .await?
+                .into_iter()
+                .filter(|m| m.source_id.as_deref() == Self::SOURCE_ID)
+                .map(AppliedMigration::decompressed)
+                .collect::<TernResult<Vec<_>>>()?;
+            applied.sort_by(|a, b| Self::ORDERING.cmp(&a.version, &b.version));
+
+            Ok(applied)
+        })
+    }
+
+    /// How far the history is from the migrations in the source.
+    fn status(&mut self) -> BoxFuture<'_, TernResult<HistoryStatus>>
+    where
+        Self: Sized,
+    {
+        Box::pin(async move {
+            let applied = self.list_applied().await?;
+            Ok(HistoryStatus::new(
+                &applied,
+                &Self::embedded_migrations(),
+                Self::ORDERING,
+            ))
+        })
+    }
+
+    /// Validate the history with the built-in rules of the
+    /// [`validate`](crate::validate) module and their default severities, as
+    /// before an operation of a `Runner`, returning the warnings from the
+    /// rules that only warn.
+    ///
+    /// Rules that need the source pass when there isn't one.
+    fn validate(&mut self) -> BoxFuture<'_, TernResult<Vec<Warning>>>
+    where
+        Self: Sized,
+    {
+        Box::pin(async move {
+            let applied = self.list_applied().await?;
+            let validation = Validation::from_embedded(
+                Self::embedded_migrations(),
+                Self::LOCKFILE,
+                &applied,
+                self.config(),
+                Self::ORDERING,
+            );
+            validate::check_rules(
+                validate::builtin_rules(),
+                &Severities::default(),
+                &validation,
+            )
+        })
+    }
+}
+
+/// The applied and pending migrations, from [`HistoryReader::status`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HistoryStatus {
+    /// The number of applied migrations.
+    pub applied: usize,
+    /// The latest version applied, if there is one.
+    pub latest_version: Option<Version>,
+    /// The migrations in the source that have not been applied, in order.
+    pub pending: Vec<MigrationId>,
+}
+
+impl HistoryStatus {
+    /// The status of the history `applied` of the migrations `source`.
+    pub fn new(
+        applied: &[AppliedMigration],
+        source: &[EmbeddedMigration],
+        ordering: &dyn MigrationOrdering,
+    ) -> Self {
+        let applied_ids = applied
+            .iter()
+            .map(|m| MigrationId::new(m.version.clone(), m.description.clone()))
+            .collect::<HashSet<_>>();
+        let mut pending = source
+            .iter()
+            .map(EmbeddedMigration::migration_id)
+            .filter(|id| !applied_ids.contains(id))
+            .collect::<Vec<_>>();
+        pending.sort_by(|a, b| ordering.cmp(&a.version(), &b.version()));
+        let latest_version = applied
+            .iter()
+            .map(|m| &m.version)
+            .max_by(|a, b| ordering.cmp(a, b))
+            .cloned();
+
+        Self { applied: applied.len(), latest_version, pending }
+    }
+
+    /// Whether every migration in the source is applied.
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HistoryStatus;
+    use crate::migration::{
+        AppliedMigration, EmbeddedMigration, MigrationId, NumericOrdering,
+        Version,
+    };
+
+    use chrono::Utc;
+
+    fn embedded(version: i64, description: &'static str) -> EmbeddedMigration {
+        EmbeddedMigration {
+            version: Version::Integer(version),
+            description,
+            filename: "",
+            no_tx: false,
+            checksum: "",
+            content: "",
+        }
+    }
+
+    #[test]
+    fn pending_migrations() {
+        let id = MigrationId::new(Version::Integer(1), "users".into());
+        let applied = [AppliedMigration::new(id, "", 1, Utc::now())];
+        let source =
+            [embedded(3, "orders"), embedded(1, "users"), embedded(2, "items")];
+
+        let status = HistoryStatus::new(&applied, &source, &NumericOrdering);
+        assert_eq!(status.applied, 1);
+        assert_eq!(status.latest_version, Some(Version::Integer(1)));
+        let pending =
+            status.pending.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(pending, ["V2__items", "V3__orders"]);
+        assert!(!status.is_up_to_date());
+
+        let status = HistoryStatus::new(&applied, &[], &NumericOrdering);
+        assert!(status.is_up_to_date());
+    }
+}
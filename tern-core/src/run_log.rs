@@ -0,0 +1,293 @@
+//! A log of runs, for aggregating the outcomes of many of them.
+//!
+//! A tool that runs the migrations against many targets, like a database for
+//! each tenant or shard, can keep a run log: a file with a line of JSON for
+//! each run, which is a [`RunRecord`] of the target and the outcome of its
+//! [`Report`].  The CLI appends to one with `--run-log <path>`, naming the
+//! target with `--target`.  Reading it back with [`RunLog::parse`] gives the
+//! records, and [`RunLog::summary`] a [`RunSummary`] of all of them with a
+//! breakdown for each target.
+//!
+//! A run that failed before it had a report, like one that couldn't connect,
+//! is recorded from its error with [`Report::from_error`].
+use crate::error::{Error, TernResult};
+use crate::runner::Report;
+
+use chrono::{DateTime, Utc};
+use display_json::{DebugAsJson, DisplayAsJsonPretty};
+use serde::{Deserialize, Serialize};
+
+/// The records of a run log, in the order they were appended.
+#[derive(Debug, Clone, Default)]
+pub struct RunLog {
+    records: Vec<RunRecord>,
+}
+
+impl RunLog {
+    /// Parse the contents of a run log, which has a line of JSON for each
+    /// record.  Blank lines are ignored.
+    pub fn parse(contents: &str) -> TernResult<Self> {
+        let records = contents
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, l)| !l.is_empty())
+            .map(|(i, l)| {
+                serde_json::from_str(l).map_err(|e| {
+                    Error::Invalid(format!(
+                        "invalid record on line {} of the run log: {e}",
+                        i + 1
+                    ))
+                })
+            })
+            .collect::<TernResult<Vec<_>>>()?;
+
+        Ok(Self { records })
+    }
+
+    /// The records in the log, in order.
+    pub fn records(&self) -> &[RunRecord] {
+        &self.records
+    }
+
+    /// Add a record after the others.
+    pub fn push(&mut self, record: RunRecord) {
+        self.records.push(record);
+    }
+
+    /// The summary of the runs in the log.
+    pub fn summary(&self) -> RunSummary {
+        RunSummary::new(self.records.iter().cloned())
+    }
+}
+
+/// The outcome of one run against a target, which is a line of a run log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// The name of what the run was against, like a tenant or a shard.
+    pub target: String,
+    /// When the run finished.
+    pub finished_at: DateTime<Utc>,
+    /// The number of migrations in the report of the run.
+    pub migrations: usize,
+    /// The number of migrations that failed, see [`Report::failures`].
+    pub failures: usize,
+    /// The number of warnings of the run and of its migrations.
+    pub warnings: usize,
+    /// Whether the run stopped because its deadline passed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deadline_exceeded: bool,
+    /// Whether the run was stopped early.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+    /// The error that stopped the run, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RunError>,
+}
+
+impl RunRecord {
+    /// The record of the report of a run against `target` that finished now.
+    pub fn new(target: impl Into<String>, report: &Report) -> Self {
+        Self {
+            target: target.into(),
+            finished_at: Utc::now(),
+            migrations: report.count(),
+            failures: report.failures(),
+            warnings: report.all_warnings().count(),
+            deadline_exceeded: report.deadline_exceeded(),
+            interrupted: report.interrupted(),
+            error: report.error().map(|e| RunError {
+                code: e.code.to_string(),
+                message: e.message.clone(),
+            }),
+        }
+    }
+
+    /// Whether the run finished without an error and without a migration
+    /// failing.
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none() && self.failures == 0
+    }
+
+    /// The record as a line of a run log, without the newline.
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("a run record is valid JSON")
+    }
+}
+
+/// The error that stopped a run, in a [`RunRecord`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunError {
+    /// The stable code of the error, see [`Error::code`].
+    pub code: String,
+    /// The error message.
+    pub message: String,
+}
+
+/// The outcomes of many runs, in total and for each target.
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty, Default)]
+pub struct RunSummary {
+    runs: usize,
+    failed_runs: usize,
+    migrations: usize,
+    failures: usize,
+    warnings: usize,
+    targets: Vec<TargetSummary>,
+}
+
+/// The outcomes of the runs against one target, in a [`RunSummary`].
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetSummary {
+    /// The name of the target.
+    pub target: String,
+    /// The number of runs against it.
+    pub runs: usize,
+    /// The number of those that didn't succeed, see [`RunRecord::succeeded`].
+    pub failed_runs: usize,
+    /// The number of migrations in the reports of all of its runs.
+    pub migrations: usize,
+    /// The number of those that failed.
+    pub failures: usize,
+    /// The number of warnings of all of its runs.
+    pub warnings: usize,
+    /// The last run against it.
+    pub last: RunRecord,
+}
+
+impl TargetSummary {
+    fn new(record: RunRecord) -> Self {
+        let mut summary = Self {
+            target: record.target.clone(),
+            runs: 0,
+            failed_runs: 0,
+            migrations: 0,
+            failures: 0,
+            warnings: 0,
+            last: record.clone(),
+        };
+        summary.add(record);
+        summary
+    }
+
+    fn add(&mut self, record: RunRecord) {
+        self.runs += 1;
+        self.failed_runs += usize::from(!record.succeeded());
+        self.migrations += record.migrations;
+        self.failures += record.failures;
+        self.warnings += record.warnings;
+        self.last = record;
+    }
+
+    /// Whether the last run against the target succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.last.succeeded()
+    }
+}
+
+impl RunSummary {
+    /// The summary of these records, with the targets in the order that they
+    /// first appear.
+    pub fn new(records: impl IntoIterator<Item = RunRecord>) -> Self {
+        let mut summary = Self::default();
+        for record in records {
+            summary.runs += 1;
+            summary.failed_runs += usize::from(!record.succeeded());
+            summary.migrations += record.migrations;
+            summary.failures += record.failures;
+            summary.warnings += record.warnings;
+            match summary.targets.iter_mut().find(|t| t.target == record.target)
+            {
+                Some(target) => target.add(record),
+                None => summary.targets.push(TargetSummary::new(record)),
+            }
+        }
+
+        summary
+    }
+
+    /// The summary of the reports of runs against each target.
+    pub fn from_reports<'a>(
+        reports: impl IntoIterator<Item = (&'a str, &'a Report)>,
+    ) -> Self {
+        Self::new(
+            reports
+                .into_iter()
+                .map(|(target, report)| RunRecord::new(target, report)),
+        )
+    }
+
+    /// The number of runs.
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    /// The number of runs that didn't succeed.
+    pub fn failed_runs(&self) -> usize {
+        self.failed_runs
+    }
+
+    /// The number of migrations in the reports of all of the runs.
+    pub fn migrations(&self) -> usize {
+        self.migrations
+    }
+
+    /// The number of those that failed.
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    /// The breakdown for each target.
+    pub fn targets(&self) -> &[TargetSummary] {
+        &self.targets
+    }
+
+    /// The summary of the target named `target`, if it had a run.
+    pub fn get(&self, target: &str) -> Option<&TargetSummary> {
+        self.targets.iter().find(|t| t.target == target)
+    }
+
+    /// The targets whose last run didn't succeed.
+    pub fn failed_targets(&self) -> impl Iterator<Item = &TargetSummary> {
+        self.targets.iter().filter(|t| !t.succeeded())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunLog, RunRecord};
+    use crate::error::Error;
+    use crate::runner::Report;
+
+    #[test]
+    fn summarize_run_log() {
+        let failed = Report::from_error(&Error::Invalid("no target".into()));
+        let lines = [
+            RunRecord::new("tenant_a", &Report::default()).to_line(),
+            RunRecord::new("tenant_b", &failed).to_line(),
+            String::new(),
+            RunRecord::new("tenant_b", &Report::default()).to_line(),
+            RunRecord::new("tenant_a", &failed).to_line(),
+        ];
+        let log = RunLog::parse(&lines.join("\n")).unwrap();
+        assert_eq!(log.records().len(), 4);
+        assert_eq!(
+            log.records()[1].error.as_ref().unwrap().code,
+            "TERN_INVALID"
+        );
+
+        let summary = log.summary();
+        assert_eq!(summary.runs(), 4);
+        assert_eq!(summary.failed_runs(), 2);
+        let targets = summary
+            .targets()
+            .iter()
+            .map(|t| (t.target.as_str(), t.runs, t.failed_runs))
+            .collect::<Vec<_>>();
+        assert_eq!(targets, [("tenant_a", 2, 1), ("tenant_b", 2, 1)]);
+        let failed_targets =
+            summary.failed_targets().map(|t| &t.target).collect::<Vec<_>>();
+        assert_eq!(failed_targets, ["tenant_a"]);
+
+        assert!(RunLog::parse("{\"target\": \"a\"}").is_err());
+    }
+}
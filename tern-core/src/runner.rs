@@ -5,20 +5,132 @@
 //!
 //! Each method also exists as a (sub)command of the `App`, available with the
 //! feature flag "cli" enabled.
+use crate::config::{ConfigSource, Flags};
 use crate::error::{DatabaseError as _, Error, TernResult};
+use crate::inventory::{self, Inventory, InventoryMatch, SoftApplyPlan};
+use crate::lint::{Linter, dialect_caveats};
+use crate::locks::{self, StatementLock};
 use crate::migration::{
-    AppliedMigration, Migration, MigrationContext, MigrationId,
+    Annotations, AppliedMigration, AppliedRepeatable, ApplyOptions, BASELINE,
+    Dialect, EmbeddedMigration, Executor, FAILED, Migration, MigrationContext,
+    MigrationId, MigrationOrdering, MigrationSet, QuarantinedMigration, Query,
+    QueryRepository, RepeatableMigration, SOFT_APPLIED, Step, Version,
+    apply_built, history_content, redacted,
+};
+use crate::operation::Operation;
+use crate::redact::redact;
+use crate::schema::Schema;
+use crate::validate::{
+    self, Severities, Severity, Validation, ValidationCategory, ValidationRule,
 };
 
 use chrono::{DateTime, Utc};
 use display_json::{DebugAsJson, DisplayAsJsonPretty};
+use futures_core::{Future, Stream};
+use futures_util::stream;
 use serde::Serialize;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+mod csv;
+mod html;
+mod junit;
+mod markdown;
+
+// How long to wait before retrying a migration with `tern:retries` the first
+// time, which is longer for each attempt after.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+// The options of the migration that the executor's database doesn't support,
+// which it is applied without.
+fn backend_warnings<C: MigrationContext>(
+    migration: &dyn Migration<Ctx = C>,
+    options: &ApplyOptions,
+) -> Vec<Warning> {
+    type Queries<C> = <<C as MigrationContext>::Exec as Executor>::Queries;
+    if !options.has_session_settings()
+        || Queries::<C>::set_session_query(options).is_some()
+    {
+        return Vec::new();
+    }
+    let message = format!(
+        "the database does not support the lock timeout, statement timeout, or role of migration {}, which are ignored",
+        migration.migration_id()
+    );
+    vec![Warning::new(
+        WarningKind::Backend,
+        vec![migration.migration_id()],
+        message,
+    )]
+}
+
+// The caveats of the dialect for the unapplied SQL migrations.
+fn dialect_warnings(
+    validation: &Validation<'_>,
+    dialect: Dialect,
+) -> TernResult<Vec<Warning>> {
+    let unapplied =
+        validation.unapplied().map(|m| m.id()).collect::<HashSet<_>>();
+    let mut warnings = Vec::new();
+    for migration in validation
+        .embedded()
+        .iter()
+        .filter(|m| unapplied.contains(&m.migration_id()))
+    {
+        for caveat in dialect_caveats(migration, dialect)? {
+            log::warn!("migration {}: {caveat}", migration.migration_id());
+            warnings.push(Warning::new(
+                WarningKind::Dialect,
+                vec![migration.migration_id()],
+                caveat,
+            ));
+        }
+    }
+
+    Ok(warnings)
+}
+
+// Where `run_apply_stream` is in the run.
+enum ApplyStream<'a, C: MigrationContext> {
+    Start(&'a mut Runner<C>),
+    Running {
+        runner: &'a mut Runner<C>,
+        unapplied: MigrationSet<C>,
+        next: usize,
+    },
+    Done,
+}
 
 /// Run operations on a set of migrations for the chosen context.
 pub struct Runner<C: MigrationContext> {
     context: C,
+    // Taken the first time an operation waits for the database, since it only
+    // needs to become ready once.
+    wait_for_db: Option<WaitForDb>,
+    // Whether the context's session setup has been given to the executor.
+    session_prepared: bool,
+    lock: bool,
+    // Whether the lock is held, e.g., by an apply stream that was dropped
+    // before it ended, so that the next operation keeps it instead of taking
+    // it again.
+    locked: bool,
+    deadline: Option<Duration>,
+    stop: StopHandle,
+    continue_on_error: bool,
+    soft_apply_from: Option<Version>,
+    history_backup: bool,
+    report_content: ReportContent,
+    rules: Vec<Box<dyn ValidationRule>>,
+    severities: Severities,
+    apply_options: ApplyOptions,
+    // Found by the last validation, for the report of the operation.
+    warnings: Vec<Warning>,
+    // Of the last run that applied migrations.
+    timings: RunTimings,
 }
 
 impl<C> Runner<C>
@@ -27,274 +139,3264 @@ where
 {
     /// Create a new `Runner` with default arguments from a context.
     pub fn new(context: C) -> Self {
-        Self { context }
+        Self {
+            context,
+            wait_for_db: None,
+            session_prepared: false,
+            lock: false,
+            locked: false,
+            deadline: None,
+            stop: StopHandle::default(),
+            continue_on_error: false,
+            soft_apply_from: None,
+            history_backup: true,
+            report_content: ReportContent::default(),
+            rules: Vec::new(),
+            severities: Severities::default(),
+            apply_options: ApplyOptions::default(),
+            warnings: Vec::new(),
+            timings: RunTimings::default(),
+        }
+    }
+
+    /// The context of the runner, e.g., to take back an executor that holds a
+    /// transaction of the caller once the migrations have been applied in it.
+    pub fn into_context(self) -> C {
+        self.context
+    }
+
+    /// Hold the run lock while changing the database or its history, so that
+    /// runs from different processes, like replicas of an application starting
+    /// at once, happen one after another instead of at the same time.
+    ///
+    /// What the lock is depends on the executor; see
+    /// [`Executor::acquire_lock`](crate::migration::Executor::acquire_lock).
+    pub fn with_lock(mut self) -> Self {
+        self.lock = true;
+        self
+    }
+
+    /// Before the first operation, wait up to `timeout` for the database to
+    /// accept queries, checking every `interval` at first and backing off from
+    /// there.
+    ///
+    /// This is for when the database and the application start at the same
+    /// time, such as in containers.
+    pub fn with_wait_for_db(
+        mut self,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Self {
+        self.wait_for_db = Some(WaitForDb::new(timeout, interval));
+        self
+    }
+
+    /// Stop applying migrations once `deadline` has passed since the run
+    /// started.
+    ///
+    /// The migration being applied at that time is finished, the rest are
+    /// skipped, and the report of the ones that were applied is returned with
+    /// [`Report::deadline_exceeded`] set.  This is for deploys that have to
+    /// finish in a certain window; the skipped migrations are applied by the
+    /// next run.
+    ///
+    /// This applies to [`run_apply`](Self::run_apply) and
+    /// [`run_apply_all`](Self::run_apply_all).
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Go on to the next migration when one fails, instead of stopping, and
+    /// return the report of every migration, where the ones that failed have
+    /// their [`error`](MigrationResult::error).
+    ///
+    /// A migration with the annotation `tern:dependsOn=<version>,...` is
+    /// skipped if a migration it depends on failed or was skipped.  A failed
+    /// migration is not in the history, so the next run tries it again, but
+    /// after the migrations that did apply, which the validation finds out of
+    /// sync.  This is for databases that are rebuilt, like a nightly test
+    /// environment, where every failure of a run is more useful than the first.
+    ///
+    /// This applies to [`run_apply`](Self::run_apply) and
+    /// [`run_apply_all`](Self::run_apply_all).
+    pub fn with_continue_on_error(mut self) -> Self {
+        self.continue_on_error = true;
+        self
+    }
+
+    /// A handle for stopping a run of this runner from somewhere else, e.g., a
+    /// signal handler.
+    ///
+    /// Once it is stopped, the migration being applied is finished, the rest
+    /// are skipped, and the report of the ones that were applied is returned
+    /// with [`Report::interrupted`] set, like with a
+    /// [deadline](Self::with_deadline).  A stream from
+    /// [`run_apply_stream`](Self::run_apply_stream) ends instead.
+    ///
+    /// The handle stays stopped, so later runs stop before applying anything.
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Use this stop handle instead of the runner's own, e.g., to stop several
+    /// runners with one handle.
+    pub fn with_stop_handle(mut self, stop: StopHandle) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Soft apply only the migrations from `version` on, so that with a target
+    /// version, a block of versions in the middle of the set can be soft
+    /// applied, e.g., ones that were applied while the history was in a table
+    /// that has been renamed since.
+    ///
+    /// Unapplied migrations before `version` stay unapplied, which is a gap
+    /// that the [`NoGaps`](crate::validate::NoGaps) rule finds in later runs
+    /// until they are applied.  This applies to
+    /// [`run_soft_apply`](Self::run_soft_apply).
+    pub fn with_soft_apply_from(mut self, version: Version) -> Self {
+        self.soft_apply_from = Some(version);
+        self
+    }
+
+    /// Whether to copy the history table to `<table>_backup_<timestamp>` before
+    /// an operation that drops it or removes rows from it, which is on by
+    /// default, so that a mistake with one of them can be undone.
+    ///
+    /// This applies to [`drop_history`](Self::drop_history),
+    /// [`set_history_version`](Self::set_history_version), and
+    /// [`forget`](Self::forget).  There is no backup of an empty table.
+    pub fn with_history_backup(mut self, backup: bool) -> Self {
+        self.history_backup = backup;
+        self
+    }
+
+    /// How much of the content of each migration to put in the report of an
+    /// operation, which is all of it by default.
+    ///
+    /// The content of a big data migration can be most of a report, so it can
+    /// be cut to its first lines or left out.
+    pub fn with_report_content(mut self, content: ReportContent) -> Self {
+        self.report_content = content;
+        self
+    }
+
+    /// Fail a statement of a migration that waits longer than `timeout` for a
+    /// lock, instead of waiting behind long-running queries, and everything
+    /// else waiting behind it, for as long as they take.
+    ///
+    /// A migration with the annotation `tern:lockTimeout` uses that instead.
+    /// This is Postgres' `lock_timeout`, and other databases ignore it.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.apply_options = self.apply_options.with_lock_timeout(timeout);
+        self
+    }
+
+    /// Fail a statement of a migration that runs longer than `timeout`.
+    ///
+    /// A migration with the annotation `tern:statementTimeout` uses that
+    /// instead.  This is Postgres' `statement_timeout`, and other databases
+    /// ignore it.
+    pub fn with_statement_timeout(mut self, timeout: Duration) -> Self {
+        self.apply_options = self.apply_options.with_statement_timeout(timeout);
+        self
+    }
+
+    /// Resume a migration that is not applied in a transaction from the
+    /// statement that failed in an earlier run, instead of running the ones
+    /// before it again, which may not be safe to run twice.
+    ///
+    /// Each statement is recorded in the table `<history table>_progress` as
+    /// it completes.  A migration with the annotation `tern:resumable` does
+    /// this without it.
+    pub fn with_resume(mut self) -> Self {
+        self.apply_options = self.apply_options.with_resume(true);
+        self
+    }
+
+    /// Apply a migration that runs in a transaction one statement at a time,
+    /// instead of sending all of it at once, so that a long migration logs
+    /// each statement as it completes and has a step for each in the report.
+    ///
+    /// They are in the same transaction either way.  A migration with the
+    /// annotation `tern:splitStatements` does this without it.
+    pub fn with_split_statements(mut self) -> Self {
+        self.apply_options = self.apply_options.with_split_statements(true);
+        self
+    }
+
+    /// Refresh the statistics of the tables that each migration changes once
+    /// it is applied, with `ANALYZE`, so that query plans don't suffer after a
+    /// big change to the schema or a backfill.
+    ///
+    /// The tables are read from the statements of the migration, see
+    /// [`locks::changed_tables`].  A migration with the annotation
+    /// `tern:analyze` does this without it.
+    pub fn with_analyze(mut self) -> Self {
+        self.apply_options = self.apply_options.with_analyze(true);
+        self
+    }
+
+    /// Use `config` as the configuration of the context, in place of its
+    /// [`config`](MigrationContext::config), for the values that the runner
+    /// reads, like the history table, and the ones that migrations read when
+    /// they are built.
+    ///
+    /// It applies to every context of the type `C`, so that contexts of other
+    /// types that run in the same process, like in a [`TernGroup`], keep their
+    /// own.  To add values to the context's own config, layer them in front
+    /// of it with [`ConfigSource::or`].
+    ///
+    /// [`TernGroup`]: crate::group::TernGroup
+    pub fn with_config(self, config: ConfigSource) -> Self {
+        crate::config::set_context_config::<C>(config);
+        self
+    }
+
+    /// Set the options of the `preset` for a kind of environment.  Options set
+    /// after this take the place of the ones it set.
+    pub fn with_preset(self, preset: Preset) -> Self {
+        let linter = || {
+            let dialect =
+                <<C::Exec as Executor>::Queries as QueryRepository>::DIALECT;
+            dialect.map_or_else(Linter::new, |d| Linter::new().with_dialect(d))
+        };
+        let runner = self.with_report_content(preset.report_content());
+        match preset {
+            Preset::CiCheck => runner
+                .with_validation_severity(
+                    ValidationCategory::Drift,
+                    Severity::Error,
+                )
+                .with_validation_rule(linter()),
+            Preset::ProdApply => runner
+                .with_lock()
+                .with_lock_timeout(Duration::from_secs(5))
+                .with_validation_severity(
+                    ValidationCategory::Drift,
+                    Severity::Error,
+                )
+                .with_validation_rule(linter()),
+            Preset::DevFast => [
+                ValidationCategory::OutOfSync,
+                ValidationCategory::Gaps,
+                ValidationCategory::Drift,
+                ValidationCategory::Unlocked,
+            ]
+            .into_iter()
+            .fold(runner, |runner, category| {
+                runner.with_validation_severity(category, Severity::Warn)
+            }),
+        }
+    }
+
+    /// Also validate the migrations with `rule` before an operation, after the
+    /// built-in rules and any rules added before it.
+    ///
+    /// See [`validate`](crate::validate) for the rules there are to begin with.
+    pub fn with_validation_rule(
+        mut self,
+        rule: impl ValidationRule + 'static,
+    ) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// What to do when a validation rule in `category` fails.
+    ///
+    /// For instance, with [`Severity::Warn`] for
+    /// [`ValidationCategory::OutOfSync`], an applied migration that is missing
+    /// from the source is logged instead of stopping the operation.
+    pub fn with_validation_severity(
+        mut self,
+        category: ValidationCategory,
+        severity: Severity,
+    ) -> Self {
+        self.severities.set(category, severity);
+        self
+    }
+
+    /// `CREATE IF NOT EXISTS` the history table, returning whether it was
+    /// created.
+    ///
+    /// Whether the table exists already is from [`Executor::history_exists`].
+    pub async fn init_history(&mut self) -> TernResult<bool> {
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.init_history_unlocked().await;
+        self.unlock(res).await
     }
 
-    /// `CREATE IF NOT EXISTS` the history table.
-    pub async fn init_history(&mut self) -> TernResult<()> {
-        self.context.check_history_table().await
+    async fn init_history_unlocked(&mut self) -> TernResult<bool> {
+        let history_table = self.context.history_table();
+        let existed =
+            self.context.executor().history_exists(&history_table).await?;
+        self.context.check_history_table().await?;
+
+        Ok(!existed)
     }
 
     /// `DROP` the history table.
+    ///
+    /// With a [`SOURCE_ID`], when the table has rows from other migration
+    /// sources, only the rows of this one are deleted and the table is kept.
+    ///
+    /// [`SOURCE_ID`]: MigrationContext::SOURCE_ID
     pub async fn drop_history(&mut self) -> TernResult<()> {
-        self.context.drop_history_table().await
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.drop_history_unlocked().await;
+        self.unlock(res).await
     }
 
-    // Find applied migrations that are not in the source directory.
-    async fn validate_source(&mut self) -> TernResult<()> {
+    /// The name of the history table, how many rows of this migration source
+    /// it has, and the latest version in it, e.g., to see what
+    /// [`drop_history`](Self::drop_history) would lose.
+    pub async fn describe_history(&mut self) -> TernResult<HistorySummary> {
+        self.prepare().await?;
         self.context.check_history_table().await?;
-        let applied: HashSet<MigrationId> = self
+        let table = self.context.history_table();
+        let (rows, other_rows) = self.history_rows().await?;
+        let latest_version = self.context.latest_version().await?;
+
+        Ok(HistorySummary {
+            table,
+            rows: rows.len(),
+            other_rows,
+            latest_version,
+        })
+    }
+
+    async fn drop_history_unlocked(&mut self) -> TernResult<()> {
+        self.context.check_history_table().await?;
+        self.backup_history().await?;
+        let (rows, other_rows) = self.history_rows().await?;
+        if other_rows == 0 {
+            return self.context.drop_history_table().await;
+        }
+        log::info!(
+            "history table has {other_rows} rows from other sources, \
+             deleting only the {} of this one",
+            rows.len()
+        );
+        for applied in rows {
+            self.context.delete_applied(&applied).await?;
+        }
+
+        Ok(())
+    }
+
+    // The rows of the history table from this source, and how many there are
+    // from other sources sharing it.
+    async fn history_rows(
+        &mut self,
+    ) -> TernResult<(Vec<AppliedMigration>, usize)> {
+        let history_table = self.context.history_table();
+        let (rows, others): (Vec<_>, Vec<_>) = self
             .context
-            .previously_applied()
+            .executor()
+            .get_all_applied(&history_table)
             .await?
             .into_iter()
-            .map(MigrationId::from)
-            .collect();
-        let source: HashSet<MigrationId> = self
-            .context
-            .migration_set(None)
-            .migration_ids()
-            .into_iter()
+            .partition(|m| m.source_id.as_deref() == C::SOURCE_ID);
+
+        Ok((rows, others.len()))
+    }
+
+    // Copy the history table before changing it, if it has rows and a backup
+    // was not turned off.
+    async fn backup_history(&mut self) -> TernResult<()> {
+        if !self.history_backup {
+            return Ok(());
+        }
+        let history_table = self.context.history_table();
+        let executor = self.context.executor();
+        if executor.get_all_applied(&history_table).await?.is_empty() {
+            return Ok(());
+        }
+        let backup = format!(
+            "{history_table}_backup_{}",
+            Utc::now().format("%Y%m%d%H%M%S")
+        );
+        executor.backup_history(&history_table, &backup).await?;
+        log::info!("copied the history table {history_table} to {backup}");
+
+        Ok(())
+    }
+
+    async fn lock(&mut self) -> TernResult<()> {
+        if !self.lock || self.locked {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let res = self.context.lock().await;
+        self.timings.lock_wait += start.elapsed();
+        self.locked = res.is_ok();
+        res
+    }
+
+    /// How long the parts of the last run of [`run_apply`], [`retry_failed`],
+    /// or [`reapply`] took, and how many rows it wrote to the history table,
+    /// for sending to a telemetry pipeline with the report.
+    ///
+    /// [`run_apply`]: Self::run_apply
+    /// [`retry_failed`]: Self::retry_failed
+    /// [`reapply`]: Self::reapply
+    pub fn timings(&self) -> &RunTimings {
+        &self.timings
+    }
+
+    // Start timing a run, forgetting the timings of the last one.
+    fn start_timings(&mut self) -> Instant {
+        self.timings = RunTimings::default();
+        Instant::now()
+    }
+
+    // Finish timing a run that started at `start` with the durations of the
+    // migrations in its report, or in the report of the part that ran.
+    fn finish_timings(&mut self, start: Instant, res: &TernResult<Report>) {
+        self.timings.total = start.elapsed();
+        let report = match res {
+            Ok(report) | Err(Error::Partial { report, .. }) => report,
+            Err(_) => return,
+        };
+        self.timings.migrations = report
+            .migrations
+            .iter()
+            .filter_map(|m| match m.duration_ms {
+                RunDuration::Duration(ms) if !m.dryrun => Some((
+                    MigrationId::new(m.version.clone(), m.description.clone()),
+                    Duration::from_millis(ms.max(0) as u64),
+                )),
+                _ => None,
+            })
             .collect();
+    }
 
-        check_migrations_in_sync(applied, source)
+    // The report of an operation, or of a partial run, with the content that
+    // the runner was configured to have in reports, the flags of the run, and
+    // the warnings from validating it.
+    fn with_content(&mut self, res: TernResult<Report>) -> TernResult<Report> {
+        let content = self.report_content;
+        let flags = self.context.flags();
+        let warnings = std::mem::take(&mut self.warnings);
+        let report = |report: Report| Report {
+            flags,
+            warnings,
+            ..report.with_content(content)
+        };
+        match res {
+            Ok(r) => Ok(report(r)),
+            Err(Error::Partial { source, report: r }) => {
+                Err(Error::Partial { source, report: report(r) })
+            },
+            Err(e) => Err(e),
+        }
     }
 
-    // Check that the target migration version (for some operation) is valid.
-    fn validate_target(
-        &self,
-        last_applied: Option<i64>,
-        target_version: Option<i64>,
-    ) -> TernResult<()> {
-        let Some(source) = self.context.migration_set(None).max() else {
+    // Release the lock after `res` was the result of the operation that was
+    // done holding it.
+    async fn unlock<T>(&mut self, res: TernResult<T>) -> TernResult<T> {
+        if !self.locked {
+            return res;
+        }
+        let unlocked = self.context.unlock().await;
+        self.locked = false;
+        let v = res?;
+        unlocked?;
+
+        Ok(v)
+    }
+
+    async fn wait_for_db(&mut self) -> TernResult<()> {
+        let Some(wait) = self.wait_for_db.take() else {
             return Ok(());
         };
-        if let Some(target) = target_version {
-            match last_applied {
-                Some(applied) if target < applied => {
-                    Err(Error::Invalid(format!(
-                        "target version V{target} earlier than latest applied version V{applied}",
-                    )))?
-                },
-                _ if target > source => Err(Error::Invalid(format!(
-                    "target version V{target} does not exist, latest version found was V{source}",
-                )))?,
-                _ => Ok(()),
+        let mut backoff = wait.backoff();
+        loop {
+            match self.context.executor().ping().await {
+                Ok(()) => return Ok(()),
+                Err(e) if !backoff.wait(&e).await => return Err(e),
+                Err(_) => {},
             }
-        } else {
-            Ok(())
         }
     }
 
-    /// Apply unapplied migrations up to and including the specified version.
-    pub async fn run_apply(
-        &mut self,
-        target_version: Option<i64>,
-        dryrun: bool,
-    ) -> TernResult<Report> {
-        self.validate_source().await?;
-        let last_applied = self.context.latest_version().await?;
-        self.validate_target(last_applied, target_version)?;
-
-        let unapplied = self.context.migration_set(last_applied);
+    // Before the first operation, wait for the database and set up the
+    // session of the executor's connections.  Before every operation, forget
+    // the warnings of the last one.
+    async fn prepare(&mut self) -> TernResult<()> {
+        self.warnings.clear();
+        self.wait_for_db().await?;
+        if !self.session_prepared {
+            self.context.prepare_session().await?;
+            self.session_prepared = true;
+        }
 
-        let mut results = Vec::new();
-        for migration in &unapplied.migrations {
-            let id = migration.migration_id();
-            let ver = migration.version();
+        Ok(())
+    }
 
-            // Reached the target version, break the loop.
-            if matches!(target_version, Some(end) if ver > end) {
-                break;
-            }
+    // Run the built-in validation rules and then the ones that were added, and
+    // return the latest applied version.
+    pub(crate) async fn validate(
+        &mut self,
+        target_version: Option<&Version>,
+    ) -> TernResult<Option<Version>> {
+        self.prepare().await?;
+        let start = Instant::now();
+        self.context.check_history_table().await?;
+        let applied = self.context.previously_applied().await?;
+        let source = self.context.migration_set(None);
+        let validation = Validation::new(
+            &source,
+            &applied,
+            target_version,
+            self.context.config(),
+        );
+        let rules = validate::builtin_rules()
+            .into_iter()
+            .chain(self.rules.iter().map(|rule| rule.as_ref()));
+        let mut warnings =
+            validate::check_rules(rules, &self.severities, &validation)?;
+        if let Some(dialect) =
+            <<C::Exec as Executor>::Queries as QueryRepository>::DIALECT
+        {
+            warnings.extend(dialect_warnings(&validation, dialect)?);
+        }
+        self.warnings = warnings;
+        self.timings.validation += start.elapsed();
 
-            let result = if dryrun {
-                // Build each query, which possibly includes dynamic ones.
-                let query = migration
-                    .build(&mut self.context)
-                    .await
-                    .with_report(&results)?;
+        Ok(validation.latest_applied().cloned())
+    }
 
-                MigrationResult::from_unapplied(migration.as_ref(), query.sql())
-            } else {
-                log::trace!("applying migration {id}");
+    /// Check that every migration in the source has been applied and that
+    /// nothing in the history is missing from the source.
+    ///
+    /// This is meant to be called when an application starts, so that it
+    /// refuses to run against a schema that it doesn't expect.  Unlike the
+    /// other operations, it does not create the history table if it doesn't
+    /// exist, so it can be run with a read-only connection.
+    pub async fn assert_up_to_date(&mut self) -> TernResult<()> {
+        self.prepare().await?;
+        let applied: HashSet<MigrationId> = self
+            .context
+            .previously_applied()
+            .await?
+            .into_iter()
+            .map(MigrationId::from)
+            .collect();
+        let source = self.context.migration_set(None).migration_ids();
 
-                self.context
-                    .apply(migration.as_ref())
-                    .await
-                    .tern_migration_result(migration.as_ref())
-                    .with_report(&results)
-                    .map(|v| {
-                        MigrationResult::from_applied(
-                            &v,
-                            Some(migration.no_tx()),
-                        )
-                    })?
-            };
+        let mut out_of_sync: Vec<MigrationId> =
+            applied.iter().filter(|id| !source.contains(id)).cloned().collect();
+        out_of_sync.sort_by(|a, b| {
+            C::ORDERING
+                .cmp(&a.version(), &b.version())
+                .then_with(|| a.description().cmp(&b.description()))
+        });
+        let pending: Vec<MigrationId> =
+            source.into_iter().filter(|id| !applied.contains(id)).collect();
 
-            results.push(result);
+        if pending.is_empty() && out_of_sync.is_empty() {
+            return Ok(());
         }
 
-        Ok(Report::new(results))
+        Err(Error::NotUpToDate { pending, out_of_sync })
     }
 
-    /// Apply all unapplied migrations.
-    #[deprecated(since = "3.1.0", note = "use `run_apply_all`")]
-    pub async fn apply_all(&mut self) -> TernResult<Report> {
-        self.run_apply(None, false).await
-    }
+    /// Compare the migrations in the source with the history of the database.
+    ///
+    /// The report has the migrations in the source that haven't been applied,
+    /// the applied ones that aren't in the source, and the applied SQL
+    /// migrations whose content has changed, by the checksum of what was
+    /// applied and of what is embedded now.  Like [`assert_up_to_date`], it
+    /// does not create the history table, so it can be run on a schedule with
+    /// a read-only connection to detect drift.
+    ///
+    /// [`assert_up_to_date`]: Runner::assert_up_to_date
+    pub async fn check_drift(&mut self) -> TernResult<DriftReport> {
+        self.prepare().await?;
+        let applied = self.context.previously_applied().await?;
+        let applied_ids: HashSet<MigrationId> =
+            applied.iter().cloned().map(MigrationId::from).collect();
+        let source = self.context.migration_set(None).migration_ids();
 
-    /// Apply all unapplied migrations.
-    pub async fn run_apply_all(&mut self, dryrun: bool) -> TernResult<Report> {
-        self.run_apply(None, dryrun).await
+        let mut unknown: Vec<MigrationId> = applied_ids
+            .iter()
+            .filter(|id| !source.contains(id))
+            .cloned()
+            .collect();
+        unknown.sort_by(|a, b| {
+            C::ORDERING
+                .cmp(&a.version(), &b.version())
+                .then_with(|| a.description().cmp(&b.description()))
+        });
+        let unapplied =
+            source.into_iter().filter(|id| !applied_ids.contains(id)).collect();
+
+        let embedded = C::embedded_migrations();
+        // A soft applied migration has the query that was built instead of
+        // the source, and a failed one the error, so there's nothing to
+        // compare.
+        let changed = applied
+            .iter()
+            .filter(|applied| applied.was_run())
+            .filter_map(|applied| {
+                let m = embedded.iter().find(|m| {
+                    m.filename.ends_with(".sql")
+                        && m.version == applied.version
+                        && m.description == applied.description
+                })?;
+                let applied_checksum = applied.checksum();
+                (applied_checksum != m.checksum).then(|| ChangedMigration {
+                    id: m.migration_id(),
+                    checksum: m.checksum.to_string(),
+                    applied_checksum,
+                })
+            })
+            .collect();
+
+        Ok(DriftReport { unapplied, unknown, changed })
     }
 
-    /// List the migrations that have already been applied.
-    pub async fn list_applied(&mut self) -> TernResult<Report> {
-        self.validate_source().await?;
+    /// Check the rows of the history table from this source for problems
+    /// that would otherwise only show up as confusing failures to apply:
+    /// versions with more than one row, rows without a checksum of what was
+    /// applied, and rows applied before a row with an earlier version.
+    ///
+    /// The last is also what a migration that was reapplied, or applied out
+    /// of order, looks like, so those are worth a look but not always wrong.
+    /// Like [`check_drift`], it does not create the history table, and it
+    /// reads the rows as they are, so a row that can't be decompressed is in
+    /// the report instead of being an error.
+    ///
+    /// [`check_drift`]: Runner::check_drift
+    pub async fn verify_history(&mut self) -> TernResult<HistoryVerification> {
+        self.prepare().await?;
+        let history_table = self.context.history_table();
+        let rows = self
+            .context
+            .executor()
+            .get_all_applied(&history_table)
+            .await?
+            .into_iter()
+            .filter(|m| m.source_id.as_deref() == C::SOURCE_ID)
+            .collect();
 
-        let applied = self
+        Ok(HistoryVerification::new(rows, C::ORDERING))
+    }
+
+    /// The statement that creates each table, index, and view in the
+    /// database, for a snapshot of the schema that the migrations built.  See
+    /// [`schema`](crate::schema).
+    ///
+    /// The history table and the tables that are kept next to it, like its
+    /// backups, are not part of the schema and are left out.  Nothing is
+    /// changed.
+    pub async fn dump_schema(&mut self) -> TernResult<Schema> {
+        self.prepare().await?;
+        let history_table = self.context.history_table();
+        let prefix = history_table
+            .rsplit('.')
+            .next()
+            .unwrap_or(&history_table)
+            .trim_matches('"')
+            .to_lowercase();
+        let objects = self
             .context
-            .previously_applied()
+            .executor()
+            .schema()
             .await?
-            .iter()
-            .map(|m| MigrationResult::from_applied(m, None))
+            .into_iter()
+            .filter(|o| !o.name.to_lowercase().starts_with(&prefix));
+
+        Ok(Schema::new(objects))
+    }
+
+    /// The version of the migration in the source with this description, for
+    /// giving the target of an operation by name instead of by version.
+    ///
+    /// It is an error if no migration or more than one has the description.
+    pub fn version_named(&self, description: &str) -> TernResult<Version> {
+        let named = self
+            .context
+            .migration_set(None)
+            .migration_ids()
+            .into_iter()
+            .filter(|id| id.description() == description)
             .collect::<Vec<_>>();
-        let report = Report::new(applied);
+        match &named[..] {
+            [id] => Ok(id.version()),
+            [] => Err(Error::Invalid(format!(
+                "no migration has the description {description}"
+            ))),
+            _ => Err(Error::Invalid(format!(
+                "more than one migration has the description {description}: {named:?}"
+            ))),
+        }
+    }
 
-        Ok(report)
+    /// The version for the target of an operation, which can be a version, a
+    /// migration name like `V3__create_indexes`, or the name of a migration
+    /// without its version, like `V__create_indexes`.
+    ///
+    /// A name is checked against the migrations in the source with
+    /// [`version_named`](Self::version_named).
+    pub fn resolve_target(&self, target: &str) -> TernResult<Version> {
+        if let Some(description) = target.strip_prefix("V__") {
+            return self.version_named(description);
+        }
+        if let Ok(id) = target.parse::<MigrationId>() {
+            let version = self.version_named(&id.description())?;
+            let given =
+                Version::parse(&id.version().to_string(), C::VERSION_KIND)?;
+            if version != given {
+                return Err(Error::Invalid(format!(
+                    "the migration {} has the version {version}, not {given}",
+                    id.description()
+                )));
+            }
+            return Ok(version);
+        }
+
+        Version::parse(target, C::VERSION_KIND)
     }
 
-    #[deprecated(
-        since = "3.1.0",
-        note = "no valid use case for `start_version`"
-    )]
-    pub async fn soft_apply(
+    /// Like [`resolve_target`](Self::resolve_target), but also accepts a target
+    /// relative to the latest applied version, which is found from the history
+    /// table when this is called.
+    ///
+    /// `+N` is the version of the `N`th unapplied migration, or of the last
+    /// one if there are fewer than `N`, so that applying up to it applies the
+    /// next `N`. When there are none unapplied it is the latest applied
+    /// version. `-N` would be `N` migrations back from the latest applied, but
+    /// that needs down migrations, which aren't supported, so it's an error.
+    pub async fn resolve_relative_target(
         &mut self,
-        start_version: Option<i64>,
-        target_version: Option<i64>,
-    ) -> TernResult<Report> {
-        if start_version.is_some() {
-            return Err(Error::Invalid(
-                "no valid `start_version` other than the first unapplied, use `run_soft_apply`"
-                    .into(),
-            ));
+        target: &str,
+    ) -> TernResult<Version> {
+        if let Some(n) = target.strip_prefix('+') {
+            let n = n.parse::<usize>().ok().filter(|n| *n > 0).ok_or_else(
+                || Error::Invalid(format!("invalid relative target {target}")),
+            )?;
+            return self.version_ahead(n).await;
+        }
+        if target.starts_with('-') {
+            return Err(Error::Invalid(format!(
+                "relative target {target} needs down migrations, which are not \
+                 supported"
+            )));
         }
-        self.run_soft_apply(target_version, false).await
+
+        self.resolve_target(target)
     }
 
-    /// Run a "soft apply" of the migrations up to and including the specified
-    /// version.
+    // The version `n` migrations ahead of the latest applied.
+    async fn version_ahead(&mut self, n: usize) -> TernResult<Version> {
+        let last_applied = self.validate(None).await?;
+        let unapplied = self.context.migration_set(last_applied.clone());
+        let ahead = unapplied
+            .migrations
+            .get(n - 1)
+            .or(unapplied.migrations.last())
+            .map(|m| m.version());
+
+        ahead.or(last_applied).ok_or_else(|| {
+            Error::Invalid("there are no migrations in the source".into())
+        })
+    }
+
+    /// Apply unapplied migrations up to and including the specified version.
     ///
-    /// This means that the migration will be saved in the history table, but
+    /// When that leaves no migration unapplied, the repeatable migrations that
+    /// are new or have changed are applied after them, see
+    /// [`repeatable_migrations`].
+    ///
+    /// If any were applied, this then runs the context's
+    /// [`post_apply`](MigrationContext::post_apply) SQL, and if that fails
+    /// it is an [`Error::Partial`] with the report of the migrations.
+    ///
+    /// [`repeatable_migrations`]: crate::migration::MigrationSource::repeatable_migrations
+    pub async fn run_apply(
+        &mut self,
+        target_version: Option<Version>,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        let start = self.start_timings();
+        let deadline = self.deadline.map(|d| start + d);
+        let target_version = target_version.as_ref();
+        if dryrun {
+            let res =
+                self.apply_unlocked(target_version, dryrun, deadline).await;
+            self.finish_timings(start, &res);
+            return self.with_content(res);
+        }
+        self.prepare().await?;
+        self.lock().await?;
+        let res =
+            match self.apply_unlocked(target_version, dryrun, deadline).await {
+                Ok(report) => self.post_apply(report).await,
+                Err(e) => Err(e),
+            };
+        let res = self.unlock(res).await;
+        self.finish_timings(start, &res);
+        self.with_content(res)
+    }
+
+    // Run the post-apply SQL of the context after a run that applied
+    // something.
+    async fn post_apply(&mut self, report: Report) -> TernResult<Report> {
+        let Some(query) = self.context.post_apply() else {
+            return Ok(report);
+        };
+        if report.count() == 0 && report.repeatable.is_empty() {
+            return Ok(report);
+        }
+        log::trace!("running post-apply SQL");
+        match self.context.executor().apply_no_tx(&query).await {
+            Ok(()) => Ok(report),
+            Err(e) => Err(Error::Partial { source: Box::new(e), report }),
+        }
+    }
+
+    // Run the post-apply SQL of the context at the end of an apply stream
+    // that applied something.
+    async fn post_apply_stream(&mut self, applied: usize) -> TernResult<()> {
+        let Some(query) = self.context.post_apply().filter(|_| applied > 0)
+        else {
+            return Ok(());
+        };
+        log::trace!("running post-apply SQL");
+        self.context.executor().apply_no_tx(&query).await
+    }
+
+    async fn apply_unlocked(
+        &mut self,
+        target_version: Option<&Version>,
+        dryrun: bool,
+        deadline: Option<Instant>,
+    ) -> TernResult<Report> {
+        let unapplied = self.unapplied(target_version).await?;
+
+        let mut results = Vec::new();
+        // With `continue_on_error`, the migrations that failed or were skipped.
+        let mut failed = Vec::new();
+        for (n, migration) in unapplied.migrations.iter().enumerate() {
+            let ver = migration.version();
+
+            // Reached the target version, break the loop.
+            if matches!(target_version, Some(end) if C::ORDERING.cmp(&ver, end).is_gt())
+            {
+                break;
+            }
+
+            let stopped = self.stop.is_stopped();
+            if stopped || deadline.is_some_and(|d| Instant::now() >= d) {
+                let skipped = unapplied.migrations[n..]
+                    .iter()
+                    .filter(|m| !matches!(target_version, Some(end) if C::ORDERING.cmp(&m.version(), end).is_gt()))
+                    .count();
+                let report = Report::new(results);
+                if stopped {
+                    log::warn!(
+                        "run stopped, skipping {skipped} remaining migrations"
+                    );
+                    return Ok(report.with_interrupted());
+                }
+                log::warn!(
+                    "run deadline exceeded, skipping {skipped} remaining migrations"
+                );
+                return Ok(report.with_deadline_exceeded());
+            }
+
+            let result = if self.continue_on_error {
+                self.apply_continuing(migration.as_ref(), dryrun, &mut failed)
+                    .await
+            } else {
+                self.apply_one(migration.as_ref(), dryrun).await
+            };
+            results.push(result.with_report(&results)?);
+        }
+
+        // Repeatable migrations go after every versioned one, so not when the
+        // run stops before the last of them.
+        let report = Report::new(results);
+        let reached_end = unapplied.migrations.last().is_none_or(|m| {
+            !matches!(target_version, Some(end) if C::ORDERING.cmp(&m.version(), end).is_gt())
+        });
+        if !reached_end {
+            return Ok(report);
+        }
+        if !failed.is_empty() {
+            log::warn!(
+                "skipping repeatable migrations after migrations that did not apply"
+            );
+            return Ok(report);
+        }
+        self.apply_repeatable(report, dryrun).await
+    }
+
+    // Apply the repeatable migrations that are new or have changed since they
+    // were last applied, in the order of their descriptions, adding them to
+    // the report of the versioned ones, or only add them if this is a dry run.
+    async fn apply_repeatable(
+        &mut self,
+        mut report: Report,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        let repeatable = C::repeatable_migrations();
+        if repeatable.is_empty() {
+            return Ok(report);
+        }
+        let history_table = self.context.history_table();
+        let applied = match self
+            .context
+            .executor()
+            .get_applied_repeatable(&history_table)
+            .await
+        {
+            Ok(applied) => applied,
+            Err(e) => {
+                return Err(Error::Partial { source: Box::new(e), report });
+            },
+        };
+        let applied = applied
+            .into_iter()
+            .map(|m| (m.description, m.checksum))
+            .collect::<HashMap<_, _>>();
+        let mut results = Vec::new();
+        for migration in repeatable {
+            if applied
+                .get(migration.description)
+                .is_some_and(|checksum| checksum == migration.checksum)
+            {
+                continue;
+            }
+            if dryrun {
+                results.push(RepeatableResult::unapplied(&migration));
+                continue;
+            }
+            match self.apply_repeatable_one(&history_table, &migration).await {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    report.repeatable = results.into();
+                    return Err(Error::Partial { source: Box::new(e), report });
+                },
+            }
+        }
+        report.repeatable = results.into();
+
+        Ok(report)
+    }
+
+    async fn apply_repeatable_one(
+        &mut self,
+        history_table: &str,
+        migration: &RepeatableMigration,
+    ) -> TernResult<RepeatableResult> {
+        log::trace!("applying repeatable migration {}", migration.filename);
+        let start = Instant::now();
+        let options = self.apply_options.clone().with_no_tx(migration.no_tx);
+        let executor = self.context.executor();
+        executor.apply(&migration.query(), &options).await.map_err(|e| {
+            Error::ExecuteRepeatable(Box::new(e), migration.filename.into())
+        })?;
+        let applied = AppliedRepeatable {
+            description: migration.description.to_string(),
+            checksum: migration.checksum.to_string(),
+            applied_at: Utc::now(),
+            duration_ms: start.elapsed().as_millis() as i64,
+        };
+        executor.upsert_applied_repeatable(history_table, &applied).await?;
+        self.timings.history_rows += 1;
+
+        Ok(RepeatableResult::applied(migration, &applied))
+    }
+
+    // Apply one migration, but with a failure being its result instead of an
+    // error, and skip it if it depends on one that failed or was skipped.
+    async fn apply_continuing(
+        &mut self,
+        migration: &dyn Migration<Ctx = C>,
+        dryrun: bool,
+        failed: &mut Vec<Version>,
+    ) -> TernResult<MigrationResult> {
+        let id = migration.migration_id();
+        let depends_on = migration
+            .annotations()
+            .depends_on()
+            .into_iter()
+            .map(|v| Version::parse(v, C::VERSION_KIND))
+            .collect::<TernResult<Vec<_>>>()
+            .tern_migration_result(migration)?;
+        if let Some(unmet) = depends_on.iter().find(|v| failed.contains(v)) {
+            log::warn!(
+                "skipping migration {id}, which depends on version {unmet} that did not apply"
+            );
+            failed.push(migration.version());
+            return Ok(MigrationResult::skipped(migration, dryrun));
+        }
+
+        match self.apply_one(migration, dryrun).await {
+            Err(e) => {
+                log::warn!("migration {id} failed, continuing: {e}");
+                failed.push(migration.version());
+                Ok(MigrationResult::from_error(migration, dryrun, &e))
+            },
+            res => res,
+        }
+    }
+
+    // Apply one migration, or only build it if this is a dry run.
+    async fn apply_one(
+        &mut self,
+        migration: &dyn Migration<Ctx = C>,
+        dryrun: bool,
+    ) -> TernResult<MigrationResult> {
+        if dryrun {
+            // Check that the annotations are valid, then build each query,
+            // which possibly includes dynamic ones.
+            let options = self
+                .apply_options
+                .for_migration(migration)
+                .tern_migration_result(migration)?;
+            migration
+                .annotations()
+                .retries()
+                .tern_migration_result(migration)?;
+            migration
+                .annotations()
+                .concurrent_index_retries()
+                .tern_migration_result(migration)?;
+            let query = migration.build(&mut self.context).await?;
+            let mut result =
+                MigrationResult::from_unapplied(migration, query.sql())
+                    .with_warnings(backend_warnings::<C>(migration, &options));
+            if <<C::Exec as Executor>::Queries as QueryRepository>::DIALECT
+                == Some(Dialect::Postgres)
+            {
+                result.locks = locks::analyze(query.sql())?;
+            }
+            if options.analyze {
+                result.analyzed = locks::changed_tables(query.sql())?;
+            }
+            if migration.no_tx() {
+                let steps = query
+                    .split_statements()
+                    .tern_migration_result(migration)?
+                    .into_iter()
+                    .map(StepResult::unapplied)
+                    .collect();
+                result = result.with_steps(steps);
+            }
+
+            return Ok(result.redact_statements(migration));
+        }
+        log::trace!("applying migration {}", migration.migration_id());
+
+        let options = self
+            .apply_options
+            .for_migration(migration)
+            .tern_migration_result(migration)?;
+        let mut warnings = backend_warnings::<C>(migration, &options);
+        let mut retry = |message: String| {
+            log::warn!("{message}");
+            warnings.push(Warning::new(
+                WarningKind::Retry,
+                vec![migration.migration_id()],
+                message,
+            ));
+        };
+        let retries = migration
+            .annotations()
+            .retries()
+            .tern_migration_result(migration)?;
+        let index_retries = migration
+            .annotations()
+            .concurrent_index_retries()
+            .tern_migration_result(migration)?;
+        // The query is built once and applied again for a retry, since building
+        // a Rust migration can do work that shouldn't be done twice.
+        let mut built: Option<(Query, Duration)> = None;
+        let (mut attempts, mut retried, mut index_retried) = (1, 0, 0);
+        let res = loop {
+            let (query, built_in) = match built.clone() {
+                Some(built) => built,
+                None => {
+                    let start = Instant::now();
+                    let query = match migration
+                        .build(&mut self.context)
+                        .await
+                        .tern_migration_result(migration)
+                    {
+                        Ok(query) => query,
+                        Err(e) if retried < retries && e.is_transient() => {
+                            retried += 1;
+                            retry(format!(
+                                "migration {} failed to build, retrying ({retried} of {retries}): {e}",
+                                migration.migration_id()
+                            ));
+                            futures_timer::Delay::new(RETRY_DELAY * retried)
+                                .await;
+                            attempts += 1;
+                            continue;
+                        },
+                        Err(e) => break Err(e),
+                    };
+                    let built_in = start.elapsed();
+                    // An index left invalid by a run before this one would be
+                    // skipped by `IF NOT EXISTS` and stay invalid.
+                    if index_retries > 0 {
+                        let dropped = self.drop_invalid_indexes(&query).await?;
+                        if !dropped.is_empty() {
+                            retry(format!(
+                                "dropped invalid indexes {} of migration {} before applying it",
+                                dropped.join(", "),
+                                migration.migration_id()
+                            ));
+                        }
+                    }
+                    built.insert((query, built_in)).clone()
+                },
+            };
+            let e = match apply_built(
+                &mut self.context,
+                migration,
+                &query,
+                self.apply_options.clone(),
+                built_in,
+            )
+            .await
+            .tern_migration_result(migration)
+            {
+                Ok((applied, steps)) => break Ok((applied, steps, query)),
+                Err(e) => e,
+            };
+            if index_retried < index_retries {
+                match self.drop_invalid_indexes(&query).await {
+                    Ok(dropped) if !dropped.is_empty() => {
+                        index_retried += 1;
+                        retry(format!(
+                            "migration {} failed and left invalid indexes {}, dropped them and retrying ({index_retried} of {index_retries}): {e}",
+                            migration.migration_id(),
+                            dropped.join(", ")
+                        ));
+                        attempts += 1;
+                        continue;
+                    },
+                    Ok(_) => {},
+                    Err(cleanup) => log::warn!(
+                        "could not drop the invalid indexes of migration {}: {cleanup}",
+                        migration.migration_id()
+                    ),
+                }
+            }
+            if retried < retries && e.is_transient() {
+                retried += 1;
+                retry(format!(
+                    "migration {} failed, retrying ({retried} of {retries}): {e}",
+                    migration.migration_id()
+                ));
+                futures_timer::Delay::new(RETRY_DELAY * retried).await;
+                attempts += 1;
+                continue;
+            }
+            break Err(e);
+        };
+        let (applied, steps, query) = match res {
+            Ok(applied) => {
+                self.timings.history_rows += 1;
+                applied
+            },
+            Err(e) if migration.allow_failure() => {
+                let result = self.failed_allowed(migration, e).await?;
+                return Ok(result.with_warnings(warnings));
+            },
+            Err(e) => {
+                self.quarantine(migration, &e).await;
+                return Err(e);
+            },
+        };
+        let history_table = self.context.history_table();
+        self.context
+            .executor()
+            .release_quarantined(&history_table, C::SOURCE_ID, &applied.version)
+            .await?;
+
+        let mut result =
+            MigrationResult::from_applied(&applied, Some(migration.no_tx()));
+        if retries > 0 || index_retries > 0 {
+            result.attempts = Some(attempts);
+        }
+        if options.analyze {
+            result.analyzed =
+                self.analyze_tables(migration, &query, &mut warnings).await;
+        }
+
+        Ok(result
+            .with_steps(steps.into_iter().map(StepResult::applied).collect())
+            .with_warnings(warnings)
+            .redact_statements(migration))
+    }
+
+    // Refresh the statistics of the tables that an applied migration changed,
+    // returning the ones that were analyzed.  The tables are the ones in the
+    // query that it was applied with, since building it again would run what
+    // a Rust migration does when it's built again.  The migration is applied
+    // by then, so not being able to is a warning.
+    async fn analyze_tables(
+        &mut self,
+        migration: &dyn Migration<Ctx = C>,
+        query: &Query,
+        warnings: &mut Vec<Warning>,
+    ) -> Vec<String> {
+        let id = migration.migration_id();
+        let mut warn = |message: String| {
+            log::warn!("{message}");
+            warnings.push(Warning::new(
+                WarningKind::Backend,
+                vec![id.clone()],
+                message,
+            ));
+        };
+        let tables = match locks::changed_tables(query.sql()) {
+            Ok(tables) if tables.is_empty() => return tables,
+            Ok(tables) => tables,
+            Err(e) => {
+                warn(format!(
+                    "could not find the tables that migration {id} changed to analyze them: {e}"
+                ));
+                return Vec::new();
+            },
+        };
+        let Some(analyze) =
+            <<C::Exec as Executor>::Queries as QueryRepository>::analyze_query(
+                &tables,
+            )
+        else {
+            warn(format!(
+                "the database does not support analyzing tables, not analyzing the tables of migration {id}"
+            ));
+            return Vec::new();
+        };
+        log::trace!("analyzing {} after migration {id}", tables.join(", "));
+        if let Err(e) = self.context.executor().apply_tx(&analyze).await {
+            warn(format!(
+                "could not analyze {} after migration {id}: {e}",
+                tables.join(", ")
+            ));
+            return Vec::new();
+        }
+
+        tables
+    }
+
+    // Drop the indexes that the query of a migration builds with `CREATE INDEX
+    // CONCURRENTLY` and that are invalid after it failed, returning their
+    // names, so that applying it again can build them from the start.
+    async fn drop_invalid_indexes(
+        &mut self,
+        query: &Query,
+    ) -> TernResult<Vec<String>> {
+        let built = inventory::concurrent_indexes(query.sql())?;
+        if built.is_empty() {
+            return Ok(Vec::new());
+        }
+        let invalid = self.context.executor().invalid_indexes().await?;
+        let mut dropped = Vec::new();
+        for index in built.into_iter().filter(|i| invalid.contains(i)) {
+            let drop = Query::new(format!(
+                "DROP INDEX CONCURRENTLY IF EXISTS {};",
+                index.name
+            ));
+            self.context.executor().apply_no_tx(&drop).await?;
+            dropped.push(index.name);
+        }
+
+        Ok(dropped)
+    }
+
+    // Record a failed attempt to apply a migration.  This is only for the
+    // operator to see, so failing to record it is a warning and not an error
+    // that would hide the one from the migration.
+    async fn quarantine(
+        &mut self,
+        migration: &dyn Migration<Ctx = C>,
+        error: &Error,
+    ) {
+        let history_table = self.context.history_table();
+        let id = migration.migration_id();
+        let res = self
+            .context
+            .executor()
+            .record_failure(
+                &history_table,
+                C::SOURCE_ID,
+                &id,
+                &error.to_string(),
+            )
+            .await;
+        if let Err(e) = res {
+            log::warn!("could not record that migration {id} failed: {e}");
+        }
+    }
+
+    // Record a migration with `tern:allowFailure` that failed in the history
+    // with the error, so that the run goes on and the migrations after it
+    // aren't out of sync with it.  The row makes it count as applied, so the
+    // next run doesn't retry it, and `reapply` is what does.
+    async fn failed_allowed(
+        &mut self,
+        migration: &dyn Migration<Ctx = C>,
+        error: Error,
+    ) -> TernResult<MigrationResult> {
+        log::warn!(
+            "migration {} failed and is allowed to: {error}",
+            migration.migration_id()
+        );
+        let mut applied = migration.to_applied(0, Utc::now(), "");
+        applied.search_path = self.context.search_path();
+        let message = error.to_string();
+        let first_line = message.lines().next().unwrap_or_default();
+        let mut content = format!("{FAILED} {first_line}\n\n");
+        writeln!(
+            content,
+            "{}",
+            history_content(migration, &migration.content())
+        )?;
+        applied.content = content;
+        self.context.insert_applied(&applied).await?;
+        self.timings.history_rows += 1;
+
+        Ok(MigrationResult::from_failed(&applied, migration.no_tx(), &error))
+    }
+
+    /// The migrations that failed to apply and haven't been applied since, with
+    /// the error of the last attempt and how many there were.
+    ///
+    /// Each failure is recorded in a table next to the history table, named
+    /// after it with the suffix `_quarantine`, so that a migration that keeps
+    /// failing is visible across deploys.  A migration leaves it when it is
+    /// applied.  This is empty for an executor that doesn't support it.
+    pub async fn quarantined(
+        &mut self,
+    ) -> TernResult<Vec<QuarantinedMigration>> {
+        self.prepare().await?;
+        let history_table = self.context.history_table();
+        self.context
+            .executor()
+            .get_quarantined(&history_table, C::SOURCE_ID, C::VERSION_KIND)
+            .await
+    }
+
+    /// Apply again only the migrations that are [`quarantined`], in order.
+    ///
+    /// Unlike [`run_apply`], this doesn't require the migrations before them to
+    /// be applied, so it is how a migration that failed in a run with
+    /// [`with_continue_on_error`] is applied after it is fixed.  One that was
+    /// applied some other way in the meantime is released without applying it.
+    ///
+    /// [`quarantined`]: Self::quarantined
+    /// [`run_apply`]: Self::run_apply
+    /// [`with_continue_on_error`]: Self::with_continue_on_error
+    pub async fn retry_failed(&mut self, dryrun: bool) -> TernResult<Report> {
+        let start = self.start_timings();
+        self.prepare().await?;
+        if dryrun {
+            let res = self.retry_failed_unlocked(dryrun).await;
+            self.finish_timings(start, &res);
+            return self.with_content(res);
+        }
+        self.lock().await?;
+        let res = self.retry_failed_unlocked(dryrun).await;
+        let res = self.unlock(res).await;
+        self.finish_timings(start, &res);
+        self.with_content(res)
+    }
+
+    async fn retry_failed_unlocked(
+        &mut self,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        self.context.check_history_table().await?;
+        let quarantined = self
+            .quarantined()
+            .await?
+            .into_iter()
+            .map(|q| q.version)
+            .collect::<Vec<_>>();
+        let applied = self
+            .context
+            .previously_applied()
+            .await?
+            .into_iter()
+            .map(|m| m.version)
+            .collect::<Vec<_>>();
+        let history_table = self.context.history_table();
+        let source = self.context.migration_set(None);
+
+        let mut results = Vec::new();
+        let mut failed = Vec::new();
+        for migration in source.migrations.iter() {
+            let ver = migration.version();
+            if !quarantined.contains(&ver) {
+                continue;
+            }
+            if applied.contains(&ver) {
+                if !dryrun {
+                    log::info!("migration {ver} was already applied");
+                    self.context
+                        .executor()
+                        .release_quarantined(&history_table, C::SOURCE_ID, &ver)
+                        .await?;
+                }
+                continue;
+            }
+            if self.stop.is_stopped() {
+                log::warn!(
+                    "run stopped, not retrying the remaining migrations"
+                );
+                return Ok(Report::new(results).with_interrupted());
+            }
+
+            let result = if self.continue_on_error {
+                self.apply_continuing(migration.as_ref(), dryrun, &mut failed)
+                    .await
+            } else {
+                self.apply_one(migration.as_ref(), dryrun).await
+            };
+            results.push(result.with_report(&results)?);
+        }
+
+        Ok(Report::new(results))
+    }
+
+    /// Apply the unapplied migrations up to and including the target version
+    /// in a transaction that is rolled back instead of committed, to time them
+    /// without changing anything, e.g., against a copy of production.
+    ///
+    /// The migrations are applied in order in the same transaction, so each
+    /// one has the ones before it.  Migrations that are not applied in a
+    /// transaction are skipped, and are in the report as not applied.  The
+    /// others are in it as rolled back, with how long they took.  The executor
+    /// has to support [`Executor::apply_rolled_back`], which the `sqlx`
+    /// executors for Postgres and SQLite do.
+    pub async fn bench(
+        &mut self,
+        target_version: Option<Version>,
+    ) -> TernResult<Report> {
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.apply_rolled_back(target_version.as_ref()).await;
+        let res = self.unlock(res).await;
+        self.with_content(res)
+    }
+
+    /// Check that all of the unapplied migrations run against the database,
+    /// and not only that they parse, by applying them in a transaction that is
+    /// always rolled back, as a gate before deploying, e.g., on a copy of the
+    /// database in staging.
+    ///
+    /// This is [`bench`](Self::bench) for the whole set of unapplied
+    /// migrations, so it is an error with the report of the migrations before
+    /// it when one of them fails, and migrations that are not applied in a
+    /// transaction are skipped.
+    pub async fn try_apply_all(&mut self) -> TernResult<Report> {
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.apply_rolled_back(None).await;
+        let res = self.unlock(res).await;
+        self.with_content(res)
+    }
+
+    async fn apply_rolled_back(
+        &mut self,
+        target_version: Option<&Version>,
+    ) -> TernResult<Report> {
+        let unapplied = self.unapplied(target_version).await?;
+        let migrations = unapplied
+            .migrations
+            .iter()
+            .filter(|m| !matches!(target_version, Some(end) if C::ORDERING.cmp(&m.version(), end).is_gt()))
+            .collect::<Vec<_>>();
+
+        let mut results = Vec::new();
+        let mut queries = Vec::new();
+        let mut benched = Vec::new();
+        for migration in migrations {
+            let options = self
+                .apply_options
+                .for_migration(migration.as_ref())
+                .tern_migration_result(migration.as_ref())?;
+            let query = migration.build(&mut self.context).await?;
+            if options.no_tx {
+                log::info!(
+                    "skipping {}, which is not applied in a transaction",
+                    migration.migration_id()
+                );
+                results.push(MigrationResult::from_unapplied(
+                    migration.as_ref(),
+                    query.sql(),
+                ));
+                continue;
+            }
+            benched.push((results.len(), migration));
+            results.push(MigrationResult::from_unapplied(
+                migration.as_ref(),
+                query.sql(),
+            ));
+            queries.push((query, options));
+        }
+
+        let rolled_back =
+            self.context.executor().apply_rolled_back(&queries).await?;
+        for ((n, _), duration) in benched.iter().zip(&rolled_back.durations) {
+            results[*n].set_rolled_back(*duration);
+        }
+        if let Some(e) = rolled_back.error {
+            let (n, migration) = benched[rolled_back.durations.len()];
+            let source = Error::ExecuteMigration(
+                Box::new(e),
+                migration.migration_id(),
+                false,
+            );
+            return Err(Error::Partial {
+                source: Box::new(source),
+                report: Report::new(results[..n].to_vec()),
+            });
+        }
+
+        Ok(Report::new(results))
+    }
+
+    /// Apply a migration that has already been applied again, and replace its
+    /// row in the history table with the content, duration, and time of this
+    /// run.
+    ///
+    /// This is for a migration that was fixed after it was applied, when the
+    /// query is idempotent and it is safe to run again.  Validation is not run,
+    /// because the migration is expected to have changed since it was applied,
+    /// but it has to be in the source and in the history.
+    pub async fn reapply(&mut self, version: Version) -> TernResult<Report> {
+        let start = self.start_timings();
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.reapply_unlocked(&version).await;
+        let res = self.unlock(res).await;
+        self.finish_timings(start, &res);
+        self.with_content(res)
+    }
+
+    async fn reapply_unlocked(
+        &mut self,
+        version: &Version,
+    ) -> TernResult<Report> {
+        self.context.check_history_table().await?;
+        let applied = self.context.previously_applied().await?;
+        if !applied.iter().any(|m| &m.version == version) {
+            return Err(Error::Invalid(format!(
+                "version {version} has not been applied, there is nothing to reapply"
+            )));
+        }
+        let source = self.context.migration_set(None);
+        let Some(migration) =
+            source.migrations.iter().find(|m| &m.version() == version)
+        else {
+            return Err(Error::Invalid(format!(
+                "version {version} is not in the migration source"
+            )));
+        };
+        let migration = migration.as_ref();
+        log::trace!("reapplying migration {}", migration.migration_id());
+
+        let start = Instant::now();
+        let options = self
+            .apply_options
+            .for_migration(migration)
+            .tern_migration_result(migration)?;
+        let query = migration.build(&mut self.context).await?;
+        let steps = self
+            .context
+            .executor()
+            .apply_statements(&query, &options)
+            .await
+            .tern_migration_result(migration)?;
+        let duration_ms = start.elapsed().as_millis() as i64;
+        let mut applied =
+            migration.to_applied(duration_ms, Utc::now(), query.sql());
+        applied.search_path = self.context.search_path();
+        self.context.upsert_applied(&applied).await?;
+        self.timings.history_rows += 1;
+
+        let result =
+            MigrationResult::from_applied(&applied, Some(options.no_tx))
+                .with_steps(
+                    steps.into_iter().map(StepResult::applied).collect(),
+                )
+                .redact_statements(migration);
+
+        Ok(Report::new(vec![result]))
+    }
+
+    /// Like [`run_apply`](Self::run_apply), but the result of each migration
+    /// is yielded as soon as the migration is done, instead of all together in
+    /// a `Report` at the end, for reporting progress while the run goes on.
+    ///
+    /// Repeatable migrations are not applied, since they don't have a
+    /// `MigrationResult`; use `run_apply` for those.
+    ///
+    /// The stream ends after the first error, or when the runner's
+    /// [`StopHandle`] is stopped.  When it ends after applying any migration,
+    /// the context's [`post_apply`](MigrationContext::post_apply) SQL is run,
+    /// and if that fails it is the last item of the stream.
+    ///
+    /// The run lock, if there is one, is released when the stream ends, so it
+    /// should be read to the end.  If it is dropped before that, the lock is
+    /// held until the next operation of the runner that takes the lock, which
+    /// keeps it and releases it when done, or until the runner is dropped.
+    pub fn run_apply_stream(
+        &mut self,
+        target_version: Option<Version>,
+        dryrun: bool,
+    ) -> impl Stream<Item = TernResult<MigrationResult>> + Send + '_ {
+        stream::unfold(ApplyStream::Start(self), move |state| {
+            let target_version = target_version.clone();
+            async move {
+                let target_version = target_version.as_ref();
+                match state {
+                    ApplyStream::Start(runner) => {
+                        match runner
+                            .start_apply_stream(target_version, dryrun)
+                            .await
+                        {
+                            Ok(unapplied) => {
+                                let state = ApplyStream::Running {
+                                    runner,
+                                    unapplied,
+                                    next: 0,
+                                };
+                                Self::next_in_stream(
+                                    state,
+                                    target_version,
+                                    dryrun,
+                                )
+                                .await
+                            },
+                            Err(e) => Some((Err(e), ApplyStream::Done)),
+                        }
+                    },
+                    state @ ApplyStream::Running { .. } => {
+                        Self::next_in_stream(state, target_version, dryrun)
+                            .await
+                    },
+                    ApplyStream::Done => None,
+                }
+            }
+        })
+    }
+
+    // Take the lock, if not a dry run, and find the migrations to apply.
+    async fn start_apply_stream(
+        &mut self,
+        target_version: Option<&Version>,
+        dryrun: bool,
+    ) -> TernResult<MigrationSet<C>> {
+        if dryrun {
+            return self.unapplied(target_version).await;
+        }
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.unapplied(target_version).await;
+        if res.is_err() {
+            return self.unlock(res).await;
+        }
+
+        res
+    }
+
+    async fn unapplied(
+        &mut self,
+        target_version: Option<&Version>,
+    ) -> TernResult<MigrationSet<C>> {
+        let last_applied = self.validate(target_version).await?;
+
+        Ok(self.context.migration_set(last_applied))
+    }
+
+    // Apply the next migration in the stream, or release the lock if there
+    // are no more.
+    async fn next_in_stream<'a>(
+        state: ApplyStream<'a, C>,
+        target_version: Option<&Version>,
+        dryrun: bool,
+    ) -> Option<(TernResult<MigrationResult>, ApplyStream<'a, C>)> {
+        let ApplyStream::Running { runner, unapplied, next } = state else {
+            return None;
+        };
+        let migration = unapplied.migrations.get(next).filter(
+            |m| !matches!(target_version, Some(end) if C::ORDERING.cmp(&m.version(), end).is_gt()),
+        );
+        let migration = migration.filter(|_| {
+            let stopped = runner.stop.is_stopped();
+            if stopped {
+                log::warn!("run stopped, ending the stream");
+            }
+            !stopped
+        });
+        let Some(migration) = migration else {
+            if dryrun {
+                return None;
+            }
+            let res = runner.post_apply_stream(next).await;
+            return match runner.unlock(res).await {
+                Ok(()) => None,
+                Err(e) => Some((Err(e), ApplyStream::Done)),
+            };
+        };
+        match runner.apply_one(migration.as_ref(), dryrun).await {
+            Ok(result) => {
+                let result = result.with_content(runner.report_content);
+                let state =
+                    ApplyStream::Running { runner, unapplied, next: next + 1 };
+                Some((Ok(result), state))
+            },
+            Err(e) if dryrun => Some((Err(e), ApplyStream::Done)),
+            Err(e) => {
+                let res = runner.unlock(Err(e)).await;
+                Some((res, ApplyStream::Done))
+            },
+        }
+    }
+
+    /// Apply all unapplied migrations.
+    #[deprecated(since = "3.1.0", note = "use `run_apply_all`")]
+    pub async fn apply_all(&mut self) -> TernResult<Report> {
+        self.run_apply(None, false).await
+    }
+
+    /// Apply all unapplied migrations.
+    pub async fn run_apply_all(&mut self, dryrun: bool) -> TernResult<Report> {
+        self.run_apply(None, dryrun).await
+    }
+
+    /// Every row of the history table, including ones from other migration
+    /// sources, without validating them, e.g., to export the history.
+    pub async fn dump_history(&mut self) -> TernResult<Report> {
+        self.prepare().await?;
+        self.context.check_history_table().await?;
+        let history_table = self.context.history_table();
+        let applied = self
+            .context
+            .executor()
+            .get_all_applied(&history_table)
+            .await?
+            .into_iter()
+            .map(|m| {
+                let m = m.decompressed()?;
+                Ok(MigrationResult::from_applied(&m, None))
+            })
+            .collect::<TernResult<_>>()?;
+
+        self.with_content(Ok(Report::new(applied)))
+    }
+
+    /// What the history was at a point in time: the migrations that had been
+    /// applied by then, according to their `applied_at`, and the migrations in
+    /// the source now that would have been pending, in the order they are
+    /// applied in, e.g., to see what the schema was during an incident.
+    ///
+    /// Migrations that are applied later are in the report as unapplied.
+    pub async fn replay(&mut self, at: DateTime<Utc>) -> TernResult<Report> {
+        self.prepare().await?;
+        self.context.check_history_table().await?;
+        let applied = self
+            .context
+            .previously_applied()
+            .await?
+            .into_iter()
+            .filter(|m| m.applied_at <= at)
+            .collect::<Vec<_>>();
+        let results = self.applied_and_unapplied(&applied);
+
+        self.with_content(Ok(Report::new(results)))
+    }
+
+    /// Every migration in the source and in the history, in the order they
+    /// are applied in, with the ones in the history as applied and the rest
+    /// as pending, instead of comparing [`list_applied`] with the source.
+    ///
+    /// [`Report::last_applied`] is the latest applied version and
+    /// [`Report::pending`] is how many are pending.  Nothing is changed.
+    ///
+    /// [`list_applied`]: Self::list_applied
+    pub async fn status(&mut self) -> TernResult<Report> {
+        self.prepare().await?;
+        self.context.check_history_table().await?;
+        let applied = self.context.previously_applied().await?;
+        let mut results = self.applied_and_unapplied(&applied);
+        for result in &mut results {
+            if result.state == MigrationState::Unapplied {
+                result.state = MigrationState::Pending;
+            }
+        }
+
+        self.with_content(Ok(Report::new(results)))
+    }
+
+    /// The migrations that a run up to `target_version`, or of every one,
+    /// would apply, in the order it would apply them, with the query that each
+    /// one builds, e.g., to attach to a pull request for review.
+    ///
+    /// Like [`check_drift`], it only reads the history table and doesn't
+    /// create it, and nothing is locked or applied.  The query of a migration
+    /// with the annotation `tern:redact` is redacted.
+    ///
+    /// [`check_drift`]: Runner::check_drift
+    pub async fn plan(
+        &mut self,
+        target_version: Option<Version>,
+    ) -> TernResult<Plan> {
+        self.prepare().await?;
+        let last_applied = self
+            .context
+            .previously_applied()
+            .await?
+            .into_iter()
+            .map(|m| m.version)
+            .max_by(|a, b| C::ORDERING.cmp(a, b));
+        let unapplied = self.context.migration_set(last_applied.clone());
+        let mut migrations = Vec::new();
+        for migration in unapplied.migrations.iter().filter(|m| {
+            target_version
+                .as_ref()
+                .is_none_or(|end| C::ORDERING.cmp(&m.version(), end).is_le())
+        }) {
+            let query = migration.build(&mut self.context).await?;
+            migrations.push(PlannedMigration::new(
+                migration.as_ref(),
+                query.sql(),
+                migrations.len() + 1,
+            ));
+        }
+
+        Ok(Plan { last_applied, migrations })
+    }
+
+    // The results of the `applied` migrations and of the ones in the source
+    // that aren't, sorted by version.
+    fn applied_and_unapplied(
+        &mut self,
+        applied: &[AppliedMigration],
+    ) -> Vec<MigrationResult> {
+        let source = self.context.migration_set(None);
+        let unapplied = source
+            .migrations
+            .iter()
+            .filter(|m| !applied.iter().any(|a| a.version == m.version()))
+            .map(|m| MigrationResult {
+                dryrun: false,
+                ..MigrationResult::from_unapplied(m.as_ref(), &m.content())
+            });
+        let mut results = applied
+            .iter()
+            .map(|m| MigrationResult::from_applied(m, None))
+            .chain(unapplied)
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| C::ORDERING.cmp(&a.version, &b.version));
+
+        results
+    }
+
+    /// Run a custom [`Operation`] with the context, once its session is set
+    /// up, and every migration in the source.
+    pub async fn run_operation(
+        &mut self,
+        operation: &dyn Operation<C>,
+        args: &[String],
+    ) -> TernResult<()> {
+        self.prepare().await?;
+        let migrations = self.context.migration_set(None);
+        log::info!("running operation {}", operation.name());
+
+        operation.run(&mut self.context, &migrations, args).await
+    }
+
+    /// A dry run that doesn't connect to the database: every migration
+    /// embedded in the source, or the ones after `assume_applied` if the
+    /// history is assumed to end there, in the order they would be applied,
+    /// e.g., to review them where the database can't be reached.
+    ///
+    /// Nothing is validated against the history, and the content of a Rust
+    /// migration is its source file, since its query is built with the
+    /// context.
+    pub fn offline_dryrun(assume_applied: Option<&Version>) -> Report {
+        let mut migrations = C::embedded_migrations();
+        migrations.sort_by(|a, b| C::ORDERING.cmp(&a.version, &b.version));
+        let pending = migrations
+            .iter()
+            .filter(|m| {
+                assume_applied
+                    .is_none_or(|v| C::ORDERING.cmp(&m.version, v).is_gt())
+            })
+            .map(MigrationResult::from_embedded)
+            .collect();
+
+        Report::new(pending)
+    }
+
+    /// List the migrations that have already been applied.
+    pub async fn list_applied(&mut self) -> TernResult<Report> {
+        self.validate(None).await?;
+
+        let applied = self
+            .context
+            .previously_applied()
+            .await?
+            .iter()
+            .map(|m| MigrationResult::from_applied(m, None))
+            .collect::<Vec<_>>();
+        let report = Report::new(applied);
+
+        self.with_content(Ok(report))
+    }
+
+    #[deprecated(
+        since = "3.1.0",
+        note = "no valid use case for `start_version`"
+    )]
+    pub async fn soft_apply(
+        &mut self,
+        start_version: Option<i64>,
+        target_version: Option<i64>,
+    ) -> TernResult<Report> {
+        if start_version.is_some() {
+            return Err(Error::Invalid(
+                "no valid `start_version` other than the first unapplied, use `run_soft_apply`"
+                    .into(),
+            ));
+        }
+        self.run_soft_apply(target_version.map(Version::from), false).await
+    }
+
+    /// Run a "soft apply" of the migrations up to and including the specified
+    /// version.
+    ///
+    /// This means that the migration will be saved in the history table, but
     /// will not have its query applied.  This is useful in the case where you
     /// want to change migration tables, apply a patch to the current one,
     /// migrate from a different migration tool, etc.
+    ///
+    /// With [`with_soft_apply_from`](Self::with_soft_apply_from), it starts
+    /// from a version instead of the first unapplied migration.
     pub async fn run_soft_apply(
         &mut self,
-        target_version: Option<i64>,
+        target_version: Option<Version>,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        let target_version = target_version.as_ref();
+        if dryrun {
+            let res = self.soft_apply_unlocked(target_version, dryrun).await;
+            return self.with_content(res);
+        }
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.soft_apply_unlocked(target_version, dryrun).await;
+        let res = self.unlock(res).await;
+        self.with_content(res)
+    }
+
+    /// Suggest the unapplied migrations to soft apply, because the tables,
+    /// indexes, and views that they create are already in the database, for
+    /// adopting tern for a database that was migrated some other way.  See
+    /// [`inventory`](crate::inventory).
+    ///
+    /// Nothing is changed.  The plan is for an operator to confirm, and then
+    /// its `target` can be given to [`run_soft_apply`](Self::run_soft_apply).
+    pub async fn suggest_soft_apply(&mut self) -> TernResult<SoftApplyPlan> {
+        let last_applied = self.validate(None).await?;
+        let inventory =
+            Inventory::new(self.context.executor().inventory().await?);
+        log::trace!("found {} objects in the database", inventory.len());
+
+        let unapplied = self.context.migration_set(last_applied);
+        let mut queries = Vec::new();
+        for migration in &unapplied.migrations {
+            let query = migration.build(&mut self.context).await?;
+            queries.push((migration.migration_id(), query));
+        }
+
+        // Going from the last migration to the first, so that what the later
+        // ones drop is known.
+        let mut dropped = HashSet::new();
+        let mut matches = Vec::new();
+        for (id, query) in queries.into_iter().rev() {
+            let created = inventory::created_objects(query.sql())?;
+            matches
+                .push(InventoryMatch::new(id, created, &dropped, &inventory));
+            dropped.extend(inventory::dropped_objects(query.sql())?);
+        }
+        matches.reverse();
+
+        Ok(SoftApplyPlan::new(matches))
+    }
+
+    async fn soft_apply_unlocked(
+        &mut self,
+        target_version: Option<&Version>,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        let from_version = self.soft_apply_from.clone();
+        if let (Some(from), Some(end)) = (&from_version, target_version) {
+            if C::ORDERING.cmp(from, end).is_gt() {
+                return Err(Error::Invalid(format!(
+                    "soft apply from version {from} is after the target version {end}"
+                )));
+            }
+        }
+        let last_applied = self.validate(target_version).await?;
+
+        let unapplied = self.context.migration_set(last_applied);
+
+        let mut results = Vec::new();
+        for migration in &unapplied.migrations {
+            let id = migration.migration_id();
+            let ver = migration.version();
+
+            // Reached the last version, break the loop.
+            if matches!(target_version, Some(end) if C::ORDERING.cmp(&ver, end).is_gt())
+            {
+                break;
+            }
+
+            // Before the first version, skip it.
+            if let Some(from) = &from_version {
+                if C::ORDERING.cmp(&ver, from).is_lt() {
+                    log::warn!(
+                        "not soft applying {id}, which is before version {from}"
+                    );
+                    continue;
+                }
+            }
+
+            let applied = self
+                .soft_applied(migration.as_ref())
+                .await
+                .with_report(&results)?;
+            let result = MigrationResult::from_soft_applied(&applied, dryrun);
+
+            if !dryrun {
+                log::trace!("soft applying migration {id}");
+                self.context
+                    .insert_applied(&applied)
+                    .await
+                    .with_report(&results)?;
+            }
+
+            results.push(result);
+        }
+        let report = Report::new(results);
+
+        Ok(report)
+    }
+
+    /// Remove the row of one applied migration from the history, e.g., when
+    /// it was reverted by hand, so that it is unapplied again.
+    ///
+    /// Nothing is reverted in the database.  The report has the row that was
+    /// removed, which with `dryrun` is the row that would be.
+    pub async fn forget(
+        &mut self,
+        version: Version,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        if dryrun {
+            let res = self.forget_unlocked(&version, dryrun).await;
+            return self.with_content(res);
+        }
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.forget_unlocked(&version, dryrun).await;
+        let res = self.unlock(res).await;
+        self.with_content(res)
+    }
+
+    async fn forget_unlocked(
+        &mut self,
+        version: &Version,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        self.prepare().await?;
+        self.context.check_history_table().await?;
+        let applied = self.context.previously_applied().await?;
+        let Some(applied) = applied.iter().find(|m| &m.version == version)
+        else {
+            return Err(Error::Invalid(format!(
+                "version {version} has not been applied, there is nothing to forget"
+            )));
+        };
+        if !dryrun {
+            self.backup_history().await?;
+            log::trace!("removing migration {version} from history");
+            self.context.delete_applied(applied).await?;
+        }
+
+        Ok(Report::new(vec![MigrationResult::from_removed(applied, dryrun)]))
+    }
+
+    // The history row of a migration that is soft applied.
+    async fn soft_applied(
+        &mut self,
+        migration: &dyn Migration<Ctx = C>,
+    ) -> TernResult<AppliedMigration> {
+        // Build the query, which is possibly a dynamic one.
+        let query = migration.build(&mut self.context).await?;
+        // The content is set after, since only the query after the marker is
+        // kept the way the context keeps content.  The checksum is of the
+        // query, which is what the source has for a SQL migration.
+        let mut applied = migration.to_applied(0, Utc::now(), query.sql());
+        let mut content = format!("{SOFT_APPLIED}\n\n");
+        writeln!(content, "{}", history_content(migration, query.sql()))?;
+        applied.content = content;
+
+        Ok(applied)
+    }
+
+    /// Take a baseline of a database that was migrated before it had a
+    /// history table, by soft applying every migration up to and including
+    /// `version`, for introducing tern to a long-lived database.
+    ///
+    /// The row of `version` is marked as the baseline, see
+    /// [`AppliedMigration::is_baseline`].  It is an error if the history
+    /// already has migrations from this source, which is what
+    /// [`run_soft_apply`](Self::run_soft_apply) is for, or if `version` is
+    /// not in the source.  The report has the rows inserted as soft applied,
+    /// which with `dryrun` are the ones that would be.
+    pub async fn baseline(
+        &mut self,
+        version: Version,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        if dryrun {
+            let res = self.baseline_unlocked(&version, dryrun).await;
+            return self.with_content(res);
+        }
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.baseline_unlocked(&version, dryrun).await;
+        let res = self.unlock(res).await;
+        self.with_content(res)
+    }
+
+    async fn baseline_unlocked(
+        &mut self,
+        version: &Version,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        self.prepare().await?;
+        self.context.check_history_table().await?;
+        let applied = self.context.previously_applied().await?;
+        if !applied.is_empty() {
+            return Err(Error::Invalid(format!(
+                "the history already has {} migrations, a baseline is for a database without history",
+                applied.len()
+            )));
+        }
+        let source = self.context.migration_set(None);
+        if !source.migrations.iter().any(|m| &m.version() == version) {
+            return Err(Error::Invalid(format!(
+                "version {version} is not in the migration source"
+            )));
+        }
+
+        let mut results = Vec::new();
+        for migration in &source.migrations {
+            let ver = migration.version();
+            if C::ORDERING.cmp(&ver, version).is_gt() {
+                break;
+            }
+            let mut applied = self
+                .soft_applied(migration.as_ref())
+                .await
+                .with_report(&results)?;
+            let is_baseline = &ver == version;
+            if is_baseline {
+                applied.content =
+                    applied.content.replacen(SOFT_APPLIED, BASELINE, 1);
+            }
+            if !dryrun {
+                log::trace!("soft applying migration {ver} for the baseline");
+                self.context
+                    .insert_applied(&applied)
+                    .await
+                    .with_report(&results)?;
+            }
+            let mut result =
+                MigrationResult::from_soft_applied(&applied, dryrun);
+            if is_baseline {
+                result.transactional = Transactional::Other("Baseline".into());
+            }
+            results.push(result);
+        }
+
+        Ok(Report::new(results))
+    }
+
+    /// Change the history so that `version` is the latest applied version, by
+    /// soft applying the migrations up to it that are not in the history and
+    /// removing the rows of the ones after it.
+    ///
+    /// This is an escape hatch for when the history no longer says what was
+    /// done to the database, e.g., after fixing it by hand in an emergency.
+    /// No migration is applied or reverted, and validation is not run.  The
+    /// report has the rows inserted as soft applied and the rows removed as
+    /// removed, which with `dryrun` are the ones that would be.
+    pub async fn set_history_version(
+        &mut self,
+        version: Version,
+        dryrun: bool,
+    ) -> TernResult<Report> {
+        if dryrun {
+            let res = self.set_history_version_unlocked(&version, dryrun).await;
+            return self.with_content(res);
+        }
+        self.prepare().await?;
+        self.lock().await?;
+        let res = self.set_history_version_unlocked(&version, dryrun).await;
+        let res = self.unlock(res).await;
+        self.with_content(res)
+    }
+
+    async fn set_history_version_unlocked(
+        &mut self,
+        version: &Version,
         dryrun: bool,
     ) -> TernResult<Report> {
-        self.validate_source().await?;
-        let last_applied = self.context.latest_version().await?;
-        self.validate_target(last_applied, target_version)?;
+        self.prepare().await?;
+        self.context.check_history_table().await?;
+        let applied = self.context.previously_applied().await?;
+        let source = self.context.migration_set(None);
+        if !source.migrations.iter().any(|m| &m.version() == version) {
+            return Err(Error::Invalid(format!(
+                "version {version} is not in the migration source"
+            )));
+        }
+
+        if !dryrun {
+            self.backup_history().await?;
+        }
+
+        let mut results = Vec::new();
+        for migration in &source.migrations {
+            let ver = migration.version();
+            if C::ORDERING.cmp(&ver, version).is_gt()
+                || applied.iter().any(|m| m.version == ver)
+            {
+                continue;
+            }
+            let applied = self
+                .soft_applied(migration.as_ref())
+                .await
+                .with_report(&results)?;
+            if !dryrun {
+                log::trace!("soft applying migration {}", applied.version);
+                self.context
+                    .insert_applied(&applied)
+                    .await
+                    .with_report(&results)?;
+            }
+            results.push(MigrationResult::from_soft_applied(&applied, dryrun));
+        }
+
+        let mut removed = applied
+            .into_iter()
+            .filter(|m| C::ORDERING.cmp(&m.version, version).is_gt())
+            .collect::<Vec<_>>();
+        removed.sort_by(|a, b| C::ORDERING.cmp(&a.version, &b.version));
+        for applied in &removed {
+            if !dryrun {
+                log::trace!(
+                    "removing migration {} from history",
+                    applied.version
+                );
+                self.context
+                    .delete_applied(applied)
+                    .await
+                    .with_report(&results)?;
+            }
+            results.push(MigrationResult::from_removed(applied, dryrun));
+        }
+
+        Ok(Report::new(results))
+    }
+}
+
+/// How long to wait for the database to be ready and how often to check.
+///
+/// Checks are made every `interval` at first, and the time between them
+/// doubles after each failure, up to a limit of eight times `interval`.
+#[derive(Debug, Clone, Copy)]
+pub struct WaitForDb {
+    timeout: Duration,
+    interval: Duration,
+}
+
+impl WaitForDb {
+    pub fn new(timeout: Duration, interval: Duration) -> Self {
+        Self { timeout, interval }
+    }
+
+    /// Call `f` until it succeeds, or return its last error if it still hasn't
+    /// after the timeout.
+    pub async fn retry<T, F, Fut>(&self, mut f: F) -> TernResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = TernResult<T>>,
+    {
+        let mut backoff = self.backoff();
+        loop {
+            match f().await {
+                Ok(v) => return Ok(v),
+                Err(e) if !backoff.wait(&e).await => return Err(e),
+                Err(_) => {},
+            }
+        }
+    }
+
+    fn backoff(&self) -> Backoff {
+        Backoff {
+            deadline: Instant::now() + self.timeout,
+            delay: self.interval,
+            max_delay: self.interval * 8,
+        }
+    }
+}
+
+struct Backoff {
+    deadline: Instant,
+    delay: Duration,
+    max_delay: Duration,
+}
+
+impl Backoff {
+    // Sleep until the next attempt, or return `false` if there isn't time for
+    // one.
+    async fn wait(&mut self, e: &Error) -> bool {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return false;
+        }
+        let delay = self.delay.min(self.deadline - now);
+        log::debug!(
+            "database not ready, retrying in {delay:?}: {}",
+            redact(&e.to_string())
+        );
+        futures_timer::Delay::new(delay).await;
+        self.delay = (self.delay * 2).min(self.max_delay);
+        true
+    }
+}
+
+/// Stops a run of the [`Runner`] it came from after the migration that is
+/// being applied, see [`Runner::stop_handle`].
+///
+/// It can be cloned and sent to another task, such as one waiting for a
+/// shutdown signal:
+///
+/// ```rust,ignore
+/// let stop = runner.stop_handle();
+/// tokio::spawn(async move {
+///     tokio::signal::ctrl_c().await.unwrap();
+///     stop.stop();
+/// });
+/// let report = runner.run_apply_all(false).await?;
+/// if report.interrupted() {
+///     // Some migrations are left for the next run.
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    /// Stop the run.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the run has been stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// How long the parts of a run took, from [`Runner::timings`], for
+/// observability pipelines that want more than the report without parsing
+/// the logs.
+#[derive(Debug, Clone, Default)]
+pub struct RunTimings {
+    total: Duration,
+    validation: Duration,
+    lock_wait: Duration,
+    migrations: Vec<(MigrationId, Duration)>,
+    history_rows: usize,
+}
+
+impl RunTimings {
+    /// How long the whole run took.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// How long validating the migrations against the history took.
+    pub fn validation(&self) -> Duration {
+        self.validation
+    }
+
+    /// How long the run waited for the run lock, which is zero without
+    /// [`Runner::with_lock`].
+    pub fn lock_wait(&self) -> Duration {
+        self.lock_wait
+    }
+
+    /// How long each migration that was applied took, in the order they were
+    /// applied.
+    pub fn migrations(&self) -> &[(MigrationId, Duration)] {
+        &self.migrations
+    }
+
+    /// How many rows of the history table, and of the table of repeatable
+    /// migrations, were inserted or replaced.
+    pub fn history_rows(&self) -> usize {
+        self.history_rows
+    }
+}
+
+/// A formatted version of a collection of migrations.
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty, Default)]
+pub struct Report {
+    migrations: Vec<MigrationResult>,
+    // A boxed slice for the same reason as `error`, since it is rarely
+    // changed once the run is done.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    repeatable: Box<[RepeatableResult]>,
+    #[serde(skip_serializing_if = "Flags::is_empty")]
+    flags: Flags,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    deadline_exceeded: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    interrupted: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<Warning>,
+    // Boxed, since a report is in an `Error::Partial`, which would make every
+    // `TernResult` bigger.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Box<ReportError>>,
+}
+
+/// The error that stopped a run, in a [`Report`], or that a migration in it
+/// failed with.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportError {
+    /// The stable code of the error, see [`Error::code`].
+    pub code: &'static str,
+    /// The error message.
+    pub message: String,
+}
+
+impl ReportError {
+    fn new(error: &Error) -> Self {
+        Self { code: error.code(), message: error.to_string() }
+    }
+}
+
+/// A problem that did not stop the run, in a [`Report`] or in the
+/// [`MigrationResult`] of the migration it came from, so that automation sees
+/// it and not only the logs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    /// What found the problem.
+    pub kind: WarningKind,
+    /// The migrations that it is about, if it is about particular ones.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub migrations: Vec<MigrationId>,
+    /// What the problem is.
+    pub message: String,
+}
+
+impl Warning {
+    /// A warning of this kind about the migrations.
+    pub fn new(
+        kind: WarningKind,
+        migrations: Vec<MigrationId>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self { kind, migrations, message: message.into() }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+/// What found a [`Warning`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// A validation rule failed with the severity [`Severity::Warn`], which is
+    /// how a [`Linter`] that is a rule of the runner warns too.
+    Validation,
+    /// A statement behaves differently than a migration might expect on the
+    /// database, like one that MySQL commits the transaction for.
+    Dialect,
+    /// The database or executor couldn't do something that the migration
+    /// asked for, like setting a lock timeout, and it was applied without it.
+    Backend,
+    /// The migration failed and was tried again.
+    Retry,
+}
+
+impl std::fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Validation => write!(f, "validation"),
+            Self::Dialect => write!(f, "dialect"),
+            Self::Backend => write!(f, "backend"),
+            Self::Retry => write!(f, "retry"),
+        }
+    }
+}
+
+impl Report {
+    pub fn new(migrations: Vec<MigrationResult>) -> Self {
+        Self {
+            migrations,
+            repeatable: Box::default(),
+            flags: Flags::default(),
+            deadline_exceeded: false,
+            interrupted: false,
+            warnings: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// The report of a run that stopped with this error, which is the report
+    /// of an [`Error::Partial`] or an empty one, with the code and message of
+    /// the error.
+    pub fn from_error(error: &Error) -> Self {
+        // The message of a partial run would have the report in it again.
+        let (report, message) = match error {
+            Error::Partial { source, report } => {
+                (report.clone(), redact(&source.to_string()))
+            },
+            e => (Self::default(), redact(&e.to_string())),
+        };
+        let error = ReportError { code: error.code(), message };
+
+        Self { error: Some(Box::new(error)), ..report }
+    }
+
+    /// The report of this run followed by `other`, like the runs of the same
+    /// migrations against several shards, as one report.
+    ///
+    /// It has the migrations and warnings of both, the flags of both with the
+    /// ones of `other` taking the place of ones with the same name, and the
+    /// error of this run, or if it has none, of `other`.  It stopped early if
+    /// either did.  For what happened to each target, see
+    /// [`RunSummary`](crate::run_log::RunSummary).
+    pub fn merge(mut self, other: Report) -> Self {
+        self.migrations.extend(other.migrations);
+        let mut repeatable = self.repeatable.into_vec();
+        repeatable.extend(other.repeatable.into_vec());
+        self.repeatable = repeatable.into();
+        self.flags.extend(other.flags);
+        self.deadline_exceeded |= other.deadline_exceeded;
+        self.interrupted |= other.interrupted;
+        self.warnings.extend(other.warnings);
+        self.error = self.error.or(other.error);
+        self
+    }
+
+    /// The feature flags of the run, which the queries of Rust migrations in
+    /// the report may have been built with.
+    pub fn flags(&self) -> &Flags {
+        &self.flags
+    }
+
+    /// The error that stopped the run, if the report is from one.
+    pub fn error(&self) -> Option<&ReportError> {
+        self.error.as_deref()
+    }
+
+    /// The problems that didn't stop the run and that were found before
+    /// applying the migrations, like by a validation rule with the severity
+    /// [`Severity::Warn`].  The ones from applying a migration are in its
+    /// result, see [`MigrationResult::warnings`].
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    // The warnings of the run followed by the ones of each migration.
+    pub(crate) fn all_warnings(&self) -> impl Iterator<Item = &Warning> {
+        self.warnings
+            .iter()
+            .chain(self.migrations.iter().flat_map(|r| &r.warnings))
+    }
+
+    fn with_deadline_exceeded(mut self) -> Self {
+        self.deadline_exceeded = true;
+        self
+    }
+
+    /// Whether the run stopped early because its deadline passed, leaving
+    /// migrations unapplied.
+    ///
+    /// See [`Runner::with_deadline`].
+    pub fn deadline_exceeded(&self) -> bool {
+        self.deadline_exceeded
+    }
+
+    fn with_interrupted(mut self) -> Self {
+        self.interrupted = true;
+        self
+    }
+
+    /// Whether the run was stopped early with a [`StopHandle`], leaving
+    /// migrations unapplied.
+    pub fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    pub fn count(&self) -> usize {
+        self.migrations.len()
+    }
+
+    /// The number of migrations in the report that are pending, which are the
+    /// ones not applied yet in the report of [`Runner::status`].
+    pub fn pending(&self) -> usize {
+        self.migrations
+            .iter()
+            .filter(|r| r.state == MigrationState::Pending)
+            .count()
+    }
+
+    /// The version of the last migration in the report that is applied, which
+    /// in the report of [`Runner::status`] is the latest applied version.
+    pub fn last_applied(&self) -> Option<&Version> {
+        self.migrations
+            .iter()
+            .rev()
+            .find(|r| {
+                matches!(
+                    r.state,
+                    MigrationState::Applied | MigrationState::SoftApplied
+                )
+            })
+            .map(|r| &r.version)
+    }
+
+    /// The number of migrations in the report that failed, see
+    /// [`MigrationResult::error`].
+    pub fn failures(&self) -> usize {
+        self.migrations.iter().filter(|r| r.error.is_some()).count()
+    }
+
+    /// Return the vector of results.
+    pub fn results(&self) -> Vec<MigrationResult> {
+        self.migrations.clone()
+    }
+
+    /// The results of the repeatable migrations that were applied after the
+    /// versioned ones, or that would be in a dry run.
+    pub fn repeatable_results(&self) -> &[RepeatableResult] {
+        &self.repeatable
+    }
+
+    /// Return an iterator of the migration results.
+    pub fn iter_results(&self) -> impl Iterator<Item = MigrationResult> {
+        self.migrations.clone().into_iter()
+    }
+
+    /// The report as a self-contained HTML page, with a table of the
+    /// migrations that can be sorted by clicking a column and the SQL of each
+    /// one in a collapsible section.
+    pub fn to_html(&self) -> String {
+        html::render(self)
+    }
+
+    /// The report as a Markdown table of the version, description, state, and
+    /// duration of each migration, to post in a comment on a pull request.
+    pub fn to_markdown(&self) -> String {
+        markdown::render(self)
+    }
+
+    /// The report as JUnit XML, a test suite with a test case for each
+    /// migration, for CI systems to show like the results of tests.
+    pub fn to_junit(&self) -> String {
+        junit::render(self, None)
+    }
+
+    /// The report as JUnit XML, with the error that stopped the run as a
+    /// failed test case for the migration that it came from.
+    ///
+    /// The report of an [`Error::Partial`] is the migrations that were applied
+    /// before it.
+    pub fn to_junit_with_error(&self, error: &Error) -> String {
+        junit::render(self, Some(error))
+    }
+
+    /// The report as CSV, with a header and a row for each migration, for
+    /// loading it into a spreadsheet or another tool.
+    pub fn to_csv(&self) -> String {
+        csv::render(self)
+    }
+
+    /// The report without the content of each migration, which can be most
+    /// of a report, for when only the rest of it is needed.
+    pub fn without_content(self) -> Self {
+        self.with_content(ReportContent::Omit)
+    }
+
+    /// The report with as much of the content of each migration as `content`
+    /// says.
+    pub fn with_content(mut self, content: ReportContent) -> Self {
+        self.migrations = self
+            .migrations
+            .into_iter()
+            .map(|result| result.with_content(content))
+            .collect();
+        self
+    }
+
+    /// The report in the format.
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Json => self.to_string(),
+            ReportFormat::Html => self.to_html(),
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Junit => self.to_junit(),
+            ReportFormat::Csv => self.to_csv(),
+        }
+    }
+}
+
+/// How much of the content of each migration a [`Report`] has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportContent {
+    /// All of it.
+    #[default]
+    Full,
+    /// The first this many lines, followed by a line `... N more lines` when
+    /// there are more.
+    Lines(usize),
+    /// None of it.
+    Omit,
+}
 
-        let unapplied = self.context.migration_set(last_applied);
+/// A format to render a [`Report`] in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Pretty-printed JSON, which is how a report is displayed.
+    #[default]
+    Json,
+    /// An HTML page, see [`Report::to_html`].
+    Html,
+    /// A Markdown table, see [`Report::to_markdown`].
+    Markdown,
+    /// JUnit XML, see [`Report::to_junit`].
+    Junit,
+    /// CSV, see [`Report::to_csv`].
+    Csv,
+}
 
-        let mut results = Vec::new();
-        for migration in &unapplied.migrations {
-            let id = migration.migration_id();
-            let ver = migration.version();
+impl ReportFormat {
+    /// The names that `ReportFormat` is parsed from.
+    pub const VARIANTS: [&str; 5] =
+        ["json", "html", "markdown", "junit", "csv"];
+}
 
-            // Reached the last version, break the loop.
-            if matches!(target_version, Some(end) if ver > end) {
-                break;
-            }
+impl FromStr for ReportFormat {
+    type Err = Error;
 
-            // Build each query, which possibly includes dynamic ones.
-            let query = migration
-                .build(&mut self.context)
-                .await
-                .with_report(&results)?;
-            let mut content = String::from("-- SOFT APPLIED:\n\n");
-            writeln!(content, "{query}")?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "html" => Ok(Self::Html),
+            "markdown" => Ok(Self::Markdown),
+            "junit" => Ok(Self::Junit),
+            "csv" => Ok(Self::Csv),
+            _ => Err(Error::Invalid(format!("unknown report format {s}"))),
+        }
+    }
+}
 
-            let applied = migration.to_applied(0, Utc::now(), &content);
-            let result = MigrationResult::from_soft_applied(&applied, dryrun);
+/// A bundle of runner options for a kind of environment, so that it has safe
+/// defaults without setting each one, see [`Runner::with_preset`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Checking the migrations in CI, against a database that is thrown away
+    /// after: an applied migration that changed is an error, the unapplied
+    /// SQL migrations are linted, and reports are JUnit.
+    CiCheck,
+    /// Applying the migrations to production: the run lock is held, a
+    /// statement waits at most 5 seconds for a lock, an applied migration that
+    /// changed is an error, the unapplied SQL migrations are linted, and
+    /// reports are JSON with the first 20 lines of each migration.
+    ProdApply,
+    /// Applying the migrations to a developer's database, which is often
+    /// behind or ahead of the source: the validation rules for the history
+    /// only warn, there is no lock, and reports have none of the content.
+    DevFast,
+}
 
-            if !dryrun {
-                log::trace!("soft applying migration {id}");
-                self.context
-                    .insert_applied(&applied)
-                    .await
-                    .with_report(&results)?;
-            }
+impl Preset {
+    /// The names that `Preset` is parsed from.
+    pub const VARIANTS: [&str; 3] = ["ci-check", "prod-apply", "dev-fast"];
 
-            results.push(result);
+    /// The format of the reports for this environment, if there is one, for
+    /// the CLI to print them in.
+    pub fn report_format(self) -> Option<ReportFormat> {
+        match self {
+            Self::CiCheck => Some(ReportFormat::Junit),
+            Self::ProdApply => Some(ReportFormat::Json),
+            Self::DevFast => None,
         }
-        let report = Report::new(results);
+    }
 
-        Ok(report)
+    /// How much of each migration the reports have.
+    pub fn report_content(self) -> ReportContent {
+        match self {
+            Self::CiCheck => ReportContent::Full,
+            Self::ProdApply => ReportContent::Lines(20),
+            Self::DevFast => ReportContent::Omit,
+        }
     }
 }
 
-/// A formatted version of a collection of migrations.
+impl FromStr for Preset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ci-check" => Ok(Self::CiCheck),
+            "prod-apply" => Ok(Self::ProdApply),
+            "dev-fast" => Ok(Self::DevFast),
+            _ => Err(Error::Invalid(format!("unknown preset {s}"))),
+        }
+    }
+}
+
+/// How the history of a database differs from the source, returned by
+/// [`Runner::check_drift`].
 #[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty, Default)]
-pub struct Report {
-    migrations: Vec<MigrationResult>,
+pub struct DriftReport {
+    /// Migrations in the source that have not been applied.
+    pub unapplied: Vec<MigrationId>,
+    /// Applied migrations that are not in the source.
+    pub unknown: Vec<MigrationId>,
+    /// Applied SQL migrations that have changed in the source since.
+    pub changed: Vec<ChangedMigration>,
 }
 
-impl Report {
-    pub fn new(migrations: Vec<MigrationResult>) -> Self {
-        Self { migrations }
+impl DriftReport {
+    /// Whether the database and the source are the same.
+    pub fn is_empty(&self) -> bool {
+        self.unapplied.is_empty()
+            && self.unknown.is_empty()
+            && self.changed.is_empty()
     }
 
+    /// The number of migrations that differ.
     pub fn count(&self) -> usize {
-        self.migrations.len()
+        self.unapplied.len() + self.unknown.len() + self.changed.len()
     }
+}
 
-    /// Return the vector of results.
-    pub fn results(&self) -> Vec<MigrationResult> {
-        self.migrations.clone()
+/// The history table and what is in it, returned by
+/// [`Runner::describe_history`].
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
+pub struct HistorySummary {
+    /// The name of the history table.
+    pub table: String,
+    /// The number of rows in it from this migration source.
+    pub rows: usize,
+    /// The number of rows in it from other migration sources sharing it, which
+    /// [`Runner::drop_history`] keeps along with the table.
+    pub other_rows: usize,
+    /// The latest version applied from this source, if there is one.
+    pub latest_version: Option<Version>,
+}
+
+/// The migrations that would be applied and their queries, returned by
+/// [`Runner::plan`].
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
+pub struct Plan {
+    /// The latest version applied, which the plan starts after.
+    pub last_applied: Option<Version>,
+    /// The migrations to apply, in the order they would be applied.
+    pub migrations: Vec<PlannedMigration>,
+}
+
+impl Plan {
+    /// Whether there is nothing to apply.
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
     }
 
-    /// Return an iterator of the migration results.
-    pub fn iter_results(&self) -> impl Iterator<Item = MigrationResult> {
-        self.migrations.clone().into_iter()
+    /// The versions to apply, in order.
+    pub fn versions(&self) -> Vec<Version> {
+        self.migrations.iter().map(|m| m.version.clone()).collect()
+    }
+}
+
+/// A migration in a [`Plan`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedMigration {
+    /// Its position in the order the migrations are applied in, from 1.
+    pub order: usize,
+    /// The version of the migration.
+    pub version: Version,
+    /// The description of the migration.
+    pub description: String,
+    /// Whether it is applied outside of a transaction.
+    pub no_tx: bool,
+    /// The query that it built.
+    pub sql: String,
+}
+
+impl PlannedMigration {
+    fn new<M>(migration: &M, sql: &str, order: usize) -> Self
+    where
+        M: Migration + ?Sized,
+    {
+        let sql = if migration.annotations().redact() {
+            redacted(sql)
+        } else {
+            sql.to_string()
+        };
+
+        Self {
+            order,
+            version: migration.version(),
+            description: migration.migration_id().description(),
+            no_tx: migration.no_tx(),
+            sql,
+        }
+    }
+}
+
+/// The problems with the rows of the history table, returned by
+/// [`Runner::verify_history`].
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty, Default)]
+pub struct HistoryVerification {
+    /// The number of rows from this source that were checked.
+    pub rows: usize,
+    /// Versions that have more than one row.
+    pub duplicates: Vec<DuplicateVersion>,
+    /// Rows without a checksum of what was applied.
+    pub missing_checksums: Vec<MissingChecksum>,
+    /// Rows applied before a row with an earlier version.
+    pub inversions: Vec<AppliedAtInversion>,
+}
+
+impl HistoryVerification {
+    /// Check the rows of the history table from one source, whose versions
+    /// are in the order of `ordering`.
+    pub fn new(
+        mut rows: Vec<AppliedMigration>,
+        ordering: &dyn MigrationOrdering,
+    ) -> Self {
+        rows.sort_by(|a, b| {
+            ordering
+                .cmp(&a.version, &b.version)
+                .then_with(|| a.applied_at.cmp(&b.applied_at))
+        });
+        let id = |row: &AppliedMigration| {
+            MigrationId::new(row.version.clone(), row.description.clone())
+        };
+
+        let duplicates = rows
+            .chunk_by(|a, b| a.version == b.version)
+            .filter(|rows| rows.len() > 1)
+            .map(|rows| DuplicateVersion {
+                version: rows[0].version.clone(),
+                descriptions: rows
+                    .iter()
+                    .map(|r| r.description.clone())
+                    .collect(),
+            })
+            .collect();
+
+        // The content of a migration that failed is the error, which has no
+        // checksum to begin with.
+        let missing_checksums = rows
+            .iter()
+            .filter(|row| !row.content.starts_with(FAILED))
+            .filter_map(|row| {
+                let reason = match row.clone().decompressed() {
+                    Err(e) => e.to_string(),
+                    Ok(row) if row.has_checksum() => return None,
+                    Ok(row) if row.is_redacted() || row.is_checksum_only() => {
+                        "the placeholder for the content has no checksum".into()
+                    },
+                    Ok(_) => "the content is empty".into(),
+                };
+                Some(MissingChecksum { id: id(row), reason })
+            })
+            .collect();
+
+        let mut inversions = Vec::new();
+        let mut latest: Option<&AppliedMigration> = None;
+        for row in &rows {
+            match latest {
+                Some(prev) if row.applied_at < prev.applied_at => {
+                    inversions.push(AppliedAtInversion {
+                        id: id(row),
+                        applied_at: row.applied_at,
+                        preceding: id(prev),
+                        preceding_applied_at: prev.applied_at,
+                    });
+                },
+                _ => latest = Some(row),
+            }
+        }
+
+        Self { rows: rows.len(), duplicates, missing_checksums, inversions }
+    }
+
+    /// Whether no problem was found.
+    pub fn is_empty(&self) -> bool {
+        self.duplicates.is_empty()
+            && self.missing_checksums.is_empty()
+            && self.inversions.is_empty()
+    }
+
+    /// The number of problems that were found.
+    pub fn count(&self) -> usize {
+        self.duplicates.len()
+            + self.missing_checksums.len()
+            + self.inversions.len()
     }
 }
 
+/// A version that has more than one row in the history table.
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
+pub struct DuplicateVersion {
+    /// The version.
+    pub version: Version,
+    /// The description in each of its rows, in the order they were applied.
+    pub descriptions: Vec<String>,
+}
+
+/// A row of the history table without a checksum of what was applied.
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
+pub struct MissingChecksum {
+    /// The migration.
+    pub id: MigrationId,
+    /// Why there is no checksum.
+    pub reason: String,
+}
+
+/// A row of the history table that was applied before the row of a migration
+/// that comes before it.
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
+pub struct AppliedAtInversion {
+    /// The migration.
+    pub id: MigrationId,
+    /// When it was applied.
+    pub applied_at: DateTime<Utc>,
+    /// The migration with an earlier version that was applied after it.
+    pub preceding: MigrationId,
+    /// When that one was applied.
+    pub preceding_applied_at: DateTime<Utc>,
+}
+
+/// An applied migration whose source has changed.
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
+pub struct ChangedMigration {
+    /// The migration.
+    pub id: MigrationId,
+    /// The checksum of the source file now.
+    pub checksum: String,
+    /// The checksum of the content that was applied.
+    pub applied_checksum: String,
+}
+
 /// A formatted version of a migration that is the return type for `Runner`
 /// actions.
 #[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
 #[allow(dead_code)]
 pub struct MigrationResult {
     dryrun: bool,
-    version: i64,
+    version: Version,
     state: MigrationState,
     applied_at: Option<DateTime<Utc>>,
     description: String,
-    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
     transactional: Transactional,
     duration_ms: RunDuration,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    locks: Vec<StatementLock>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    analyzed: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    steps: Vec<StepResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ReportError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<Warning>,
 }
 
 impl MigrationResult {
+    /// The locks that the statements of the migration would take, which dry
+    /// runs on Postgres find with [`locks::analyze`].
+    pub fn locks(&self) -> &[StatementLock] {
+        &self.locks
+    }
+
+    /// The tables whose statistics were refreshed after the migration, with
+    /// [`Runner::with_analyze`] or the annotation `tern:analyze`, or that
+    /// would be in a dry run.
+    pub fn analyzed(&self) -> &[String] {
+        &self.analyzed
+    }
+
+    /// The statements of the migration, with the state and duration of each,
+    /// when they are applied one at a time, which is how a migration that is
+    /// not applied in a transaction is, and one that is with
+    /// [`Runner::with_split_statements`].
+    pub fn steps(&self) -> &[StepResult] {
+        &self.steps
+    }
+
+    /// The error of a migration that failed and was allowed to, with the
+    /// annotation `tern:allowFailure`, or that failed in a run that continues
+    /// after errors.
+    pub fn error(&self) -> Option<&ReportError> {
+        self.error.as_ref()
+    }
+
+    /// How many times the migration was attempted, for one that has the
+    /// annotation `tern:retries` or `tern:concurrentIndexRetries`.
+    pub fn attempts(&self) -> Option<u32> {
+        self.attempts
+    }
+
+    /// The problems that didn't stop the migration from being applied, like
+    /// a lock timeout that the database doesn't support, or a retry.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    fn with_warnings(mut self, warnings: Vec<Warning>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    fn with_steps(mut self, steps: Vec<StepResult>) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    // The statements of a migration with the annotation `tern:redact` are
+    // replaced like its content.
+    fn redact_statements<M>(mut self, migration: &M) -> Self
+    where
+        M: Migration + ?Sized,
+    {
+        if migration.annotations().redact() {
+            for step in &mut self.steps {
+                step.statement = redacted(&step.statement);
+            }
+            for lock in &mut self.locks {
+                lock.statement = redacted(&lock.statement);
+            }
+        }
+        self
+    }
+
+    fn with_content(mut self, content: ReportContent) -> Self {
+        self.content = match (content, self.content) {
+            (ReportContent::Omit, _) | (_, None) => None,
+            (ReportContent::Full, full) => full,
+            (ReportContent::Lines(n), Some(full)) => {
+                let total = full.lines().count();
+                if total <= n {
+                    Some(full)
+                } else {
+                    let mut lines = full.lines().take(n).collect::<Vec<_>>();
+                    let more = format!("... {} more lines", total - n);
+                    lines.push(&more);
+                    Some(lines.join("\n"))
+                }
+            },
+        };
+        self
+    }
+
     pub(crate) fn from_applied(
         applied: &AppliedMigration,
         no_tx: Option<bool>,
     ) -> Self {
         Self {
             dryrun: false,
-            version: applied.version,
+            version: applied.version.clone(),
             state: MigrationState::Applied,
             applied_at: Some(applied.applied_at),
             description: applied.description.clone(),
-            content: applied.content.clone(),
+            content: Some(applied.content.clone()),
             transactional: no_tx.map(Transactional::from_boolean).unwrap_or(
                 Transactional::Other("Previously applied".to_string()),
             ),
             duration_ms: RunDuration::Duration(applied.duration_ms),
+            locks: Vec::new(),
+            analyzed: Vec::new(),
+            steps: Vec::new(),
+            error: None,
+            attempts: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_failed(
+        applied: &AppliedMigration,
+        no_tx: bool,
+        error: &Error,
+    ) -> Self {
+        Self {
+            state: MigrationState::Failed,
+            error: Some(ReportError::new(error)),
+            ..Self::from_applied(applied, Some(no_tx))
+        }
+    }
+
+    // A migration that failed in a run that continues after errors, so it is
+    // not in the history.
+    fn from_error<M>(migration: &M, dryrun: bool, error: &Error) -> Self
+    where
+        M: Migration + ?Sized,
+    {
+        Self {
+            dryrun,
+            state: MigrationState::Failed,
+            error: Some(ReportError::new(error)),
+            ..Self::from_unapplied(migration, &migration.content())
+        }
+    }
+
+    // A migration that was not applied because one it depends on failed.
+    fn skipped<M>(migration: &M, dryrun: bool) -> Self
+    where
+        M: Migration + ?Sized,
+    {
+        Self {
+            dryrun,
+            state: MigrationState::Skipped,
+            ..Self::from_unapplied(migration, &migration.content())
         }
     }
 
@@ -304,13 +3406,64 @@ impl MigrationResult {
     ) -> Self {
         Self {
             dryrun,
-            version: applied.version,
+            version: applied.version.clone(),
             state: MigrationState::SoftApplied,
             applied_at: Some(applied.applied_at),
             description: applied.description.clone(),
-            content: applied.content.clone(),
+            content: Some(applied.content.clone()),
             transactional: Transactional::Other("Soft applied".to_string()),
             duration_ms: RunDuration::Duration(applied.duration_ms),
+            locks: Vec::new(),
+            analyzed: Vec::new(),
+            steps: Vec::new(),
+            error: None,
+            attempts: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_removed(
+        applied: &AppliedMigration,
+        dryrun: bool,
+    ) -> Self {
+        Self {
+            dryrun,
+            state: MigrationState::Removed,
+            ..Self::from_applied(applied, None)
+        }
+    }
+
+    // A migration that was applied and then rolled back, which is what the
+    // state says instead of it being a dry run.
+    fn set_rolled_back(&mut self, duration: Duration) {
+        self.dryrun = false;
+        self.state = MigrationState::RolledBack;
+        self.duration_ms = RunDuration::Duration(duration.as_millis() as i64);
+    }
+
+    // A migration that would be applied, from what is known about it without
+    // a context.
+    pub(crate) fn from_embedded(migration: &EmbeddedMigration) -> Self {
+        let content = if Annotations::parse(migration.content).redact() {
+            redacted(migration.content)
+        } else {
+            migration.content.to_string()
+        };
+        Self {
+            dryrun: true,
+            version: migration.version.clone(),
+            state: MigrationState::Unapplied,
+            applied_at: None,
+            description: migration.description.to_string(),
+            content: Some(content),
+            transactional: Transactional::from_boolean(migration.no_tx),
+            duration_ms: RunDuration::Unapplied,
+            locks: Vec::new(),
+            analyzed: Vec::new(),
+            steps: Vec::new(),
+            error: None,
+            attempts: None,
+            warnings: Vec::new(),
         }
     }
 
@@ -318,15 +3471,114 @@ impl MigrationResult {
     where
         M: Migration + ?Sized,
     {
+        let content = if migration.annotations().redact() {
+            redacted(content)
+        } else {
+            content.to_string()
+        };
         Self {
             dryrun: true,
             version: migration.version(),
             state: MigrationState::Unapplied,
             applied_at: None,
             description: migration.migration_id().description(),
-            content: content.into(),
+            content: Some(content),
             transactional: Transactional::from_boolean(migration.no_tx()),
             duration_ms: RunDuration::Unapplied,
+            locks: Vec::new(),
+            analyzed: Vec::new(),
+            steps: Vec::new(),
+            error: None,
+            attempts: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// A statement of a migration that is applied by itself.
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
+pub struct StepResult {
+    statement: String,
+    state: MigrationState,
+    duration_ms: RunDuration,
+}
+
+impl StepResult {
+    /// The statement.
+    pub fn statement(&self) -> &str {
+        &self.statement
+    }
+
+    fn applied(step: Step) -> Self {
+        Self {
+            statement: step.statement,
+            state: MigrationState::Applied,
+            duration_ms: RunDuration::Duration(step.duration.as_millis() as i64),
+        }
+    }
+
+    fn unapplied(statement: String) -> Self {
+        Self {
+            statement,
+            state: MigrationState::Unapplied,
+            duration_ms: RunDuration::Unapplied,
+        }
+    }
+}
+
+/// A repeatable migration that was applied because it is new or changed, in a
+/// [`Report`].
+#[derive(Clone, Serialize, DebugAsJson, DisplayAsJsonPretty)]
+pub struct RepeatableResult {
+    dryrun: bool,
+    description: String,
+    filename: String,
+    checksum: String,
+    state: MigrationState,
+    applied_at: Option<DateTime<Utc>>,
+    transactional: Transactional,
+    duration_ms: RunDuration,
+}
+
+impl RepeatableResult {
+    /// The description of the migration.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The name of the source file of the migration.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// The checksum of the content that it was applied with.
+    pub fn checksum(&self) -> &str {
+        &self.checksum
+    }
+
+    fn unapplied(migration: &RepeatableMigration) -> Self {
+        Self {
+            dryrun: true,
+            description: migration.description.to_string(),
+            filename: migration.filename.to_string(),
+            checksum: migration.checksum.to_string(),
+            state: MigrationState::Unapplied,
+            applied_at: None,
+            transactional: Transactional::from_boolean(migration.no_tx),
+            duration_ms: RunDuration::Unapplied,
+        }
+    }
+
+    fn applied(
+        migration: &RepeatableMigration,
+        applied: &AppliedRepeatable,
+    ) -> Self {
+        Self {
+            dryrun: false,
+            state: MigrationState::Applied,
+            applied_at: Some(applied.applied_at),
+            duration_ms: RunDuration::Duration(applied.duration_ms),
+            ..Self::unapplied(migration)
         }
     }
 }
@@ -336,6 +3588,11 @@ enum MigrationState {
     Applied,
     SoftApplied,
     Unapplied,
+    Pending,
+    RolledBack,
+    Removed,
+    Failed,
+    Skipped,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -376,6 +3633,11 @@ impl std::fmt::Display for MigrationState {
             Self::Applied => write!(f, "Applied"),
             Self::SoftApplied => write!(f, "Soft Applied"),
             Self::Unapplied => write!(f, "Not Applied"),
+            Self::Pending => write!(f, "Pending"),
+            Self::RolledBack => write!(f, "Rolled Back"),
+            Self::Removed => write!(f, "Removed"),
+            Self::Failed => write!(f, "Failed"),
+            Self::Skipped => write!(f, "Skipped"),
         }
     }
 }
@@ -389,115 +3651,139 @@ impl std::fmt::Display for RunDuration {
     }
 }
 
-// Migrations that have been applied already but do not exist locally.
-fn check_migrations_in_sync(
-    applied: HashSet<MigrationId>,
-    source: HashSet<MigrationId>,
-) -> TernResult<()> {
-    let source_not_found: Vec<&MigrationId> =
-        applied.difference(&source).collect();
+#[cfg(test)]
+mod tests {
+    use super::{
+        HistoryVerification, MigrationResult, MigrationState, Preset, Report,
+        ReportContent, ReportFormat,
+    };
+    use crate::migration::{
+        AppliedMigration, EmbeddedMigration, MigrationId, NumericOrdering,
+        Version,
+    };
 
-    if !source_not_found.is_empty() {
-        return Err(Error::OutOfSync {
-            at_issue: source_not_found.into_iter().cloned().collect(),
-            msg: "version/name applied but missing in source".into(),
-        });
-    }
+    use chrono::{Duration, Utc};
 
-    Ok(())
-}
+    #[test]
+    fn truncate_content() {
+        let id = MigrationId::new(Version::Integer(1), "load".into());
+        let applied = AppliedMigration::new(
+            id,
+            "INSERT 1;\nINSERT 2;\nINSERT 3;\n",
+            1,
+            Utc::now(),
+        );
+        let report =
+            Report::new(vec![MigrationResult::from_applied(&applied, None)]);
+        let content = |report: Report| report.migrations[0].content.clone();
 
-#[cfg(test)]
-mod tests {
-    use super::Error;
-    use super::MigrationId;
+        assert_eq!(
+            content(report.clone().with_content(ReportContent::Lines(1))),
+            Some("INSERT 1;\n... 2 more lines".to_string())
+        );
+        assert_eq!(
+            content(report.clone().with_content(ReportContent::Lines(3))),
+            Some("INSERT 1;\nINSERT 2;\nINSERT 3;\n".to_string())
+        );
+        assert_eq!(content(report.with_content(ReportContent::Omit)), None);
+    }
 
-    use std::collections::HashSet;
+    #[test]
+    fn redact_embedded_content() {
+        let migration = EmbeddedMigration {
+            version: Version::Integer(1),
+            description: "create_role",
+            filename: "V1__create_role.sql",
+            no_tx: false,
+            checksum: "",
+            content: "-- tern:redact\nCREATE ROLE app PASSWORD 'secret';\n",
+        };
+        let result = MigrationResult::from_embedded(&migration);
+        assert!(result.dryrun);
+        assert!(!result.content.unwrap().contains("secret"));
+    }
 
     #[test]
-    fn missing_source() {
-        let source: HashSet<MigrationId> = vec![
-            MigrationId::new(1, "first".into()),
-            MigrationId::new(2, "second".into()),
-            MigrationId::new(3, "fourth".into()),
-        ]
-        .into_iter()
-        .collect();
-        let applied: HashSet<MigrationId> = vec![
-            MigrationId::new(1, "first".into()),
-            MigrationId::new(2, "second".into()),
-            MigrationId::new(3, "third".into()),
-        ]
-        .into_iter()
-        .collect();
-        let missing = vec![MigrationId::new(3, "third".into())];
-        let result = super::check_migrations_in_sync(applied, source);
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(
-            matches!(err, Error::OutOfSync { at_issue, .. } if *at_issue == missing)
+    fn status_counts() {
+        let applied = AppliedMigration::new(
+            MigrationId::new(Version::Integer(1), "users".into()),
+            "CREATE TABLE users (id int);",
+            1,
+            Utc::now(),
         );
+        let pending = |v: i64| MigrationResult {
+            state: MigrationState::Pending,
+            ..MigrationResult::from_applied(
+                &AppliedMigration::new(
+                    MigrationId::new(Version::Integer(v), "more".into()),
+                    "",
+                    0,
+                    Utc::now(),
+                ),
+                None,
+            )
+        };
+        let report = Report::new(vec![
+            MigrationResult::from_applied(&applied, None),
+            pending(2),
+            pending(3),
+        ]);
+        assert_eq!(report.last_applied(), Some(&Version::Integer(1)));
+        assert_eq!(report.pending(), 2);
+
+        let report = Report::new(vec![pending(1)]);
+        assert_eq!(report.last_applied(), None);
     }
 
     #[test]
-    fn fewer_in_source() {
-        let source: HashSet<MigrationId> = vec![
-            MigrationId::new(1, "first".into()),
-            MigrationId::new(2, "second".into()),
-            MigrationId::new(3, "third".into()),
-        ]
-        .into_iter()
-        .collect();
-        let applied: HashSet<MigrationId> = vec![
-            MigrationId::new(1, "first".into()),
-            MigrationId::new(2, "second".into()),
-            MigrationId::new(3, "third".into()),
-            MigrationId::new(4, "fourth".into()),
-        ]
-        .into_iter()
-        .collect();
-        let missing = vec![MigrationId::new(4, "fourth".into())];
-        let result = super::check_migrations_in_sync(applied, source);
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(
-            matches!(err, Error::OutOfSync { at_issue, .. } if *at_issue == missing)
+    fn parse_presets() {
+        let presets = Preset::VARIANTS
+            .iter()
+            .map(|name| name.parse::<Preset>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            presets,
+            [Preset::CiCheck, Preset::ProdApply, Preset::DevFast]
         );
+        assert_eq!(Preset::CiCheck.report_format(), Some(ReportFormat::Junit));
+        assert_eq!(Preset::DevFast.report_content(), ReportContent::Omit);
+        assert!("prod".parse::<Preset>().is_err());
     }
 
     #[test]
-    fn mismatched_source() {
-        let source: HashSet<MigrationId> = vec![
-            MigrationId::new(1, "first".into()),
-            MigrationId::new(2, "second".into()),
-            MigrationId::new(3, "third".into()),
-            MigrationId::new(4, "fifth".into()),
-            MigrationId::new(5, "sixth".into()),
-            MigrationId::new(6, "seventh".into()),
-            MigrationId::new(7, "eighth".into()),
-        ]
-        .into_iter()
-        .collect();
-        let applied: HashSet<MigrationId> = vec![
-            MigrationId::new(1, "first".into()),
-            MigrationId::new(2, "second".into()),
-            MigrationId::new(3, "third".into()),
-            MigrationId::new(4, "fourth".into()),
-            MigrationId::new(5, "fifth".into()),
-        ]
-        .into_iter()
-        .collect();
-        let divergence = vec![
-            MigrationId::new(4, "fourth".into()),
-            MigrationId::new(5, "fifth".into()),
-        ];
-        let result = super::check_migrations_in_sync(applied, source);
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        let Error::OutOfSync { mut at_issue, .. } = err else {
-            panic!("expected Error::OutOfSync");
+    fn verify_history_rows() {
+        let start = Utc::now();
+        let row = |v: i64, description: &str, content: &str, minutes: i64| {
+            AppliedMigration::new(
+                MigrationId::new(Version::Integer(v), description.into()),
+                content,
+                1,
+                start + Duration::minutes(minutes),
+            )
         };
-        at_issue.sort_by_key(|migration| migration.version());
-        assert_eq!(divergence, at_issue);
+        let rows = vec![
+            row(1, "create_users", "CREATE TABLE users (id int);", 0),
+            row(2, "add_email", "ALTER TABLE users ADD email text;", 3),
+            row(2, "add_name", "ALTER TABLE users ADD name text;", 4),
+            row(3, "load", "", 1),
+            row(4, "grants", "-- REDACTED sha256:", 5),
+        ];
+        let verification = HistoryVerification::new(rows, &NumericOrdering);
+
+        assert_eq!(verification.rows, 5);
+        assert_eq!(verification.count(), 4);
+        assert_eq!(
+            verification.duplicates[0].descriptions,
+            ["add_email", "add_name"]
+        );
+        let missing = verification
+            .missing_checksums
+            .iter()
+            .map(|m| m.id.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(missing, ["V3__load", "V4__grants"]);
+        let inversion = &verification.inversions[0];
+        assert_eq!(inversion.id.to_string(), "V3__load");
+        assert_eq!(inversion.preceding.to_string(), "V2__add_name");
     }
 }
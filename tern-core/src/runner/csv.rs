@@ -0,0 +1,105 @@
+//! Rendering a [`Report`] as CSV.
+use super::{MigrationResult, Report, RunDuration};
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+const COLUMNS: [&str; 6] = [
+    "version",
+    "description",
+    "state",
+    "applied_at",
+    "transactional",
+    "duration_ms",
+];
+
+pub(super) fn render(report: &Report) -> String {
+    let mut csv = String::new();
+    let _ = write_report(&mut csv, report);
+    csv
+}
+
+fn write_report(csv: &mut String, report: &Report) -> std::fmt::Result {
+    // A report without the content of its migrations doesn't have the column.
+    let content = report.migrations.iter().any(|r| r.content.is_some());
+    write!(csv, "{}", COLUMNS.join(","))?;
+    if content {
+        write!(csv, ",content")?;
+    }
+    writeln!(csv)?;
+    for result in &report.migrations {
+        write_row(csv, result, content)?;
+    }
+
+    Ok(())
+}
+
+fn write_row(
+    csv: &mut String,
+    result: &MigrationResult,
+    content: bool,
+) -> std::fmt::Result {
+    let applied_at =
+        result.applied_at.map(|at| at.to_rfc3339()).unwrap_or_default();
+    let duration_ms = match result.duration_ms {
+        RunDuration::Duration(ms) => ms.to_string(),
+        RunDuration::Unapplied => String::new(),
+    };
+    write!(
+        csv,
+        "{},{},{},{},{},{}",
+        escape(&result.version.to_string()),
+        escape(&result.description),
+        escape(&result.state.to_string()),
+        applied_at,
+        escape(&result.transactional.to_string()),
+        duration_ms
+    )?;
+    if content {
+        let sql = result.content.as_deref().unwrap_or_default();
+        write!(csv, ",{}", escape(sql))?;
+    }
+    writeln!(csv)
+}
+
+// A field with a separator, a quote, or a line break is quoted, with quotes in
+// it doubled.
+fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains([',', '"', '\n', '\r']) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migration::{AppliedMigration, MigrationId, Version};
+    use crate::runner::{MigrationResult, Report};
+
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn render_csv() {
+        let id = MigrationId::new(Version::Integer(1), "create_t".into());
+        let applied_at = Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+        let applied = AppliedMigration::new(
+            id,
+            "CREATE TABLE t (x text DEFAULT 'a,\"b\"');\n",
+            12,
+            applied_at,
+        );
+        let report = Report::new(vec![MigrationResult::from_applied(
+            &applied,
+            Some(false),
+        )]);
+
+        assert_eq!(
+            report.to_csv(),
+            "version,description,state,applied_at,transactional,duration_ms,content\n1,create_t,Applied,2026-01-02T03:04:05+00:00,In Transaction,12,\"CREATE TABLE t (x text DEFAULT 'a,\"\"b\"\"');\n\"\n"
+        );
+        assert_eq!(
+            report.without_content().to_csv(),
+            "version,description,state,applied_at,transactional,duration_ms\n1,create_t,Applied,2026-01-02T03:04:05+00:00,In Transaction,12\n"
+        );
+    }
+}
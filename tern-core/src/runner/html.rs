@@ -0,0 +1,215 @@
+//! Rendering a [`Report`] as a self-contained HTML page.
+use super::{MigrationResult, Report, RunDuration};
+
+use std::fmt::Write;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #ccc; padding: 0.4em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f0f0f0; cursor: pointer; user-select: none; }
+th[aria-sort=ascending]::after { content: \" \\25B2\"; }
+th[aria-sort=descending]::after { content: \" \\25BC\"; }
+pre { margin: 0.5em 0 0; white-space: pre-wrap; }
+.warning { color: #a60; }
+";
+
+// Sorts the table by a column when its header is clicked, by the `data-sort`
+// value of the cells, which is numeric when every value in it is a number.
+const SCRIPT: &str = "
+document.querySelectorAll('th').forEach((th, col) => {
+  th.addEventListener('click', () => {
+    const tbody = th.closest('table').tBodies[0];
+    const rows = Array.from(tbody.rows);
+    const asc = th.getAttribute('aria-sort') !== 'ascending';
+    const key = (row) => row.cells[col].dataset.sort;
+    const numeric = rows.every((row) => key(row) !== '' && !isNaN(key(row)));
+    rows.sort((a, b) => {
+      const cmp = numeric ? key(a) - key(b) : key(a).localeCompare(key(b));
+      return asc ? cmp : -cmp;
+    });
+    th.parentNode.querySelectorAll('th').forEach((h) => h.removeAttribute('aria-sort'));
+    th.setAttribute('aria-sort', asc ? 'ascending' : 'descending');
+    rows.forEach((row) => tbody.appendChild(row));
+  });
+});
+";
+
+const COLUMNS: [&str; 7] = [
+    "Version",
+    "Description",
+    "State",
+    "Applied at",
+    "Transaction",
+    "Duration",
+    "SQL",
+];
+
+pub(super) fn render(report: &Report) -> String {
+    let mut html = String::new();
+    let _ = write_report(&mut html, report);
+    html
+}
+
+fn write_report(html: &mut String, report: &Report) -> std::fmt::Result {
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html lang=\"en\">")?;
+    writeln!(html, "<head>")?;
+    writeln!(html, "<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>Migration report</title>")?;
+    writeln!(html, "<style>{STYLE}</style>")?;
+    writeln!(html, "</head>")?;
+    writeln!(html, "<body>")?;
+    writeln!(html, "<h1>Migration report</h1>")?;
+    writeln!(html, "<p>Migrations: {}</p>", report.count())?;
+    if !report.flags.is_empty() {
+        let flags = escape(&report.flags.to_string());
+        writeln!(html, "<p>Flags: <code>{flags}</code></p>")?;
+    }
+    if report.deadline_exceeded {
+        writeln!(
+            html,
+            "<p class=\"warning\">The deadline passed before every migration was applied.</p>"
+        )?;
+    }
+    if report.interrupted {
+        writeln!(
+            html,
+            "<p class=\"warning\">The run was stopped before every migration was applied.</p>"
+        )?;
+    }
+    let mut warnings = report.all_warnings().peekable();
+    if warnings.peek().is_some() {
+        writeln!(html, "<ul class=\"warning\">")?;
+        for warning in warnings {
+            writeln!(html, "<li>{}</li>", escape(&warning.to_string()))?;
+        }
+        writeln!(html, "</ul>")?;
+    }
+    writeln!(html, "<table>")?;
+    writeln!(html, "<thead><tr>")?;
+    for column in COLUMNS {
+        writeln!(html, "<th>{column}</th>")?;
+    }
+    writeln!(html, "</tr></thead>")?;
+    writeln!(html, "<tbody>")?;
+    for result in &report.migrations {
+        write_row(html, result)?;
+    }
+    writeln!(html, "</tbody>")?;
+    writeln!(html, "</table>")?;
+    writeln!(html, "<script>{SCRIPT}</script>")?;
+    writeln!(html, "</body>")?;
+    writeln!(html, "</html>")
+}
+
+fn write_row(html: &mut String, result: &MigrationResult) -> std::fmt::Result {
+    let version = result.version.to_string();
+    let applied_at = result
+        .applied_at
+        .map(|at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default();
+    let duration_ms = match result.duration_ms {
+        RunDuration::Duration(ms) => ms.to_string(),
+        RunDuration::Unapplied => String::new(),
+    };
+    let mut state = result.state.to_string();
+    if result.dryrun {
+        state.push_str(" (dry run)");
+    }
+
+    writeln!(html, "<tr>")?;
+    write_cell(html, &version, &version)?;
+    write_cell(html, &result.description, &result.description)?;
+    write_cell(html, &state, &state)?;
+    write_cell(html, &applied_at, &applied_at)?;
+    let transactional = result.transactional.to_string();
+    write_cell(html, &transactional, &transactional)?;
+    write_cell(html, &result.duration_ms.to_string(), &duration_ms)?;
+    write!(html, "<td data-sort=\"\"><details><summary>SQL</summary>")?;
+    if let Some(content) = &result.content {
+        write!(html, "<pre>{}</pre>", escape(content))?;
+    }
+    if !result.steps.is_empty() {
+        write!(html, "<ol>")?;
+        for step in &result.steps {
+            write!(
+                html,
+                "<li>{} ({}): <code>{}</code></li>",
+                step.state,
+                step.duration_ms,
+                escape(&step.statement)
+            )?;
+        }
+        write!(html, "</ol>")?;
+    }
+    for lock in &result.locks {
+        write!(
+            html,
+            "<p>{} on {}: <code>{}</code></p>",
+            lock.lock,
+            escape(&lock.relations.join(", ")),
+            escape(&lock.statement)
+        )?;
+    }
+    writeln!(html, "</details></td>")?;
+    writeln!(html, "</tr>")
+}
+
+fn write_cell(
+    html: &mut String,
+    value: &str,
+    sort_key: &str,
+) -> std::fmt::Result {
+    writeln!(
+        html,
+        "<td data-sort=\"{}\">{}</td>",
+        escape(sort_key),
+        escape(value)
+    )
+}
+
+pub(super) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migration::{AppliedMigration, MigrationId, Version};
+    use crate::runner::{MigrationResult, Report};
+
+    use chrono::Utc;
+
+    #[test]
+    fn render_html() {
+        let id = MigrationId::new(Version::Integer(1), "create_t".into());
+        let applied = AppliedMigration::new(
+            id,
+            "CREATE TABLE t (x text DEFAULT '<none>');",
+            12,
+            Utc::now(),
+        );
+        let html = Report::new(vec![MigrationResult::from_applied(
+            &applied,
+            Some(false),
+        )])
+        .to_html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<td data-sort=\"1\">1</td>"));
+        assert!(html.contains("<td data-sort=\"12\">12ms</td>"));
+        assert!(html.contains("DEFAULT &#39;&lt;none&gt;&#39;"));
+        assert!(!html.contains("<none>"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Rendering a [`Report`] as JUnit XML.
+use super::html::escape;
+use super::{MigrationResult, MigrationState, Report, RunDuration, Warning};
+use crate::error::Error;
+use crate::redact::redact;
+
+use std::error::Error as StdError;
+use std::fmt::Write;
+
+const SUITE: &str = "migrations";
+
+pub(super) fn render(report: &Report, error: Option<&Error>) -> String {
+    let mut xml = String::new();
+    let _ = write_report(&mut xml, report, error);
+    xml
+}
+
+fn write_report(
+    xml: &mut String,
+    report: &Report,
+    error: Option<&Error>,
+) -> std::fmt::Result {
+    let tests = report.count() + usize::from(error.is_some());
+    let failed = report.migrations.iter().filter(|r| r.error.is_some());
+    let failures = failed.count() + usize::from(error.is_some());
+    let skipped = report.migrations.iter().filter(|r| is_skipped(r)).count();
+    let time = report.migrations.iter().map(duration_ms).sum::<i64>();
+    let attrs = format!(
+        "tests=\"{tests}\" failures=\"{failures}\" errors=\"0\" skipped=\"{skipped}\" time=\"{}\"",
+        seconds(time)
+    );
+
+    writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(xml, "<testsuites {attrs}>")?;
+    writeln!(xml, "  <testsuite name=\"{SUITE}\" {attrs}>")?;
+    for result in &report.migrations {
+        write_test_case(xml, result)?;
+    }
+    if let Some(error) = error {
+        // A migration that failed is a test case with the error, and the
+        // report of a partial run is already the rest of the test cases.
+        let name = error
+            .migration_id()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| SUITE.to_string());
+        let detail = match error {
+            Error::Partial { source, .. } => redact(&source.to_string()),
+            e => redact(&e.to_string()),
+        };
+        // The error from the database, without what it is wrapped in.
+        let mut cause: &dyn StdError = error;
+        while let Some(source) = cause.source() {
+            cause = source;
+        }
+        writeln!(
+            xml,
+            "    <testcase classname=\"{SUITE}\" name=\"{}\" time=\"0\">",
+            escape(&name)
+        )?;
+        writeln!(
+            xml,
+            "      <failure message=\"{}\" type=\"{}\">{}</failure>",
+            escape(&redact(&cause.to_string())),
+            error.code(),
+            escape(&detail)
+        )?;
+        writeln!(xml, "    </testcase>")?;
+    }
+    write_warnings(xml, &report.warnings, "    ")?;
+    writeln!(xml, "  </testsuite>")?;
+    writeln!(xml, "</testsuites>")
+}
+
+fn write_test_case(
+    xml: &mut String,
+    result: &MigrationResult,
+) -> std::fmt::Result {
+    let name = format!("V{}__{}", result.version, result.description);
+    write!(
+        xml,
+        "    <testcase classname=\"{SUITE}\" name=\"{}\" time=\"{}\"",
+        escape(&name),
+        seconds(duration_ms(result))
+    )?;
+    if result.error.is_none()
+        && !is_skipped(result)
+        && result.warnings.is_empty()
+    {
+        return writeln!(xml, "/>");
+    }
+    writeln!(xml, ">")?;
+    // A migration that failed and was allowed to is a failed test case, too.
+    if let Some(error) = &result.error {
+        writeln!(
+            xml,
+            "      <failure message=\"{}\" type=\"{}\"/>",
+            escape(&error.message),
+            error.code
+        )?;
+    } else if is_skipped(result) {
+        let message = if result.dryrun { "dry run" } else { "skipped" };
+        writeln!(xml, "      <skipped message=\"{message}\"/>")?;
+    }
+    write_warnings(xml, &result.warnings, "      ")?;
+    writeln!(xml, "    </testcase>")
+}
+
+// Warnings are the standard error of the test suite or test case, which CI
+// systems show with it without counting it as a failure.
+fn write_warnings(
+    xml: &mut String,
+    warnings: &[Warning],
+    indent: &str,
+) -> std::fmt::Result {
+    if warnings.is_empty() {
+        return Ok(());
+    }
+    let lines = warnings
+        .iter()
+        .map(|w| escape(&w.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    writeln!(xml, "{indent}<system-err>{lines}</system-err>")
+}
+
+fn is_skipped(result: &MigrationResult) -> bool {
+    result.dryrun
+        || matches!(
+            result.state,
+            MigrationState::Skipped | MigrationState::Pending
+        )
+}
+
+fn duration_ms(result: &MigrationResult) -> i64 {
+    match result.duration_ms {
+        RunDuration::Duration(ms) => ms,
+        RunDuration::Unapplied => 0,
+    }
+}
+
+fn seconds(ms: i64) -> String {
+    format!("{:.3}", ms as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::migration::{AppliedMigration, MigrationId, Version};
+    use crate::runner::{MigrationResult, Report};
+
+    use chrono::Utc;
+
+    #[test]
+    fn render_junit() {
+        let id = MigrationId::new(Version::Integer(1), "create_t".into());
+        let applied = AppliedMigration::new(id, "", 1500, Utc::now());
+        let report = Report::new(vec![MigrationResult::from_applied(
+            &applied,
+            Some(false),
+        )]);
+        let error = Error::ExecuteMigration(
+            "relation \"t\" already exists".into(),
+            MigrationId::new(Version::Integer(2), "add_t".into()),
+            false,
+        );
+        let xml = report.to_junit_with_error(&error);
+
+        assert!(xml.contains("<testsuite name=\"migrations\" tests=\"2\" failures=\"1\" errors=\"0\" skipped=\"0\" time=\"1.500\">"));
+        assert!(xml.contains(
+            "<testcase classname=\"migrations\" name=\"V1__create_t\" time=\"1.500\"/>"
+        ));
+        assert!(xml.contains("name=\"V2__add_t\""));
+        assert!(xml.contains("type=\"TERN_EXECUTE_MIGRATION\""));
+        assert!(
+            xml.contains("relation &quot;t&quot; already exists</failure>")
+        );
+    }
+
+    #[test]
+    fn render_failed_migration() {
+        let id = MigrationId::new(Version::Integer(3), "analyze".into());
+        let applied = AppliedMigration::new(id.clone(), "", 0, Utc::now());
+        let error = Error::ExecuteMigration("timeout".into(), id, false);
+        let report = Report::new(vec![MigrationResult::from_failed(
+            &applied, false, &error,
+        )]);
+        let xml = report.to_junit();
+
+        assert!(xml.contains("tests=\"1\" failures=\"1\""));
+        assert!(xml.contains("type=\"TERN_EXECUTE_MIGRATION\"/>"));
+    }
+}
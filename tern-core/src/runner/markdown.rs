@@ -0,0 +1,122 @@
+//! Rendering a [`Report`] as a Markdown table.
+use super::{MigrationResult, Report};
+
+use std::fmt::Write;
+
+pub(super) fn render(report: &Report) -> String {
+    let mut md = String::new();
+    let _ = write_report(&mut md, report);
+    md
+}
+
+fn write_report(md: &mut String, report: &Report) -> std::fmt::Result {
+    writeln!(md, "| Version | Description | State | Duration |")?;
+    writeln!(md, "| ---: | --- | --- | ---: |")?;
+    for result in &report.migrations {
+        write_row(md, result)?;
+    }
+    if report.deadline_exceeded {
+        writeln!(md)?;
+        writeln!(
+            md,
+            "The deadline passed before every migration was applied."
+        )?;
+    }
+    if report.interrupted {
+        writeln!(md)?;
+        writeln!(
+            md,
+            "The run was stopped before every migration was applied."
+        )?;
+    }
+    if !report.flags.is_empty() {
+        writeln!(md)?;
+        writeln!(md, "Flags: `{}`", report.flags)?;
+    }
+    let mut warnings = report.all_warnings().peekable();
+    if warnings.peek().is_some() {
+        writeln!(md)?;
+        writeln!(md, "Warnings:")?;
+        writeln!(md)?;
+        for warning in warnings {
+            writeln!(md, "* {}", escape(&warning.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_row(md: &mut String, result: &MigrationResult) -> std::fmt::Result {
+    let mut state = result.state.to_string();
+    if result.dryrun {
+        state.push_str(" (dry run)");
+    }
+    writeln!(
+        md,
+        "| {} | {} | {} | {} |",
+        result.version,
+        escape(&result.description),
+        state,
+        result.duration_ms
+    )
+}
+
+// A `|` would end the cell, and markup in a description should show as it is.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '|' | '\\' | '`' | '*' | '_' | '<' | '>' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migration::{AppliedMigration, MigrationId, Version};
+    use crate::runner::{MigrationResult, Report, Warning, WarningKind};
+
+    use chrono::Utc;
+
+    #[test]
+    fn render_markdown() {
+        let id = MigrationId::new(Version::Integer(3), "add_x|y".into());
+        let applied = AppliedMigration::new(id, "", 7, Utc::now());
+        let md = Report::new(vec![MigrationResult::from_applied(
+            &applied,
+            Some(false),
+        )])
+        .to_markdown();
+
+        assert_eq!(
+            md,
+            "| Version | Description | State | Duration |\n| ---: | --- | --- | ---: |\n| 3 | add\\_x\\|y | Applied | 7ms |\n"
+        );
+    }
+
+    #[test]
+    fn render_warnings() {
+        let mut report = Report::new(Vec::new());
+        report.warnings.push(Warning::new(
+            WarningKind::Validation,
+            Vec::new(),
+            "validation rule lint failed",
+        ));
+        let md = report.to_markdown();
+
+        assert!(md.ends_with(
+            "\n\nWarnings:\n\n* validation: validation rule lint failed\n"
+        ));
+    }
+
+    #[test]
+    fn render_interrupted() {
+        let md = Report::new(Vec::new()).with_interrupted().to_markdown();
+
+        assert!(md.ends_with(
+            "\n\nThe run was stopped before every migration was applied.\n"
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+//! A snapshot of the schema that the migrations build.
+//!
+//! `schema.sql` is a file committed next to the migrations that has the
+//! `CREATE` statement of every table, index, and view in the database, and it
+//! is written with the CLI command `schema dump` after applying them.  It is
+//! the cumulative effect of all of the migrations in one place, so a change to
+//! the schema shows up in review next to the migration that made it, and
+//! `schema dump --check` fails if the file isn't up to date, for CI.
+//!
+//! The statements come from the catalog of the database, see
+//! [`Executor::schema`]: on Postgres they are built from `pg_catalog` the way
+//! `pg_dump --schema-only` would, on MySQL they are `SHOW CREATE TABLE` and
+//! `SHOW CREATE VIEW`, and on SQLite they are the ones in `sqlite_master`.
+//! A [`Schema`] sorts them by kind and name and leaves out what differs
+//! between databases with the same schema, so that the file only changes when
+//! the schema does.
+//!
+//! [`Executor::schema`]: crate::migration::Executor::schema
+use crate::inventory::ObjectKind;
+use crate::lint::regex;
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// The default name of the schema file.
+pub const SCHEMA_FILE_NAME: &str = "schema.sql";
+
+const HEADER: &str =
+    "-- This file is generated by `schema dump`.  Do not edit it by hand.";
+
+// Table and view options that say more about the database than the schema:
+// MySQL's next value of the auto-increment counter, and the user who created
+// a view.
+static VOLATILE_RE: OnceLock<Regex> = OnceLock::new();
+
+/// A table, index, or view and the statement that creates it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaObject {
+    /// The kind of object.
+    pub kind: ObjectKind,
+    /// The name of the object, without the schema.
+    pub name: String,
+    /// The statement that creates it.
+    pub ddl: String,
+}
+
+impl SchemaObject {
+    /// The object of this kind with this name, created by `ddl`.
+    pub fn new(
+        kind: ObjectKind,
+        name: impl Into<String>,
+        ddl: impl Into<String>,
+    ) -> Self {
+        Self { kind, name: name.into(), ddl: ddl.into() }
+    }
+}
+
+/// The objects in a database in a canonical order, from
+/// [`Runner::dump_schema`].
+///
+/// [`Runner::dump_schema`]: crate::runner::Runner::dump_schema
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    objects: Vec<SchemaObject>,
+}
+
+impl Schema {
+    /// The schema of these objects, which are sorted by kind and then name,
+    /// each with the statement that creates it ending in `;` and without
+    /// trailing whitespace on its lines.
+    pub fn new(objects: impl IntoIterator<Item = SchemaObject>) -> Self {
+        let re = regex(&VOLATILE_RE, r"\s+AUTO_INCREMENT=\d+|\s+DEFINER=\S+");
+        let mut objects = objects
+            .into_iter()
+            .map(|o| {
+                let ddl = re.replace_all(&o.ddl, "");
+                let ddl = ddl
+                    .lines()
+                    .map(str::trim_end)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let ddl = ddl.trim().trim_end_matches(';').trim_end();
+                SchemaObject { ddl: format!("{ddl};"), ..o }
+            })
+            .collect::<Vec<_>>();
+        objects.sort_by(|a, b| {
+            a.kind
+                .cmp(&b.kind)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+
+        Self { objects }
+    }
+
+    /// The objects, in order.
+    pub fn objects(&self) -> &[SchemaObject] {
+        &self.objects
+    }
+
+    /// Whether there are no objects.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl std::fmt::Display for Schema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for object in &self.objects {
+            writeln!(f)?;
+            writeln!(f, "{}", object.ddl)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Schema, SchemaObject};
+    use crate::inventory::ObjectKind;
+
+    #[test]
+    fn canonical_schema() {
+        let schema = Schema::new([
+            SchemaObject::new(
+                ObjectKind::View,
+                "active_users",
+                "CREATE ALGORITHM=UNDEFINED DEFINER=`root`@`%` SQL SECURITY DEFINER VIEW `active_users` AS select 1",
+            ),
+            SchemaObject::new(
+                ObjectKind::Index,
+                "users_email",
+                "CREATE INDEX users_email ON users (email);  \n",
+            ),
+            SchemaObject::new(
+                ObjectKind::Table,
+                "users",
+                "CREATE TABLE `users` (\n  `id` int NOT NULL AUTO_INCREMENT,  \n  PRIMARY KEY (`id`)\n) ENGINE=InnoDB AUTO_INCREMENT=42",
+            ),
+        ]);
+        let names = schema
+            .objects()
+            .iter()
+            .map(|o| o.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["users", "users_email", "active_users"]);
+
+        assert_eq!(
+            schema.to_string(),
+            "-- This file is generated by `schema dump`.  Do not edit it by hand.
+
+CREATE TABLE `users` (
+  `id` int NOT NULL AUTO_INCREMENT,
+  PRIMARY KEY (`id`)
+) ENGINE=InnoDB;
+
+CREATE INDEX users_email ON users (email);
+
+CREATE ALGORITHM=UNDEFINED SQL SECURITY DEFINER VIEW `active_users` AS select 1;
+"
+        );
+    }
+}
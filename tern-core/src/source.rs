@@ -0,0 +1,12 @@
+//! Reading migrations from where they are written.
+//!
+//! The derive macro for `MigrationSource` embeds the migrations in a directory
+//! when it is compiled, and [`fs`] is the same parsing and validation of that
+//! directory at runtime, for tools that work with the migration files
+//! themselves, like linters and generators.
+//!
+//! [`runtime`] reads a directory of SQL migrations when the program starts
+//! instead, for a binary that applies whatever migrations are on the server
+//! it runs on.
+pub mod fs;
+pub mod runtime;
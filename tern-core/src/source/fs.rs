@@ -0,0 +1,597 @@
+//! Parsing a directory of migration files.
+//!
+//! A migration file is named `V{version}__{description}.sql` or
+//! `V{version}__{description}.rs`, where the version is an integer or, for a
+//! context with text versions, letters, digits, `.`, and `-`.  This is what the
+//! derive macro for `MigrationSource` reads when it embeds the migrations in a
+//! `#[tern(source = "..")]` directory, so a tool that uses [`from_dir`] sees
+//! the same migrations, in the same order, and fails on the same mistakes:
+//!
+//! ```rust,no_run
+//! use tern_core::migration::VersionKind;
+//! use tern_core::source::fs;
+//!
+//! let migrations =
+//!     fs::from_dir("src/migrations", VersionKind::Integer, false)?;
+//! for migration in migrations {
+//!     println!("{} {}", migration.filename, migration.checksum());
+//! }
+//! # Ok::<(), fs::SourceError>(())
+//! ```
+//!
+//! A repeatable migration is named `R__{description}.sql`, and is read
+//! separately with [`repeatable_from_dir`].
+use crate::migration::{MigrationId, Version, VersionKind};
+
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// A migration file.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// The version and description from the filename.
+    pub id: MigrationId,
+    /// The name of the file.
+    pub filename: String,
+    /// The path to the file.
+    pub path: PathBuf,
+    /// Whether it is a SQL or a Rust migration.
+    pub source_type: SourceType,
+    /// The contents of the file.
+    pub content: String,
+    /// Whether it is a SQL migration with the `tern:noTransaction` annotation
+    /// on its first line.
+    pub no_tx: bool,
+}
+
+impl SourceFile {
+    /// Read the migration file at this path, which has a version of the given
+    /// kind in its name.
+    pub fn read(
+        path: impl AsRef<Path>,
+        kind: VersionKind,
+    ) -> Result<Self, SourceError> {
+        let path = path.as_ref();
+        let filename = utf8_filename(path)?;
+        let captures =
+            filename_re(kind).captures(&filename).ok_or_else(|| {
+                SourceError::Name {
+                    filename: filename.clone(),
+                    msg: format!("expected the format `{}`", format(kind)),
+                }
+            })?;
+        let version = Version::parse(&captures[1], kind).map_err(|e| {
+            SourceError::Name { filename: filename.clone(), msg: e.to_string() }
+        })?;
+        let id = MigrationId::new(version, captures[2].to_string());
+        let source_type = match &captures[3] {
+            "sql" => SourceType::Sql,
+            _ => SourceType::Rust,
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            SourceError::Io { path: path.to_path_buf(), msg: e.to_string() }
+        })?;
+        let no_tx = source_type == SourceType::Sql && no_tx(&content);
+
+        Ok(Self {
+            id,
+            filename,
+            path: path.to_path_buf(),
+            source_type,
+            content,
+            no_tx,
+        })
+    }
+
+    /// The name of the module that the derive macro puts the migration in,
+    /// which is the filename without its extension and with `.` and `-`
+    /// replaced by `_`.
+    pub fn module(&self) -> String {
+        self.id.to_string().replace(['.', '-'], "_")
+    }
+
+    /// The hex-encoded SHA-256 hash of the contents, which is the checksum of
+    /// the embedded migration.
+    pub fn checksum(&self) -> String {
+        checksum(&self.content)
+    }
+}
+
+/// A repeatable migration file, named `R__{description}.sql`, which is applied
+/// again whenever its contents change, after the versioned migrations.
+#[derive(Debug, Clone)]
+pub struct RepeatableFile {
+    /// The description from the filename, which identifies the migration.
+    pub description: String,
+    /// The name of the file.
+    pub filename: String,
+    /// The path to the file.
+    pub path: PathBuf,
+    /// The contents of the file.
+    pub content: String,
+    /// Whether it has the `tern:noTransaction` annotation on its first line.
+    pub no_tx: bool,
+}
+
+impl RepeatableFile {
+    /// Read the repeatable migration file at this path.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SourceError> {
+        let path = path.as_ref();
+        let filename = utf8_filename(path)?;
+        let captures =
+            repeatable_re().captures(&filename).ok_or_else(|| {
+                SourceError::Name {
+                    filename: filename.clone(),
+                    msg: format!("expected the format `{REPEATABLE_FORMAT}`"),
+                }
+            })?;
+        let description = captures[1].to_string();
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            SourceError::Io { path: path.to_path_buf(), msg: e.to_string() }
+        })?;
+        let no_tx = no_tx(&content);
+
+        Ok(Self {
+            description,
+            filename,
+            path: path.to_path_buf(),
+            content,
+            no_tx,
+        })
+    }
+
+    /// The hex-encoded SHA-256 hash of the contents, which is what decides
+    /// whether it is applied again.
+    pub fn checksum(&self) -> String {
+        checksum(&self.content)
+    }
+}
+
+/// Whether a file is named like a repeatable migration, which starts with
+/// `R__`.
+pub fn is_repeatable(filename: &str) -> bool {
+    filename.starts_with("R__")
+}
+
+/// The language a migration is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceType {
+    /// A `.sql` file.
+    Sql,
+    /// A `.rs` file.
+    Rust,
+}
+
+impl SourceType {
+    /// The file extension.
+    pub fn ext(&self) -> &'static str {
+        match self {
+            Self::Sql => "sql",
+            Self::Rust => "rs",
+        }
+    }
+}
+
+/// The ways reading a directory of migrations can fail.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum SourceError {
+    /// The directory could not be read.
+    #[error("could not read migration directory {path:?}: {msg}")]
+    Directory { path: PathBuf, msg: String },
+    /// A file in the directory is not named like a migration.
+    #[error("invalid migration filename {filename}: {msg}")]
+    Name { filename: String, msg: String },
+    /// A migration file could not be read.
+    #[error("could not read migration {path:?}: {msg}")]
+    Io { path: PathBuf, msg: String },
+    /// More than one migration has the same version.
+    #[error("duplicate migration versions found: {}", join(.0))]
+    DuplicateVersions(Vec<Version>),
+    /// The integer versions are not the sequence `1, 2, ..`.
+    #[error(
+        "expected version {expected} for a set with {count} migrations, found {found}"
+    )]
+    MissingVersion { expected: Version, found: Version, count: usize },
+    /// More than one migration has the same description.
+    #[error("migrations with the same description found: {}", .0.join(", "))]
+    DuplicateDescriptions(Vec<String>),
+    /// A Rust migration was found where only SQL migrations can be read,
+    /// since a Rust migration has to be compiled in.
+    #[error(
+        "{0} is a Rust migration, which has to be embedded by the derive macro"
+    )]
+    RustMigration(String),
+}
+
+/// Read and validate the migrations in a directory, sorted by version.
+///
+/// Versions have to be unique, and integer versions have to be the sequence
+/// `1, 2, ..` unless `allow_gaps`, which is what the derive macro does for a
+/// context with a custom ordering.
+pub fn from_dir(
+    dir: impl AsRef<Path>,
+    kind: VersionKind,
+    allow_gaps: bool,
+) -> Result<Vec<SourceFile>, SourceError> {
+    let migrations = read_dir(dir, kind)?;
+    validate(&migrations, allow_gaps)?;
+
+    Ok(migrations)
+}
+
+/// Read and validate the migrations in a directory like [`from_dir`], where
+/// every migration has to be SQL, which is what can be applied without being
+/// compiled.
+pub fn sql_from_dir(
+    dir: impl AsRef<Path>,
+    kind: VersionKind,
+    allow_gaps: bool,
+) -> Result<Vec<SourceFile>, SourceError> {
+    let migrations = from_dir(dir, kind, allow_gaps)?;
+    if let Some(m) =
+        migrations.iter().find(|m| m.source_type == SourceType::Rust)
+    {
+        return Err(SourceError::RustMigration(m.filename.clone()));
+    }
+
+    Ok(migrations)
+}
+
+/// Read the migrations in a directory, sorted by version, without validating
+/// them.
+///
+/// Every file is expected to be a migration, except for `mod.rs`, hidden
+/// files, and repeatable migrations, which are read by
+/// [`repeatable_from_dir`].
+pub fn read_dir(
+    dir: impl AsRef<Path>,
+    kind: VersionKind,
+) -> Result<Vec<SourceFile>, SourceError> {
+    let mut migrations = migration_paths(dir.as_ref())?
+        .into_iter()
+        .filter(|(filename, _)| !is_repeatable(filename))
+        .map(|(_, path)| SourceFile::read(path, kind))
+        .collect::<Result<Vec<_>, _>>()?;
+    migrations.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(migrations)
+}
+
+/// Read the repeatable migrations in a directory, sorted by description, which
+/// is the order they are applied in.
+///
+/// Repeatable migrations are SQL only, so a file `R__{description}.rs` is an
+/// error.
+pub fn repeatable_from_dir(
+    dir: impl AsRef<Path>,
+) -> Result<Vec<RepeatableFile>, SourceError> {
+    let mut migrations = migration_paths(dir.as_ref())?
+        .into_iter()
+        .filter(|(filename, _)| is_repeatable(filename))
+        .map(|(_, path)| RepeatableFile::read(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    migrations.sort_by(|a, b| a.description.cmp(&b.description));
+
+    Ok(migrations)
+}
+
+// The name and path of every file in the directory, except for `mod.rs` and
+// hidden files.
+fn migration_paths(dir: &Path) -> Result<Vec<(String, PathBuf)>, SourceError> {
+    let dir_err = |e: std::io::Error| SourceError::Directory {
+        path: dir.to_path_buf(),
+        msg: e.to_string(),
+    };
+    let location = dir.canonicalize().map_err(dir_err)?;
+    let paths = std::fs::read_dir(location)
+        .map_err(dir_err)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let filename = entry.file_name().to_string_lossy().into_owned();
+            let skip = filename == "mod.rs" || filename.starts_with('.');
+            (!skip).then(|| (filename, entry.path()))
+        })
+        .collect();
+
+    Ok(paths)
+}
+
+/// Check that migrations, sorted by version, have unique versions, and that
+/// integer versions are the sequence `1, 2, ..` unless `allow_gaps`.
+pub fn validate(
+    migrations: &[SourceFile],
+    allow_gaps: bool,
+) -> Result<(), SourceError> {
+    let versions =
+        migrations.iter().map(|m| m.id.version()).collect::<Vec<_>>();
+    duplicate_versions(&versions)?;
+    if !allow_gaps {
+        missing_versions(&versions)?;
+    }
+
+    Ok(())
+}
+
+/// Check that no two migrations have the same description, which is usually a
+/// migration that was copied and not renamed.
+///
+/// The error has the filenames of every migration with a description that
+/// another one has.
+pub fn unique_descriptions(
+    migrations: &[SourceFile],
+) -> Result<(), SourceError> {
+    let mut counts = HashMap::new();
+    for m in migrations {
+        *counts.entry(m.id.description()).or_insert(0) += 1;
+    }
+    let duplicates = migrations
+        .iter()
+        .filter(|m| counts[&m.id.description()] > 1)
+        .map(|m| m.filename.clone())
+        .collect::<Vec<_>>();
+    if !duplicates.is_empty() {
+        return Err(SourceError::DuplicateDescriptions(duplicates));
+    }
+
+    Ok(())
+}
+
+/// The hex-encoded SHA-256 hash of the contents of a migration file.
+pub fn checksum(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+fn duplicate_versions(versions: &[Version]) -> Result<(), SourceError> {
+    let mut seen = HashSet::new();
+    let duplicates = versions
+        .iter()
+        .filter(|v| !seen.insert(*v))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !duplicates.is_empty() {
+        return Err(SourceError::DuplicateVersions(duplicates));
+    }
+
+    Ok(())
+}
+
+// Only integer versions are expected to be the sequence `1, 2, ..`; text
+// versions have whatever scheme they have.
+fn missing_versions(versions: &[Version]) -> Result<(), SourceError> {
+    let count = versions.len();
+    match versions.last() {
+        Some(Version::Integer(v)) if *v != count as i64 => {
+            for (ix, found) in versions.iter().enumerate() {
+                let expected = Version::Integer((ix + 1) as i64);
+                if *found != expected {
+                    return Err(SourceError::MissingVersion {
+                        expected,
+                        found: found.clone(),
+                        count,
+                    });
+                }
+            }
+            Ok(())
+        },
+        _ => Ok(()),
+    }
+}
+
+// For static SQL migrations, the first line can have the special
+// `tern:noTransaction` annotation.
+fn no_tx(content: &str) -> bool {
+    content.lines().next().is_some_and(|l| l.contains("tern:noTransaction"))
+}
+
+fn utf8_filename(path: &Path) -> Result<String, SourceError> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(ToString::to_string)
+        .ok_or_else(|| SourceError::Name {
+            filename: path.to_string_lossy().into_owned(),
+            msg: "not a UTF-8 filename".into(),
+        })
+}
+
+fn join(versions: &[Version]) -> String {
+    let versions = versions.iter().map(ToString::to_string).collect::<Vec<_>>();
+    format!("[{}]", versions.join(","))
+}
+
+fn format(kind: VersionKind) -> &'static str {
+    match kind {
+        VersionKind::Integer => r"^V(\d+)__(\w+)\.(sql|rs)$",
+        VersionKind::Text => r"^V([0-9A-Za-z.\-]+)__(\w+)\.(sql|rs)$",
+    }
+}
+
+fn filename_re(kind: VersionKind) -> &'static Regex {
+    static INTEGER: OnceLock<Regex> = OnceLock::new();
+    static TEXT: OnceLock<Regex> = OnceLock::new();
+    let re = match kind {
+        VersionKind::Integer => &INTEGER,
+        VersionKind::Text => &TEXT,
+    };
+    re.get_or_init(|| Regex::new(format(kind)).unwrap())
+}
+
+const REPEATABLE_FORMAT: &str = r"^R__(\w+)\.sql$";
+
+fn repeatable_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(REPEATABLE_FORMAT).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        SourceError, SourceFile, checksum, from_dir, repeatable_from_dir,
+        sql_from_dir, unique_descriptions, validate,
+    };
+    use crate::migration::{MigrationId, Version, VersionKind};
+
+    use std::path::PathBuf;
+
+    fn source_file(version: Version) -> SourceFile {
+        described(version, "m")
+    }
+
+    fn described(version: Version, description: &str) -> SourceFile {
+        let id = MigrationId::new(version, description.into());
+        SourceFile {
+            filename: id.to_filename("sql"),
+            id,
+            path: PathBuf::new(),
+            source_type: super::SourceType::Sql,
+            content: String::new(),
+            no_tx: false,
+        }
+    }
+
+    fn integers(vs: Vec<i64>) -> Vec<SourceFile> {
+        vs.into_iter().map(|v| source_file(Version::Integer(v))).collect()
+    }
+
+    #[test]
+    fn duplicate_version() {
+        let res = validate(&integers(vec![1, 2, 3, 3, 4, 5, 6, 6, 7]), true);
+        assert!(matches!(
+            res,
+            Err(SourceError::DuplicateVersions(vs))
+                if vs == vec![Version::Integer(3), Version::Integer(6)]
+        ));
+    }
+
+    #[test]
+    fn missing_version() {
+        let res = validate(&integers(vec![1, 2, 3, 4, 5, 6, 8, 9, 10]), false);
+        assert!(matches!(
+            res,
+            Err(SourceError::MissingVersion { found, .. })
+                if found == Version::Integer(8)
+        ));
+    }
+
+    #[test]
+    fn gaps_allowed() {
+        let vs = integers(vec![1, 2, 1001, 1002, 2001]);
+        assert!(validate(&vs, false).is_err());
+        assert!(validate(&vs, true).is_ok());
+        assert!(validate(&integers(vec![1, 1001, 1001]), true).is_err());
+        assert!(validate(&integers(vec![1, 2, 3, 4, 5, 6]), false).is_ok());
+    }
+
+    #[test]
+    fn duplicate_description() {
+        let vs = [(1, "a"), (2, "b"), (3, "a"), (4, "c")]
+            .map(|(v, d)| described(Version::Integer(v), d));
+        assert!(matches!(
+            unique_descriptions(&vs),
+            Err(SourceError::DuplicateDescriptions(fs))
+                if fs == ["V1__a.sql", "V3__a.sql"]
+        ));
+        assert!(unique_descriptions(&vs[1..]).is_ok());
+    }
+
+    #[test]
+    fn read_text_versions() {
+        let dir = std::env::temp_dir()
+            .join(format!("tern-source-fs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (filename, content) in [
+            ("V2024.01.15.10__c.sql", "-- tern:noTransaction\nSELECT 1;\n"),
+            ("V2024.01.15.2__b.rs", ""),
+            ("V2023.12.01-1__a.sql", "SELECT 1;\n"),
+            ("mod.rs", ""),
+        ] {
+            std::fs::write(dir.join(filename), content).unwrap();
+        }
+
+        let migrations = from_dir(&dir, VersionKind::Text, false).unwrap();
+        let filenames =
+            migrations.iter().map(|m| m.filename.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            filenames,
+            [
+                "V2023.12.01-1__a.sql",
+                "V2024.01.15.2__b.rs",
+                "V2024.01.15.10__c.sql"
+            ]
+        );
+        assert_eq!(migrations[0].module(), "V2023_12_01_1__a");
+        assert!(!migrations[0].no_tx && migrations[2].no_tx);
+        assert!(matches!(
+            from_dir(&dir, VersionKind::Integer, false),
+            Err(SourceError::Name { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_repeatable() {
+        let dir = std::env::temp_dir()
+            .join(format!("tern-source-repeatable-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (filename, content) in [
+            ("V1__users.sql", "CREATE TABLE users(id int);\n"),
+            ("R__views.sql", "CREATE OR REPLACE VIEW v AS SELECT 1;\n"),
+            ("R__functions.sql", "-- tern:noTransaction\nSELECT 1;\n"),
+        ] {
+            std::fs::write(dir.join(filename), content).unwrap();
+        }
+
+        let migrations = from_dir(&dir, VersionKind::Integer, false).unwrap();
+        assert_eq!(migrations.len(), 1);
+        let repeatable = repeatable_from_dir(&dir).unwrap();
+        let descriptions = repeatable
+            .iter()
+            .map(|m| m.description.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(descriptions, ["functions", "views"]);
+        assert!(repeatable[0].no_tx && !repeatable[1].no_tx);
+
+        std::fs::write(dir.join("R__views.rs"), "").unwrap();
+        assert!(matches!(
+            repeatable_from_dir(&dir),
+            Err(SourceError::Name { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_sql_only() {
+        let dir = std::env::temp_dir()
+            .join(format!("tern-source-sql-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (filename, content) in [
+            ("V1__users.sql", "CREATE TABLE users(id int);\n"),
+            ("V2__posts.sql", "CREATE TABLE posts(id int);\n"),
+            ("R__views.sql", "CREATE OR REPLACE VIEW v AS SELECT 1;\n"),
+        ] {
+            std::fs::write(dir.join(filename), content).unwrap();
+        }
+
+        let migrations =
+            sql_from_dir(&dir, VersionKind::Integer, false).unwrap();
+        assert_eq!(migrations.len(), 2);
+
+        std::fs::write(dir.join("V3__backfill.rs"), "").unwrap();
+        assert!(matches!(
+            sql_from_dir(&dir, VersionKind::Integer, false),
+            Err(SourceError::RustMigration(f)) if f == "V3__backfill.rs"
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checksum_is_sha256() {
+        assert_eq!(
+            checksum("SELECT 1;\n"),
+            "b4e0497804e46e0a0b0b8c31975b062152d551bac49c3c2e80932567b4085dcd"
+        );
+    }
+}
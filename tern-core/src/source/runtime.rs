@@ -0,0 +1,125 @@
+//! A migration source that is read from a directory at runtime.
+//!
+//! The derive macro for `MigrationSource` embeds the migrations when the
+//! binary is compiled, which is the default and what most projects want.  An
+//! [`FsMigrationSource`] reads the directory when the program runs instead,
+//! so a new `.sql` file can be dropped on a server and applied by a binary
+//! that was already built.
+//!
+//! A context keeps it in a field and implements `MigrationSource` by hand:
+//!
+//! ```rust,ignore
+//! use tern::source::runtime::FsMigrationSource;
+//! use tern::migration::Version;
+//! use tern::{MigrationContext, MigrationSet, MigrationSource};
+//!
+//! #[derive(MigrationContext)]
+//! pub struct Ctx {
+//!     #[tern(executor_via)]
+//!     executor: SqlxPgExecutor,
+//!     source: FsMigrationSource<Ctx>,
+//! }
+//!
+//! impl MigrationSource for Ctx {
+//!     type Ctx = Self;
+//!
+//!     fn migration_set(&self, last_applied: Option<Version>) -> MigrationSet<Self> {
+//!         self.source.migration_set(last_applied)
+//!     }
+//! }
+//! ```
+//!
+//! Only SQL migrations can be read this way, since a Rust migration has to be
+//! compiled, and repeatable migrations are not read.  Nothing is embedded, so
+//! the checks that compare the history to the embedded migrations, like drift
+//! and the lockfile, have nothing to compare it to.
+use super::fs::{self, SourceError, SourceFile};
+use crate::error::TernResult;
+use crate::future::BoxFuture;
+use crate::migration::{
+    Migration, MigrationContext, MigrationId, MigrationSet, Query, Version,
+};
+
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// The SQL migrations in a directory, read when it was created.
+#[derive(Debug)]
+pub struct FsMigrationSource<C> {
+    migrations: Vec<SourceFile>,
+    ctx: PhantomData<fn() -> C>,
+}
+
+impl<C> Clone for FsMigrationSource<C> {
+    fn clone(&self) -> Self {
+        Self { migrations: self.migrations.clone(), ctx: PhantomData }
+    }
+}
+
+impl<C: MigrationContext> FsMigrationSource<C> {
+    /// Read and validate the migrations in a directory, which have versions
+    /// of the kind the context has.
+    ///
+    /// It fails for the same reasons as [`fs::sql_from_dir`].
+    pub fn from_dir(
+        dir: impl AsRef<Path>,
+        allow_gaps: bool,
+    ) -> Result<Self, SourceError> {
+        let migrations = fs::sql_from_dir(dir, C::VERSION_KIND, allow_gaps)?;
+
+        Ok(Self { migrations, ctx: PhantomData })
+    }
+
+    /// The migrations that were read.
+    pub fn migrations(&self) -> &[SourceFile] {
+        &self.migrations
+    }
+
+    /// The set of migrations since `last_applied`, which is what
+    /// `MigrationSource::migration_set` of the context should return.
+    pub fn migration_set(
+        &self,
+        last_applied: Option<Version>,
+    ) -> MigrationSet<C> {
+        let migrations = self
+            .migrations
+            .iter()
+            .map(|m| {
+                Box::new(FsMigration::<C> { file: m.clone(), ctx: PhantomData })
+                    as Box<dyn Migration<Ctx = C>>
+            })
+            .collect::<Vec<_>>();
+
+        MigrationSet::new(migrations).after(last_applied)
+    }
+}
+
+// A SQL migration read at runtime, which builds to the contents of the file.
+struct FsMigration<C> {
+    file: SourceFile,
+    ctx: PhantomData<fn() -> C>,
+}
+
+impl<C: MigrationContext> Migration for FsMigration<C> {
+    type Ctx = C;
+
+    fn migration_id(&self) -> MigrationId {
+        self.file.id.clone()
+    }
+
+    fn content(&self) -> String {
+        self.file.content.clone()
+    }
+
+    fn no_tx(&self) -> bool {
+        self.file.no_tx
+    }
+
+    fn build<'a>(
+        &'a self,
+        _: &'a mut Self::Ctx,
+    ) -> BoxFuture<'a, TernResult<Query>> {
+        let sql = self.file.content.clone();
+        Box::pin(async move { Ok(Query::new(sql)) })
+    }
+}
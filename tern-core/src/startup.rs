@@ -0,0 +1,131 @@
+//! Running migrations when an application starts.
+//!
+//! [`MigrateOnStartup`] applies the unapplied migrations with defaults suited
+//! to a service: it waits for the database, holds the run lock so that
+//! replicas starting together take turns, and returns the first error so that
+//! the service can exit instead of running against a partially migrated
+//! schema.  When it finishes, its [`Readiness`] is set, which a readiness
+//! probe can report.
+//!
+//! With the feature flags "axum" and "actix", the modules [`axum`] and
+//! [`actix`] have a route for such a probe in those frameworks.
+//!
+//! ```rust,ignore
+//! let startup = MigrateOnStartup::new();
+//! let app = axum::Router::new()
+//!     .merge(tern::startup::axum::readiness_router("/ready", startup.readiness()));
+//! startup.run(context).await?;
+//! ```
+use crate::error::TernResult;
+use crate::migration::MigrationContext;
+use crate::runner::{Report, Runner};
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "actix")]
+#[cfg_attr(docsrs, doc(cfg(feature = "actix")))]
+pub mod actix;
+
+#[cfg(feature = "axum")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum")))]
+pub mod axum;
+
+/// Whether migrations have finished, shared between the task running them
+/// and whatever reports it.
+#[derive(Debug, Clone, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the migrations have been applied.
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Mark the migrations as applied.
+    pub fn set_ready(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// Options for applying migrations when an application starts.
+#[derive(Debug, Clone)]
+pub struct MigrateOnStartup {
+    wait_for_db: Option<(Duration, Duration)>,
+    lock: bool,
+    readiness: Readiness,
+}
+
+impl MigrateOnStartup {
+    /// Wait up to 30 seconds for the database and hold the run lock.
+    pub fn new() -> Self {
+        Self {
+            wait_for_db: Some((
+                Duration::from_secs(30),
+                Duration::from_secs(1),
+            )),
+            lock: true,
+            readiness: Readiness::new(),
+        }
+    }
+
+    /// Change how long to wait for the database and how often to check.
+    pub fn with_wait_for_db(
+        mut self,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Self {
+        self.wait_for_db = Some((timeout, interval));
+        self
+    }
+
+    /// Don't wait for the database.
+    pub fn without_wait_for_db(mut self) -> Self {
+        self.wait_for_db = None;
+        self
+    }
+
+    /// Don't hold the run lock.
+    pub fn without_lock(mut self) -> Self {
+        self.lock = false;
+        self
+    }
+
+    /// The signal that is set when the migrations have been applied.
+    pub fn readiness(&self) -> Readiness {
+        self.readiness.clone()
+    }
+
+    /// Apply all unapplied migrations, then set the readiness signal.
+    ///
+    /// If this returns an error, readiness is not set, and the application
+    /// should exit.
+    pub async fn run<C>(&self, context: C) -> TernResult<Report>
+    where
+        C: MigrationContext,
+    {
+        let mut runner = Runner::new(context);
+        if self.lock {
+            runner = runner.with_lock();
+        }
+        if let Some((timeout, interval)) = self.wait_for_db {
+            runner = runner.with_wait_for_db(timeout, interval);
+        }
+        let report = runner.run_apply_all(false).await?;
+        log::info!("applied {} migrations on startup", report.count());
+        self.readiness.set_ready();
+
+        Ok(report)
+    }
+}
+
+impl Default for MigrateOnStartup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
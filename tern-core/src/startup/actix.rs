@@ -0,0 +1,19 @@
+//! Readiness probe for `actix-web`.
+use super::Readiness;
+
+use actix_web::{HttpResponse, Resource, web};
+
+/// A resource at `path` whose `GET` responds with `200 OK` when the migrations
+/// have been applied and `503 Service Unavailable` until then.
+pub fn readiness_resource(path: &str, readiness: Readiness) -> Resource {
+    web::resource(path).route(web::get().to(move || {
+        let readiness = readiness.clone();
+        async move {
+            if readiness.is_ready() {
+                HttpResponse::Ok().finish()
+            } else {
+                HttpResponse::ServiceUnavailable().finish()
+            }
+        }
+    }))
+}
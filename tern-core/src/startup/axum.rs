@@ -0,0 +1,24 @@
+//! Readiness probe for `axum`.
+use super::Readiness;
+
+use axum::Router;
+use axum::http::StatusCode;
+use axum::routing::get;
+
+/// A router with a `GET` route at `path` that responds with `200 OK` when the
+/// migrations have been applied and `503 Service Unavailable` until then.
+pub fn readiness_router<S>(path: &str, readiness: Readiness) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route(
+        path,
+        get(move || async move {
+            if readiness.is_ready() {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }),
+    )
+}
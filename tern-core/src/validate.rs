@@ -0,0 +1,756 @@
+//! Checking the migration source and history before an operation.
+//!
+//! Before the runner changes anything, it validates the migrations with a
+//! pipeline of [`ValidationRule`]s.  The first are the built-in rules:
+//!
+//! * [`InSync`]: every applied migration is still in the source.
+//! * [`NoGaps`]: no migration in the source is unapplied while a later one has
+//!   been applied, since it would never be applied.
+//! * [`NoDrift`]: applied SQL migrations have not been edited since.
+//! * [`ValidTarget`]: the target version of the operation, if it has one, is in
+//!   the range of versions that can be applied.
+//! * [`Locked`]: the migrations are the ones in the lockfile, if the source has
+//!   one.
+//!
+//! After them come the rules registered with
+//! [`Runner::with_validation_rule`](crate::runner::Runner::with_validation_rule),
+//! for policies of an organization, like requiring that descriptions reference
+//! a ticket.  The first rule to fail stops the operation with its error.
+//!
+//! Each rule has a [`ValidationCategory`], and what happens when a rule fails
+//! is the [`Severity`] of its category, set with
+//! [`Runner::with_validation_severity`](crate::runner::Runner::with_validation_severity).
+//! A failure of a rule in a category that is [`Severity::Warn`] is logged and
+//! the operation goes ahead, and rules in a category that is
+//! [`Severity::Ignore`] are not run at all.  Every category is
+//! [`Severity::Error`] by default, except for drift, which is a warning.
+//!
+//! ```rust,ignore
+//! struct TicketInDescription;
+//!
+//! impl ValidationRule for TicketInDescription {
+//!     fn name(&self) -> &str {
+//!         "ticket-in-description"
+//!     }
+//!
+//!     fn validate(&self, validation: &Validation<'_>) -> TernResult<()> {
+//!         let at_issue = validation
+//!             .unapplied()
+//!             .filter(|m| !m.id().description().starts_with("jira_"))
+//!             .map(|m| m.id().clone())
+//!             .collect::<Vec<_>>();
+//!         if at_issue.is_empty() {
+//!             return Ok(());
+//!         }
+//!         Err(Error::Validation {
+//!             rule: self.name().to_string(),
+//!             at_issue,
+//!             msg: "description does not start with a ticket".into(),
+//!         })
+//!     }
+//! }
+//!
+//! let runner = Runner::new(context).with_validation_rule(TicketInDescription);
+//! ```
+use crate::config::ConfigSource;
+use crate::error::{Error, TernResult};
+use crate::lockfile::Lockfile;
+use crate::migration::{
+    AppliedMigration, EmbeddedMigration, Migration, MigrationContext,
+    MigrationId, MigrationOrdering, MigrationSet, Version,
+};
+use crate::runner::{Warning, WarningKind};
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// A check of the migration source and history that has to pass for an
+/// operation to go ahead.
+pub trait ValidationRule: Send + Sync {
+    /// The name of the rule.
+    fn name(&self) -> &str;
+
+    /// The kind of problem the rule finds, which decides the [`Severity`] of
+    /// its failure.
+    fn category(&self) -> ValidationCategory {
+        ValidationCategory::Custom
+    }
+
+    /// Check the migrations, returning the error to stop the operation with if
+    /// the check fails.
+    fn validate(&self, validation: &Validation<'_>) -> TernResult<()>;
+}
+
+/// The kind of problem that a [`ValidationRule`] finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValidationCategory {
+    /// Applied migrations that are not in the source.
+    OutOfSync,
+    /// Unapplied migrations that are earlier than applied ones.
+    Gaps,
+    /// Applied migrations that have changed in the source.
+    Drift,
+    /// A target version that can't be applied up to.
+    Target,
+    /// Risky operations in unapplied migrations, found by a
+    /// [`Linter`](crate::lint::Linter).
+    Destructive,
+    /// Migrations that are not the ones in the lockfile.
+    Unlocked,
+    /// Any other rule.
+    Custom,
+}
+
+impl ValidationCategory {
+    pub const VARIANTS: [&str; 7] = [
+        "out-of-sync",
+        "gaps",
+        "drift",
+        "target",
+        "destructive",
+        "unlocked",
+        "custom",
+    ];
+}
+
+impl FromStr for ValidationCategory {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "out-of-sync" => Ok(Self::OutOfSync),
+            "gaps" => Ok(Self::Gaps),
+            "drift" => Ok(Self::Drift),
+            "target" => Ok(Self::Target),
+            "destructive" => Ok(Self::Destructive),
+            "unlocked" => Ok(Self::Unlocked),
+            "custom" => Ok(Self::Custom),
+            _ => {
+                Err(Error::Invalid(format!("unknown validation category {s}")))
+            },
+        }
+    }
+}
+
+/// What to do when a [`ValidationRule`] fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Stop the operation with the error.
+    Error,
+    /// Log the error and go ahead.
+    Warn,
+    /// Don't run the rule.
+    Ignore,
+}
+
+/// The [`Severity`] of each [`ValidationCategory`].
+#[derive(Debug, Clone, Default)]
+pub struct Severities(HashMap<ValidationCategory, Severity>);
+
+impl Severities {
+    /// The severity of the category.
+    pub fn get(&self, category: ValidationCategory) -> Severity {
+        match self.0.get(&category) {
+            Some(severity) => *severity,
+            None if category == ValidationCategory::Drift => Severity::Warn,
+            None => Severity::Error,
+        }
+    }
+
+    /// Set the severity of the category.
+    pub fn set(&mut self, category: ValidationCategory, severity: Severity) {
+        self.0.insert(category, severity);
+    }
+}
+
+/// What a [`ValidationRule`] has to go on.
+pub struct Validation<'a> {
+    source: Vec<SourceMigration>,
+    embedded: Vec<EmbeddedMigration>,
+    lockfile: Option<&'static str>,
+    applied: &'a [AppliedMigration],
+    target: Option<&'a Version>,
+    config: &'a ConfigSource,
+    ordering: &'static dyn MigrationOrdering,
+}
+
+impl<'a> Validation<'a> {
+    pub(crate) fn new<C: MigrationContext>(
+        source: &MigrationSet<C>,
+        applied: &'a [AppliedMigration],
+        target: Option<&'a Version>,
+        config: &'a ConfigSource,
+    ) -> Self {
+        let source = source
+            .migrations
+            .iter()
+            .map(|m| SourceMigration::new(m.as_ref()))
+            .collect();
+
+        Self {
+            source,
+            embedded: C::embedded_migrations(),
+            lockfile: C::LOCKFILE,
+            applied,
+            target,
+            config,
+            ordering: C::ORDERING,
+        }
+    }
+
+    // The validation of migrations known only from what was embedded of them,
+    // for reading the history without a context.
+    pub(crate) fn from_embedded(
+        embedded: Vec<EmbeddedMigration>,
+        lockfile: Option<&'static str>,
+        applied: &'a [AppliedMigration],
+        config: &'a ConfigSource,
+        ordering: &'static dyn MigrationOrdering,
+    ) -> Self {
+        let source = embedded
+            .iter()
+            .map(|m| SourceMigration {
+                id: m.migration_id(),
+                content: m.content.to_string(),
+                no_tx: m.no_tx,
+            })
+            .collect();
+
+        Self {
+            source,
+            embedded,
+            lockfile,
+            applied,
+            target: None,
+            config,
+            ordering,
+        }
+    }
+
+    /// Every migration in the source, in order.
+    pub fn source(&self) -> &[SourceMigration] {
+        &self.source
+    }
+
+    /// The migrations in the source that have not been applied.
+    pub fn unapplied(&self) -> impl Iterator<Item = &SourceMigration> {
+        let applied = self.applied_ids();
+        self.source.iter().filter(move |m| !applied.contains(m.id()))
+    }
+
+    /// What is known about the migrations at compile time, if they were
+    /// embedded with the derive macro.
+    ///
+    /// See [`MigrationSource::embedded_migrations`].
+    ///
+    /// [`MigrationSource::embedded_migrations`]: crate::migration::MigrationSource::embedded_migrations
+    pub fn embedded(&self) -> &[EmbeddedMigration] {
+        &self.embedded
+    }
+
+    /// The contents of the lockfile of the source, if it has one.
+    ///
+    /// See [`MigrationSource::LOCKFILE`].
+    ///
+    /// [`MigrationSource::LOCKFILE`]: crate::migration::MigrationSource::LOCKFILE
+    pub fn lockfile(&self) -> Option<&str> {
+        self.lockfile
+    }
+
+    /// The migrations in the history table.
+    pub fn applied(&self) -> &[AppliedMigration] {
+        self.applied
+    }
+
+    /// The latest version in the history table.
+    pub fn latest_applied(&self) -> Option<&Version> {
+        self.applied
+            .iter()
+            .map(|m| &m.version)
+            .max_by(|a, b| self.ordering.cmp(a, b))
+    }
+
+    /// The version that the operation goes up to, if it has one.
+    pub fn target(&self) -> Option<&Version> {
+        self.target
+    }
+
+    /// The configuration of the context, for rules that depend on the
+    /// environment.
+    pub fn config(&self) -> &ConfigSource {
+        self.config
+    }
+
+    /// The order of migrations in the context.
+    pub fn ordering(&self) -> &dyn MigrationOrdering {
+        self.ordering
+    }
+
+    fn applied_ids(&self) -> HashSet<MigrationId> {
+        self.applied.iter().cloned().map(MigrationId::from).collect()
+    }
+}
+
+/// A migration in the source.
+#[derive(Debug, Clone)]
+pub struct SourceMigration {
+    id: MigrationId,
+    content: String,
+    no_tx: bool,
+}
+
+impl SourceMigration {
+    fn new<C: MigrationContext>(migration: &dyn Migration<Ctx = C>) -> Self {
+        Self {
+            id: migration.migration_id(),
+            content: migration.content(),
+            no_tx: migration.no_tx(),
+        }
+    }
+
+    /// The version and description of the migration.
+    pub fn id(&self) -> &MigrationId {
+        &self.id
+    }
+
+    /// The content of the migration source file.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Whether the migration is not applied in a transaction.
+    pub fn no_tx(&self) -> bool {
+        self.no_tx
+    }
+}
+
+/// The rules that every operation is validated with, in the order they run.
+pub(crate) fn builtin_rules() -> [&'static dyn ValidationRule; 5] {
+    [&InSync, &NoGaps, &NoDrift, &ValidTarget, &Locked]
+}
+
+// Run the rules in order with the severity of their category, returning the
+// error of the first one to fail that is an error and a warning for each one
+// that failed with `Severity::Warn`.
+pub(crate) fn check_rules<'r>(
+    rules: impl IntoIterator<Item = &'r dyn ValidationRule>,
+    severities: &Severities,
+    validation: &Validation<'_>,
+) -> TernResult<Vec<Warning>> {
+    let mut warnings = Vec::new();
+    for rule in rules {
+        let severity = severities.get(rule.category());
+        if severity == Severity::Ignore {
+            continue;
+        }
+        log::trace!("checking validation rule {}", rule.name());
+        match rule.validate(validation) {
+            Err(e) if severity == Severity::Warn => {
+                let message =
+                    format!("validation rule {} failed: {e}", rule.name());
+                log::warn!("{message}");
+                let migrations = match e {
+                    Error::Validation { at_issue, .. } => at_issue,
+                    _ => Vec::new(),
+                };
+                warnings.push(Warning::new(
+                    WarningKind::Validation,
+                    migrations,
+                    message,
+                ));
+            },
+            res => res?,
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Every applied migration is in the source with the same version and
+/// description.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InSync;
+
+impl ValidationRule for InSync {
+    fn name(&self) -> &str {
+        "in-sync"
+    }
+
+    fn category(&self) -> ValidationCategory {
+        ValidationCategory::OutOfSync
+    }
+
+    fn validate(&self, validation: &Validation<'_>) -> TernResult<()> {
+        let source = validation.source.iter().map(|m| m.id.clone()).collect();
+        check_migrations_in_sync(validation.applied_ids(), source)
+    }
+}
+
+/// No migration in the source is unapplied while a later one is applied.
+///
+/// Only migrations after the latest applied one are considered for applying,
+/// so these would be skipped without this check.  They happen when migrations
+/// from different branches are merged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoGaps;
+
+impl ValidationRule for NoGaps {
+    fn name(&self) -> &str {
+        "no-gaps"
+    }
+
+    fn category(&self) -> ValidationCategory {
+        ValidationCategory::Gaps
+    }
+
+    fn validate(&self, validation: &Validation<'_>) -> TernResult<()> {
+        let Some(latest) = validation.latest_applied() else {
+            return Ok(());
+        };
+        let at_issue = validation
+            .unapplied()
+            .filter(|m| {
+                validation.ordering.cmp(&m.id.version(), latest).is_lt()
+            })
+            .map(|m| m.id.clone())
+            .collect::<Vec<_>>();
+
+        if !at_issue.is_empty() {
+            return Err(Error::OutOfSync {
+                at_issue,
+                msg: format!(
+                    "version/name not applied but earlier than the latest applied version V{latest}"
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Applied SQL migrations have the same content in the source as when they
+/// were applied.
+///
+/// The content of a SQL migration is the query that is applied, so an edit to
+/// the file after it was applied shows up as a difference between its
+/// checksum and the one in the `checksum` column of the history table, or for
+/// a row from before there was one, the checksum of the content that was
+/// kept.  This only knows about migrations from [`Validation::embedded`], and
+/// it can't say whether a Rust migration has changed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDrift;
+
+impl ValidationRule for NoDrift {
+    fn name(&self) -> &str {
+        "no-drift"
+    }
+
+    fn category(&self) -> ValidationCategory {
+        ValidationCategory::Drift
+    }
+
+    fn validate(&self, validation: &Validation<'_>) -> TernResult<()> {
+        let at_issue = validation
+            .applied
+            .iter()
+            .filter(|applied| {
+                validation.embedded.iter().any(|m| {
+                    m.filename.ends_with(".sql")
+                        && m.version == applied.version
+                        && m.description == applied.description
+                        && m.checksum != applied.checksum()
+                })
+            })
+            .map(|applied| MigrationId::from(applied.clone()))
+            .collect::<Vec<_>>();
+
+        if !at_issue.is_empty() {
+            return Err(Error::OutOfSync {
+                at_issue,
+                msg: "version/name applied but changed in source since".into(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The target version is not before the latest applied version or after the
+/// last version in the source.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidTarget;
+
+impl ValidationRule for ValidTarget {
+    fn name(&self) -> &str {
+        "valid-target"
+    }
+
+    fn category(&self) -> ValidationCategory {
+        ValidationCategory::Target
+    }
+
+    fn validate(&self, validation: &Validation<'_>) -> TernResult<()> {
+        let (Some(target), Some(source)) =
+            (validation.target, validation.source.last())
+        else {
+            return Ok(());
+        };
+        let source = source.id.version();
+        let ordering = validation.ordering;
+        match validation.latest_applied() {
+            Some(applied) if ordering.cmp(target, applied).is_lt() => {
+                Err(Error::Invalid(format!(
+                    "target version V{target} earlier than latest applied version V{applied}",
+                )))
+            },
+            _ if ordering.cmp(target, &source).is_gt() => {
+                Err(Error::Invalid(format!(
+                    "target version V{target} does not exist, latest version found was V{source}",
+                )))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The migrations in the source are the ones in its lockfile, with the same
+/// checksums and in the same order.
+///
+/// This passes when the source doesn't have a lockfile.  See
+/// [`lockfile`](crate::lockfile).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Locked;
+
+impl ValidationRule for Locked {
+    fn name(&self) -> &str {
+        "locked"
+    }
+
+    fn category(&self) -> ValidationCategory {
+        ValidationCategory::Unlocked
+    }
+
+    fn validate(&self, validation: &Validation<'_>) -> TernResult<()> {
+        let Some(lockfile) = validation.lockfile else {
+            return Ok(());
+        };
+        let mismatches = Lockfile::parse(lockfile)?.check(&validation.embedded);
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let at_issue = validation
+            .embedded
+            .iter()
+            .filter(|m| mismatches.iter().any(|x| x.filename == m.filename))
+            .map(EmbeddedMigration::migration_id)
+            .collect();
+
+        Err(Error::Validation {
+            rule: self.name().to_string(),
+            at_issue,
+            msg: format!(
+                "{}, run `migrate lock` after reviewing them",
+                mismatches
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        })
+    }
+}
+
+// Migrations that have been applied already but do not exist locally.
+fn check_migrations_in_sync(
+    applied: HashSet<MigrationId>,
+    source: HashSet<MigrationId>,
+) -> TernResult<()> {
+    let source_not_found: Vec<&MigrationId> =
+        applied.difference(&source).collect();
+
+    if !source_not_found.is_empty() {
+        return Err(Error::OutOfSync {
+            at_issue: source_not_found.into_iter().cloned().collect(),
+            msg: "version/name applied but missing in source".into(),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Error, MigrationId, NoGaps, Severities, Severity, SourceMigration,
+        ValidTarget, Validation, ValidationCategory, ValidationRule,
+    };
+    use crate::config::ConfigSource;
+    use crate::migration::{AppliedMigration, NumericOrdering, Version};
+
+    use chrono::Utc;
+    use std::collections::HashSet;
+
+    fn validation<'a>(
+        source: &[i64],
+        applied: &'a [AppliedMigration],
+        target: Option<&'a Version>,
+        config: &'a ConfigSource,
+    ) -> Validation<'a> {
+        let source = source
+            .iter()
+            .map(|v| SourceMigration {
+                id: MigrationId::new(*v, format!("m{v}")),
+                content: String::new(),
+                no_tx: false,
+            })
+            .collect();
+        Validation {
+            source,
+            embedded: Vec::new(),
+            lockfile: None,
+            applied,
+            target,
+            config,
+            ordering: &NumericOrdering,
+        }
+    }
+
+    fn applied(versions: &[i64]) -> Vec<AppliedMigration> {
+        versions
+            .iter()
+            .map(|v| {
+                let id = MigrationId::new(*v, format!("m{v}"));
+                AppliedMigration::new(id, "", 0, Utc::now())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gap_in_history() {
+        let config = ConfigSource::default();
+        let history = applied(&[1, 2, 4]);
+        let v = validation(&[1, 2, 3, 4, 5], &history, None, &config);
+        let err = NoGaps.validate(&v).unwrap_err();
+        assert!(
+            matches!(err, Error::OutOfSync { at_issue, .. } if at_issue == vec![MigrationId::new(3, "m3".into())])
+        );
+
+        let history = applied(&[1, 2]);
+        let v = validation(&[1, 2, 3, 4, 5], &history, None, &config);
+        assert!(NoGaps.validate(&v).is_ok());
+    }
+
+    #[test]
+    fn default_severities() {
+        let mut severities = Severities::default();
+        assert_eq!(severities.get(ValidationCategory::Gaps), Severity::Error);
+        assert_eq!(severities.get(ValidationCategory::Drift), Severity::Warn);
+        severities.set(ValidationCategory::Drift, Severity::Error);
+        severities.set(ValidationCategory::Gaps, Severity::Ignore);
+        assert_eq!(severities.get(ValidationCategory::Gaps), Severity::Ignore);
+        assert_eq!(severities.get(ValidationCategory::Drift), Severity::Error);
+    }
+
+    #[test]
+    fn target_out_of_range() {
+        let config = ConfigSource::default();
+        let history = applied(&[1, 2, 3]);
+        let source = [1, 2, 3, 4, 5];
+        let ok = Version::from(4);
+        let v = validation(&source, &history, Some(&ok), &config);
+        assert!(ValidTarget.validate(&v).is_ok());
+        let before = Version::from(2);
+        let v = validation(&source, &history, Some(&before), &config);
+        assert!(ValidTarget.validate(&v).is_err());
+        let after = Version::from(6);
+        let v = validation(&source, &history, Some(&after), &config);
+        assert!(ValidTarget.validate(&v).is_err());
+    }
+
+    #[test]
+    fn missing_source() {
+        let source: HashSet<MigrationId> = vec![
+            MigrationId::new(1, "first".into()),
+            MigrationId::new(2, "second".into()),
+            MigrationId::new(3, "fourth".into()),
+        ]
+        .into_iter()
+        .collect();
+        let applied: HashSet<MigrationId> = vec![
+            MigrationId::new(1, "first".into()),
+            MigrationId::new(2, "second".into()),
+            MigrationId::new(3, "third".into()),
+        ]
+        .into_iter()
+        .collect();
+        let missing = vec![MigrationId::new(3, "third".into())];
+        let result = super::check_migrations_in_sync(applied, source);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert!(
+            matches!(err, Error::OutOfSync { at_issue, .. } if *at_issue == missing)
+        );
+    }
+
+    #[test]
+    fn fewer_in_source() {
+        let source: HashSet<MigrationId> = vec![
+            MigrationId::new(1, "first".into()),
+            MigrationId::new(2, "second".into()),
+            MigrationId::new(3, "third".into()),
+        ]
+        .into_iter()
+        .collect();
+        let applied: HashSet<MigrationId> = vec![
+            MigrationId::new(1, "first".into()),
+            MigrationId::new(2, "second".into()),
+            MigrationId::new(3, "third".into()),
+            MigrationId::new(4, "fourth".into()),
+        ]
+        .into_iter()
+        .collect();
+        let missing = vec![MigrationId::new(4, "fourth".into())];
+        let result = super::check_migrations_in_sync(applied, source);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert!(
+            matches!(err, Error::OutOfSync { at_issue, .. } if *at_issue == missing)
+        );
+    }
+
+    #[test]
+    fn mismatched_source() {
+        let source: HashSet<MigrationId> = vec![
+            MigrationId::new(1, "first".into()),
+            MigrationId::new(2, "second".into()),
+            MigrationId::new(3, "third".into()),
+            MigrationId::new(4, "fifth".into()),
+            MigrationId::new(5, "sixth".into()),
+            MigrationId::new(6, "seventh".into()),
+            MigrationId::new(7, "eighth".into()),
+        ]
+        .into_iter()
+        .collect();
+        let applied: HashSet<MigrationId> = vec![
+            MigrationId::new(1, "first".into()),
+            MigrationId::new(2, "second".into()),
+            MigrationId::new(3, "third".into()),
+            MigrationId::new(4, "fourth".into()),
+            MigrationId::new(5, "fifth".into()),
+        ]
+        .into_iter()
+        .collect();
+        let divergence = vec![
+            MigrationId::new(4, "fourth".into()),
+            MigrationId::new(5, "fifth".into()),
+        ];
+        let result = super::check_migrations_in_sync(applied, source);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        let Error::OutOfSync { mut at_issue, .. } = err else {
+            panic!("expected Error::OutOfSync");
+        };
+        at_issue.sort_by_key(|migration| migration.version());
+        assert_eq!(divergence, at_issue);
+    }
+}
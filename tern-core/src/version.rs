@@ -0,0 +1,272 @@
+//! Migration versions.
+//!
+//! A version is the part of a migration filename between `V` and `__`.  By
+//! default it is an integer, but a context can use text versions instead, for
+//! schemes that don't fit in an `i64` or that have a format of their own, like
+//! zero-padded numbers or a date followed by a sequence number.
+//!
+//! Versions of either kind are compared in "natural" order: they are split
+//! into runs of digits and runs of everything else, runs of digits are compared
+//! as numbers, and the rest as text.  This puts `2024.01.15.2` before
+//! `2024.01.15.10`, and it is the usual order for integers.  A context that
+//! needs a different order supplies a [`MigrationOrdering`].
+use crate::error::{Error, TernResult};
+
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+/// Which kind of version a migration context uses, which decides the type of
+/// the version column in the history table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VersionKind {
+    /// Versions are integers, stored as a 64-bit integer.
+    #[default]
+    Integer,
+    /// Versions are text, stored as text.
+    Text,
+}
+
+/// A strategy for ordering migrations by their version.
+///
+/// The order is used everywhere migrations are put in sequence: sorting the
+/// migration set, finding the latest applied version, and deciding which
+/// migrations come before a target version.  For example, teams that reserve a
+/// range of versions each could order by range and then by the sequence in it:
+///
+/// ```rust
+/// use std::cmp::Ordering;
+/// use tern_core::migration::{MigrationOrdering, Version};
+///
+/// /// Versions like `payments-3`, ordered by squad, then sequence.
+/// struct SquadOrdering;
+///
+/// impl MigrationOrdering for SquadOrdering {
+///     fn cmp(&self, a: &Version, b: &Version) -> Ordering {
+///         let (a, b) = (a.to_string(), b.to_string());
+///         let key = |v: &str| {
+///             let (squad, seq) = v.rsplit_once('-').unwrap_or(("", v));
+///             (squad.to_string(), seq.parse::<i64>().unwrap_or_default())
+///         };
+///         key(&a).cmp(&key(&b))
+///     }
+/// }
+/// ```
+///
+/// With the derive macros this is `#[tern(ordering = "SquadOrdering")]`.
+pub trait MigrationOrdering: Send + Sync {
+    /// Compare two versions.
+    fn cmp(&self, a: &Version, b: &Version) -> Ordering;
+}
+
+/// The default ordering, which is the numeric order of versions, or the
+/// natural order of text versions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NumericOrdering;
+
+impl MigrationOrdering for NumericOrdering {
+    fn cmp(&self, a: &Version, b: &Version) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// The version of a migration.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Version {
+    Integer(i64),
+    Text(String),
+}
+
+impl Version {
+    /// Parse a version of the given kind.
+    pub fn parse(version: &str, kind: VersionKind) -> TernResult<Self> {
+        if version.is_empty() {
+            return Err(Error::Invalid("empty migration version".into()));
+        }
+        match kind {
+            VersionKind::Integer => version.parse().map(Self::Integer).map_err(
+                |_| {
+                    Error::Invalid(format!(
+                        "invalid migration version {version}, expected an integer"
+                    ))
+                },
+            ),
+            VersionKind::Text => Ok(Self::Text(version.to_string())),
+        }
+    }
+
+    /// The kind of this version.
+    pub fn kind(&self) -> VersionKind {
+        match self {
+            Self::Integer(_) => VersionKind::Integer,
+            Self::Text(_) => VersionKind::Text,
+        }
+    }
+
+    /// This version as the given kind.
+    pub fn to_kind(&self, kind: VersionKind) -> TernResult<Self> {
+        if self.kind() == kind {
+            return Ok(self.clone());
+        }
+        Self::parse(&self.to_string(), kind)
+    }
+
+    /// The integer, if this is an integer version.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Self::Integer(v) => Some(*v),
+            Self::Text(_) => None,
+        }
+    }
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Self::Integer(0)
+    }
+}
+
+impl From<i64> for Version {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<String> for Version {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for Version {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(v) => write!(f, "{v}"),
+            Self::Text(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) => a.cmp(b),
+            // `0010` and `10` are the same in natural order, but they are
+            // different versions.
+            _ => {
+                let (a, b) = (self.to_string(), other.to_string());
+                natural_cmp(&a, &b).then_with(|| a.cmp(&b))
+            },
+        }
+    }
+}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Consistent with `Eq`, which has `Integer(1)` equal to `Text("1")`.
+        self.to_string().hash(state);
+    }
+}
+
+// Compare runs of digits as numbers and everything else as text.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (segment(a), segment(b)) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some((x, rest_a)), Some((y, rest_b))) => {
+                let ord = match (is_digits(x), is_digits(y)) {
+                    (true, true) => {
+                        let (x, y) = (
+                            x.trim_start_matches('0'),
+                            y.trim_start_matches('0'),
+                        );
+                        x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+                    },
+                    _ => x.cmp(y),
+                };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                (a, b) = (rest_a, rest_b);
+            },
+        }
+    }
+}
+
+// The first run of digits or non-digits and the rest of the string.
+fn segment(s: &str) -> Option<(&str, &str)> {
+    let first = s.chars().next()?;
+    let end = s
+        .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+        .unwrap_or(s.len());
+    Some(s.split_at(end))
+}
+
+fn is_digits(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Version, VersionKind};
+
+    fn text(v: &str) -> Version {
+        Version::Text(v.to_string())
+    }
+
+    #[test]
+    fn natural_order() {
+        assert!(text("2024.01.15.2") < text("2024.01.15.10"));
+        assert!(text("2024.01.15.10") < text("2024.02.01.1"));
+        assert!(text("0009") < text("0010"));
+        assert!(text("9") < text("0010"));
+        assert!(text("a1") < text("b0"));
+        assert!(Version::Integer(9) < Version::Integer(10));
+        assert!(Version::Integer(-1) < Version::Integer(0));
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(Version::Integer(12), text("12"));
+        assert_ne!(text("012"), text("12"));
+        assert!(text("012") != Version::Integer(12));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Version::parse("0042", VersionKind::Integer).unwrap(),
+            Version::Integer(42)
+        );
+        assert!(Version::parse("1.2", VersionKind::Integer).is_err());
+        assert_eq!(
+            Version::parse("0042", VersionKind::Text).unwrap().to_string(),
+            "0042"
+        );
+        assert!(Version::parse("", VersionKind::Text).is_err());
+    }
+}
@@ -1,4 +1,5 @@
-use syn::{Result, spanned::Spanned};
+use syn::Result;
+use syn::spanned::Spanned;
 
 /// Token stream parsed from the `syn::DeriveInput`.
 pub struct Container<'a, Der, Fld> {
@@ -118,7 +119,8 @@ where
 
 /// Parse attributes found at any level of the ast.
 ///
-/// It's always the same procedure, just a different starting input (represented by `I`).
+/// It's always the same procedure, just a different starting input (represented
+/// by `I`).
 pub trait ParseAttr<I> {
     /// Initialize empty attributes.
     fn init() -> Self;
@@ -150,9 +152,11 @@ impl ParseAttr<syn::DeriveInput> for SkipParseAttr {
     fn init() -> Self {
         SkipParseAttr
     }
+
     fn attrs(_: &syn::DeriveInput) -> impl Iterator<Item = &syn::Attribute> {
         std::iter::empty::<&syn::Attribute>()
     }
+
     fn update(&mut self, _: &syn::Attribute) -> Result<()> {
         Ok(())
     }
@@ -162,9 +166,11 @@ impl ParseAttr<syn::Field> for SkipParseAttr {
     fn init() -> Self {
         SkipParseAttr
     }
+
     fn attrs(_: &syn::Field) -> impl Iterator<Item = &syn::Attribute> {
         std::iter::empty::<&syn::Attribute>()
     }
+
     fn update(&mut self, _: &syn::Attribute) -> Result<()> {
         Ok(())
     }
@@ -8,7 +8,10 @@ mod quote;
 /// implementation of [`Migration`] for it.
 ///
 /// With the macro attribute `no_transaction`, the `Migration` implementation
-/// is constructed to not run the migration in a database transaction.
+/// is constructed to not run the migration in a database transaction.  With
+/// `allow_failure`, a failure of the migration is recorded instead of stopping
+/// the run, like the annotation `tern:allowFailure`.  The failure is a row in
+/// the history table, so it isn't retried by later runs, only by `reapply`.
 ///
 /// ## Usage
 ///
@@ -38,8 +41,8 @@ pub fn migration(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 /// Custom, dynamic behavior for a migration can be defined for the context,
 /// which is available to [`QueryBuilder`].
 ///
-/// The macro exposes one optional macro attribute and one optional field
-/// attribute:
+/// The macro exposes one optional macro attribute and two optional field
+/// attributes:
 ///
 /// * `table` is the optional macro attribute.  With it enabled, the migration
 ///   history will be stored in this table, located in the default schema for
@@ -47,17 +50,22 @@ pub fn migration(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 /// * `executor_via` decorates the field holding an [`Executor`], which is
 ///   required of the type to be a context.  If not specified then it is
 ///   expected that the type itself implements `Executor`.
+/// * `config` decorates a field holding a [`ConfigSource`], which is then what
+///   the context's `config()` returns.  If not specified then the context reads
+///   configuration values from environment variables.
 ///
 /// ## Usage
 ///
 /// ```rust,ignore
-/// use tern::{SqlxPgExecutor, MigrationContext};
+/// use tern::{ConfigSource, SqlxPgExecutor, MigrationContext};
 ///
 /// #[derive(MigrationContext)]
 /// #[tern(table = "_my_migration_history")]
 /// pub struct MyContext {
 ///     #[tern(executor_via)]
 ///     executor: SqlxPgExecutor,
+///     #[tern(config)]
+///     config: ConfigSource,
 /// }
 /// ```
 ///
@@ -67,6 +75,7 @@ pub fn migration(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 /// [source-core]: https://docs.rs/tern/latest/tern/trait.MigrationSource.html
 /// [`MigrationSource`]: crate::MigrationSource
 /// [`Executor`]: https://docs.rs/tern/latest/tern/trait.Executor.html
+/// [`ConfigSource`]: https://docs.rs/tern/latest/tern/config/struct.ConfigSource.html
 #[proc_macro_derive(MigrationContext, attributes(tern))]
 pub fn migration_context(
     input: proc_macro::TokenStream,
@@ -109,3 +118,82 @@ pub fn migration_source(
         .unwrap_or_else(syn::Error::into_compile_error)
         .into()
 }
+
+/// `embed!` expands a directory of migrations into a [`MigrationSet`], as an
+/// alternative to the derive macro [`MigrationSource`] for a context that
+/// implements the trait by hand.  The path is relative to the project root,
+/// and `version = "text"` can follow it for text versions.
+///
+/// The context is inferred.  A SQL migration can be applied in any context,
+/// and a Rust migration is applied in the context of its `QueryBuilder`.
+/// Only the migrations are embedded: the source digest, lockfile, and
+/// repeatable migrations are what the derive macro adds.
+///
+/// ## Usage
+///
+/// ```rust,ignore
+/// use tern::migration::Version;
+/// use tern::{MigrationContext, MigrationSet, MigrationSource, SqlxPgExecutor};
+///
+/// #[derive(MigrationContext)]
+/// pub struct MyContext {
+///     #[tern(executor_via)]
+///     executor: SqlxPgExecutor,
+/// }
+///
+/// impl MigrationSource for MyContext {
+///     type Ctx = Self;
+///
+///     fn migration_set(&self, last_applied: Option<Version>) -> MigrationSet<Self> {
+///         tern::embed!("src/migrations").after(last_applied)
+///     }
+/// }
+/// ```
+///
+/// [`MigrationSet`]: https://docs.rs/tern/latest/tern/struct.MigrationSet.html
+/// [`MigrationSource`]: crate::MigrationSource
+#[proc_macro]
+pub fn embed(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as quote::EmbedInput);
+    quote::expand_embed(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// `HistoryReader` implements the trait [`HistoryReader`], which reads the
+/// migration history, its status, and its validation, for a type that must
+/// never be able to apply migrations, like a dashboard or a monitoring
+/// service.  It is not a `MigrationContext`, so it can't be given to a
+/// `Runner`.
+///
+/// It takes the attributes of [`MigrationContext`] that are about reading the
+/// history, `table`, `ordering`, `source_id`, and the field attributes
+/// `executor_via` and `config`.  With `source`, and optionally `lockfile`, the
+/// migrations are embedded to compare the history with, as with
+/// [`MigrationSource`], but they are not built.
+///
+/// ## Usage
+///
+/// ```rust,ignore
+/// use tern::{HistoryReader, SqlxPgExecutor};
+///
+/// #[derive(HistoryReader)]
+/// #[tern(source = "src/migrations", table = "_my_migration_history")]
+/// pub struct Dashboard {
+///     #[tern(executor_via)]
+///     executor: SqlxPgExecutor,
+/// }
+/// ```
+///
+/// [`HistoryReader`]: https://docs.rs/tern/latest/tern/reader/trait.HistoryReader.html
+/// [`MigrationContext`]: crate::MigrationContext
+/// [`MigrationSource`]: crate::MigrationSource
+#[proc_macro_derive(HistoryReader, attributes(tern))]
+pub fn history_reader(
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    quote::expand_impl_history_reader(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,87 @@
+use proc_macro2::TokenStream;
+use syn::Result;
+use syn::parse::{Parse, ParseStream};
+
+use super::migration_source::MigrationSetContainer;
+use crate::internal::parse;
+use tern_core::migration::VersionKind;
+
+// The input to `embed!`, the migration directory and optionally the kind of
+// version in the filenames, `embed!("src/migrations", version = "text")`.
+pub struct EmbedInput {
+    source: syn::LitStr,
+    version: VersionKind,
+}
+
+impl Parse for EmbedInput {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let source: syn::LitStr = input.parse()?;
+        let mut version = VersionKind::default();
+        if input.parse::<Option<syn::Token![,]>>()?.is_some()
+            && !input.is_empty()
+        {
+            let key: syn::Ident = input.parse()?;
+            if key != "version" {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "unknown `embed!` argument",
+                ));
+            }
+            input.parse::<syn::Token![=]>()?;
+            let value: syn::LitStr = input.parse()?;
+            version =
+                parse::version_kind_from_attr(&value.value()).ok_or_else(
+                    || {
+                        syn::Error::new(
+                            value.span(),
+                            "expected `version = \"integer\"` or `version = \"text\"`",
+                        )
+                    },
+                )?;
+            input.parse::<Option<syn::Token![,]>>()?;
+        }
+
+        Ok(Self { source, version })
+    }
+}
+
+impl EmbedInput {
+    pub fn quote_embed(&self) -> Result<TokenStream> {
+        let ident = syn::Ident::new("embed", self.source.span());
+        let migration_set = MigrationSetContainer::new(
+            &ident,
+            &Some(self.source.clone()),
+            self.version,
+            false,
+            false,
+        )?;
+
+        Ok(migration_set.quote_embedded_set())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmbedInput;
+    use tern_core::migration::VersionKind;
+
+    #[test]
+    fn parse_embed_input() {
+        let input: EmbedInput = syn::parse_str(r#""src/migrations""#).unwrap();
+        assert_eq!(input.source.value(), "src/migrations");
+        assert_eq!(input.version, VersionKind::Integer);
+
+        let input: EmbedInput =
+            syn::parse_str(r#""src/migrations", version = "text","#).unwrap();
+        assert_eq!(input.version, VersionKind::Text);
+
+        assert!(
+            syn::parse_str::<EmbedInput>(r#""src/migrations", version = "x""#)
+                .is_err()
+        );
+        assert!(
+            syn::parse_str::<EmbedInput>(r#""src/migrations", table = "t""#)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Result;
+
+use super::TernDeriveAttr;
+use super::migration_context::MigrationContextFieldAttr;
+use super::migration_source::MigrationSetContainer;
+use crate::internal::ast::Container;
+
+// Derive `HistoryReader`, which is the part of `MigrationContext` that reads
+// the history table, and the `EmbeddedMigration`s of `MigrationSource` when
+// there is a source, without building the migrations.  The container is the
+// same type as the one of `MigrationContext`, so it has its `new`.
+pub type HistoryReaderContainer<'a> =
+    Container<'a, TernDeriveAttr, MigrationContextFieldAttr>;
+
+impl HistoryReaderContainer<'_> {
+    pub fn quote_impl_history_reader(&self) -> Result<TokenStream> {
+        let ident = &self.ty.ident;
+        let quote_assoc_const = self.quote_assoc_const();
+        let quote_source = self.quote_source()?;
+        let quote_fields = self.quote_fields()?;
+
+        Ok(quote! {
+            #[automatically_derived]
+            impl ::tern::reader::HistoryReader for #ident {
+                #quote_assoc_const
+                #quote_source
+                #quote_fields
+            }
+        })
+    }
+
+    // The same defaults as `MigrationContext`.
+    fn quote_assoc_const(&self) -> TokenStream {
+        let quote_table = match &self.attrs.table {
+            Some(t) => quote! {const HISTORY_TABLE: &str = #t;},
+            _ => quote! {const HISTORY_TABLE: &str = "_tern_migrations";},
+        };
+        let quote_ordering = match &self.attrs.ordering {
+            Some(ordering) => quote! {
+                const ORDERING: &'static dyn ::tern::migration::MigrationOrdering =
+                    &#ordering;
+            },
+            _ => quote! {},
+        };
+        let quote_source_id = match &self.attrs.source_id {
+            Some(id) => quote! {
+                const SOURCE_ID: Option<&'static str> = Some(#id);
+            },
+            _ => quote! {},
+        };
+
+        quote! {
+            #quote_table
+            #quote_ordering
+            #quote_source_id
+        }
+    }
+
+    // Without a source, the history isn't compared with anything.
+    fn quote_source(&self) -> Result<TokenStream> {
+        if self.attrs.source.is_none() {
+            return Ok(quote! {});
+        }
+        let migration_set = MigrationSetContainer::new(
+            self.ty.ident,
+            &self.attrs.source,
+            self.attrs.version,
+            self.attrs.ordering.is_some(),
+            self.attrs.strict_names,
+        )?;
+        let quote_lockfile = migration_set.quote_lockfile(
+            &self.attrs.lockfile,
+            self.attrs.ordering.is_none(),
+        )?;
+        let embedded_migrations =
+            migration_set.quote_unbuilt_embedded_migrations();
+
+        Ok(quote! {
+            #quote_lockfile
+
+            fn embedded_migrations() -> Vec<::tern::migration::EmbeddedMigration> {
+                let ordering = <Self as ::tern::reader::HistoryReader>::ORDERING;
+                let mut migrations: Vec<::tern::migration::EmbeddedMigration> = vec![#(#embedded_migrations),*];
+                migrations.sort_by(|a, b| ordering.cmp(&a.version, &b.version));
+                migrations
+            }
+        })
+    }
+
+    // The executor and the configuration, from fields like a
+    // `MigrationContext`.
+    fn quote_fields(&self) -> Result<TokenStream> {
+        let fields = &self.fields.fields;
+        let exec_field =
+            fields.iter().filter(|f| f.attrs.executor_via).collect::<Vec<_>>();
+        if exec_field.len() > 1 {
+            Err(syn::Error::new(
+                Span::call_site(),
+                "at most one field may have the annotation `#[tern(executor_via)]`",
+            ))?
+        }
+        let config_field =
+            fields.iter().filter(|f| f.attrs.config).collect::<Vec<_>>();
+        if config_field.len() > 1 {
+            Err(syn::Error::new(
+                Span::call_site(),
+                "at most one field may have the annotation `#[tern(config)]`",
+            ))?
+        }
+        let quote_exec = match &exec_field[..] {
+            [field] => {
+                let accessor = &field.member;
+                let ty = &field.ty;
+                quote! {
+                    type Exec = #ty;
+                    fn executor(&mut self) -> &mut Self::Exec {
+                        &mut self.#accessor
+                    }
+                }
+            },
+            _ => quote! {
+                type Exec = Self;
+                fn executor(&mut self) -> &mut Self::Exec {
+                    self
+                }
+            },
+        };
+        let quote_config = match &config_field[..] {
+            [field] => {
+                let accessor = &field.member;
+                quote! {
+                    fn config(&self) -> &::tern::config::ConfigSource {
+                        &self.#accessor
+                    }
+                }
+            },
+            _ => quote! {},
+        };
+
+        Ok(quote! {
+            #quote_exec
+            #quote_config
+        })
+    }
+}
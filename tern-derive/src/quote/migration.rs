@@ -5,7 +5,7 @@ use syn::Result;
 use crate::internal::ast::{Container, ParseAttr, SkipParseAttr};
 
 // A Rust migration needs to implements `Migration` and this helper macro
-// contributes a method `no_tx` for that.
+// contributes methods `no_tx` and `allow_failure` for that.
 pub type MigrationContainer<'a> = Container<'a, MigrationAttr, SkipParseAttr>;
 
 impl<'a> MigrationContainer<'a> {
@@ -15,12 +15,17 @@ impl<'a> MigrationContainer<'a> {
 
     pub fn quote_impl_migration(&self) -> TokenStream {
         let no_tx = &self.attrs.no_tx;
+        let allow_failure = &self.attrs.allow_failure;
 
         quote! {
             impl TernMigration {
                 pub fn no_tx(&self) -> bool {
                     #no_tx
                 }
+
+                pub fn allow_failure(&self) -> bool {
+                    #allow_failure
+                }
             }
         }
     }
@@ -29,6 +34,7 @@ impl<'a> MigrationContainer<'a> {
 #[derive(Default)]
 pub struct MigrationAttr {
     no_tx: bool,
+    allow_failure: bool,
 }
 
 impl ParseAttr<syn::DeriveInput> for MigrationAttr {
@@ -51,6 +57,9 @@ impl ParseAttr<syn::DeriveInput> for MigrationAttr {
                 if meta.path.is_ident("no_transaction") {
                     self.no_tx = true;
                 }
+                if meta.path.is_ident("allow_failure") {
+                    self.allow_failure = true;
+                }
 
                 Ok(())
             })?;
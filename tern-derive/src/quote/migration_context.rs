@@ -4,6 +4,7 @@ use syn::Result;
 
 use super::TernDeriveAttr;
 use crate::internal::ast::{Container, ParseAttr};
+use tern_core::migration::VersionKind;
 
 // Derive `MigrationContext`.  This assumes that the type implements
 // `MigrationSource`, which can be done with the macro for it in this crate,
@@ -45,12 +46,98 @@ impl<'a> MigrationContextContainer<'a> {
                 "at most one field may have the annotation `#[tern(executor_via)]`",
             ))?
         }
+        let config_field = self
+            .fields
+            .fields
+            .clone()
+            .into_iter()
+            .filter(|f| f.attrs.config)
+            .collect::<Vec<_>>();
+
+        if config_field.len() > 1 {
+            Err(syn::Error::new(
+                Span::call_site(),
+                "at most one field may have the annotation `#[tern(config)]`",
+            ))?
+        }
         // The target table for schema migration history defaults to
         // `_tern_migrations`.
-        let quote_assoc_const = match table {
+        let quote_table = match table {
             Some(t) => quote! {const HISTORY_TABLE: &str = #t;},
             _ => quote! {const HISTORY_TABLE: &str = "_tern_migrations";},
         };
+        // Integer versions are the default of the trait.
+        let quote_version_kind = match self.attrs.version {
+            VersionKind::Text => quote! {
+                const VERSION_KIND: ::tern::migration::VersionKind =
+                    ::tern::migration::VersionKind::Text;
+            },
+            VersionKind::Integer => quote! {},
+        };
+        // So is the numeric order of versions.
+        let quote_ordering = match &self.attrs.ordering {
+            Some(ordering) => quote! {
+                const ORDERING: &'static dyn ::tern::migration::MigrationOrdering =
+                    &#ordering;
+            },
+            _ => quote! {},
+        };
+        // And not sharing the history table.
+        let quote_source_id = match &self.attrs.source_id {
+            Some(id) => quote! {
+                const SOURCE_ID: Option<&'static str> = Some(#id);
+            },
+            _ => quote! {},
+        };
+        // Nor setting up the session.
+        let quote_session_setup = match &self.attrs.session_setup {
+            Some(sql) => quote! {
+                const SESSION_SETUP: Option<&'static str> = Some(#sql);
+            },
+            _ => quote! {},
+        };
+        // Nor a `search_path`.
+        let quote_search_path = match &self.attrs.search_path {
+            Some(path) => quote! {
+                const SEARCH_PATH: Option<&'static str> = Some(#path);
+            },
+            _ => quote! {},
+        };
+        // Nor running anything after applying.
+        let quote_post_apply = match &self.attrs.post_apply {
+            Some(sql) => quote! {
+                const POST_APPLY: Option<&'static str> = Some(#sql);
+            },
+            _ => quote! {},
+        };
+        // Or keeping only the checksum of the content in the history.
+        let quote_history_content = match &self.attrs.history_content {
+            Some(c) if c.value() == "checksum" => quote! {
+                const HISTORY_CONTENT: ::tern::migration::HistoryContent =
+                    ::tern::migration::HistoryContent::Checksum;
+            },
+            Some(c) if c.value() == "zstd" => quote! {
+                const HISTORY_CONTENT: ::tern::migration::HistoryContent =
+                    ::tern::migration::HistoryContent::Zstd;
+            },
+            Some(c) if c.value() != "full" => {
+                return Err(syn::Error::new(
+                    c.span(),
+                    "expected `history_content` to be \"full\", \"checksum\", or \"zstd\"",
+                ));
+            },
+            _ => quote! {},
+        };
+        let quote_assoc_const = quote! {
+            #quote_table
+            #quote_version_kind
+            #quote_ordering
+            #quote_source_id
+            #quote_session_setup
+            #quote_search_path
+            #quote_post_apply
+            #quote_history_content
+        };
         // Construct the part of the impl body about the underlying query
         // executor type (i.e., database connection).
         let quote_exec_body = match &exec_field[..] {
@@ -73,14 +160,32 @@ impl<'a> MigrationContextContainer<'a> {
                 }
             },
         };
+        // Without a field for it, the context uses the default configuration
+        // source of the trait.
+        let quote_config = match &config_field[..] {
+            [field] => {
+                let accessor = &field.member;
+                quote! {
+                    fn config(&self) -> &::tern::config::ConfigSource {
+                        ::tern::config::context_config::<Self>()
+                            .unwrap_or(&self.#accessor)
+                    }
+                }
+            },
+            _ => quote! {},
+        };
 
-        Ok(quote_exec_body)
+        Ok(quote! {
+            #quote_exec_body
+            #quote_config
+        })
     }
 }
 
 #[derive(Default, Clone)]
 pub struct MigrationContextFieldAttr {
-    executor_via: bool,
+    pub(super) executor_via: bool,
+    pub(super) config: bool,
 }
 
 impl ParseAttr<syn::Field> for MigrationContextFieldAttr {
@@ -103,6 +208,8 @@ impl ParseAttr<syn::Field> for MigrationContextFieldAttr {
                 // have `field.attrs.executor_via = true`.
                 if meta.path.is_ident("executor_via") {
                     self.executor_via = true;
+                } else if meta.path.is_ident("config") {
+                    self.config = true;
                 }
 
                 Ok(())
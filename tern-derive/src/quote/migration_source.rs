@@ -1,10 +1,13 @@
+use chrono::{DateTime, SecondsFormat, Utc};
 use proc_macro2::{Span, TokenStream};
-use quote::quote;
+use quote::{ToTokens, quote};
 use syn::Result;
 
 use super::TernDeriveAttr;
 use crate::internal::ast::{Container, SkipParseAttr};
 use crate::internal::parse;
+use tern_core::migration::{Version, VersionKind};
+use tern_core::source::fs::{self, RepeatableFile, SourceFile, SourceType};
 
 // The derive macro `MigrationSource` does most of the work.  It reads
 // the migration sources, builds implementations of `Migration` for
@@ -21,10 +24,20 @@ impl<'a> MigrationSourceContainer<'a> {
     pub fn quote_impl_migration_source(&self) -> Result<TokenStream> {
         let ident = &self.ty.ident;
         let source = &self.attrs.source;
-        let migration_set = MigrationSetContainer::new(ident, source)?;
+        let migration_set = MigrationSetContainer::new(
+            ident,
+            source,
+            self.attrs.version,
+            self.attrs.ordering.is_some(),
+            self.attrs.strict_names,
+        )?;
+        let quote_lockfile = migration_set.quote_lockfile(
+            &self.attrs.lockfile,
+            self.attrs.ordering.is_none(),
+        )?;
 
         let quote_migration_source_impl =
-            migration_set.quote_migration_source_impl();
+            migration_set.quote_migration_source_impl(quote_lockfile);
         let quote_migration_mods = migration_set.quote_migration_modules();
         let quote_migration_impls = migration_set.quote_migration_impls();
 
@@ -43,9 +56,10 @@ impl<'a> MigrationSourceContainer<'a> {
 }
 
 // The sorted list of tokens parsed from all migration files.
-struct MigrationSetContainer {
+pub(super) struct MigrationSetContainer {
     ident: syn::Ident,
     migrations: Vec<MigrationContainer>,
+    repeatable: Vec<RepeatableContainer>,
 }
 
 // A single parsed migration file.
@@ -56,21 +70,42 @@ enum MigrationContainer {
 
 struct SqlSourceContainer {
     module: syn::Ident,
-    version: syn::LitInt,
+    filename: String,
+    version: TokenStream,
     description: syn::LitStr,
     content: syn::LitStr,
+    checksum: syn::LitStr,
+    no_tx: syn::LitBool,
+}
+
+// A repeatable migration, which is only ever SQL, so it is embedded as what
+// is known about it and not as a `Migration`.
+struct RepeatableContainer {
+    description: syn::LitStr,
+    filename: syn::LitStr,
+    content: syn::LitStr,
+    checksum: syn::LitStr,
     no_tx: syn::LitBool,
 }
 
 struct RustSourceContainer {
     module: syn::Ident,
-    version: syn::LitInt,
+    filename: String,
+    path: String,
+    version: TokenStream,
     description: syn::LitStr,
     content: syn::LitStr,
+    checksum: syn::LitStr,
 }
 
 impl MigrationSetContainer {
-    fn new(ident: &syn::Ident, source: &Option<syn::LitStr>) -> Result<Self> {
+    pub(super) fn new(
+        ident: &syn::Ident,
+        source: &Option<syn::LitStr>,
+        kind: VersionKind,
+        custom_ordering: bool,
+        strict_names: bool,
+    ) -> Result<Self> {
         let src = source.as_ref().map(|s| s.value()).ok_or_else(|| {
             syn::Error::new(
                 ident.span(),
@@ -78,49 +113,140 @@ impl MigrationSetContainer {
             )
         })?;
         let migration_dir = parse::cargo_manifest_dir().join(src);
+        // The versions in a custom order are not necessarily a sequence, e.g.,
+        // if ranges of versions are reserved.
+        let source_err = |e: fs::SourceError| {
+            syn::Error::new(
+                ident.span(),
+                format!("error with migration source: {e}"),
+            )
+        };
+        let files = fs::from_dir(&migration_dir, kind, custom_ordering)
+            .map_err(source_err)?;
+        // Two migrations with the same description are almost always one that
+        // was copied and not renamed.
+        if strict_names {
+            fs::unique_descriptions(&files).map_err(source_err)?;
+        }
         let migrations =
-            parse::MigrationSource::from_migration_dir(migration_dir)
-                .map_err(|e| {
-                    syn::Error::new(
-                        ident.span(),
-                        format!("error with migration source: {e:?}"),
-                    )
-                })?
-                .into_iter()
-                .map(MigrationContainer::from)
-                .collect::<Vec<_>>();
-
-        Ok(Self { ident: ident.clone(), migrations })
+            files.into_iter().map(MigrationContainer::from).collect::<Vec<_>>();
+        let repeatable = fs::repeatable_from_dir(migration_dir)
+            .map_err(source_err)?
+            .into_iter()
+            .map(RepeatableContainer::from)
+            .collect::<Vec<_>>();
+
+        Ok(Self { ident: ident.clone(), migrations, repeatable })
     }
 
     // Use the expanded vector of `Box::new(module_name::TernMigration)`s
     // to build a `Vec<Box<dyn Migration<Ctx = Self>>>`.  This works because we
     // have already implemented `Migration` for all of them.
-    fn quote_migration_source_impl(&self) -> TokenStream {
+    fn quote_migration_source_impl(
+        &self,
+        quote_lockfile: TokenStream,
+    ) -> TokenStream {
         let ctx = &self.ident;
         let boxed_migrations = self.quote_boxed_qualified_migration_types();
+        let embedded_migrations = self.quote_embedded_migrations();
+        let repeatable_migrations = self
+            .repeatable
+            .iter()
+            .map(RepeatableContainer::quote_repeatable_migration)
+            .collect::<Vec<_>>();
+        let source_digest = self.source_digest();
+        let built_at = built_at();
 
         quote! {
             #[automatically_derived]
             impl ::tern::migration::MigrationSource for #ctx {
                 type Ctx = #ctx;
 
+                const SOURCE_DIGEST: &'static str = #source_digest;
+
+                const BUILD_INFO: ::tern::migration::BuildInfo = ::tern::migration::BuildInfo {
+                    crate_version: option_env!("CARGO_PKG_VERSION"),
+                    git_sha: option_env!("TERN_GIT_SHA"),
+                    built_at: Some(#built_at),
+                };
+
+                #quote_lockfile
+
                 fn migration_set(
                     &self,
-                    last_applied: Option<i64>,
+                    last_applied: Option<::tern::migration::Version>,
                 ) -> ::tern::migration::MigrationSet<Self::Ctx>
                 {
                     let all: Vec<Box<dyn ::tern::migration::Migration<Ctx = Self::Ctx>>> = vec![#(#boxed_migrations),*];
                     let Some(v) = last_applied else {
                         return ::tern::migration::MigrationSet::new(all);
                     };
+                    let ordering = <Self::Ctx as ::tern::migration::MigrationContext>::ORDERING;
                     let migrations: Vec<Box<dyn ::tern::migration::Migration<Ctx = Self::Ctx>>> = all
                         .into_iter()
-                        .skip_while(|m| m.as_ref().version() <= v)
+                        .filter(|m| ordering.cmp(&m.as_ref().version(), &v).is_gt())
                         .collect::<Vec<_>>();
 
                     ::tern::migration::MigrationSet::new(migrations)
                 }
+
+                fn embedded_migrations() -> Vec<::tern::migration::EmbeddedMigration> {
+                    let ordering = <Self::Ctx as ::tern::migration::MigrationContext>::ORDERING;
+                    let mut migrations: Vec<::tern::migration::EmbeddedMigration> = vec![#(#embedded_migrations),*];
+                    migrations.sort_by(|a, b| ordering.cmp(&a.version, &b.version));
+                    migrations
+                }
+
+                fn repeatable_migrations() -> Vec<::tern::migration::RepeatableMigration> {
+                    vec![#(#repeatable_migrations),*]
+                }
+            }
+        }
+    }
+
+    // The expression of `embed!`, a `MigrationSet` for any context that the
+    // Rust migrations are built in.  A SQL migration is a `SqlMigration`, which
+    // doesn't need a module, and the module of a Rust migration is declared in
+    // the block, with the path to the file since it isn't in the directory
+    // that the block's module is.  Its `Migration` impl has the context of its
+    // `QueryBuilder`.
+    pub(super) fn quote_embedded_set(&self) -> TokenStream {
+        let query_builder_ctx =
+            quote! { <Self as ::tern::migration::QueryBuilder>::Ctx };
+        let mut modules = Vec::new();
+        let mut impls = Vec::new();
+        let mut boxed = Vec::new();
+        for m in &self.migrations {
+            match m {
+                MigrationContainer::Sql(_) => {
+                    let embedded = m.quote_embedded_migration(false);
+                    boxed.push(quote! {
+                        Box::new(::tern::migration::SqlMigration::new(#embedded))
+                    });
+                },
+                MigrationContainer::Rs(s) => {
+                    let module = &s.module;
+                    let path = &s.path;
+                    modules.push(quote! {
+                        #[path = #path]
+                        mod #module;
+                    });
+                    impls.push(m.quote_impl_migration(&query_builder_ctx));
+                    boxed.push(m.quote_boxed_qualified_migration_type());
+                },
+            }
+        }
+
+        quote! {
+            {
+                #(#modules)*
+                #[allow(unused_qualifications)]
+                const _: () = {
+                    #(#impls)*
+                };
+                let migrations: Vec<Box<dyn ::tern::migration::Migration<Ctx = _>>> =
+                    vec![#(#boxed),*];
+                ::tern::migration::MigrationSet::new(migrations)
             }
         }
     }
@@ -157,6 +283,84 @@ impl MigrationSetContainer {
         })
     }
 
+    // Check the migrations against the lockfile, if the source has one, and
+    // embed it.  The migrations are only known to be in order when the
+    // ordering is the default one.  A lockfile that doesn't exist yet is the
+    // same as an empty one, so that it can be written by `migrate lock`.
+    pub(super) fn quote_lockfile(
+        &self,
+        lockfile: &Option<syn::LitStr>,
+        ordered: bool,
+    ) -> Result<TokenStream> {
+        let Some(lockfile) = lockfile else {
+            return Ok(quote! {});
+        };
+        let path = parse::cargo_manifest_dir().join(lockfile.value());
+        if !path.exists() {
+            return Ok(quote! {
+                const LOCKFILE: Option<&'static str> = Some("");
+            });
+        }
+        let contents = std::fs::read_to_string(&path).map_err(|e| {
+            syn::Error::new(
+                lockfile.span(),
+                format!("could not read lockfile {}: {e}", path.display()),
+            )
+        })?;
+        let migrations = self
+            .migrations
+            .iter()
+            .map(|m| (m.filename(), m.checksum().value()))
+            .collect::<Vec<_>>();
+        let migrations = migrations
+            .iter()
+            .map(|(f, c)| (*f, c.as_str()))
+            .collect::<Vec<_>>();
+        parse::check_lockfile(&contents, &migrations, ordered).map_err(|e| {
+            syn::Error::new(
+                lockfile.span(),
+                format!(
+                    "migrations do not match lockfile {}: {e}; restore the migrations, or lock them again by removing them from the lockfile and running `migrate lock`",
+                    lockfile.value()
+                ),
+            )
+        })?;
+        let path = path.display().to_string();
+
+        Ok(quote! {
+            const LOCKFILE: Option<&'static str> = Some(include_str!(#path));
+        })
+    }
+
+    // The hash of the filename and checksum of every migration, one per line,
+    // in version order.
+    fn source_digest(&self) -> syn::LitStr {
+        let lines = self
+            .migrations
+            .iter()
+            .map(|m| format!("{} {}\n", m.filename(), m.checksum().value()))
+            .collect::<String>();
+
+        syn::LitStr::new(&fs::checksum(&lines), Span::call_site())
+    }
+
+    // The `EmbeddedMigration` for each source file.
+    fn quote_embedded_migrations(&self) -> Vec<TokenStream> {
+        self.migrations
+            .iter()
+            .map(|s| s.quote_embedded_migration(true))
+            .collect::<Vec<_>>()
+    }
+
+    // The same for a type that only reads the history, which doesn't build
+    // the migrations, so Rust migrations aren't declared as modules.
+    pub(super) fn quote_unbuilt_embedded_migrations(&self) -> Vec<TokenStream> {
+        self.migrations
+            .iter()
+            .map(|s| s.quote_embedded_migration(false))
+            .collect::<Vec<_>>()
+    }
+
     // A vector of all the `TernMigration`s for each module.
     fn quote_boxed_qualified_migration_types(&self) -> Vec<TokenStream> {
         self.migrations
@@ -166,6 +370,20 @@ impl MigrationSetContainer {
     }
 }
 
+// When the derive macro was expanded, which is when the crate was last
+// compiled, or `SOURCE_DATE_EPOCH` for a reproducible build.
+fn built_at() -> syn::LitStr {
+    let epoch = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .and_then(|secs| DateTime::from_timestamp(secs, 0));
+    let built_at = epoch
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    syn::LitStr::new(&built_at, Span::call_site())
+}
+
 impl MigrationContainer {
     // `Box<module_name::TernMigration>`.
     fn quote_boxed_qualified_migration_type(&self) -> TokenStream {
@@ -173,6 +391,40 @@ impl MigrationContainer {
         quote! {Box::new(#module::TernMigration)}
     }
 
+    // What is known about the migration at compile time.  A Rust migration
+    // says whether it's `no_tx` in its own `Migration` impl, if it is `built`.
+    fn quote_embedded_migration(&self, built: bool) -> TokenStream {
+        let version = self.version();
+        let description = self.description();
+        let content = self.content();
+        let checksum = self.checksum();
+        let filename = self.filename();
+        let no_tx = match self {
+            Self::Sql(s) => {
+                let no_tx = &s.no_tx;
+                quote! { #no_tx }
+            },
+            Self::Rs(s) if built => {
+                let module = &s.module;
+                quote! {
+                    ::tern::migration::Migration::no_tx(&#module::TernMigration)
+                }
+            },
+            Self::Rs(_) => quote! { false },
+        };
+
+        quote! {
+            ::tern::migration::EmbeddedMigration {
+                version: #version,
+                description: #description,
+                filename: #filename,
+                no_tx: #no_tx,
+                checksum: #checksum,
+                content: #content,
+            }
+        }
+    }
+
     // Make a child module in this module.  Rust ones already exist so this just
     // declares it: `mod rust_migration_filename;`.  SQL ones we have to create,
     // and then declare `TernMigration` and impl `QueryBuilder` for it.
@@ -195,6 +447,15 @@ impl MigrationContainer {
                     }
                 }
             },
+            // A text version can have characters that aren't allowed in a
+            // module name, so the file needs to be given as a path.
+            Self::Rs(s) if s.filename != format!("{module}.rs") => {
+                let path = &s.path;
+                quote! {
+                    #[path = #path]
+                    mod #module;
+                }
+            },
             Self::Rs(_) => {
                 quote! {
                     mod #module;
@@ -207,7 +468,7 @@ impl MigrationContainer {
     // it's expected that `TernMigration` implements the `QueryBuilder`
     // trait.  SQL migrations do it inside the ad-hoc module we create in
     // `quote_migration_module` and Rust migrations expect the user to do it.
-    fn quote_impl_migration(&self, ctx: &syn::Ident) -> TokenStream {
+    fn quote_impl_migration(&self, ctx: &impl ToTokens) -> TokenStream {
         let module = self.module();
         let quote_common = self.quote_common_migration_fns();
         let no_tx_body = match self {
@@ -217,6 +478,17 @@ impl MigrationContainer {
             },
             _ => quote! { self.no_tx() },
         };
+        // A SQL migration only has the annotation, which is the default.
+        let allow_failure = match self {
+            Self::Sql(_) => quote! {},
+            _ => quote! {
+                fn allow_failure(&self) -> bool {
+                    self.allow_failure()
+                        || ::tern::migration::Migration::annotations(self)
+                            .allow_failure()
+                }
+            },
+        };
 
         quote! {
             impl ::tern::migration::Migration for #module::TernMigration {
@@ -227,6 +499,8 @@ impl MigrationContainer {
                 fn no_tx(&self) -> bool {
                     #no_tx_body
                 }
+
+                #allow_failure
             }
         }
     }
@@ -264,24 +538,41 @@ impl MigrationContainer {
             Self::Rs(s) => &s.module,
         }
     }
-    fn version(&self) -> &syn::LitInt {
+
+    fn version(&self) -> &TokenStream {
         match self {
             Self::Sql(s) => &s.version,
             Self::Rs(s) => &s.version,
         }
     }
+
     fn description(&self) -> &syn::LitStr {
         match self {
             Self::Sql(s) => &s.description,
             Self::Rs(s) => &s.description,
         }
     }
+
     fn content(&self) -> &syn::LitStr {
         match self {
             Self::Sql(s) => &s.content,
             Self::Rs(s) => &s.content,
         }
     }
+
+    fn filename(&self) -> &str {
+        match self {
+            Self::Sql(s) => &s.filename,
+            Self::Rs(s) => &s.filename,
+        }
+    }
+
+    fn checksum(&self) -> &syn::LitStr {
+        match self {
+            Self::Sql(s) => &s.checksum,
+            Self::Rs(s) => &s.checksum,
+        }
+    }
 }
 
 impl SqlSourceContainer {
@@ -309,50 +600,89 @@ impl SqlSourceContainer {
     }
 }
 
-impl From<parse::SqlSource> for SqlSourceContainer {
-    fn from(value: parse::SqlSource) -> Self {
+impl From<SourceFile> for SqlSourceContainer {
+    fn from(value: SourceFile) -> Self {
         Self {
-            module: syn::Ident::new(&value.module, Span::call_site()),
-            version: syn::LitInt::new(
-                &format!("{}", value.version),
+            module: syn::Ident::new(&value.module(), Span::call_site()),
+            version: quote_version(&value.id.version()),
+            description: syn::LitStr::new(
+                &value.id.description(),
                 Span::call_site(),
             ),
+            checksum: syn::LitStr::new(&value.checksum(), Span::call_site()),
+            content: syn::LitStr::new(&value.content, Span::call_site()),
+            no_tx: syn::LitBool::new(value.no_tx, Span::call_site()),
+            filename: value.filename,
+        }
+    }
+}
+
+impl RepeatableContainer {
+    fn quote_repeatable_migration(&self) -> TokenStream {
+        let Self { description, filename, content, checksum, no_tx } = self;
+
+        quote! {
+            ::tern::migration::RepeatableMigration {
+                description: #description,
+                filename: #filename,
+                no_tx: #no_tx,
+                checksum: #checksum,
+                content: #content,
+            }
+        }
+    }
+}
+
+impl From<RepeatableFile> for RepeatableContainer {
+    fn from(value: RepeatableFile) -> Self {
+        Self {
             description: syn::LitStr::new(
                 &value.description,
                 Span::call_site(),
             ),
+            filename: syn::LitStr::new(&value.filename, Span::call_site()),
+            checksum: syn::LitStr::new(&value.checksum(), Span::call_site()),
             content: syn::LitStr::new(&value.content, Span::call_site()),
             no_tx: syn::LitBool::new(value.no_tx, Span::call_site()),
         }
     }
 }
 
-impl From<parse::RustSource> for RustSourceContainer {
-    fn from(value: parse::RustSource) -> Self {
+impl From<SourceFile> for RustSourceContainer {
+    fn from(value: SourceFile) -> Self {
         Self {
-            module: syn::Ident::new(&value.module, Span::call_site()),
-            version: syn::LitInt::new(
-                &format!("{}", value.version),
-                Span::call_site(),
-            ),
+            module: syn::Ident::new(&value.module(), Span::call_site()),
+            path: value.path.to_str().unwrap_or_default().to_string(),
+            version: quote_version(&value.id.version()),
             description: syn::LitStr::new(
-                &value.description,
+                &value.id.description(),
                 Span::call_site(),
             ),
+            checksum: syn::LitStr::new(&value.checksum(), Span::call_site()),
             content: syn::LitStr::new(&value.content, Span::call_site()),
+            filename: value.filename,
         }
     }
 }
 
-impl From<parse::MigrationSource> for MigrationContainer {
-    fn from(value: parse::MigrationSource) -> Self {
-        match value {
-            parse::MigrationSource::Sql(s) => {
-                Self::Sql(SqlSourceContainer::from(s))
-            },
-            parse::MigrationSource::Rs(s) => {
-                Self::Rs(RustSourceContainer::from(s))
-            },
+// The `tern::migration::Version` for the parsed version.
+fn quote_version(version: &Version) -> TokenStream {
+    match version {
+        Version::Integer(v) => {
+            let v = syn::LitInt::new(&format!("{v}i64"), Span::call_site());
+            quote! { ::tern::migration::Version::Integer(#v) }
+        },
+        Version::Text(v) => {
+            quote! { ::tern::migration::Version::Text(#v.to_string()) }
+        },
+    }
+}
+
+impl From<SourceFile> for MigrationContainer {
+    fn from(value: SourceFile) -> Self {
+        match value.source_type {
+            SourceType::Sql => Self::Sql(SqlSourceContainer::from(value)),
+            SourceType::Rust => Self::Rs(RustSourceContainer::from(value)),
         }
     }
 }
@@ -3,7 +3,13 @@ use syn::Result;
 use syn::spanned::Spanned;
 
 use crate::internal::ast::ParseAttr;
+use crate::internal::parse;
+use tern_core::migration::VersionKind;
 
+pub use embed::EmbedInput;
+
+mod embed;
+mod history_reader;
 mod migration;
 mod migration_context;
 mod migration_source;
@@ -30,12 +36,34 @@ pub fn expand_impl_migration_source(
     Ok(output)
 }
 
-/// The derive macros `MigrationSource` and `MigrationContext` share the `tern`
-/// attribute, so they have to share the same parsed representation of it.
+pub fn expand_embed(input: &EmbedInput) -> Result<TokenStream> {
+    input.quote_embed()
+}
+
+pub fn expand_impl_history_reader(
+    input: &syn::DeriveInput,
+) -> Result<TokenStream> {
+    let container = history_reader::HistoryReaderContainer::new(input)?;
+    let output = container.quote_impl_history_reader()?;
+    Ok(output)
+}
+
+/// The derive macros `MigrationSource`, `MigrationContext`, and
+/// `HistoryReader` share the `tern` attribute, so they have to share the same
+/// parsed representation of it.
 #[derive(Default, Clone)]
 pub struct TernDeriveAttr {
     source: Option<syn::LitStr>,
     table: Option<syn::LitStr>,
+    version: VersionKind,
+    ordering: Option<syn::Expr>,
+    source_id: Option<syn::LitStr>,
+    session_setup: Option<syn::LitStr>,
+    search_path: Option<syn::LitStr>,
+    post_apply: Option<syn::LitStr>,
+    history_content: Option<syn::LitStr>,
+    lockfile: Option<syn::LitStr>,
+    strict_names: bool,
 }
 
 impl ParseAttr<syn::DeriveInput> for TernDeriveAttr {
@@ -61,6 +89,43 @@ impl ParseAttr<syn::DeriveInput> for TernDeriveAttr {
                 } else if meta.path.is_ident("source") {
                     let parsed_source: syn::LitStr = meta.value()?.parse()?;
                     self.source = Some(parsed_source);
+                } else if meta.path.is_ident("version") {
+                    let parsed_version: syn::LitStr = meta.value()?.parse()?;
+                    self.version = parse::version_kind_from_attr(
+                        &parsed_version.value(),
+                    )
+                    .ok_or_else(|| {
+                        syn::Error::new(
+                            parsed_version.span(),
+                            "expected `version = \"integer\"` or `version = \"text\"`",
+                        )
+                    })?;
+                } else if meta.path.is_ident("ordering") {
+                    let parsed_ordering: syn::LitStr = meta.value()?.parse()?;
+                    self.ordering = Some(parsed_ordering.parse()?);
+                } else if meta.path.is_ident("source_id") {
+                    let parsed_source_id: syn::LitStr = meta.value()?.parse()?;
+                    self.source_id = Some(parsed_source_id);
+                } else if meta.path.is_ident("session_setup") {
+                    let parsed_session_setup: syn::LitStr =
+                        meta.value()?.parse()?;
+                    self.session_setup = Some(parsed_session_setup);
+                } else if meta.path.is_ident("search_path") {
+                    let parsed_search_path: syn::LitStr =
+                        meta.value()?.parse()?;
+                    self.search_path = Some(parsed_search_path);
+                } else if meta.path.is_ident("post_apply") {
+                    let parsed_post_apply: syn::LitStr = meta.value()?.parse()?;
+                    self.post_apply = Some(parsed_post_apply);
+                } else if meta.path.is_ident("history_content") {
+                    let parsed_history_content: syn::LitStr =
+                        meta.value()?.parse()?;
+                    self.history_content = Some(parsed_history_content);
+                } else if meta.path.is_ident("lockfile") {
+                    let parsed_lockfile: syn::LitStr = meta.value()?.parse()?;
+                    self.lockfile = Some(parsed_lockfile);
+                } else if meta.path.is_ident("strict_names") {
+                    self.strict_names = true;
                 } else {
                     Err(syn::Error::new(
                         attr.span(),